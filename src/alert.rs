@@ -0,0 +1,136 @@
+//! # alert
+//!
+//! Alerts and effects the lights run by themselves: blinking a light to tell which one it is, and
+//! the colorloop effect that cycles a color light through all hues until it is stopped.
+
+use std::error::Error;
+use std::fmt;
+
+use hue::Hue;
+use selector::Selector;
+use state::LightStateUpdate;
+
+/// How a light blinks.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertMode {
+    /// Stops blinking.
+    None,
+    /// Blinks once.
+    Select,
+    /// Blinks for 15 seconds, or until stopped.
+    LSelect,
+}
+
+impl AlertMode {
+    /// Parses an alert mode by the name the bridge uses, e.x. "lselect".
+    pub fn parse(mode: &str) -> Result<AlertMode, Box<dyn Error>> {
+        match mode.to_lowercase().as_str() {
+            "none" => Ok(AlertMode::None),
+            "select" => Ok(AlertMode::Select),
+            "lselect" => Ok(AlertMode::LSelect),
+            _ => Err(From::from(format!(
+                "Unknown alert '{}', expected 'select', 'lselect', or 'none'.",
+                mode
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for AlertMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AlertMode::None => f.write_str("none"),
+            AlertMode::Select => f.write_str("select"),
+            AlertMode::LSelect => f.write_str("lselect"),
+        }
+    }
+}
+
+/// An effect a light runs until stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Effect {
+    /// Stops the running effect.
+    None,
+    /// Cycles through all hues, keeping brightness and saturation. Only color lights have it.
+    ColorLoop,
+}
+
+impl Effect {
+    /// Parses an effect by the name the bridge uses, e.x. "colorloop".
+    pub fn parse(effect: &str) -> Result<Effect, Box<dyn Error>> {
+        match effect.to_lowercase().as_str() {
+            "none" => Ok(Effect::None),
+            "colorloop" => Ok(Effect::ColorLoop),
+            _ => Err(From::from(format!(
+                "Unknown effect '{}', expected 'colorloop' or 'none'.",
+                effect
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Effect::None => f.write_str("none"),
+            Effect::ColorLoop => f.write_str("colorloop"),
+        }
+    }
+}
+
+impl Hue {
+    /// Sets the alert of a single light given its index.
+    pub fn set_alert_by_index(&self, index: &str, mode: AlertMode) -> Result<(), Box<dyn Error>> {
+        self.set_light_state(index, &LightStateUpdate::new().alert(mode))
+    }
+
+    /// Sets the effect of a single light given its index.
+    pub fn set_effect_by_index(&self, index: &str, effect: Effect) -> Result<(), Box<dyn Error>> {
+        self.set_light_state(index, &LightStateUpdate::new().effect(effect))
+    }
+
+    /// Sets the alert of the selected lights, e.x. to blink a light to identify it.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use rusty_hue::alert::AlertMode;
+    /// use rusty_hue::hue::Hue;
+    /// use rusty_hue::selector::Selector;
+    ///
+    /// let hue = Hue::new().unwrap();
+    /// hue.set_alert(&Selector::parse("Desk").unwrap(), AlertMode::Select)
+    ///     .unwrap();
+    /// ```
+    pub fn set_alert(&self, selector: &Selector, mode: AlertMode) -> Result<(), Box<dyn Error>> {
+        for index in self.resolve(selector)? {
+            self.set_alert_by_index(&index, mode)?;
+        }
+        Ok(())
+    }
+
+    /// Starts or stops an effect on the selected lights.
+    pub fn set_effect(&self, selector: &Selector, effect: Effect) -> Result<(), Box<dyn Error>> {
+        for index in self.resolve(selector)? {
+            self.set_effect_by_index(&index, effect)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_modes() {
+        assert_eq!(AlertMode::parse("LSelect").unwrap(), AlertMode::LSelect);
+        assert_eq!(AlertMode::parse("none").unwrap().to_string(), "none");
+        assert!(AlertMode::parse("blink").is_err());
+
+        assert_eq!(Effect::parse("colorloop").unwrap(), Effect::ColorLoop);
+        assert!(Effect::parse("rainbow").is_err());
+    }
+}
@@ -0,0 +1,478 @@
+//! # animation
+//!
+//! Plays animations on lights: an animation decides the state of each light at every step, and
+//! the steps are sent to the bridge at a fixed interval. The interval is kept long enough that the
+//! bridge, which takes about ten light commands a second, isn't flooded. Animations that repeat a
+//! fixed cycle of states can instead be exported as timers on the bridge, which keep playing them
+//! while this tool isn't running.
+//!
+//! Flashing more than three times a second can trigger seizures in people with photosensitive
+//! epilepsy, so while playing, abrupt changes of a light that come faster than the maximum flash
+//! rate are softened into fades. The limit can only be lifted explicitly.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use hue;
+use hue::Hue;
+use schedules::{Schedule, ScheduleCommand};
+use shuffle::Rng;
+use state::LightStateUpdate;
+
+/// Time the bridge needs per light command, which bounds how fast an animation can step.
+pub const TIME_PER_COMMAND: Duration = Duration::from_millis(100);
+
+/// Description given to the timers of exported animations, so they can be found and removed.
+pub const EXPORT_DESCRIPTION: &str = "rusty_hue effect";
+
+/// Most timers an exported animation may create. The bridge holds at most 100 schedules.
+const MAX_EXPORTED_TIMERS: usize = 60;
+
+/// Most flashes a second of a light by default.
+pub const DEFAULT_MAX_FLASH_RATE: f32 = 3.0;
+
+/// Most flashes a second of a light for the whole process, as the bits of an `f32`. Infinity
+/// when the limit is lifted.
+static MAX_FLASH_RATE: AtomicU32 = AtomicU32::new(DEFAULT_MAX_FLASH_RATE.to_bits());
+
+/// Change in brightness that is a flash when abrupt, about a tenth of the range.
+const FLASH_BRI: u8 = 25;
+
+/// Distance between colors that is a flash when abrupt.
+const FLASH_XY: f32 = 0.05;
+
+/// State of one light at a step of an animation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LightFrame {
+    pub index: String,
+    /// Color, ignored for lights that can't show colors.
+    pub xy: Option<(f32, f32)>,
+    pub bri: u8,
+    /// How long the light takes to reach the state.
+    pub transition: Duration,
+}
+
+/// An animation, deciding the state of each light at every step.
+pub trait Animation {
+    /// Returns the frames of a step, only for the lights that change in it.
+    fn frame(&mut self, step: u32, lights: &[String], rng: &mut Rng) -> Vec<LightFrame>;
+
+    /// Returns the steps of the animation when it repeats them in a fixed cycle, so it can be
+    /// exported to the bridge, or otherwise why it needs this tool running, e.x. "it picks
+    /// random brightnesses".
+    fn cycle(&self, _lights: &[String]) -> Result<Vec<Vec<LightFrame>>, String> {
+        Err(String::from("it doesn't repeat a fixed cycle"))
+    }
+}
+
+/// Shortest interval between steps for the given number of lights, on a bridge that keeps up.
+pub fn min_interval(lights: usize) -> Duration {
+    TIME_PER_COMMAND * lights.max(1) as u32
+}
+
+/// Sets the most flashes a second of a light for all animations played, or lifts the limit with
+/// `None`. The limit should only be lifted when the user explicitly asked for it.
+pub fn set_max_flash_rate(max_flash_rate: Option<f32>) {
+    let rate = max_flash_rate.unwrap_or(f32::INFINITY);
+    MAX_FLASH_RATE.store(rate.to_bits(), Ordering::Relaxed);
+}
+
+/// Most flashes a second of a light, `None` if the limit was lifted.
+pub fn max_flash_rate() -> Option<f32> {
+    Some(f32::from_bits(MAX_FLASH_RATE.load(Ordering::Relaxed))).filter(|rate| rate.is_finite())
+}
+
+/// Shortest time between two flashes of a light at the given number of flashes a second.
+pub fn min_flash_gap(max_flash_rate: f32) -> Duration {
+    Duration::from_secs_f32(1.0 / max_flash_rate)
+}
+
+/// Keeps lights from flashing faster than a number of times a second. A frame is a flash when it
+/// changes the light's brightness or color noticeably from its last frame, quicker than the time
+/// allowed between flashes. Flashes that come too soon after the last one get a transition long
+/// enough to make them a fade instead.
+#[derive(Debug)]
+struct StrobeGuard {
+    /// `None` when flashing isn't limited.
+    min_gap: Option<Duration>,
+    last_frames: HashMap<String, LightFrame>,
+    last_flashes: HashMap<String, Instant>,
+}
+
+impl StrobeGuard {
+    fn new(max_flash_rate: Option<f32>) -> StrobeGuard {
+        StrobeGuard {
+            min_gap: max_flash_rate.map(min_flash_gap),
+            last_frames: HashMap::new(),
+            last_flashes: HashMap::new(),
+        }
+    }
+
+    /// Returns the frame to send at the given time, softened if it would flash too soon.
+    fn limit(&mut self, mut frame: LightFrame, now: Instant) -> LightFrame {
+        let min_gap = match self.min_gap {
+            Some(min_gap) => min_gap,
+            None => return frame,
+        };
+
+        let flash = frame.transition < min_gap
+            && self
+                .last_frames
+                .get(&frame.index)
+                .is_some_and(|last| is_flash(last, &frame));
+
+        if flash {
+            match self.last_flashes.get(&frame.index) {
+                Some(&at) if now.duration_since(at) < min_gap => {
+                    // Rounded up to the tenths of a second the bridge takes transitions in.
+                    let tenths = min_gap.as_millis().div_ceil(100) as u64;
+                    frame.transition = Duration::from_millis(tenths * 100);
+                }
+                _ => {
+                    self.last_flashes.insert(frame.index.clone(), now);
+                }
+            }
+        }
+
+        self.last_frames.insert(frame.index.clone(), frame.clone());
+        frame
+    }
+}
+
+/// Helper function to tell whether going from one frame to the next changes the light enough to
+/// be seen as a flash, if abrupt.
+fn is_flash(last: &LightFrame, next: &LightFrame) -> bool {
+    let color_change = match (last.xy, next.xy) {
+        (Some((x1, y1)), Some((x2, y2))) => ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt(),
+        _ => 0.0,
+    };
+
+    last.bri.abs_diff(next.bri) >= FLASH_BRI || color_change >= FLASH_XY
+}
+
+impl Hue {
+    /// Shortest interval between steps for the given number of lights, at the pace learned for
+    /// the bridge or pinned with `frame_rate`.
+    pub fn min_interval(&self, lights: usize) -> Duration {
+        self.time_per_command() * lights.max(1) as u32
+    }
+
+    /// Plays an animation on the lights, a step every interval, until the duration runs out or
+    /// forever if there is none. Lights flash at most `max_flash_rate()` times a second. Returns
+    /// the number of steps played.
+    pub fn play(
+        &self,
+        animation: &mut dyn Animation,
+        lights: &[String],
+        interval: Duration,
+        duration: Option<Duration>,
+    ) -> Result<u32, Box<dyn Error>> {
+        if lights.is_empty() {
+            return Err(From::from("No lights to animate."));
+        }
+
+        let min = self.min_interval(lights.len());
+        if interval < min {
+            return Err(From::from(format!(
+                "An interval of {} ms is too short for {} light(s), the bridge needs at least {} \
+                 ms.",
+                interval.as_millis(),
+                lights.len(),
+                min.as_millis()
+            )));
+        }
+
+        let mut rng = Rng::from_time();
+        let mut guard = StrobeGuard::new(max_flash_rate());
+        let start = Instant::now();
+        let mut step = 0;
+
+        while duration.is_none_or(|duration| start.elapsed() < duration) {
+            for frame in animation.frame(step, lights, &mut rng) {
+                self.set_frame(&guard.limit(frame, Instant::now()))?;
+            }
+            step += 1;
+
+            if let Some(wait) = (interval * step).checked_sub(start.elapsed()) {
+                thread::sleep(wait);
+            }
+        }
+
+        Ok(step)
+    }
+
+    /// Exports an animation as timers on the bridge, playing a step every interval until the
+    /// timers are removed with `clear_exported`. Each light gets a timer for each step of the
+    /// cycle, repeating once per cycle, with the timers of later steps started an interval
+    /// later. Returns the number of timers created. Animations that don't repeat a fixed cycle,
+    /// or that step more often than once a second, the most the bridge's timers can fire, are an
+    /// error naming why they need this tool running.
+    pub fn export(
+        &self,
+        name: &str,
+        animation: &dyn Animation,
+        lights: &[String],
+        interval: Duration,
+    ) -> Result<usize, Box<dyn Error>> {
+        let live = |reason: &str| -> Box<dyn Error> {
+            From::from(format!(
+                "The '{}' effect can't be exported and needs this tool running: {}.",
+                name, reason
+            ))
+        };
+
+        let steps = animation.cycle(lights).map_err(|reason| live(&reason))?;
+        if interval < Duration::from_secs(1) {
+            return Err(live(&format!(
+                "it steps every {} ms, but the bridge's timers fire at most once a second",
+                interval.as_millis()
+            )));
+        }
+
+        let timers: usize = steps.iter().map(Vec::len).sum();
+        if timers > MAX_EXPORTED_TIMERS {
+            return Err(live(&format!(
+                "it needs {} timers, more than the {} the bridge can spare",
+                timers, MAX_EXPORTED_TIMERS
+            )));
+        }
+
+        // Timers fire on whole seconds, so the interval is rounded to them.
+        let interval = Duration::from_secs((interval.as_millis() as u64 + 500) / 1000);
+        let localtime = timer(interval * steps.len() as u32);
+        let start = Instant::now();
+
+        for (step, frames) in steps.iter().enumerate() {
+            if let Some(wait) = (interval * step as u32).checked_sub(start.elapsed()) {
+                thread::sleep(wait);
+            }
+
+            for frame in frames {
+                self.create_schedule(&Schedule {
+                    name: format!("{} {}", name, step + 1),
+                    description: String::from(EXPORT_DESCRIPTION),
+                    command: ScheduleCommand {
+                        address: self.api_path(&format!("lights/{}/state", frame.index)),
+                        method: String::from("PUT"),
+                        body: self.frame_body(frame).to_value(),
+                    },
+                    localtime: localtime.clone(),
+                    status: String::from("enabled"),
+                    autodelete: None,
+                })?;
+            }
+        }
+
+        Ok(timers)
+    }
+
+    /// Removes the timers of exported animations. Returns the number removed.
+    pub fn clear_exported(&self) -> Result<usize, Box<dyn Error>> {
+        let exported: Vec<String> = self
+            .list_schedules()?
+            .into_iter()
+            .filter(|(_, schedule)| schedule.description == EXPORT_DESCRIPTION)
+            .map(|(id, _)| id)
+            .collect();
+
+        for id in &exported {
+            self.delete_schedule(id)?;
+        }
+
+        Ok(exported.len())
+    }
+
+    /// Helper function to send a frame to its light.
+    fn set_frame(&self, frame: &LightFrame) -> Result<(), Box<dyn Error>> {
+        self.set_light_state(&frame.index, &self.frame_body(frame))
+    }
+
+    /// Helper function to build the light state change of a frame.
+    fn frame_body(&self, frame: &LightFrame) -> LightStateUpdate {
+        let mut update = LightStateUpdate::new()
+            .on(true)
+            .bri(frame.bri)
+            .transition(hue::transition_time(frame.transition));
+        if let (Some((x, y)), true) = (frame.xy, self.light_supports_color(&frame.index)) {
+            update = update.xy(x, y);
+        }
+
+        update
+    }
+}
+
+/// Formats a period for the `localtime` of a timer repeating forever, e.x. "R/PT00:00:02".
+fn timer(period: Duration) -> String {
+    let seconds = period.as_secs();
+    format!(
+        "R/PT{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock;
+
+    #[test]
+    fn minimum_intervals() {
+        assert_eq!(min_interval(0), Duration::from_millis(100));
+        assert_eq!(min_interval(5), Duration::from_millis(500));
+    }
+
+    fn frame(bri: u8, transition: Duration) -> LightFrame {
+        LightFrame {
+            index: String::from("1"),
+            xy: Some((0.3, 0.3)),
+            bri,
+            transition,
+        }
+    }
+
+    #[test]
+    fn strobes_softened() {
+        let mut guard = StrobeGuard::new(Some(DEFAULT_MAX_FLASH_RATE));
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let abrupt = Duration::from_secs(0);
+
+        assert_eq!(guard.limit(frame(254, abrupt), at(0)).transition, abrupt);
+        assert_eq!(guard.limit(frame(20, abrupt), at(100)).transition, abrupt);
+        // Too soon after the last flash, so it fades.
+        assert_eq!(
+            guard.limit(frame(254, abrupt), at(200)).transition,
+            Duration::from_millis(400)
+        );
+        assert_eq!(guard.limit(frame(20, abrupt), at(500)).transition, abrupt);
+        // Small changes and slow fades aren't flashes.
+        assert_eq!(guard.limit(frame(30, abrupt), at(600)).transition, abrupt);
+        let fade = Duration::from_secs(1);
+        assert_eq!(guard.limit(frame(254, fade), at(650)).transition, fade);
+
+        let mut unlimited = StrobeGuard::new(None);
+        for millis in 0..5 {
+            let bri = if millis % 2 == 0 { 254 } else { 20 };
+            assert_eq!(
+                unlimited
+                    .limit(frame(bri, abrupt), at(millis * 50))
+                    .transition,
+                abrupt
+            );
+        }
+    }
+
+    #[test]
+    fn timers() {
+        assert_eq!(timer(Duration::from_secs(2)), "R/PT00:00:02");
+        assert_eq!(timer(Duration::from_secs(3725)), "R/PT01:02:05");
+    }
+
+    /// Blinks the desk light between two brightnesses.
+    struct Blink;
+
+    impl Animation for Blink {
+        fn frame(&mut self, step: u32, lights: &[String], _rng: &mut Rng) -> Vec<LightFrame> {
+            self.cycle(lights).unwrap()[step as usize % 2].clone()
+        }
+
+        fn cycle(&self, _lights: &[String]) -> Result<Vec<Vec<LightFrame>>, String> {
+            let abrupt = Duration::from_secs(0);
+            Ok(vec![vec![frame(254, abrupt)], vec![frame(20, abrupt)]])
+        }
+    }
+
+    #[test]
+    fn exports_timers() {
+        let bridge = mock::bridge();
+        let hue = bridge.hue();
+        let lights = [String::from("1")];
+
+        assert_eq!(
+            hue.export("blink", &Blink, &lights, Duration::from_secs(1))
+                .unwrap(),
+            2
+        );
+
+        let created = bridge.changes();
+        assert_eq!(created.len(), 2);
+        for (step, request) in created.iter().enumerate() {
+            assert_eq!((&*request.method, &*request.path), ("POST", "schedules"));
+            let schedule = mock::body(request);
+            assert_eq!(schedule["name"], format!("blink {}", step + 1));
+            assert_eq!(schedule["description"], EXPORT_DESCRIPTION);
+            assert_eq!(schedule["localtime"], "R/PT00:00:02");
+            assert_eq!(
+                schedule["command"]["address"],
+                format!("/api/{}/lights/1/state", mock::TOKEN)
+            );
+        }
+        assert_eq!(mock::body(&created[0])["command"]["body"]["bri"], 254);
+        assert_eq!(mock::body(&created[1])["command"]["body"]["bri"], 20);
+    }
+
+    #[test]
+    fn export_refused() {
+        let bridge = mock::bridge();
+        let hue = bridge.hue();
+        let lights = [String::from("1")];
+
+        struct Random;
+        impl Animation for Random {
+            fn frame(&mut self, _: u32, _: &[String], _: &mut Rng) -> Vec<LightFrame> {
+                Vec::new()
+            }
+        }
+
+        assert!(hue
+            .export("random", &Random, &lights, Duration::from_secs(1))
+            .is_err());
+        assert!(hue
+            .export("blink", &Blink, &lights, Duration::from_millis(400))
+            .is_err());
+        assert!(bridge.changes().is_empty());
+
+        bridge.answer(
+            "POST",
+            "schedules",
+            vec![json!([{"error": {"type": 301, "address": "/schedules",
+                "description": "resource, schedules, not available"}}])],
+        );
+        assert!(hue
+            .export("blink", &Blink, &lights, Duration::from_secs(1))
+            .is_err());
+        assert_eq!(bridge.changes().len(), 1);
+    }
+
+    #[test]
+    fn clears_exported() {
+        let schedule = |description: &str| {
+            json!({
+                "name": "timer",
+                "description": description,
+                "command": {"address": "/api/token/lights/1/state", "method": "PUT",
+                            "body": {"on": true}},
+                "localtime": "R/PT00:00:02",
+                "status": "enabled"
+            })
+        };
+        let bridge = mock::MockBridge::start(json!({
+            "lights": mock::lights(),
+            "schedules": {"1": schedule(EXPORT_DESCRIPTION), "2": schedule("wake up")}
+        }));
+
+        assert_eq!(bridge.hue().clear_exported().unwrap(), 1);
+
+        let deleted = bridge.changes();
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(
+            (&*deleted[0].method, &*deleted[0].path),
+            ("DELETE", "schedules/1")
+        );
+    }
+}
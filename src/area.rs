@@ -0,0 +1,187 @@
+//! # area
+//!
+//! Entertainment areas, the groups the bridge streams colors to, e.x. for ambilight. An area can be
+//! set up in one go from the lights of a room that can stream, placed at default positions, so
+//! simple setups don't need to be configured by hand in the Hue app first.
+
+use serde_json::{Map, Value};
+use std::error::Error;
+use std::f64::consts::PI;
+
+use hue::Hue;
+
+/// Most lights an entertainment area can hold.
+pub const MAX_AREA_LIGHTS: usize = 10;
+
+/// Position of a light in an entertainment area, each coordinate from -1 to 1: x from left to
+/// right, y from behind to in front of the viewer, and z from the floor to the ceiling.
+pub type Position = [f64; 3];
+
+/// A newly created entertainment area.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutoArea {
+    pub id: String,
+    pub name: String,
+    /// Index and position of each light.
+    pub lights: Vec<(String, Position)>,
+}
+
+/// Default positions for a number of lights: spread evenly from left to right on an arc in front
+/// of the viewer, at the viewer's height.
+pub fn default_positions(count: usize) -> Vec<Position> {
+    (0..count)
+        .map(|i| {
+            let angle = PI * (1.0 - (i as f64 + 0.5) / count as f64);
+            [round(angle.cos()), round(angle.sin()), 0.0]
+        })
+        .collect()
+}
+
+/// Helper function to round a coordinate to hundredths, the bridge's precision. Adding 0 turns
+/// -0 into 0.
+fn round(coordinate: f64) -> f64 {
+    (coordinate * 100.0).round() / 100.0 + 0.0
+}
+
+impl Hue {
+    /// Creates an entertainment area from the lights of a room that can stream, found by name or
+    /// id, with the lights at default positions. The area is named after the room unless a name
+    /// is given, e.x. "Living area". An area whose lights can't be placed is removed again.
+    pub fn auto_area(&self, room: &str, name: Option<&str>) -> Result<AutoArea, Box<dyn Error>> {
+        let (_, group) = self.find_group(room)?;
+        if group.group_type == "Entertainment" {
+            return Err(From::from(format!(
+                "'{}' is already an entertainment area.",
+                group.name
+            )));
+        }
+
+        let mut lights: Vec<String> = group
+            .lights
+            .iter()
+            .filter(|index| self.light_supports_streaming(index))
+            .cloned()
+            .collect();
+        lights.sort_by_key(|index| index.parse::<u32>().unwrap_or(u32::MAX));
+
+        if lights.is_empty() {
+            return Err(From::from(format!(
+                "'{}' has no lights that can stream for an entertainment area.",
+                group.name
+            )));
+        }
+        if lights.len() > MAX_AREA_LIGHTS {
+            return Err(From::from(format!(
+                "'{}' has {} lights that can stream, but an entertainment area holds at most {}.",
+                group.name,
+                lights.len(),
+                MAX_AREA_LIGHTS
+            )));
+        }
+
+        let name = name.map_or_else(|| format!("{} area", group.name), String::from);
+        if self.get_groups()?.values().any(|g| g.name == name) {
+            return Err(From::from(format!(
+                "A group named '{}' already exists.",
+                name
+            )));
+        }
+
+        let id = self.post(
+            &self.api_url("groups"),
+            json!({
+                "name": name,
+                "type": "Entertainment",
+                "class": "Other",
+                "lights": lights,
+            })
+            .to_string(),
+        )?;
+
+        let positions = default_positions(lights.len());
+        let lights: Vec<(String, Position)> = lights.into_iter().zip(positions).collect();
+        let locations: Map<String, Value> = lights
+            .iter()
+            .map(|(index, position)| (index.clone(), json!(position)))
+            .collect();
+        let placed = self.put(
+            &self.api_url(&format!("groups/{}", id)),
+            json!({ "locations": locations }).to_string(),
+        );
+        if let Err(e) = placed {
+            // Removing the area is only cleaning up, so the error placing it is the one reported.
+            let _ = self.delete(&self.api_url(&format!("groups/{}", id)));
+            return Err(e);
+        }
+
+        Ok(AutoArea { id, name, lights })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock;
+    use mock::MockBridge;
+
+    /// Helper function to start a bridge with a room holding both test lights.
+    fn bridge() -> MockBridge {
+        MockBridge::start(json!({
+            "lights": mock::lights(),
+            "groups": {
+                "1": {"name": "Living", "lights": ["1", "2"], "type": "Room", "class": "Living room"}
+            }
+        }))
+    }
+
+    #[test]
+    fn area_from_streaming_lights() {
+        let bridge = bridge();
+
+        let area = bridge.hue().auto_area("Living", None).unwrap();
+        assert_eq!(area.name, "Living area");
+        assert_eq!(area.lights, vec![(String::from("1"), [0.0, 1.0, 0.0])]);
+
+        let changes = bridge.changes();
+        assert_eq!(changes.len(), 2);
+        assert_eq!((&*changes[0].method, &*changes[0].path), ("POST", "groups"));
+        assert_eq!(mock::body(&changes[0])["lights"], json!(["1"]));
+        assert_eq!(
+            (&*changes[1].method, &*changes[1].path),
+            ("PUT", "groups/1")
+        );
+    }
+
+    #[test]
+    fn unplaced_area_removed() {
+        let bridge = bridge();
+        bridge.answer(
+            "PUT",
+            "groups/1",
+            vec![
+                json!([{"error": {"type": 7, "address": "/groups/1/locations",
+                "description": "invalid value"}}]),
+            ],
+        );
+
+        assert!(bridge.hue().auto_area("Living", None).is_err());
+
+        let changes = bridge.changes();
+        assert_eq!(changes.len(), 3);
+        assert_eq!(
+            (&*changes[2].method, &*changes[2].path),
+            ("DELETE", "groups/1")
+        );
+    }
+
+    #[test]
+    fn positions_on_an_arc() {
+        assert_eq!(default_positions(1), vec![[0.0, 1.0, 0.0]]);
+        assert_eq!(
+            default_positions(2),
+            vec![[-0.71, 0.71, 0.0], [0.71, 0.71, 0.0]]
+        );
+        assert_eq!(default_positions(3)[1], [0.0, 1.0, 0.0]);
+        assert!(default_positions(0).is_empty());
+    }
+}
@@ -0,0 +1,158 @@
+//! # batteries
+//!
+//! Battery levels of the battery powered sensors and switches paired with the bridge, e.x. motion
+//! sensors and dimmer switches, and alerts when one runs low so it can be replaced before it
+//! stops working.
+
+use reqwest;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::error::Error;
+
+use hue::Hue;
+
+/// Battery level of a sensor or switch.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Battery {
+    pub id: String,
+    pub name: String,
+    pub kind: String,
+    /// Percentage left, between 0 and 100.
+    pub level: u8,
+}
+
+/// Picks the battery powered devices out of the bridge's sensors, lowest level first. Sensors
+/// without a battery, e.x. the daylight sensor built into the bridge, are skipped.
+pub fn batteries(sensors: &Value) -> Vec<Battery> {
+    let mut batteries: Vec<Battery> = match sensors.as_object() {
+        Some(sensors) => sensors
+            .iter()
+            .filter_map(|(id, sensor)| {
+                Some(Battery {
+                    id: id.clone(),
+                    name: sensor["name"].as_str().unwrap_or(id).to_string(),
+                    kind: sensor["type"].as_str().unwrap_or("").to_string(),
+                    level: sensor["config"]["battery"].as_u64()?.min(100) as u8,
+                })
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+
+    batteries.sort_by(|a, b| a.level.cmp(&b.level).then_with(|| a.name.cmp(&b.name)));
+    batteries
+}
+
+/// Tracks which batteries are low, so each is alerted on once when it drops below the threshold
+/// rather than on every check. A battery is alerted on again only after it was replaced.
+#[derive(Debug)]
+pub struct BatteryAlerts {
+    threshold: u8,
+    low: BTreeSet<String>,
+}
+
+impl BatteryAlerts {
+    pub fn new(threshold: u8) -> BatteryAlerts {
+        BatteryAlerts {
+            threshold,
+            low: BTreeSet::new(),
+        }
+    }
+
+    /// Checks the current battery levels, returning those that dropped below the threshold since
+    /// the last check.
+    pub fn check<'a>(&mut self, batteries: &'a [Battery]) -> Vec<&'a Battery> {
+        let mut alerts = Vec::new();
+
+        for battery in batteries {
+            if battery.level < self.threshold {
+                if self.low.insert(battery.id.clone()) {
+                    alerts.push(battery);
+                }
+            } else {
+                self.low.remove(&battery.id);
+            }
+        }
+
+        alerts
+    }
+}
+
+/// Posts an alert for a low battery to a webhook as JSON, with `text` describing it for chat
+/// services, e.x. {"text": "...", "sensor": "4", "name": "Hallway switch", "battery": 12}.
+pub fn send_webhook(url: &str, battery: &Battery, text: &str) -> Result<(), Box<dyn Error>> {
+    let body = json!({
+        "text": text,
+        "sensor": battery.id,
+        "name": battery.name,
+        "battery": battery.level,
+    });
+
+    let response = reqwest::Client::new()
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body.to_string())
+        .send()?;
+
+    if !response.status().is_success() {
+        return Err(From::from(format!(
+            "The webhook answered with status {}.",
+            response.status()
+        )));
+    }
+
+    Ok(())
+}
+
+impl Hue {
+    /// Returns the battery levels of all battery powered sensors and switches, lowest first.
+    pub fn batteries(&self) -> Result<Vec<Battery>, Box<dyn Error>> {
+        Ok(batteries(&self.fetch("sensors", true)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn battery(id: &str, level: u8) -> Battery {
+        Battery {
+            id: id.to_string(),
+            name: id.to_string(),
+            kind: String::from("ZLLSwitch"),
+            level,
+        }
+    }
+
+    #[test]
+    fn sorted_batteries() {
+        let sensors = json!({
+            "1": {"name": "Daylight", "type": "Daylight", "config": {"on": true}},
+            "4": {"name": "Hallway switch", "type": "ZLLSwitch", "config": {"battery": 80}},
+            "7": {"name": "Kitchen motion", "type": "ZLLPresence", "config": {"battery": 12}}
+        });
+
+        let batteries = batteries(&sensors);
+
+        assert_eq!(batteries.len(), 2);
+        assert_eq!(batteries[0].name, "Kitchen motion");
+        assert_eq!(batteries[0].level, 12);
+        assert_eq!(batteries[1].id, "4");
+    }
+
+    #[test]
+    fn alerts_once_per_low_battery() {
+        let mut alerts = BatteryAlerts::new(20);
+
+        assert_eq!(alerts.check(&[battery("4", 25), battery("7", 15)]).len(), 1);
+        assert!(alerts
+            .check(&[battery("4", 25), battery("7", 14)])
+            .is_empty());
+
+        let checked = [battery("4", 19), battery("7", 100)];
+        assert_eq!(alerts.check(&checked), vec![&checked[0]]);
+
+        // Replaced, then low again.
+        assert_eq!(alerts.check(&[battery("7", 10)]).len(), 1);
+    }
+}
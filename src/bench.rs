@@ -0,0 +1,212 @@
+//! # bench
+//!
+//! Measures how fast an entertainment area takes updates, for tuning the frame rate of ambilight
+//! style setups. Test frames alternating between two colors are sent to the area at a target
+//! rate, and the achieved rate, dropped frames, and round trip latency are reported.
+//!
+//! Frames are sent through the REST API rather than the DTLS entertainment stream, so the numbers
+//! are a lower bound on what streaming achieves. They still tell a slow or unsteady connection to
+//! the bridge apart from a bridge that can't keep up.
+
+use std::error::Error;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use hue::Hue;
+use state::LightStateUpdate;
+
+/// The two colors test frames alternate between, far apart so every frame is a visible change.
+const TEST_COLORS: [(f32, f32); 2] = [(0.675, 0.322), (0.167, 0.04)];
+
+/// Latency spikes this many times the median point at an unsteady connection, e.x. Wi-Fi, rather
+/// than a bridge that is slow across the board.
+const SPIKE_FACTOR: u32 = 5;
+
+/// Outcome of a benchmark.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchResult {
+    /// Frame rate aimed for, in frames per second.
+    pub target_rate: u32,
+    /// Round trip time of each frame the bridge accepted.
+    pub latencies: Vec<Duration>,
+    /// Frames skipped because the previous one was still being sent when their turn came.
+    pub dropped: u32,
+    /// Frames the bridge rejected or that failed to send.
+    pub failed: u32,
+    pub elapsed: Duration,
+}
+
+impl BenchResult {
+    /// Frames the bridge accepted per second.
+    pub fn rate(&self) -> f64 {
+        if self.elapsed == Duration::from_secs(0) {
+            return 0.0;
+        }
+
+        self.latencies.len() as f64 / self.elapsed.as_secs_f64()
+    }
+
+    /// The latency below which the given percentage of frames fall, e.x. 95.
+    pub fn percentile(&self, percent: u32) -> Option<Duration> {
+        let mut sorted = self.latencies.clone();
+        sorted.sort();
+
+        let rank = (sorted.len() * percent as usize).div_ceil(100);
+        sorted.get(rank.saturating_sub(1)).cloned()
+    }
+
+    /// Formats the result with a hint at the bottleneck, if one shows.
+    pub fn render(&self) -> String {
+        let total = self.latencies.len() as u32 + self.dropped + self.failed;
+        let millis = |duration: Option<Duration>| {
+            duration.map_or(0.0, |duration| duration.as_secs_f64() * 1000.0)
+        };
+
+        let mut output = format!(
+            "Frames:   {} sent, {} dropped, {} failed\n\
+             Rate:     {:.1} of {} frames per second\n\
+             Latency:  {:.1} ms median, {:.1} ms 95th percentile, {:.1} ms max\n",
+            self.latencies.len(),
+            self.dropped,
+            self.failed,
+            self.rate(),
+            self.target_rate,
+            millis(self.percentile(50)),
+            millis(self.percentile(95)),
+            millis(self.latencies.iter().max().cloned()),
+        );
+
+        let slot = Duration::from_secs(1) / self.target_rate.max(1);
+
+        if let (Some(median), Some(max)) = (self.percentile(50), self.latencies.iter().max()) {
+            if median > slot {
+                output += &format!(
+                    "The bridge takes longer than a frame ({:.1} ms) to answer, try a rate of {} \
+                     or less.\n",
+                    slot.as_secs_f64() * 1000.0,
+                    (1.0 / median.as_secs_f64()).floor().max(1.0)
+                );
+            } else if *max > median * SPIKE_FACTOR && self.dropped > 0 {
+                output += "Latency spikes while most frames are fast, which points at an unsteady \
+                           connection to the bridge (e.x. Wi-Fi) rather than the bridge itself.\n";
+            }
+        }
+
+        if total > 0 && self.failed * 10 > total {
+            output += "Over 10% of frames were rejected, the bridge may be rate limiting.\n";
+        }
+
+        output
+    }
+}
+
+impl Hue {
+    /// Sends test frames to an entertainment area, found by name or id, at the given rate for the
+    /// given duration. The area's lights change color while the benchmark runs.
+    pub fn bench_area(
+        &self,
+        area: &str,
+        rate: u32,
+        duration: Duration,
+    ) -> Result<BenchResult, Box<dyn Error>> {
+        if rate == 0 {
+            return Err(From::from("The frame rate must be at least 1."));
+        }
+
+        let (id, group) = self.find_group(area)?;
+        if group.group_type != "Entertainment" {
+            return Err(From::from(format!(
+                "'{}' is a {}, not an entertainment area.",
+                group.name,
+                group.group_type.to_lowercase()
+            )));
+        }
+
+        // Frames skip the usual retries so the bridge's own limits show in the result.
+        let url = self.api_url(&format!("groups/{}/action", id));
+        let slot = Duration::from_secs(1) / rate;
+
+        let mut result = BenchResult {
+            target_rate: rate,
+            latencies: Vec::new(),
+            dropped: 0,
+            failed: 0,
+            elapsed: Duration::from_secs(0),
+        };
+
+        let start = Instant::now();
+        let mut frame: u32 = 0;
+
+        while start.elapsed() < duration {
+            let (x, y) = TEST_COLORS[frame as usize % TEST_COLORS.len()];
+            let body = LightStateUpdate::new().on(true).xy(x, y).transition(0);
+
+            let sent = Instant::now();
+            let accepted = self
+                .client
+                .put(&url)
+                .body(body.to_string())
+                .send()
+                .ok()
+                .filter(|response| response.status().is_success());
+
+            match accepted {
+                Some(_) => result.latencies.push(sent.elapsed()),
+                None => result.failed += 1,
+            }
+
+            // Frames whose turn passed while this one was being sent are dropped.
+            let next = (start.elapsed().as_nanos() / slot.as_nanos()) as u32 + 1;
+            result.dropped += next - frame - 1;
+            frame = next;
+
+            let due = slot * frame;
+            if let Some(wait) = due.checked_sub(start.elapsed()) {
+                thread::sleep(wait);
+            }
+        }
+
+        result.elapsed = start.elapsed();
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn result(latencies: &[u64], dropped: u32) -> BenchResult {
+        BenchResult {
+            target_rate: 25,
+            latencies: latencies
+                .iter()
+                .map(|&ms| Duration::from_millis(ms))
+                .collect(),
+            dropped,
+            failed: 0,
+            elapsed: Duration::from_secs(1),
+        }
+    }
+
+    #[test]
+    fn latency_percentiles() {
+        let result = result(&[30, 10, 20, 40, 50], 0);
+
+        assert_eq!(result.percentile(50), Some(Duration::from_millis(30)));
+        assert_eq!(result.percentile(95), Some(Duration::from_millis(50)));
+        assert_eq!(result.rate(), 5.0);
+        assert_eq!(self::result(&[], 0).percentile(50), None);
+    }
+
+    #[test]
+    fn bottleneck_hints() {
+        // Every frame takes longer than the 40 ms a frame has at 25 per second.
+        let slow = result(&[100, 110, 120], 20);
+        assert!(slow.render().contains("try a rate of 9 or less"));
+
+        let spiky = result(&[10, 10, 12, 11, 300], 7);
+        assert!(spiky.render().contains("unsteady connection"));
+
+        assert!(!result(&[10, 11, 12], 0).render().contains("points at"));
+    }
+}
@@ -0,0 +1,151 @@
+//! # bridges
+//!
+//! Several bridges, e.x. one per floor, listed in $HOME/.config/rusty_hue/bridges.json by name:
+//! {"upstairs": {"id": "001788FFFE000000", "ip": "192.168.1.2", "token": "..."}}. A bridge is
+//! picked with `--bridge NAME` or the bridge setting, and its address and token are used. A bridge
+//! can also hold settings that only apply while it's picked, e.x. {"office": {..., "cache_ttl":
+//! "0s"}}, and pairing with a picked bridge saves the new token to its entry.
+
+use serde_json;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::PathBuf;
+
+use cache::CacheSettings;
+use files;
+use hue::Hue;
+
+/// A bridge from bridges.json.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Bridge {
+    /// Name the bridge is listed under.
+    #[serde(skip)]
+    pub name: String,
+    #[serde(default)]
+    pub id: Option<String>,
+    pub ip: String,
+    pub token: String,
+    /// Settings that apply only while the bridge is picked, overriding all others.
+    #[serde(flatten)]
+    pub settings: BTreeMap<String, Value>,
+}
+
+impl Bridge {
+    /// Connects to the bridge.
+    pub fn connect(&self, cache: Option<CacheSettings>) -> Result<Hue, Box<dyn Error>> {
+        Hue::connect_cached(Some(&self.ip), Some(&self.token), cache)
+    }
+}
+
+/// Loads the bridges from $HOME/.config/rusty_hue/bridges.json, keyed by name. A missing file
+/// lists no bridges.
+pub fn load_bridges() -> Result<BTreeMap<String, Bridge>, Box<dyn Error>> {
+    let mut f = match File::open(files::config_file("bridges.json")?) {
+        Ok(f) => f,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(From::from(e)),
+    };
+
+    let mut contents = String::new();
+    f.read_to_string(&mut contents)?;
+
+    parse_bridges(&contents)
+}
+
+/// Saves the address and token of a bridge to its entry in bridges.json, adding the entry if it
+/// isn't listed. Everything else in the file is kept. Returns the path of the file.
+pub fn save_bridge(name: &str, ip: &str, token: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let path = files::config_file("bridges.json")?;
+
+    let mut bridges = match File::open(&path) {
+        Ok(mut f) => {
+            let mut contents = String::new();
+            f.read_to_string(&mut contents)?;
+            serde_json::from_str(&contents)?
+        }
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => json!({}),
+        Err(e) => return Err(From::from(e)),
+    };
+
+    if !bridges[name].is_object() {
+        bridges[name] = json!({});
+    }
+    bridges[name]["ip"] = json!(ip);
+    bridges[name]["token"] = json!(token);
+
+    files::write_atomic(&path, serde_json::to_string_pretty(&bridges)?.as_bytes())?;
+    Ok(path)
+}
+
+/// Helper function to parse the contents of bridges.json.
+fn parse_bridges(contents: &str) -> Result<BTreeMap<String, Bridge>, Box<dyn Error>> {
+    let mut bridges: BTreeMap<String, Bridge> = serde_json::from_str(contents)?;
+
+    for (name, bridge) in bridges.iter_mut() {
+        bridge.name = name.clone();
+    }
+
+    Ok(bridges)
+}
+
+/// Finds a bridge given either its name or its id.
+pub fn find_bridge<'a>(
+    bridges: &'a BTreeMap<String, Bridge>,
+    bridge: &str,
+) -> Result<&'a Bridge, Box<dyn Error>> {
+    if let Some(found) = bridges.get(bridge) {
+        return Ok(found);
+    }
+
+    bridges
+        .values()
+        .find(|b| {
+            b.id.as_ref()
+                .is_some_and(|id| id.eq_ignore_ascii_case(bridge))
+        })
+        .ok_or_else(|| {
+            let names: Vec<&str> = bridges.keys().map(String::as_str).collect();
+            From::from(match names.len() {
+                0 => format!("No bridge '{}', bridges.json lists no bridges.", bridge),
+                _ => format!(
+                    "No bridge '{}' in bridges.json. Listed bridges: {}.",
+                    bridge,
+                    names.join(", ")
+                ),
+            })
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn find_bridges() {
+        let bridges = parse_bridges(
+            r#"{
+                "upstairs": {"id": "001788FFFE000001", "ip": "192.168.1.2", "token": "a"},
+                "garage": {"ip": "192.168.1.3", "token": "b", "cache_ttl": "0s"}
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(find_bridge(&bridges, "garage").unwrap().ip, "192.168.1.3");
+        assert_eq!(find_bridge(&bridges, "garage").unwrap().name, "garage");
+        assert_eq!(
+            find_bridge(&bridges, "garage").unwrap().settings["cache_ttl"],
+            "0s"
+        );
+        assert!(bridges["upstairs"].settings.is_empty());
+        assert_eq!(
+            find_bridge(&bridges, "001788fffe000001").unwrap().name,
+            "upstairs"
+        );
+        assert!(find_bridge(&bridges, "attic").is_err());
+        assert!(parse_bridges(r#"{"attic": {"ip": "192.168.1.4"}}"#).is_err());
+    }
+}
@@ -0,0 +1,627 @@
+//! # bus
+//!
+//! A single subscription to changes on the bridge for GUIs and daemons, whatever the bridge's
+//! firmware. Changes are learned of from the bridge's event stream when it serves one, or by
+//! polling its state otherwise, and delivered as typed events over a channel.
+
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use events::{ConnectionState, Event};
+use hue::Hue;
+use sensors;
+use sensors::{ButtonEvent, Reading};
+
+/// A change on the bridge. Resources are identified by their index or id in the bridge's API,
+/// whichever backend is used, while `changes` holds what changed as the backend reports it: the
+/// changed fields from the event stream, or the resource's new state when polling.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HueEvent {
+    LightChanged {
+        index: String,
+        changes: Value,
+    },
+    GroupChanged {
+        id: String,
+        changes: Value,
+    },
+    SensorTriggered {
+        id: String,
+        changes: Value,
+    },
+    /// A button of a switch, given by the switch's sensor id, was used, e.x. the "off" button of
+    /// a dimmer switch released after a short press.
+    ButtonPressed {
+        id: String,
+        event: ButtonEvent,
+        /// Number of the button on its switch as the event stream gives it, `None` when polling.
+        control_id: Option<u64>,
+    },
+    /// The bridge can't be reached. No events are delivered until `BridgeOnline`.
+    BridgeOffline(String),
+    BridgeOnline,
+}
+
+/// How changes on the bridge are learned of.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    /// The bridge's event stream, which pushes changes as they happen.
+    Stream,
+    /// Fetching the bridge's state at an interval and comparing it with the last.
+    Polling(Duration),
+    /// The event stream when the bridge's firmware serves it, or else polling at the interval.
+    Auto(Duration),
+}
+
+/// A subscription to changes on the bridge. Events are received with `recv`, `try_recv`, or by
+/// iterating. The subscription ends once it's dropped and the next change is seen.
+pub struct HueEvents {
+    receiver: Receiver<HueEvent>,
+}
+
+impl HueEvents {
+    /// Subscribes to changes on the bridge, taking over the Hue system for a background thread.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use rusty_hue::bus::{Backend, HueEvent, HueEvents};
+    /// use rusty_hue::hue::Hue;
+    /// use std::time::Duration;
+    ///
+    /// let backend = Backend::Auto(Duration::from_secs(2));
+    /// let events = HueEvents::subscribe(Hue::new().unwrap(), backend);
+    ///
+    /// for event in events {
+    ///     if let HueEvent::LightChanged { index, .. } = event {
+    ///         println!("Light {} changed.", index);
+    ///     }
+    /// }
+    /// ```
+    pub fn subscribe(hue: Hue, backend: Backend) -> HueEvents {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || run(hue, backend, &sender));
+
+        HueEvents { receiver }
+    }
+
+    /// Waits for the next event. Returns `None` if the subscription ended.
+    pub fn recv(&self) -> Option<HueEvent> {
+        self.receiver.recv().ok()
+    }
+
+    /// Returns the next event if there is one, without waiting.
+    pub fn try_recv(&self) -> Option<HueEvent> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+impl Hue {
+    /// Subscribes to changes on the bridge from its event stream, see `HueEvents`. The Hue
+    /// system is taken over by a background thread.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use rusty_hue::bus::HueEvent;
+    /// use rusty_hue::hue::Hue;
+    ///
+    /// for event in Hue::new().unwrap().events() {
+    ///     if let HueEvent::ButtonPressed { id, event, .. } = event {
+    ///         println!("Switch {}: {}", id, event);
+    ///     }
+    /// }
+    /// ```
+    pub fn events(self) -> HueEvents {
+        HueEvents::subscribe(self, Backend::Stream)
+    }
+
+    /// Names of the lights, groups, and sensors, for describing events with `describe`.
+    pub fn resource_names(&self) -> Result<Names, Box<dyn Error>> {
+        let mut names = Names::new();
+
+        for &(resource, _) in POLLED.iter() {
+            if let Some(items) = self.fetch(resource, false)?.as_object() {
+                for (id, item) in items {
+                    if let Some(name) = item["name"].as_str() {
+                        names.insert((resource, id.clone()), name.to_string());
+                    }
+                }
+            }
+        }
+
+        Ok(names)
+    }
+}
+
+/// Names of resources by the resource and id, e.x. ("lights", "1") -> "Desk".
+pub type Names = BTreeMap<(&'static str, String), String>;
+
+/// Describes an event for display, as a message to translate with `i18n::tr` and the values
+/// filled into it, e.x. ("Light {}: {}", ["'Desk'", "on, bri 80%"]). Resources missing from
+/// `names`, e.x. added since, are given by id.
+pub fn describe(event: &HueEvent, names: &Names) -> (&'static str, Vec<String>) {
+    let name = |resource, id: &String| match names.get(&(resource, id.clone())) {
+        Some(name) => format!("'{}'", name),
+        None => id.clone(),
+    };
+
+    match *event {
+        HueEvent::LightChanged {
+            ref index,
+            ref changes,
+        } => (
+            "Light {}: {}",
+            vec![name("lights", index), describe_changes(changes)],
+        ),
+        HueEvent::GroupChanged {
+            ref id,
+            ref changes,
+        } => (
+            "Group {}: {}",
+            vec![name("groups", id), describe_changes(changes)],
+        ),
+        HueEvent::SensorTriggered {
+            ref id,
+            ref changes,
+        } => (
+            "Sensor {}: {}",
+            vec![name("sensors", id), describe_changes(changes)],
+        ),
+        HueEvent::ButtonPressed {
+            ref id, ref event, ..
+        } => (
+            "Switch {}: {}",
+            vec![name("sensors", id), event.to_string()],
+        ),
+        HueEvent::BridgeOffline(ref error) => ("Bridge offline: {}", vec![error.clone()]),
+        HueEvent::BridgeOnline => ("Bridge online.", Vec::new()),
+    }
+}
+
+impl Iterator for HueEvents {
+    type Item = HueEvent;
+
+    fn next(&mut self) -> Option<HueEvent> {
+        self.recv()
+    }
+}
+
+/// Helper function to run a backend until the subscription is dropped.
+fn run(mut hue: Hue, backend: Backend, sender: &Sender<HueEvent>) {
+    // Interval to poll at should the stream turn out to be missing.
+    let fallback = match backend {
+        Backend::Polling(interval) => return poll(&hue, interval, sender),
+        Backend::Auto(interval) if !supports_event_stream(&hue) => {
+            return poll(&hue, interval, sender)
+        }
+        Backend::Auto(interval) => Some(interval),
+        Backend::Stream => None,
+    };
+
+    let mut online = true;
+    let result = hue.listen(
+        |state| match *state {
+            ConnectionState::Disconnected { ref error, .. } if online => {
+                online = false;
+                let _ = sender.send(HueEvent::BridgeOffline(error.clone()));
+            }
+            ConnectionState::Connected if !online => {
+                online = true;
+                let _ = sender.send(HueEvent::BridgeOnline);
+            }
+            _ => (),
+        },
+        |_, event| {
+            translate(event)
+                .into_iter()
+                .all(|event| sender.send(event).is_ok())
+        },
+    );
+
+    match (result, fallback) {
+        (Err(_), Some(interval)) => poll(&hue, interval, sender),
+        (Err(e), None) => {
+            let _ = sender.send(HueEvent::BridgeOffline(e.to_string()));
+        }
+        (Ok(()), _) => (),
+    }
+}
+
+/// Whether the bridge's firmware serves the event stream, which came with the v2 API.
+fn supports_event_stream(hue: &Hue) -> bool {
+    hue.supports_v2()
+}
+
+/// Helper function to build the event for a change to a resource of the bridge's API, e.x.
+/// ("lights", "3").
+fn event_for(resource: &str, id: &str, changes: Value) -> Option<HueEvent> {
+    let id = id.to_string();
+
+    match resource {
+        "lights" => Some(HueEvent::LightChanged { index: id, changes }),
+        "groups" => Some(HueEvent::GroupChanged { id, changes }),
+        "sensors" => Some(HueEvent::SensorTriggered { id, changes }),
+        _ => None,
+    }
+}
+
+/// Turns an event from the event stream into the changes it holds. Each changed resource names
+/// its counterpart in the bridge's API, e.x. "/lights/3", and resources without one are skipped.
+fn translate(event: &Event) -> Vec<HueEvent> {
+    let containers = match event.data.as_array() {
+        Some(containers) => containers,
+        None => return Vec::new(),
+    };
+
+    containers
+        .iter()
+        .filter_map(|container| container["data"].as_array())
+        .flatten()
+        .filter_map(|resource| {
+            let path = resource["id_v1"].as_str()?;
+            let mut parts = path.trim_start_matches('/').splitn(2, '/');
+            let (kind, id) = (parts.next()?, parts.next()?);
+
+            match stream_button_event(resource) {
+                Some((event, control_id)) => Some(HueEvent::ButtonPressed {
+                    id: id.to_string(),
+                    event,
+                    control_id: Some(control_id),
+                }),
+                None => event_for(kind, id, resource.clone()),
+            }
+        })
+        .collect()
+}
+
+/// Helper function to decode the press of a button from the event stream with its control id. The
+/// stream names what happened, e.x. "short_release", which is turned into the code of the
+/// bridge's API, e.x. 4002 for the fourth button, to be decoded as when polling.
+fn stream_button_event(resource: &Value) -> Option<(ButtonEvent, u64)> {
+    if resource["type"] != "button" {
+        return None;
+    }
+
+    // Newer firmware reports presses under "button_report", older under "last_event".
+    let button = &resource["button"];
+    let pressed = button["button_report"]["event"]
+        .as_str()
+        .or_else(|| button["last_event"].as_str())?;
+    let action = match pressed {
+        "initial_press" => 0,
+        "repeat" | "long_press" => 1,
+        "short_release" => 2,
+        "long_release" => 3,
+        _ => return None,
+    };
+    let control_id = resource["metadata"]["control_id"].as_u64()?;
+
+    ButtonEvent::from_code(control_id * 1000 + action).map(|event| (event, control_id))
+}
+
+/// Resources polled and the fields compared for each, or the whole resource when there are none.
+/// Only a sensor's type and state are compared, so battery and configuration changes don't count
+/// as triggers.
+const POLLED: [(&str, &[&str]); 3] = [
+    ("lights", &[]),
+    ("groups", &[]),
+    ("sensors", &["type", "state"]),
+];
+
+type Snapshot = BTreeMap<(&'static str, String), Value>;
+
+/// Helper function to fetch the polled state of the bridge.
+fn snapshot(hue: &Hue) -> Result<Snapshot, Box<dyn Error>> {
+    let mut snapshot = BTreeMap::new();
+
+    for &(resource, fields) in POLLED.iter() {
+        if let Some(items) = hue.fetch(resource, false)?.as_object() {
+            for (id, item) in items {
+                let value = if fields.is_empty() {
+                    item.clone()
+                } else {
+                    fields
+                        .iter()
+                        .map(|&field| (field.to_string(), item[field].clone()))
+                        .collect()
+                };
+                snapshot.insert((resource, id.clone()), value);
+            }
+        }
+    }
+
+    Ok(snapshot)
+}
+
+/// Compares two snapshots, returning an event for each resource that was added or changed.
+fn diff(previous: &Snapshot, current: &Snapshot) -> Vec<HueEvent> {
+    current
+        .iter()
+        .filter(|&(key, value)| previous.get(key) != Some(value))
+        .filter_map(|(&(resource, ref id), value)| {
+            let pressed = value["state"]["buttonevent"].as_u64().and_then(|code| {
+                ButtonEvent::from_sensor(value["type"].as_str().unwrap_or_default(), code)
+            });
+            match pressed {
+                Some(event) if resource == "sensors" => Some(HueEvent::ButtonPressed {
+                    id: id.clone(),
+                    event,
+                    control_id: None,
+                }),
+                _ => event_for(resource, id, value.clone()),
+            }
+        })
+        .collect()
+}
+
+/// Describes what changed for display, e.x. "on, bri 80%" or "motion", from the changed fields of
+/// the event stream or the state found by polling. Fields that aren't described are left out.
+pub fn describe_changes(changes: &Value) -> String {
+    // Polled lights hold their whole resource, with the state nested.
+    let state = if changes["state"].is_object() {
+        &changes["state"]
+    } else {
+        changes
+    };
+
+    let mut parts = Vec::new();
+
+    match (state["on"]["on"].as_bool(), state["on"].as_bool()) {
+        (Some(on), _) | (None, Some(on)) => parts.push(String::from(if on { "on" } else { "off" })),
+        _ => (),
+    }
+    if let Some(brightness) = state["dimming"]["brightness"].as_f64() {
+        parts.push(format!("bri {:.0}%", brightness));
+    } else if let Some(bri) = state["bri"].as_u64() {
+        parts.push(format!("bri {}", bri));
+    }
+    if let Some(mirek) = state["color_temperature"]["mirek"]
+        .as_u64()
+        .or_else(|| state["ct"].as_u64().filter(|_| state["colormode"] == "ct"))
+    {
+        parts.push(format!("ct {}", mirek));
+    }
+    if let (Some(x), Some(y)) = (
+        state["color"]["xy"]["x"].as_f64(),
+        state["color"]["xy"]["y"].as_f64(),
+    ) {
+        parts.push(format!("xy [{:.4}, {:.4}]", x, y));
+    }
+    // Sensor readings are described as `sensors::Reading` displays them.
+    if let Some(presence) = state["motion"]["motion"]
+        .as_bool()
+        .or_else(|| state["presence"].as_bool())
+    {
+        parts.push(Reading::Motion { presence }.to_string());
+    }
+    if let Some(celsius) = state["temperature"]["temperature"].as_f64().or_else(|| {
+        state["temperature"]
+            .as_f64()
+            .map(|hundredths| hundredths / 100.0)
+    }) {
+        parts.push(Reading::Temperature { celsius }.to_string());
+    }
+    if let Some(level) = state["light"]["light_level"]
+        .as_f64()
+        .or_else(|| state["lightlevel"].as_f64())
+    {
+        parts.push(
+            Reading::LightLevel {
+                lux: sensors::lux(level),
+            }
+            .to_string(),
+        );
+    }
+    if let Some(battery) = state["power_state"]["battery_level"].as_u64() {
+        parts.push(format!("battery {}%", battery));
+    }
+
+    if parts.is_empty() {
+        String::from("changed")
+    } else {
+        parts.join(", ")
+    }
+}
+
+/// Helper function to poll the bridge until the subscription is dropped.
+fn poll(hue: &Hue, interval: Duration, sender: &Sender<HueEvent>) {
+    let mut previous: Option<Snapshot> = None;
+    let mut online = true;
+
+    loop {
+        let events = match snapshot(hue) {
+            Ok(current) => {
+                let mut events = Vec::new();
+                if !online {
+                    online = true;
+                    events.push(HueEvent::BridgeOnline);
+                }
+                if let Some(ref previous) = previous {
+                    events.extend(diff(previous, &current));
+                }
+                previous = Some(current);
+                events
+            }
+            Err(ref e) if online => {
+                online = false;
+                vec![HueEvent::BridgeOffline(e.to_string())]
+            }
+            Err(_) => Vec::new(),
+        };
+
+        if events.into_iter().any(|event| sender.send(event).is_err()) {
+            return;
+        }
+
+        thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn translate_stream_events() {
+        let event = Event {
+            id: None,
+            data: json!([{"type": "update", "data": [
+                {"id_v1": "/lights/3", "on": {"on": false}, "type": "light"},
+                {"id_v1": "/sensors/12", "motion": {"motion": true}, "type": "motion"},
+                {"id": "abc", "type": "zigbee_connectivity"}
+            ]}]),
+        };
+
+        let events = translate(&event);
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[0],
+            HueEvent::LightChanged {
+                index: String::from("3"),
+                changes: json!({"id_v1": "/lights/3", "on": {"on": false}, "type": "light"}),
+            }
+        );
+        match events[1] {
+            HueEvent::SensorTriggered { ref id, .. } => assert_eq!(id, "12"),
+            ref other => panic!("expected a sensor event, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn button_events() {
+        let event = Event {
+            id: None,
+            data: json!([{"type": "update", "data": [
+                {"id_v1": "/sensors/5", "type": "button", "metadata": {"control_id": 4},
+                 "button": {"button_report": {"event": "short_release"}}}
+            ]}]),
+        };
+        let off = ButtonEvent::from_code(4002).unwrap();
+        assert_eq!(
+            translate(&event),
+            vec![HueEvent::ButtonPressed {
+                id: String::from("5"),
+                event: off,
+                control_id: Some(4),
+            }]
+        );
+
+        let sensor = |code| json!({"type": "ZLLSwitch", "state": {"buttonevent": code}});
+        let mut previous = Snapshot::new();
+        previous.insert(("sensors", String::from("5")), sensor(1002));
+        let mut current = Snapshot::new();
+        current.insert(("sensors", String::from("5")), sensor(4003));
+        assert_eq!(
+            diff(&previous, &current),
+            vec![HueEvent::ButtonPressed {
+                id: String::from("5"),
+                event: ButtonEvent::from_code(4003).unwrap(),
+                control_id: None,
+            }]
+        );
+
+        let mut tapped = Snapshot::new();
+        tapped.insert(
+            ("sensors", String::from("6")),
+            json!({"type": "ZGPSwitch", "state": {"buttonevent": 16}}),
+        );
+        assert_eq!(
+            diff(&previous, &tapped),
+            vec![HueEvent::ButtonPressed {
+                id: String::from("6"),
+                event: ButtonEvent::from_tap_code(16).unwrap(),
+                control_id: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn describe_events() {
+        assert_eq!(
+            describe_changes(&json!({"on": {"on": true}, "dimming": {"brightness": 80.3}})),
+            "on, bri 80%"
+        );
+        assert_eq!(
+            describe_changes(
+                &json!({"state": {"on": false, "bri": 120, "ct": 366, "colormode": "xy"}})
+            ),
+            "off, bri 120"
+        );
+        assert_eq!(
+            describe_changes(&json!({"presence": true, "lastupdated": "2026-10-16T12:00:00"})),
+            "motion"
+        );
+        assert_eq!(describe_changes(&json!({"temperature": 2150})), "21.5°C");
+        assert_eq!(
+            describe_changes(&json!({"light": {"light_level": 20001}})),
+            "100 lux"
+        );
+        assert_eq!(
+            describe_changes(&json!({"type": "zigbee_connectivity"})),
+            "changed"
+        );
+
+        let mut names = Names::new();
+        names.insert(("sensors", String::from("5")), String::from("Hall switch"));
+        let pressed = |id: &str, code| HueEvent::ButtonPressed {
+            id: id.to_string(),
+            event: ButtonEvent::from_code(code).unwrap(),
+            control_id: None,
+        };
+        assert_eq!(
+            describe(&pressed("5", 1002), &names),
+            (
+                "Switch {}: {}",
+                vec![String::from("'Hall switch'"), String::from("on released")]
+            )
+        );
+        assert_eq!(
+            describe(&pressed("6", 4002), &names),
+            (
+                "Switch {}: {}",
+                vec![String::from("6"), String::from("off released")]
+            )
+        );
+    }
+
+    #[test]
+    fn diff_snapshots() {
+        let mut previous = Snapshot::new();
+        previous.insert(
+            ("lights", String::from("1")),
+            json!({"state": {"on": true}}),
+        );
+        previous.insert(("groups", String::from("1")), json!({"name": "Living"}));
+
+        let mut current = previous.clone();
+        current.insert(
+            ("lights", String::from("1")),
+            json!({"state": {"on": false}}),
+        );
+        current.insert(
+            ("sensors", String::from("4")),
+            json!({"type": "ZLLPresence", "state": {"presence": true}}),
+        );
+
+        assert_eq!(
+            diff(&previous, &current),
+            vec![
+                HueEvent::LightChanged {
+                    index: String::from("1"),
+                    changes: json!({"state": {"on": false}}),
+                },
+                HueEvent::SensorTriggered {
+                    id: String::from("4"),
+                    changes: json!({"type": "ZLLPresence", "state": {"presence": true}}),
+                },
+            ]
+        );
+    }
+}
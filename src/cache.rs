@@ -0,0 +1,132 @@
+//! # cache
+//!
+//! A state cache shared between invocations. The last fetched lights, groups, and scenes are kept
+//! in a file so short-lived invocations can resolve names and print information without waiting
+//! on the bridge, until the cached copy is older than its time to live.
+
+use serde_json;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use files;
+
+/// How a Hue system uses the state cache.
+#[derive(Debug, Clone)]
+pub struct CacheSettings {
+    pub path: PathBuf,
+    pub ttl: Duration,
+    /// Ignore cached state and always fetch from the bridge, still updating the cache.
+    pub fresh: bool,
+}
+
+/// A cached resource body and when it was fetched, in seconds since the unix epoch.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CachedResource {
+    pub fetched_at: u64,
+    pub body: Value,
+}
+
+/// Contents of the cache file. Only state from the bridge at `bridge` is cached; the token is
+/// never written to the cache.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct StateCache {
+    pub bridge: String,
+    pub resources: BTreeMap<String, CachedResource>,
+}
+
+impl StateCache {
+    /// Loads the cache file. The cache is best effort, so a missing or unreadable file is
+    /// treated as an empty cache.
+    pub fn load(path: &Path) -> Option<StateCache> {
+        let contents = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    /// Writes the cache file.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        files::write_atomic(path, serde_json::to_string(self)?.as_bytes())?;
+        Ok(())
+    }
+
+    /// Returns a cached resource body for the given bridge if it is younger than the time to
+    /// live.
+    pub fn get(&self, bridge: &str, resource: &str, ttl: Duration, now: u64) -> Option<&Value> {
+        if self.bridge != bridge {
+            return None;
+        }
+
+        self.resources
+            .get(resource)
+            .filter(|cached| now.saturating_sub(cached.fetched_at) < ttl.as_secs())
+            .map(|cached| &cached.body)
+    }
+
+    /// Stores a freshly fetched resource body, dropping everything cached for another bridge.
+    pub fn insert(&mut self, bridge: &str, resource: &str, body: Value, now: u64) {
+        if self.bridge != bridge {
+            self.bridge = bridge.to_string();
+            self.resources.clear();
+        }
+
+        self.resources.insert(
+            resource.to_string(),
+            CachedResource {
+                fetched_at: now,
+                body,
+            },
+        );
+    }
+}
+
+/// Removes the cache file, e.x. after changing state on the bridge.
+pub fn invalidate(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Current time in seconds since the unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn expire_after_ttl() {
+        let mut cache = StateCache::default();
+        cache.insert("10.0.0.2", "lights", Value::from(1), 100);
+
+        let ttl = Duration::from_secs(60);
+        assert_eq!(
+            cache.get("10.0.0.2", "lights", ttl, 159),
+            Some(&Value::from(1))
+        );
+        assert_eq!(cache.get("10.0.0.2", "lights", ttl, 160), None);
+        assert_eq!(cache.get("10.0.0.2", "groups", ttl, 100), None);
+    }
+
+    #[test]
+    fn other_bridge() {
+        let mut cache = StateCache::default();
+        cache.insert("10.0.0.2", "lights", Value::from(1), 100);
+
+        let ttl = Duration::from_secs(60);
+        assert_eq!(cache.get("10.0.0.3", "lights", ttl, 100), None);
+
+        cache.insert("10.0.0.3", "groups", Value::from(2), 100);
+        assert_eq!(cache.get("10.0.0.2", "lights", ttl, 100), None);
+        assert_eq!(cache.resources.len(), 1);
+    }
+}
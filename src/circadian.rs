@@ -0,0 +1,140 @@
+//! # circadian
+//!
+//! A daily curve of white color temperature and brightness: warm and dim through the night, cool
+//! and bright around midday. Used to adapt colors to the time of day so the same command looks
+//! natural in the morning and at night.
+
+use time;
+
+use colors::RGB;
+
+/// Color temperature and relative brightness on the curve at a time of day.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub mired: u16,
+    /// Fraction of full brightness, between 0 and 1.
+    pub brightness: f32,
+}
+
+impl Point {
+    /// Brightness as a bridge value (1-254).
+    pub fn bri(&self) -> u8 {
+        (self.brightness * 254.0).round().max(1.0) as u8
+    }
+}
+
+/// Points of the curve as seconds since midnight, mireds, and brightness. Points in between are
+/// interpolated, wrapping around midnight.
+const CURVE: [(u32, u16, f32); 7] = [
+    (0, 500, 0.35),
+    (6 * 3600, 450, 0.5),
+    (8 * 3600, 250, 0.9),
+    (12 * 3600, 153, 1.0),
+    (17 * 3600, 250, 1.0),
+    (20 * 3600, 370, 0.8),
+    (22 * 3600, 450, 0.5),
+];
+
+const DAY: u32 = 24 * 3600;
+
+/// Point on the curve at a time of day given in seconds since midnight.
+pub fn point_at(seconds: u32) -> Point {
+    let seconds = seconds % DAY;
+    let next = CURVE
+        .iter()
+        .position(|&(start, _, _)| start > seconds)
+        .unwrap_or(0);
+    let previous = (next + CURVE.len() - 1) % CURVE.len();
+
+    let (start, mired_from, bri_from) = CURVE[previous];
+    let (end, mired_to, bri_to) = CURVE[next];
+    let end = if end <= start { end + DAY } else { end };
+
+    let t = (seconds - start) as f32 / (end - start) as f32;
+
+    Point {
+        mired: (f32::from(mired_from) + (f32::from(mired_to) - f32::from(mired_from)) * t).round()
+            as u16,
+        brightness: bri_from + (bri_to - bri_from) * t,
+    }
+}
+
+/// Point on the curve for the current local time.
+pub fn now() -> Point {
+    let tm = time::now();
+    point_at(tm.tm_hour as u32 * 3600 + tm.tm_min as u32 * 60 + tm.tm_sec as u32)
+}
+
+/// Shifts a color toward the curve's color temperature and scales it to the curve's brightness,
+/// the way a white balance filter would.
+pub fn adapt(rgb: &RGB, point: &Point) -> RGB {
+    let (r, g, b) = white_point(point.mired);
+    let scale =
+        |channel: u8, white: f32| (f32::from(channel) * white * point.brightness).round() as u8;
+
+    RGB {
+        r: scale(rgb.r, r),
+        g: scale(rgb.g, g),
+        b: scale(rgb.b, b),
+    }
+}
+
+/// Approximate color of a black body at a color temperature given in mireds, with each channel
+/// between 0 and 1. Based on Tanner Helland's fit of the black body curve.
+fn white_point(mired: u16) -> (f32, f32, f32) {
+    let kelvin = 1_000_000.0 / f32::from(mired.max(1)) / 100.0;
+
+    let r = if kelvin <= 66.0 {
+        255.0
+    } else {
+        329.69873 * (kelvin - 60.0).powf(-0.133_204_76)
+    };
+
+    let g = if kelvin <= 66.0 {
+        99.470_8 * kelvin.ln() - 161.119_57
+    } else {
+        288.122_16 * (kelvin - 60.0).powf(-0.075_514_846)
+    };
+
+    let b = if kelvin >= 66.0 {
+        255.0
+    } else if kelvin <= 19.0 {
+        0.0
+    } else {
+        138.517_73 * (kelvin - 10.0).ln() - 305.044_8
+    };
+
+    let clamp = |channel: f32| channel.clamp(0.0, 255.0) / 255.0;
+
+    (clamp(r), clamp(g), clamp(b))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn curve_points() {
+        assert_eq!(point_at(12 * 3600).mired, 153);
+        assert_eq!(point_at(12 * 3600).bri(), 254);
+        assert_eq!(point_at(7 * 3600).mired, 350);
+        assert_eq!(point_at(23 * 3600).mired, 475);
+        assert_eq!(point_at(DAY).mired, 500);
+    }
+
+    #[test]
+    fn adapt_color() {
+        let white = RGB {
+            r: 255,
+            g: 255,
+            b: 255,
+        };
+
+        let midday = adapt(&white, &point_at(12 * 3600));
+        assert!(midday.r > 250 && midday.g > 240 && midday.b > 240);
+
+        let night = adapt(&white, &point_at(0));
+        assert!(night.r > night.g && night.g > night.b);
+        assert!(night.r < 100);
+    }
+}
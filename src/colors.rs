@@ -3,14 +3,20 @@
 //! Collection of data structures, functions, and methods for working with the Philips Hue color space.
 
 use serde_json;
-use std::collections::HashMap;
-use std::env;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use files;
+
+static COLOR_NAMES: AtomicBool = AtomicBool::new(true);
 
 /// Simple structure to represent a 24 bit RGB color.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RGB {
     pub r: u8,
     pub g: u8,
@@ -25,33 +31,36 @@ impl RGB {
     /// ```
     /// use rusty_hue::colors;
     ///
-    /// let xy = colors::XY { x: 0.32272673, y: 0.32902290, brightness: 35 };
+    /// let xy = colors::XY { x: 0.32272673, y: 0.3290229, brightness: 35 };
     /// let rgb = colors::RGB::from_xy(&xy);
     ///
-    /// assert_eq!(rgb.r, 145);
+    /// assert_eq!(rgb.r, 103);
     /// ```
     pub fn from_xy(xy: &XY) -> RGB {
         let z = 1.0 - xy.x - xy.y;
-        let brightness = xy.brightness as f32;
+        // Brightness is the luminance scaled to 0-254, as in `XY::from_rgb`.
+        let brightness = f32::from(xy.brightness) / 254.0;
         let x = brightness / xy.y * xy.x;
         let y = brightness / xy.y * z;
 
         // Convert to RGB using Wide RGB D65 conversion
         let r = x * 1.656492 - brightness * 0.354851 - y * 0.255038;
         let g = -x * 0.707196 + brightness * 1.655397 + y * 0.036152;
-        let b = x * 0.051713 - brightness * 0.121364 + y * 1.011530;
+        let b = x * 0.051713 - brightness * 0.121364 + y * 1.01153;
 
         // Apply reverse gamma correction
         let mut rgb = [r, g, b];
 
-        for i in 0..3 {
-            if rgb[i] <= 0.0031308 {
-                rgb[i] *= 12.92;
+        for c in rgb.iter_mut() {
+            *c = c.clamp(0.0, 1.0);
+
+            if *c <= 0.0031308 {
+                *c *= 12.92;
             } else {
-                rgb[i] = 1.055 * rgb[i].powf(1.0 / 2.4) - 0.055;
+                *c = 1.055 * c.powf(1.0 / 2.4) - 0.055;
             }
 
-            rgb[i] *= 255.0;
+            *c *= 255.0;
         }
 
         RGB {
@@ -60,6 +69,126 @@ impl RGB {
             b: rgb[2] as u8,
         }
     }
+
+    /// Create an RGB color from its hue (0-360 degrees), saturation (0-100), and value (0-100).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rusty_hue::colors::RGB;
+    ///
+    /// assert_eq!(RGB::from_hsv(30.0, 100.0, 80.0), RGB { r: 204, g: 102, b: 0 });
+    /// ```
+    pub fn from_hsv(hue: f32, sat: f32, value: f32) -> RGB {
+        let sat = (sat / 100.0).clamp(0.0, 1.0);
+        let value = (value / 100.0).clamp(0.0, 1.0);
+        let chroma = value * sat;
+
+        RGB::from_chroma(hue, chroma, value - chroma)
+    }
+
+    /// Create an RGB color from its hue (0-360 degrees), saturation (0-100), and lightness
+    /// (0-100).
+    pub fn from_hsl(hue: f32, sat: f32, lightness: f32) -> RGB {
+        let sat = (sat / 100.0).clamp(0.0, 1.0);
+        let lightness = (lightness / 100.0).clamp(0.0, 1.0);
+        let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * sat;
+
+        RGB::from_chroma(hue, chroma, lightness - chroma / 2.0)
+    }
+
+    /// Helper function to build a color from its hue, chroma, and the amount added to every
+    /// channel, all but the hue from 0 to 1.
+    fn from_chroma(hue: f32, chroma: f32, offset: f32) -> RGB {
+        let sector = hue.rem_euclid(360.0) / 60.0;
+        let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+
+        let (r, g, b) = match sector as u8 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        let channel = |c: f32| ((c + offset) * 255.0).round().clamp(0.0, 255.0) as u8;
+
+        RGB {
+            r: channel(r),
+            g: channel(g),
+            b: channel(b),
+        }
+    }
+
+    /// Hue (0-360 degrees), saturation (0-100), and value (0-100) of the color.
+    pub fn to_hsv(&self) -> (f32, f32, f32) {
+        let (max, min) = self.range();
+        let sat = if max > 0.0 { (max - min) / max } else { 0.0 };
+
+        (self.hue(), sat * 100.0, max * 100.0)
+    }
+
+    /// Hue (0-360 degrees), saturation (0-100), and lightness (0-100) of the color.
+    pub fn to_hsl(&self) -> (f32, f32, f32) {
+        let (max, min) = self.range();
+        let lightness = (max + min) / 2.0;
+        let sat = if max > min {
+            (max - min) / (1.0 - (2.0 * lightness - 1.0).abs())
+        } else {
+            0.0
+        };
+
+        (self.hue(), sat * 100.0, lightness * 100.0)
+    }
+
+    /// The color as the bridge's own `hue` (0-65535) and `sat` (0-254), used by lights in the
+    /// "hs" color mode.
+    pub fn to_hue_sat(&self) -> (u16, u8) {
+        let (hue, sat, _) = self.to_hsv();
+
+        (
+            (hue / 360.0 * 65535.0).round() as u16,
+            (sat / 100.0 * 254.0).round() as u8,
+        )
+    }
+
+    /// Whether the color is close enough to white, at most 10% saturated, to be shown as a color
+    /// temperature.
+    pub fn is_near_white(&self) -> bool {
+        self.to_hsv().1 <= 10.0
+    }
+
+    /// Helper function for the largest and smallest channel, from 0 to 1.
+    fn range(&self) -> (f32, f32) {
+        let channels = [self.r, self.g, self.b];
+        let max = channels.iter().max().cloned().unwrap_or(0);
+        let min = channels.iter().min().cloned().unwrap_or(0);
+
+        (f32::from(max) / 255.0, f32::from(min) / 255.0)
+    }
+
+    /// Helper function for the hue of the color in degrees, 0 for greys.
+    fn hue(&self) -> f32 {
+        let (r, g, b) = (
+            f32::from(self.r) / 255.0,
+            f32::from(self.g) / 255.0,
+            f32::from(self.b) / 255.0,
+        );
+        let (max, min) = self.range();
+        let delta = max - min;
+
+        let hue = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+
+        hue.rem_euclid(360.0)
+    }
 }
 
 /// Structure to represent a color in a 2D color gamut.
@@ -87,13 +216,13 @@ impl XY {
         let mut rgb = [rgb.r as f32, rgb.g as f32, rgb.b as f32];
 
         // Apply gamma correction
-        for i in 0..3 {
-            rgb[i] /= 255.0;
+        for c in rgb.iter_mut() {
+            *c /= 255.0;
 
-            if rgb[i] > 0.04045 {
-                rgb[i] = ((rgb[i] + 0.055) / 1.055).powf(2.3);
+            if *c > 0.04045 {
+                *c = ((*c + 0.055) / 1.055).powf(2.3);
             } else {
-                rgb[i] /= 12.92;
+                *c /= 12.92;
             }
         }
 
@@ -108,10 +237,19 @@ impl XY {
 
         let brightness = (y * 254.0) as u8;
 
+        // Black has no chromaticity, it's given that of white at no brightness.
+        if x + y + z == 0.0 {
+            return XY {
+                x: D65_WHITE.0,
+                y: D65_WHITE.1,
+                brightness,
+            };
+        }
+
         XY {
             x: x / (x + y + z),
             y: y / (x + y + z),
-            brightness: brightness,
+            brightness,
         }
     }
 
@@ -129,7 +267,7 @@ impl XY {
         };
 
         if gamut.point_in_gamut(&gamut_point) {
-            return ();
+            return;
         }
 
         let new_gamut_point = gamut.closest_point(&gamut_point);
@@ -147,20 +285,31 @@ pub struct GamutPoint {
 }
 
 impl GamutPoint {
+    pub fn new(x: f32, y: f32) -> GamutPoint {
+        GamutPoint { x, y }
+    }
+
     /// Helper function for determining if a point is inside a triangle.
     fn sign(&self, p1: &GamutPoint, p2: &GamutPoint) -> bool {
         (self.x - p2.x) * (p1.y - p2.y) - (p1.x - p2.x) * (self.y - p2.y) < 0.0
     }
 
-    /// Helper function to find the closest point on a line between that line and another point.
+    /// Helper function to find the closest point to this one on the line segment between two
+    /// others. Points beyond either end are moved to that end, so a color past a corner of a gamut
+    /// ends up on the corner rather than on the extension of an edge.
     fn closest_point_on_line(&self, p1: &GamutPoint, p2: &GamutPoint) -> GamutPoint {
-        let mut k = (p2.y - p1.y) * (self.x - p1.x) - (p2.x - p1.x) * (self.y - p1.y);
-        k /= (p2.y - p1.y).powi(2) + (p2.x - p1.x).powi(2);
+        let (dx, dy) = (p2.x - p1.x, p2.y - p1.y);
+        let length = dx.powi(2) + dy.powi(2);
+        if length == 0.0 {
+            return GamutPoint { x: p1.x, y: p1.y };
+        }
 
-        let x = self.x - k * (p2.y - p1.y);
-        let y = self.y + k * (p2.x - p1.x);
+        let t = (((self.x - p1.x) * dx + (self.y - p1.y) * dy) / length).clamp(0.0, 1.0);
 
-        GamutPoint { x, y }
+        GamutPoint {
+            x: p1.x + t * dx,
+            y: p1.y + t * dy,
+        }
     }
 
     /// Helper function for finding the distance between 2 points.
@@ -178,6 +327,30 @@ pub struct ColorGamut {
 }
 
 impl ColorGamut {
+    /// Builds a gamut from its red, green, and blue vertices, as reported by a light's
+    /// capabilities, e.x. `[[0.6915, 0.3083], [0.17, 0.7], [0.1532, 0.0475]]`.
+    pub fn from_vertices(vertices: &[Vec<f32>]) -> Option<ColorGamut> {
+        match *vertices {
+            [ref red, ref green, ref blue] if vertices.iter().all(|v| v.len() == 2) => {
+                Some(ColorGamut {
+                    red: GamutPoint::new(red[0], red[1]),
+                    green: GamutPoint::new(green[0], green[1]),
+                    blue: GamutPoint::new(blue[0], blue[1]),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// The red, green, and blue vertices of the gamut as (x, y) coordinates.
+    pub fn vertices(&self) -> [(f32, f32); 3] {
+        [
+            (self.red.x, self.red.y),
+            (self.green.x, self.green.y),
+            (self.blue.x, self.blue.y),
+        ]
+    }
+
     /// Determines if a point is within a color gamut.
     pub fn point_in_gamut(&self, p: &GamutPoint) -> bool {
         let b1 = p.sign(&self.red, &self.green);
@@ -208,6 +381,9 @@ impl ColorGamut {
     }
 }
 
+/// The (x, y) coordinates of the D65 white point, daylight white.
+const D65_WHITE: (f32, f32) = (0.3127, 0.3290);
+
 /// Philips Hue Color Gamut A.
 pub const COLOR_GAMUT_A: ColorGamut = ColorGamut {
     red: GamutPoint { x: 0.704, y: 0.296 },
@@ -228,7 +404,7 @@ pub const COLOR_GAMUT_B: ColorGamut = ColorGamut {
 /// Philips Hue Color Gamut C.
 pub const COLOR_GAMUT_C: ColorGamut = ColorGamut {
     red: GamutPoint { x: 0.692, y: 0.308 },
-    green: GamutPoint { x: 0.17, y: 0.07 },
+    green: GamutPoint { x: 0.17, y: 0.7 },
     blue: GamutPoint { x: 0.153, y: 0.048 },
 };
 
@@ -245,30 +421,749 @@ pub fn color_gamut_lookup(model_id: &str) -> Option<ColorGamut> {
     }
 }
 
-/// Loads preconfigured colors from a JSON file in $HOME/.config/rusty_hue/colors.json.
-pub fn load_colors_from_file() -> Result<HashMap<String, RGB>, Box<Error>> {
-    match env::home_dir() {
-        Some(path) => {
-            let colors_file =
-                String::from(path.to_string_lossy()) + "/.config/rusty_hue/colors.json";
-            let mut f = File::open(colors_file)?;
+/// Location of the colors file: the one given, e.x. by the colors_file setting, or else
+/// $HOME/.config/rusty_hue/colors.json.
+pub fn colors_file(path: Option<&Path>) -> Result<PathBuf, Box<dyn Error>> {
+    match path {
+        Some(path) => Ok(path.to_path_buf()),
+        None => files::config_file("colors.json"),
+    }
+}
+
+/// Loads preconfigured colors from a colors file, see `colors_file`.
+pub fn load_colors_from_file(path: &Path) -> Result<HashMap<String, RGB>, Box<dyn Error>> {
+    let mut f = File::open(path)?;
+
+    let mut contents = String::new();
+    f.read_to_string(&mut contents)?;
+
+    let colors: HashMap<String, RGB> = serde_json::from_str(&contents)?;
+
+    Ok(colors)
+}
+
+/// Writes colors to a colors file, see `colors_file`, sorted by name.
+pub fn save_colors_to_file(
+    path: &Path,
+    colors: &HashMap<String, RGB>,
+) -> Result<(), Box<dyn Error>> {
+    let sorted: BTreeMap<&String, &RGB> = colors.iter().collect();
+
+    files::replace_file(path, serde_json::to_string_pretty(&sorted)?.as_bytes())?;
+    Ok(())
+}
+
+/// The colors saved in a colors file, see `colors_file`. A missing file holds no colors.
+pub fn saved_colors(path: &Path) -> Result<HashMap<String, RGB>, Box<dyn Error>> {
+    match load_colors_from_file(path) {
+        Ok(colors) => Ok(colors),
+        Err(e) => match e.downcast_ref::<io::Error>() {
+            Some(e) if e.kind() == io::ErrorKind::NotFound => Ok(HashMap::new()),
+            _ => Err(e),
+        },
+    }
+}
+
+/// Reads colors from any file in the layout of colors.json, checking their names.
+pub fn read_colors(path: &Path) -> Result<HashMap<String, RGB>, Box<dyn Error>> {
+    let mut f = File::open(path)?;
+
+    let mut contents = String::new();
+    f.read_to_string(&mut contents)?;
+
+    let colors: HashMap<String, RGB> = serde_json::from_str(&contents)
+        .map_err(|e| format!("Invalid colors file {}: {}", path.display(), e))?;
+    for name in colors.keys() {
+        check_name(name)?;
+    }
+
+    Ok(colors)
+}
+
+/// How merging colors into others went.
+#[derive(Debug, Default, PartialEq)]
+pub struct Merge {
+    pub added: Vec<String>,
+    pub replaced: Vec<String>,
+    /// Names that were already taken by a different color and were kept.
+    pub kept: Vec<String>,
+}
+
+/// Merges colors into others. A name that is taken by a different color is only replaced with
+/// `replace`.
+pub fn merge_colors(
+    colors: &mut HashMap<String, RGB>,
+    other: HashMap<String, RGB>,
+    replace: bool,
+) -> Merge {
+    let mut merge = Merge::default();
+
+    for (name, rgb) in other {
+        match colors.get(&name) {
+            Some(current) if *current == rgb => continue,
+            Some(_) if !replace => {
+                merge.kept.push(name);
+                continue;
+            }
+            Some(_) => merge.replaced.push(name.clone()),
+            None => merge.added.push(name.clone()),
+        }
+        colors.insert(name, rgb);
+    }
+
+    merge.added.sort();
+    merge.replaced.sort();
+    merge.kept.sort();
+    merge
+}
+
+/// Saves a color in a colors file by name. A name that is taken by a different color is only
+/// replaced with `replace`.
+pub fn add_color(path: &Path, name: &str, rgb: RGB, replace: bool) -> Result<(), Box<dyn Error>> {
+    check_name(name)?;
+    let mut colors = saved_colors(path)?;
 
-            let mut contents = String::new();
-            f.read_to_string(&mut contents)?;
+    let mut color = HashMap::new();
+    color.insert(name.to_string(), rgb);
+    if !merge_colors(&mut colors, color, replace).kept.is_empty() {
+        return Err(From::from(format!(
+            "Color '{}' is already {}, use --replace to change it.",
+            name,
+            to_hex(&colors[name])
+        )));
+    }
 
-            let colors: HashMap<String, RGB> = serde_json::from_str(&contents)?;
+    save_colors_to_file(path, &colors)
+}
+
+/// Removes a color from a colors file by name. Built-in colors can't be removed.
+pub fn remove_color(path: &Path, name: &str) -> Result<(), Box<dyn Error>> {
+    let mut colors = saved_colors(path)?;
 
-            return Ok(colors);
+    if colors.remove(name).is_none() {
+        if default_colors().contains_key(name) {
+            return Err(From::from(format!(
+                "'{}' is built in and can't be removed, but 'colors add' can change it.",
+                name
+            )));
+        }
+        return Err(From::from(format!("No color named '{}'.", name)));
+    }
+
+    save_colors_to_file(path, &colors)
+}
+
+/// Merges the colors of another file, see `read_colors`, into a colors file. A name that is taken
+/// by a different color is only replaced with `replace`.
+pub fn import_colors(path: &Path, other: &Path, replace: bool) -> Result<Merge, Box<dyn Error>> {
+    let mut colors = saved_colors(path)?;
+
+    let merge = merge_colors(&mut colors, read_colors(other)?, replace);
+    save_colors_to_file(path, &colors)?;
+    Ok(merge)
+}
+
+/// Checks that a color can be referred to by a name, which can't be mistaken for a color given
+/// another way or split apart in a list of colors.
+pub fn check_name(name: &str) -> Result<(), Box<dyn Error>> {
+    let valid = !name.trim().is_empty()
+        && name.trim() == name
+        && !name.contains(',')
+        && !name.starts_with('#')
+        && !name.starts_with("hsv:")
+        && !name.starts_with("hsl:");
+
+    if valid {
+        Ok(())
+    } else {
+        Err(From::from(format!(
+            "Invalid color name '{}', names can't be empty, contain commas, or start with '#', \
+             'hsv:', or 'hsl:'.",
+            name
+        )))
+    }
+}
+
+/// Parses a color given exactly, as hex, e.x. "#ff8800", or as "r,g,b", e.x. "255,136,0".
+pub fn parse_rgb(value: &str) -> Result<RGB, Box<dyn Error>> {
+    let value = value.trim();
+    if let Some(rgb) = value.strip_prefix('#').and_then(parse_hex) {
+        return Ok(rgb);
+    }
+
+    let parts: Vec<Result<u8, _>> = value.split(',').map(|part| part.trim().parse()).collect();
+    match parts.as_slice() {
+        [Ok(r), Ok(g), Ok(b)] => Ok(RGB {
+            r: *r,
+            g: *g,
+            b: *b,
+        }),
+        _ => Err(From::from(format!(
+            "Invalid color '{}', expected hex, e.x. '#ff8800', or 'r,g,b' with each 0-255.",
+            value
+        ))),
+    }
+}
+
+/// Formats a color as hex, e.x. "#ff8800".
+pub fn to_hex(rgb: &RGB) -> String {
+    format!("#{:02x}{:02x}{:02x}", rgb.r, rgb.g, rgb.b)
+}
+
+/// The CSS color names, of `CSS_COLOR_NAMES`, a light's color can be named by besides those in
+/// colors.json. Darker shades of the same color, e.x. "darkred", are left out as lights show
+/// them by brightness.
+const CSS_COLORS: [&str; 34] = [
+    "aquamarine",
+    "blue",
+    "blueviolet",
+    "chartreuse",
+    "coral",
+    "crimson",
+    "cyan",
+    "darkviolet",
+    "deeppink",
+    "deepskyblue",
+    "dodgerblue",
+    "gold",
+    "goldenrod",
+    "hotpink",
+    "indigo",
+    "khaki",
+    "lavender",
+    "lime",
+    "magenta",
+    "orange",
+    "orangered",
+    "peachpuff",
+    "pink",
+    "plum",
+    "red",
+    "salmon",
+    "skyblue",
+    "springgreen",
+    "tomato",
+    "turquoise",
+    "violet",
+    "wheat",
+    "white",
+    "yellow",
+];
+
+/// Every CSS color name, which colors can be given by besides the names in colors.json.
+const CSS_COLOR_NAMES: [(&str, u8, u8, u8); 148] = [
+    ("aliceblue", 240, 248, 255),
+    ("antiquewhite", 250, 235, 215),
+    ("aqua", 0, 255, 255),
+    ("aquamarine", 127, 255, 212),
+    ("azure", 240, 255, 255),
+    ("beige", 245, 245, 220),
+    ("bisque", 255, 228, 196),
+    ("black", 0, 0, 0),
+    ("blanchedalmond", 255, 235, 205),
+    ("blue", 0, 0, 255),
+    ("blueviolet", 138, 43, 226),
+    ("brown", 165, 42, 42),
+    ("burlywood", 222, 184, 135),
+    ("cadetblue", 95, 158, 160),
+    ("chartreuse", 127, 255, 0),
+    ("chocolate", 210, 105, 30),
+    ("coral", 255, 127, 80),
+    ("cornflowerblue", 100, 149, 237),
+    ("cornsilk", 255, 248, 220),
+    ("crimson", 220, 20, 60),
+    ("cyan", 0, 255, 255),
+    ("darkblue", 0, 0, 139),
+    ("darkcyan", 0, 139, 139),
+    ("darkgoldenrod", 184, 134, 11),
+    ("darkgray", 169, 169, 169),
+    ("darkgreen", 0, 100, 0),
+    ("darkgrey", 169, 169, 169),
+    ("darkkhaki", 189, 183, 107),
+    ("darkmagenta", 139, 0, 139),
+    ("darkolivegreen", 85, 107, 47),
+    ("darkorange", 255, 140, 0),
+    ("darkorchid", 153, 50, 204),
+    ("darkred", 139, 0, 0),
+    ("darksalmon", 233, 150, 122),
+    ("darkseagreen", 143, 188, 143),
+    ("darkslateblue", 72, 61, 139),
+    ("darkslategray", 47, 79, 79),
+    ("darkslategrey", 47, 79, 79),
+    ("darkturquoise", 0, 206, 209),
+    ("darkviolet", 148, 0, 211),
+    ("deeppink", 255, 20, 147),
+    ("deepskyblue", 0, 191, 255),
+    ("dimgray", 105, 105, 105),
+    ("dimgrey", 105, 105, 105),
+    ("dodgerblue", 30, 144, 255),
+    ("firebrick", 178, 34, 34),
+    ("floralwhite", 255, 250, 240),
+    ("forestgreen", 34, 139, 34),
+    ("fuchsia", 255, 0, 255),
+    ("gainsboro", 220, 220, 220),
+    ("ghostwhite", 248, 248, 255),
+    ("gold", 255, 215, 0),
+    ("goldenrod", 218, 165, 32),
+    ("gray", 128, 128, 128),
+    ("green", 0, 128, 0),
+    ("greenyellow", 173, 255, 47),
+    ("grey", 128, 128, 128),
+    ("honeydew", 240, 255, 240),
+    ("hotpink", 255, 105, 180),
+    ("indianred", 205, 92, 92),
+    ("indigo", 75, 0, 130),
+    ("ivory", 255, 255, 240),
+    ("khaki", 240, 230, 140),
+    ("lavender", 230, 230, 250),
+    ("lavenderblush", 255, 240, 245),
+    ("lawngreen", 124, 252, 0),
+    ("lemonchiffon", 255, 250, 205),
+    ("lightblue", 173, 216, 230),
+    ("lightcoral", 240, 128, 128),
+    ("lightcyan", 224, 255, 255),
+    ("lightgoldenrodyellow", 250, 250, 210),
+    ("lightgray", 211, 211, 211),
+    ("lightgreen", 144, 238, 144),
+    ("lightgrey", 211, 211, 211),
+    ("lightpink", 255, 182, 193),
+    ("lightsalmon", 255, 160, 122),
+    ("lightseagreen", 32, 178, 170),
+    ("lightskyblue", 135, 206, 250),
+    ("lightslategray", 119, 136, 153),
+    ("lightslategrey", 119, 136, 153),
+    ("lightsteelblue", 176, 196, 222),
+    ("lightyellow", 255, 255, 224),
+    ("lime", 0, 255, 0),
+    ("limegreen", 50, 205, 50),
+    ("linen", 250, 240, 230),
+    ("magenta", 255, 0, 255),
+    ("maroon", 128, 0, 0),
+    ("mediumaquamarine", 102, 205, 170),
+    ("mediumblue", 0, 0, 205),
+    ("mediumorchid", 186, 85, 211),
+    ("mediumpurple", 147, 112, 219),
+    ("mediumseagreen", 60, 179, 113),
+    ("mediumslateblue", 123, 104, 238),
+    ("mediumspringgreen", 0, 250, 154),
+    ("mediumturquoise", 72, 209, 204),
+    ("mediumvioletred", 199, 21, 133),
+    ("midnightblue", 25, 25, 112),
+    ("mintcream", 245, 255, 250),
+    ("mistyrose", 255, 228, 225),
+    ("moccasin", 255, 228, 181),
+    ("navajowhite", 255, 222, 173),
+    ("navy", 0, 0, 128),
+    ("oldlace", 253, 245, 230),
+    ("olive", 128, 128, 0),
+    ("olivedrab", 107, 142, 35),
+    ("orange", 255, 165, 0),
+    ("orangered", 255, 69, 0),
+    ("orchid", 218, 112, 214),
+    ("palegoldenrod", 238, 232, 170),
+    ("palegreen", 152, 251, 152),
+    ("paleturquoise", 175, 238, 238),
+    ("palevioletred", 219, 112, 147),
+    ("papayawhip", 255, 239, 213),
+    ("peachpuff", 255, 218, 185),
+    ("peru", 205, 133, 63),
+    ("pink", 255, 192, 203),
+    ("plum", 221, 160, 221),
+    ("powderblue", 176, 224, 230),
+    ("purple", 128, 0, 128),
+    ("rebeccapurple", 102, 51, 153),
+    ("red", 255, 0, 0),
+    ("rosybrown", 188, 143, 143),
+    ("royalblue", 65, 105, 225),
+    ("saddlebrown", 139, 69, 19),
+    ("salmon", 250, 128, 114),
+    ("sandybrown", 244, 164, 96),
+    ("seagreen", 46, 139, 87),
+    ("seashell", 255, 245, 238),
+    ("sienna", 160, 82, 45),
+    ("silver", 192, 192, 192),
+    ("skyblue", 135, 206, 235),
+    ("slateblue", 106, 90, 205),
+    ("slategray", 112, 128, 144),
+    ("slategrey", 112, 128, 144),
+    ("snow", 255, 250, 250),
+    ("springgreen", 0, 255, 127),
+    ("steelblue", 70, 130, 180),
+    ("tan", 210, 180, 140),
+    ("teal", 0, 128, 128),
+    ("thistle", 216, 191, 216),
+    ("tomato", 255, 99, 71),
+    ("turquoise", 64, 224, 208),
+    ("violet", 238, 130, 238),
+    ("wheat", 245, 222, 179),
+    ("white", 255, 255, 255),
+    ("whitesmoke", 245, 245, 245),
+    ("yellow", 255, 255, 0),
+    ("yellowgreen", 154, 205, 50),
+];
+
+/// Finds a color given by name, looked up in `named` first and then among the CSS color names,
+/// as hex, e.x. "#ff8800" or "#f80", or by hue, saturation, and value or lightness, e.x.
+/// "hsv:30,100,80" or "hsl:30,100,40".
+pub fn find_color(value: &str, named: &HashMap<String, RGB>) -> Option<RGB> {
+    let value = value.trim();
+    if let Some(rgb) = named.get(value) {
+        return Some(rgb.clone());
+    }
+
+    if let Some(hex) = value.strip_prefix('#') {
+        return parse_hex(hex);
+    }
+
+    if let Some(hsv) = value.strip_prefix("hsv:") {
+        return parse_triple(hsv).map(|(h, s, v)| RGB::from_hsv(h, s, v));
+    }
+
+    if let Some(hsl) = value.strip_prefix("hsl:") {
+        return parse_triple(hsl).map(|(h, s, l)| RGB::from_hsl(h, s, l));
+    }
+
+    css_color(&value.to_lowercase())
+}
+
+/// Helper function to look up a CSS color by its name in lowercase.
+fn css_color(name: &str) -> Option<RGB> {
+    CSS_COLOR_NAMES
+        .iter()
+        .find(|&&(css_name, _, _, _)| css_name == name)
+        .map(|&(_, r, g, b)| RGB { r, g, b })
+}
+
+/// Helper function to parse the digits of a hex color, either six or three of them.
+fn parse_hex(hex: &str) -> Option<RGB> {
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let digits: Vec<u8> = match hex.len() {
+        6 => (0..3)
+            .map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16))
+            .collect::<Result<_, _>>()
+            .ok()?,
+        // Each digit is doubled, "#f80" being "#ff8800".
+        3 => hex
+            .chars()
+            .map(|c| c.to_digit(16).map(|digit| digit as u8 * 17))
+            .collect::<Option<_>>()?,
+        _ => return None,
+    };
+
+    Some(RGB {
+        r: digits[0],
+        g: digits[1],
+        b: digits[2],
+    })
+}
+
+/// Helper function to parse a hue in degrees and two percentages, e.x. "30,100,80". Values out of
+/// range are rejected rather than clamped.
+fn parse_triple(value: &str) -> Option<(f32, f32, f32)> {
+    let parts: Vec<f32> = value
+        .split(',')
+        .map(|part| part.trim().parse().ok())
+        .collect::<Option<_>>()?;
+
+    match *parts {
+        [hue, a, b]
+            if (0.0..=360.0).contains(&hue)
+                && (0.0..=100.0).contains(&a)
+                && (0.0..=100.0).contains(&b) =>
+        {
+            Some((hue, a, b))
+        }
+        _ => None,
+    }
+}
+
+/// Parses a color given by a name from the colors file at `path` or the built-in ones, e.x.
+/// "relax", a CSS color name, e.x. "tomato", or as hex, e.x. "#ff8800". Names in the colors file
+/// take precedence, and the file may be missing.
+pub fn parse_color(value: &str, path: &Path) -> Result<RGB, Box<dyn Error>> {
+    let named = load_colors(path)?;
+
+    find_color(value, &named).ok_or_else(|| unknown_color(value))
+}
+
+/// Parses a comma separated list of colors, e.x. "red,#00ff00,hsv:240,100,100", each given as for
+/// `parse_color`.
+pub fn parse_colors(value: &str, path: &Path) -> Result<Vec<RGB>, Box<dyn Error>> {
+    let named = load_colors(path)?;
+
+    split_colors(value)
+        .iter()
+        .map(|color| find_color(color, &named).ok_or_else(|| unknown_color(color)))
+        .collect()
+}
+
+/// Helper function to split a list of colors at its commas, except those within "hsv:" and
+/// "hsl:" colors.
+fn split_colors(value: &str) -> Vec<String> {
+    let mut colors = Vec::new();
+    let mut parts = value.split(',');
+
+    while let Some(part) = parts.next() {
+        let mut color = part.trim().to_string();
+        if color.starts_with("hsv:") || color.starts_with("hsl:") {
+            for part in parts.by_ref().take(2) {
+                color = color + "," + part.trim();
+            }
+        }
+        colors.push(color);
+    }
+
+    colors
+}
+
+/// The CSS basic colors, of `CSS_COLOR_NAMES`, every install has besides the `PRESETS`. Black is
+/// left out as lights can't show it.
+const BASIC_COLORS: [&str; 15] = [
+    "aqua", "blue", "fuchsia", "gray", "green", "lime", "maroon", "navy", "olive", "purple", "red",
+    "silver", "teal", "white", "yellow",
+];
+
+/// Whites like those of the Hue app's relax, read, concentrate, and energize scenes, about 2200K,
+/// 2900K, 4300K, and 6400K.
+const PRESETS: [(&str, u8, u8, u8); 4] = [
+    ("relax", 255, 148, 43),
+    ("read", 255, 173, 102),
+    ("concentrate", 255, 213, 179),
+    ("energize", 255, 254, 250),
+];
+
+/// The built-in colors, which colors.json can override: the `BASIC_COLORS` and the `PRESETS`.
+pub fn default_colors() -> HashMap<String, RGB> {
+    let basic = BASIC_COLORS
+        .iter()
+        .filter_map(|&name| css_color(name).map(|rgb| (name.to_string(), rgb)));
+    let presets = PRESETS
+        .iter()
+        .map(|&(name, r, g, b)| (name.to_string(), RGB { r, g, b }));
+
+    basic.chain(presets).collect()
+}
+
+/// Loads the named colors: the built-in ones with those of the colors file at `path`, which may
+/// be missing, on top.
+pub fn load_colors(path: &Path) -> Result<HashMap<String, RGB>, Box<dyn Error>> {
+    let mut named = default_colors();
+
+    named.extend(saved_colors(path)?);
+    Ok(named)
+}
+
+/// Helper function to build the error for a color that isn't known.
+fn unknown_color(value: &str) -> Box<dyn Error> {
+    From::from(format!(
+        "Unknown color '{}', use a name from 'colors list', a CSS color name, hex, e.x. '#ff8800', \
+         or 'hsv:30,100,80'.",
+        value
+    ))
+}
+
+/// Largest Delta-E between a color and a named color for the color to be given the name.
+const MAX_NAME_DELTA_E: f32 = 25.0;
+
+/// Relative luminance colors are compared at when naming them, that of a mid grey. At full
+/// luminance, saturated colors a light can't quite reach would be too far from their names.
+const NAME_LUMINANCE: f32 = 0.18;
+
+/// A color in the CIE L*a*b* color space, where distances roughly match perceived differences.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+impl Lab {
+    /// Converts (x, y) coordinates at a relative luminance (0-1) to L*a*b*, relative to the D65
+    /// white point.
+    pub fn from_xy(x: f32, y: f32, luminance: f32) -> Lab {
+        let y = y.max(f32::EPSILON);
+        let xyz = [
+            luminance / y * x / 0.95047,
+            luminance,
+            luminance / y * (1.0 - x - y) / 1.08883,
+        ];
+
+        let f: Vec<f32> = xyz
+            .iter()
+            .map(|&t| {
+                if t > 0.008856 {
+                    t.cbrt()
+                } else {
+                    7.787 * t + 16.0 / 116.0
+                }
+            })
+            .collect();
+
+        Lab {
+            l: 116.0 * f[1] - 16.0,
+            a: 500.0 * (f[0] - f[1]),
+            b: 200.0 * (f[1] - f[2]),
+        }
+    }
+
+    /// The Delta-E (CIE76) difference to another color. About 2.3 is just noticeable.
+    pub fn delta_e(&self, other: &Lab) -> f32 {
+        ((self.l - other.l).powi(2) + (self.a - other.a).powi(2) + (self.b - other.b).powi(2))
+            .sqrt()
+    }
+}
+
+/// Turns naming colors in light info on or off for the whole process.
+pub fn set_color_names(enabled: bool) {
+    COLOR_NAMES.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether colors are named in light info.
+pub fn color_names() -> bool {
+    COLOR_NAMES.load(Ordering::Relaxed)
+}
+
+/// The colors colors can be named by: the CSS names, overridden and extended by the colors file at
+/// `path`.
+pub fn named_colors(path: &Path) -> HashMap<String, RGB> {
+    let mut colors: HashMap<String, RGB> = CSS_COLORS
+        .iter()
+        .filter_map(|&name| css_color(name).map(|rgb| (name.to_string(), rgb)))
+        .collect();
+
+    colors.extend(load_colors_from_file(path).unwrap_or_default());
+    colors
+}
+
+/// The name of the color nearest to the (x, y) coordinates of a light by Delta-E, if any is near
+/// enough. Named colors are compared as the light would show them, moved into its gamut, and by
+/// chromaticity only since a light's brightness is set apart from its color.
+pub fn nearest_name(
+    x: f32,
+    y: f32,
+    colors: &HashMap<String, RGB>,
+    gamut: Option<&ColorGamut>,
+) -> Option<String> {
+    let color = Lab::from_xy(x, y, NAME_LUMINANCE);
+
+    colors
+        .iter()
+        .map(|(name, rgb)| {
+            let mut xy = XY::from_rgb(rgb);
+            if let Some(gamut) = gamut {
+                xy.adjust_for_gamut(gamut);
+            }
+            (
+                color.delta_e(&Lab::from_xy(xy.x, xy.y, NAME_LUMINANCE)),
+                name,
+            )
+        })
+        .filter(|&(delta_e, _)| delta_e <= MAX_NAME_DELTA_E)
+        .min_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(b.1)))
+        .map(|(_, name)| name.clone())
+}
+
+/// Built-in names for common white color temperatures, in mireds.
+const WHITE_TEMPERATURES: [(&str, u16); 5] = [
+    ("candle", 500),
+    ("warm", 370),
+    ("neutral", 250),
+    ("cool", 200),
+    ("daylight", 153),
+];
+
+/// Loads named white color temperatures (in mireds). The built-in names can be overridden or
+/// extended by $HOME/.config/rusty_hue/temperatures.json.
+pub fn load_temperatures() -> Result<HashMap<String, u16>, Box<dyn Error>> {
+    let mut temperatures: HashMap<String, u16> = WHITE_TEMPERATURES
+        .iter()
+        .map(|&(name, mired)| (name.to_string(), mired))
+        .collect();
+
+    let mut f = match File::open(files::config_file("temperatures.json")?) {
+        Ok(f) => f,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(temperatures),
+        Err(e) => return Err(From::from(e)),
+    };
+
+    let mut contents = String::new();
+    f.read_to_string(&mut contents)?;
+
+    let overrides: HashMap<String, u16> = serde_json::from_str(&contents)?;
+    temperatures.extend(overrides);
+
+    Ok(temperatures)
+}
+
+/// Converts a color temperature in Kelvin to mireds, the unit the bridge uses.
+pub fn kelvin_to_mired(kelvin: u32) -> u16 {
+    (1_000_000.0 / f64::from(kelvin.max(1)))
+        .round()
+        .min(f64::from(u16::MAX)) as u16
+}
+
+/// The color temperature, in Kelvin, nearest to CIE (x, y) coordinates, by McCamy's
+/// approximation. Only meaningful for colors close to white, e.x. about 2700K for [0.46, 0.41].
+pub fn xy_to_kelvin(x: f32, y: f32) -> u32 {
+    let n = f64::from(x - 0.3320) / f64::from(0.1858 - y);
+    let kelvin = 449.0 * n.powi(3) + 3525.0 * n.powi(2) + 6823.3 * n + 5520.33;
+
+    kelvin.round().clamp(1000.0, 40000.0) as u32
+}
+
+/// Converts a color temperature in mireds to Kelvin.
+pub fn mired_to_kelvin(mired: u16) -> u32 {
+    (1_000_000.0 / f64::from(mired.max(1))).round() as u32
+}
+
+/// Parses a color temperature given either in mireds (e.x. "370"), in Kelvin (e.x. "2700K"), or
+/// as a named white (e.x. "warm").
+pub fn parse_temperature(
+    value: &str,
+    temperatures: &HashMap<String, u16>,
+) -> Result<u16, Box<dyn Error>> {
+    let value = value.trim();
+
+    if let Ok(mired) = value.parse::<u16>() {
+        return Ok(mired);
+    }
+
+    if let Some(kelvin) = value.strip_suffix(['K', 'k']) {
+        return match kelvin.trim().parse::<u32>() {
+            Ok(kelvin) if kelvin > 0 => Ok(kelvin_to_mired(kelvin)),
+            _ => Err(From::from(format!(
+                "Invalid color temperature '{}', expected Kelvin above 0.",
+                value
+            ))),
+        };
+    }
+
+    match temperatures.get(&value.to_lowercase()) {
+        Some(&mired) => Ok(mired),
+        None => {
+            let mut names: Vec<&str> = temperatures.keys().map(String::as_str).collect();
+            names.sort();
+            Err(From::from(format!(
+                "Unknown color temperature '{}', use mireds, Kelvin, or one of: {}.",
+                value,
+                names.join(", ")
+            )))
         }
-        None => Err(From::from("Failed to get home directory.")),
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
 
     #[test]
+    #[allow(clippy::excessive_precision)]
     fn rgb_to_xy() {
         let rgb = RGB {
             r: 100,
@@ -291,9 +1186,14 @@ mod test {
         assert_eq!(xy.x, 0.38354447);
         assert_eq!(xy.y, 0.15998589);
         assert_eq!(xy.brightness, 12);
+
+        let xy = XY::from_rgb(&RGB { r: 0, g: 0, b: 0 });
+
+        assert_eq!((xy.x, xy.y, xy.brightness), (0.3127, 0.3290, 0));
     }
 
     #[test]
+    #[allow(clippy::excessive_precision)]
     fn xy_to_rgb() {
         let xy = XY {
             x: 0.32272673,
@@ -302,9 +1202,64 @@ mod test {
         };
         let rgb = RGB::from_xy(&xy);
 
-        assert_eq!(rgb.r, 145);
-        assert_eq!(rgb.g, 145);
-        assert_eq!(rgb.b, 145);
+        assert_eq!(rgb.r, 103);
+        assert_eq!(rgb.g, 103);
+        assert_eq!(rgb.b, 103);
+    }
+
+    #[test]
+    fn delta_e() {
+        let white = Lab::from_xy(0.3127, 0.329, 1.0);
+        assert!(white.l > 99.9 && white.a.abs() < 0.1 && white.b.abs() < 0.1);
+        assert_eq!(white.delta_e(&white), 0.0);
+
+        let red = Lab::from_xy(0.64, 0.33, 1.0);
+        assert!(white.delta_e(&red) > 100.0);
+    }
+
+    #[test]
+    fn nearest_names() {
+        let mut colors = HashMap::new();
+        colors.insert(String::from("mine"), RGB { r: 255, g: 0, b: 0 });
+        colors.insert(
+            String::from("white"),
+            RGB {
+                r: 255,
+                g: 255,
+                b: 255,
+            },
+        );
+        colors.insert(
+            String::from("coral"),
+            RGB {
+                r: 255,
+                g: 127,
+                b: 80,
+            },
+        );
+
+        let coral = XY::from_rgb(&colors["coral"]);
+        assert_eq!(
+            nearest_name(coral.x, coral.y, &colors, None).as_deref(),
+            Some("coral")
+        );
+        assert_eq!(
+            nearest_name(0.32, 0.33, &colors, None).as_deref(),
+            Some("white")
+        );
+        // Pure red is outside the gamut, so it is named as the light would show it.
+        assert_eq!(
+            nearest_name(0.692, 0.308, &colors, Some(&COLOR_GAMUT_C)).as_deref(),
+            Some("mine")
+        );
+        assert_eq!(nearest_name(0.17, 0.7, &colors, None), None);
+    }
+
+    #[test]
+    fn naming_colors_are_css_colors() {
+        for name in CSS_COLORS.iter() {
+            assert!(css_color(name).is_some(), "{} isn't a CSS color", name);
+        }
     }
 
     #[test]
@@ -339,6 +1294,9 @@ mod test {
 
         assert_eq!(new_point.x, 2.0);
         assert_eq!(new_point.y, 2.0);
+
+        let beyond = GamutPoint { x: 2.5, y: 4.0 };
+        assert_eq!(beyond.closest_point_on_line(&p2, &p3), p3);
     }
 
     #[test]
@@ -372,9 +1330,225 @@ mod test {
         assert_eq!(color_gamut_lookup("WRONG"), None);
     }
 
+    #[test]
+    fn kelvin_and_mireds() {
+        assert_eq!(kelvin_to_mired(2000), 500);
+        assert_eq!(mired_to_kelvin(370), 2703);
+        assert_eq!(mired_to_kelvin(kelvin_to_mired(4000)), 4000);
+    }
+
+    #[test]
+    fn nearest_kelvin() {
+        assert_eq!(xy_to_kelvin(0.3127, 0.329), 6505);
+        assert_eq!(xy_to_kelvin(0.4476, 0.4074), 2856);
+    }
+
+    #[test]
+    fn temperature_names() {
+        let temperatures: HashMap<String, u16> = WHITE_TEMPERATURES
+            .iter()
+            .map(|&(name, mired)| (name.to_string(), mired))
+            .collect();
+
+        assert_eq!(parse_temperature("warm", &temperatures).unwrap(), 370);
+        assert_eq!(parse_temperature("Daylight", &temperatures).unwrap(), 153);
+        assert_eq!(parse_temperature("300", &temperatures).unwrap(), 300);
+        assert_eq!(parse_temperature("2700K", &temperatures).unwrap(), 370);
+        assert_eq!(parse_temperature("6500k", &temperatures).unwrap(), 154);
+        assert!(parse_temperature("0K", &temperatures).is_err());
+        assert!(parse_temperature("toasty", &temperatures).is_err());
+    }
+
+    #[test]
+    fn hsv_and_hsl() {
+        let orange = RGB {
+            r: 204,
+            g: 102,
+            b: 0,
+        };
+
+        assert_eq!(RGB::from_hsl(30.0, 100.0, 40.0), orange);
+        assert_eq!(
+            RGB::from_hsv(0.0, 0.0, 100.0),
+            RGB {
+                r: 255,
+                g: 255,
+                b: 255
+            }
+        );
+        assert_eq!(
+            RGB::from_hsv(360.0, 100.0, 100.0),
+            RGB { r: 255, g: 0, b: 0 }
+        );
+        assert_eq!(orange.to_hsv(), (30.0, 100.0, 80.0));
+        assert_eq!(orange.to_hsl(), (30.0, 100.0, 40.0));
+        assert_eq!(orange.to_hue_sat(), (5461, 254));
+        assert_eq!(find_color("hsv:30, 100, 80", &HashMap::new()), Some(orange));
+        assert_eq!(find_color("hsl:400,100,40", &HashMap::new()), None);
+    }
+
+    #[test]
+    fn find_colors() {
+        let mut named = HashMap::new();
+        named.insert(String::from("tomato"), RGB { r: 1, g: 2, b: 3 });
+
+        assert_eq!(
+            find_color("#ff8800", &named),
+            Some(RGB {
+                r: 255,
+                g: 136,
+                b: 0
+            })
+        );
+        assert_eq!(
+            find_color("#F80", &named),
+            Some(RGB {
+                r: 255,
+                g: 136,
+                b: 0
+            })
+        );
+        assert_eq!(
+            find_color("Teal", &named),
+            Some(RGB {
+                r: 0,
+                g: 128,
+                b: 128
+            })
+        );
+        assert_eq!(find_color("tomato", &named), Some(RGB { r: 1, g: 2, b: 3 }));
+        assert_eq!(find_color("#ff880", &named), None);
+        assert_eq!(find_color("#gg8800", &named), None);
+        assert_eq!(find_color("mauve", &named), None);
+    }
+
+    #[test]
+    fn split_color_lists() {
+        assert_eq!(split_colors("red"), vec!["red"]);
+        assert_eq!(
+            split_colors("red, hsv:240,100,100,#00ff00"),
+            vec!["red", "hsv:240,100,100", "#00ff00"]
+        );
+    }
+
+    #[test]
+    fn merging() {
+        let rgb = |r, g, b| RGB { r, g, b };
+        let mut colors = HashMap::new();
+        colors.insert(String::from("brand"), rgb(1, 2, 3));
+        colors.insert(String::from("sea"), rgb(0, 80, 160));
+
+        let mut other = HashMap::new();
+        other.insert(String::from("brand"), rgb(9, 9, 9));
+        other.insert(String::from("sea"), rgb(0, 80, 160));
+        other.insert(String::from("sand"), rgb(220, 200, 150));
+
+        let merge = merge_colors(&mut colors, other.clone(), false);
+        assert_eq!(merge.added, vec!["sand"]);
+        assert_eq!(merge.kept, vec!["brand"]);
+        assert_eq!(colors["brand"], rgb(1, 2, 3));
+
+        let merge = merge_colors(&mut colors, other, true);
+        assert_eq!(merge.replaced, vec!["brand"]);
+        assert_eq!(colors["brand"], rgb(9, 9, 9));
+    }
+
+    #[test]
+    fn exact_colors() {
+        let orange = RGB {
+            r: 255,
+            g: 136,
+            b: 0,
+        };
+        assert_eq!(parse_rgb("#ff8800").unwrap(), orange);
+        assert_eq!(parse_rgb("255, 136, 0").unwrap(), orange);
+        assert!(parse_rgb("256,0,0").is_err());
+        assert!(parse_rgb("orange").is_err());
+        assert_eq!(to_hex(&orange), "#ff8800");
+
+        assert!(check_name("sea foam").is_ok());
+        assert!(check_name("sea,foam").is_err());
+        assert!(check_name("#sea").is_err());
+        assert!(check_name(" ").is_err());
+    }
+
+    #[test]
+    fn default_palette() {
+        let named = default_colors();
+        assert_eq!(named.len(), BASIC_COLORS.len() + PRESETS.len());
+        assert!(!named.contains_key("black"));
+        assert_eq!(
+            find_color("relax", &named),
+            Some(RGB {
+                r: 255,
+                g: 148,
+                b: 43
+            })
+        );
+        assert!(check_name("concentrate").is_ok());
+
+        // Built-in whites are shown as color temperatures by lights that can.
+        let kelvin = |name: &str| {
+            let xy = XY::from_rgb(&named[name]);
+            xy_to_kelvin(xy.x, xy.y)
+        };
+        assert!(kelvin("relax") < kelvin("read"));
+        assert!(kelvin("read") < kelvin("concentrate"));
+        assert!(kelvin("concentrate") < kelvin("energize"));
+    }
+
+    #[test]
+    fn add_remove_and_import() {
+        let dir = env::temp_dir().join(format!("rusty_hue_colors_{}", process::id()));
+        let path = dir.join("colors.json");
+        let orange = RGB {
+            r: 255,
+            g: 136,
+            b: 0,
+        };
+        let teal = RGB {
+            r: 0,
+            g: 128,
+            b: 128,
+        };
+
+        add_color(&path, "sunset", orange.clone(), false).unwrap();
+        assert!(add_color(&path, "sunset", teal.clone(), false).is_err());
+        assert!(add_color(&path, "#sunset", teal.clone(), false).is_err());
+        assert_eq!(saved_colors(&path).unwrap()["sunset"], orange);
+        add_color(&path, "sunset", teal.clone(), true).unwrap();
+        assert_eq!(saved_colors(&path).unwrap()["sunset"], teal);
+
+        let other = dir.join("other.json");
+        fs::write(
+            &other,
+            r#"{"sunset": {"r": 255, "g": 136, "b": 0}, "sea": {"r": 0, "g": 0, "b": 128}}"#,
+        )
+        .unwrap();
+        let merge = import_colors(&path, &other, false).unwrap();
+        assert_eq!(merge.added, vec!["sea"]);
+        assert_eq!(merge.kept, vec!["sunset"]);
+        assert_eq!(saved_colors(&path).unwrap()["sunset"], teal);
+
+        remove_color(&path, "sunset").unwrap();
+        assert!(remove_color(&path, "sunset").is_err());
+        assert!(remove_color(&path, "relax").is_err());
+        assert_eq!(saved_colors(&path).unwrap().len(), 1);
+
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["colors.json", "other.json"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn load_colors_file() {
-        let colors = load_colors_from_file();
+        let colors = load_colors_from_file(&colors_file(None).unwrap());
         assert!(colors.is_ok());
         let colors = colors.unwrap();
         assert_eq!(colors["white"].r, 255);
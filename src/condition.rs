@@ -0,0 +1,571 @@
+//! # condition
+//!
+//! Boolean expressions deciding whether a trigger should fire, e.x.
+//! `motion:Hallway && !flag:vacation && time in 22:00-06:00`. Terms name an event source and the
+//! entity it refers to (`source:name`), a threshold on the source's value, or a time window, and
+//! are combined with `&&`, `||`, and `!` (or `and`, `or`, and `not`) with the usual precedence and
+//! parentheses for grouping.
+//!
+//! Thresholds compare a value, e.x. `temperature:Kitchen > 24` or
+//! `lightlevel:Desk < 20 for 5m hysteresis 10`. With `for`, the value has to stay past the limit
+//! that long before the threshold is met. With `hysteresis`, a met threshold is only left once the
+//! value moves back that far past the limit, so a value hovering around it doesn't flip back and
+//! forth.
+
+use std::cell::Cell;
+use std::error::Error;
+use std::iter::Peekable;
+use std::time::{Duration, Instant};
+use std::vec::IntoIter;
+
+use parse;
+use parse::TimeOfDay;
+
+/// A parsed condition.
+#[derive(Debug, PartialEq)]
+pub enum Condition {
+    /// An event source is active for an entity, e.x. `motion:Hallway` or `flag:vacation`.
+    Active {
+        source: String,
+        name: String,
+    },
+    /// The value of an event source for an entity is past a threshold, e.x.
+    /// `temperature:Kitchen > 24`. Whether it's met is kept between evaluations.
+    Threshold {
+        source: String,
+        name: String,
+        threshold: Threshold,
+        state: Cell<ThresholdState>,
+    },
+    /// The local time is within a window, which may wrap around midnight.
+    During {
+        from: TimeOfDay,
+        to: TimeOfDay,
+    },
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+/// A limit on a value, e.x. a temperature above 24°C.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Threshold {
+    /// Whether the value has to be above the limit, rather than below.
+    pub above: bool,
+    pub limit: f64,
+    /// How far the value has to move back past the limit before the threshold is left.
+    pub hysteresis: f64,
+    /// How long the value has to stay past the limit before the threshold is met.
+    pub hold: Duration,
+}
+
+impl Threshold {
+    /// Whether a value is past the limit.
+    fn is_past(&self, value: f64) -> bool {
+        if self.above {
+            value > self.limit
+        } else {
+            value < self.limit
+        }
+    }
+
+    /// Whether a value moved back far enough to leave the threshold.
+    fn is_back(&self, value: f64) -> bool {
+        if self.above {
+            value <= self.limit - self.hysteresis
+        } else {
+            value >= self.limit + self.hysteresis
+        }
+    }
+}
+
+/// Whether a threshold is met, and since when the value has been past it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ThresholdState {
+    pub met: bool,
+    pub since: Option<Instant>,
+}
+
+impl ThresholdState {
+    /// The state after a new value. A missing value, e.x. from an unreachable sensor, leaves the
+    /// state as is.
+    pub fn update(self, threshold: &Threshold, value: Option<f64>, now: Instant) -> ThresholdState {
+        let value = match value {
+            Some(value) => value,
+            None => return self,
+        };
+
+        if self.met {
+            if threshold.is_back(value) {
+                return ThresholdState::default();
+            }
+            return self;
+        }
+
+        if !threshold.is_past(value) {
+            return ThresholdState::default();
+        }
+
+        let since = self.since.unwrap_or(now);
+        ThresholdState {
+            met: now.duration_since(since) >= threshold.hold,
+            since: Some(since),
+        }
+    }
+}
+
+/// The state a condition is evaluated against.
+pub trait Context {
+    /// Whether an event source is currently active for an entity.
+    fn is_active(&self, source: &str, name: &str) -> bool;
+
+    /// The current local time.
+    fn time(&self) -> TimeOfDay;
+
+    /// The current value of an event source for an entity, e.x. a temperature, if it has one.
+    fn value(&self, _source: &str, _name: &str) -> Option<f64> {
+        None
+    }
+
+    /// The current instant, to time how long values have been past thresholds.
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Above,
+    Below,
+    And,
+    Or,
+    Not,
+    Word(String),
+}
+
+impl Condition {
+    /// Parses a condition expression.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rusty_hue::condition::Condition;
+    ///
+    /// let condition = Condition::parse("motion:Hallway and not flag:vacation").unwrap();
+    ///
+    /// assert_eq!(condition.references(), vec![("motion", "Hallway"), ("flag", "vacation")]);
+    /// ```
+    pub fn parse(expression: &str) -> Result<Condition, Box<dyn Error>> {
+        let mut tokens = tokenize(expression)?.into_iter().peekable();
+        let condition = parse_or(&mut tokens, expression)?;
+
+        match tokens.next() {
+            None => Ok(condition),
+            Some(token) => Err(invalid(expression, &format!("unexpected {:?}", token))),
+        }
+    }
+
+    /// Evaluates the condition against the current state. Every threshold is updated with its
+    /// current value, including those not needed to decide the outcome, so their timing stays
+    /// right.
+    pub fn evaluate<C: Context>(&self, context: &C) -> bool {
+        self.update_thresholds(context, context.now());
+        self.holds(context)
+    }
+
+    /// Helper function to update the state of every threshold.
+    fn update_thresholds<C: Context>(&self, context: &C, now: Instant) {
+        match *self {
+            Condition::Threshold {
+                ref source,
+                ref name,
+                ref threshold,
+                ref state,
+            } => state.set(
+                state
+                    .get()
+                    .update(threshold, context.value(source, name), now),
+            ),
+            Condition::Not(ref inner) => inner.update_thresholds(context, now),
+            Condition::And(ref left, ref right) | Condition::Or(ref left, ref right) => {
+                left.update_thresholds(context, now);
+                right.update_thresholds(context, now);
+            }
+            Condition::Active { .. } | Condition::During { .. } => (),
+        }
+    }
+
+    /// Helper function to evaluate the condition once thresholds are updated.
+    fn holds<C: Context>(&self, context: &C) -> bool {
+        match *self {
+            Condition::Active {
+                ref source,
+                ref name,
+            } => context.is_active(source, name),
+            Condition::Threshold { ref state, .. } => state.get().met,
+            Condition::During { from, to } => {
+                let now = context.time();
+                if from <= to {
+                    from <= now && now < to
+                } else {
+                    now >= from || now < to
+                }
+            }
+            Condition::Not(ref inner) => !inner.holds(context),
+            Condition::And(ref left, ref right) => left.holds(context) && right.holds(context),
+            Condition::Or(ref left, ref right) => left.holds(context) || right.holds(context),
+        }
+    }
+
+    /// The event sources and entities the condition refers to, in order of appearance.
+    pub fn references(&self) -> Vec<(&str, &str)> {
+        match *self {
+            Condition::Active {
+                ref source,
+                ref name,
+            }
+            | Condition::Threshold {
+                ref source,
+                ref name,
+                ..
+            } => vec![(source.as_str(), name.as_str())],
+            Condition::During { .. } => Vec::new(),
+            Condition::Not(ref inner) => inner.references(),
+            Condition::And(ref left, ref right) | Condition::Or(ref left, ref right) => {
+                let mut references = left.references();
+                references.extend(right.references());
+                references
+            }
+        }
+    }
+}
+
+/// Helper function to build a parse error for an expression.
+fn invalid(expression: &str, reason: &str) -> Box<dyn Error> {
+    From::from(format!("Invalid condition '{}': {}.", expression, reason))
+}
+
+/// Helper function to split an expression into tokens. Double quotes allow names with spaces, e.x.
+/// `motion:"Living room"`.
+fn tokenize(expression: &str) -> Result<Vec<Token>, Box<dyn Error>> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            ' ' | '\t' | '\n' => {
+                chars.next();
+            }
+            '(' | ')' | '!' | '<' | '>' => {
+                chars.next();
+                tokens.push(match c {
+                    '(' => Token::Open,
+                    ')' => Token::Close,
+                    '<' => Token::Below,
+                    '>' => Token::Above,
+                    _ => Token::Not,
+                });
+            }
+            '&' | '|' => {
+                chars.next();
+                if chars.next() != Some(c) {
+                    return Err(invalid(expression, &format!("use '{}{}'", c, c)));
+                }
+                tokens.push(if c == '&' { Token::And } else { Token::Or });
+            }
+            _ => {
+                let mut word = String::new();
+
+                while let Some(&c) = chars.peek() {
+                    match c {
+                        ' ' | '\t' | '\n' | '(' | ')' | '!' | '&' | '|' | '<' | '>' => break,
+                        '"' => {
+                            chars.next();
+                            loop {
+                                match chars.next() {
+                                    Some('"') => break,
+                                    Some(c) => word.push(c),
+                                    None => return Err(invalid(expression, "unclosed quote")),
+                                }
+                            }
+                        }
+                        _ => {
+                            word.push(c);
+                            chars.next();
+                        }
+                    }
+                }
+
+                tokens.push(match word.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Word(word),
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+type Tokens = Peekable<IntoIter<Token>>;
+
+/// Helper function to parse terms joined by `||`, which binds loosest.
+fn parse_or(tokens: &mut Tokens, expression: &str) -> Result<Condition, Box<dyn Error>> {
+    let mut condition = parse_and(tokens, expression)?;
+
+    while tokens.peek() == Some(&Token::Or) {
+        tokens.next();
+        let right = parse_and(tokens, expression)?;
+        condition = Condition::Or(Box::new(condition), Box::new(right));
+    }
+
+    Ok(condition)
+}
+
+/// Helper function to parse terms joined by `&&`.
+fn parse_and(tokens: &mut Tokens, expression: &str) -> Result<Condition, Box<dyn Error>> {
+    let mut condition = parse_not(tokens, expression)?;
+
+    while tokens.peek() == Some(&Token::And) {
+        tokens.next();
+        let right = parse_not(tokens, expression)?;
+        condition = Condition::And(Box::new(condition), Box::new(right));
+    }
+
+    Ok(condition)
+}
+
+/// Helper function to parse a possibly negated term.
+fn parse_not(tokens: &mut Tokens, expression: &str) -> Result<Condition, Box<dyn Error>> {
+    match tokens.next() {
+        Some(Token::Not) => Ok(Condition::Not(Box::new(parse_not(tokens, expression)?))),
+        Some(Token::Open) => {
+            let condition = parse_or(tokens, expression)?;
+            match tokens.next() {
+                Some(Token::Close) => Ok(condition),
+                _ => Err(invalid(expression, "missing ')'")),
+            }
+        }
+        Some(Token::Word(ref word)) if word == "time" => parse_window(tokens, expression),
+        Some(Token::Word(word)) => match word.find(':') {
+            Some(i) if i > 0 && i + 1 < word.len() => {
+                let (source, name) = (word[..i].to_string(), word[i + 1..].to_string());
+                match tokens.peek() {
+                    Some(&Token::Above) | Some(&Token::Below) => {
+                        parse_threshold(source, name, tokens, expression)
+                    }
+                    _ => Ok(Condition::Active { source, name }),
+                }
+            }
+            _ => Err(invalid(
+                expression,
+                &format!("'{}' should be written as source:name", word),
+            )),
+        },
+        Some(token) => Err(invalid(expression, &format!("unexpected {:?}", token))),
+        None => Err(invalid(expression, "missing term at the end")),
+    }
+}
+
+/// Helper function to parse the rest of a threshold, e.x. `< 20 for 5m hysteresis 10`.
+fn parse_threshold(
+    source: String,
+    name: String,
+    tokens: &mut Tokens,
+    expression: &str,
+) -> Result<Condition, Box<dyn Error>> {
+    let above = tokens.next() == Some(Token::Above);
+    let mut threshold = Threshold {
+        above,
+        limit: parse_number(tokens.next(), expression)?,
+        hysteresis: 0.0,
+        hold: Duration::from_secs(0),
+    };
+
+    loop {
+        match tokens.peek() {
+            Some(Token::Word(ref word)) if word == "for" => {
+                tokens.next();
+                threshold.hold = match tokens.next() {
+                    Some(Token::Word(duration)) => parse::parse_duration(&duration)?,
+                    _ => return Err(invalid(expression, "expected a duration after 'for'")),
+                };
+            }
+            Some(Token::Word(ref word)) if word == "hysteresis" => {
+                tokens.next();
+                threshold.hysteresis = parse_number(tokens.next(), expression)?.abs();
+            }
+            _ => break,
+        }
+    }
+
+    Ok(Condition::Threshold {
+        source,
+        name,
+        threshold,
+        state: Cell::new(ThresholdState::default()),
+    })
+}
+
+/// Helper function to parse a number of a threshold.
+fn parse_number(token: Option<Token>, expression: &str) -> Result<f64, Box<dyn Error>> {
+    match token {
+        Some(Token::Word(ref word)) => word
+            .parse()
+            .map_err(|_| invalid(expression, &format!("'{}' is not a number", word))),
+        _ => Err(invalid(expression, "expected a number")),
+    }
+}
+
+/// Helper function to parse the rest of a time window, e.x. `in 22:00-06:00`.
+fn parse_window(tokens: &mut Tokens, expression: &str) -> Result<Condition, Box<dyn Error>> {
+    let window = match (tokens.next(), tokens.next()) {
+        (Some(Token::Word(ref keyword)), Some(Token::Word(window))) if keyword == "in" => window,
+        _ => return Err(invalid(expression, "expected 'time in HH:MM-HH:MM'")),
+    };
+
+    let mut times = window.splitn(2, '-');
+    match (times.next(), times.next()) {
+        (Some(from), Some(to)) => Ok(Condition::During {
+            from: parse::parse_time_of_day(from)?,
+            to: parse::parse_time_of_day(to)?,
+        }),
+        _ => Err(invalid(expression, "expected 'time in HH:MM-HH:MM'")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct State {
+        active: Vec<(&'static str, &'static str)>,
+        time: TimeOfDay,
+        value: Option<f64>,
+        now: Instant,
+    }
+
+    impl Context for State {
+        fn is_active(&self, source: &str, name: &str) -> bool {
+            self.active.contains(&(source, name))
+        }
+
+        fn time(&self) -> TimeOfDay {
+            self.time
+        }
+
+        fn value(&self, _source: &str, _name: &str) -> Option<f64> {
+            self.value
+        }
+
+        fn now(&self) -> Instant {
+            self.now
+        }
+    }
+
+    fn state(active: Vec<(&'static str, &'static str)>, time: TimeOfDay) -> State {
+        State {
+            active,
+            time,
+            value: None,
+            now: Instant::now(),
+        }
+    }
+
+    fn at(hour: u8) -> TimeOfDay {
+        TimeOfDay {
+            hour,
+            minute: 0,
+            second: 0,
+        }
+    }
+
+    #[test]
+    fn evaluate_conditions() {
+        let condition =
+            Condition::parse("motion:Hallway && !flag:vacation && time in 22:00-06:00").unwrap();
+
+        let mut state = state(vec![("motion", "Hallway")], at(23));
+        assert!(condition.evaluate(&state));
+
+        state.time = at(12);
+        assert!(!condition.evaluate(&state));
+
+        state.time = at(2);
+        state.active.push(("flag", "vacation"));
+        assert!(!condition.evaluate(&state));
+    }
+
+    #[test]
+    fn precedence_and_quotes() {
+        let condition =
+            Condition::parse(r#"flag:a or flag:b and not (motion:"Living room")"#).unwrap();
+
+        assert_eq!(
+            condition.references(),
+            vec![("flag", "a"), ("flag", "b"), ("motion", "Living room")]
+        );
+        match condition {
+            Condition::Or(_, right) => match *right {
+                Condition::And(..) => (),
+                other => panic!("expected and, got {:?}", other),
+            },
+            other => panic!("expected or, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn thresholds_hold_and_hysteresis() {
+        let condition =
+            Condition::parse("lightlevel:Desk<20 for 5m hysteresis 10 && !flag:away").unwrap();
+        assert_eq!(
+            condition.references(),
+            vec![("lightlevel", "Desk"), ("flag", "away")]
+        );
+
+        let mut state = state(vec![("flag", "away")], at(18));
+        let start = state.now;
+        let minutes = |m: u64| start + Duration::from_secs(m * 60);
+
+        // Timed even while the rest of the condition doesn't hold.
+        state.value = Some(15.0);
+        assert!(!condition.evaluate(&state));
+
+        state.active.clear();
+        state.now = minutes(4);
+        assert!(!condition.evaluate(&state));
+
+        state.now = minutes(5);
+        assert!(condition.evaluate(&state));
+
+        // Within the hysteresis, or with no reading, the threshold stays met.
+        state.value = Some(25.0);
+        assert!(condition.evaluate(&state));
+        state.value = None;
+        assert!(condition.evaluate(&state));
+
+        state.value = Some(30.0);
+        assert!(!condition.evaluate(&state));
+
+        // Dipping back below restarts the hold.
+        state.value = Some(10.0);
+        state.now = minutes(6);
+        assert!(!condition.evaluate(&state));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Condition::parse("motion:Hallway &").is_err());
+        assert!(Condition::parse("motion:Hallway &&").is_err());
+        assert!(Condition::parse("(motion:Hallway").is_err());
+        assert!(Condition::parse("Hallway").is_err());
+        assert!(Condition::parse("time in 22:00").is_err());
+        assert!(Condition::parse("motion:\"Hall").is_err());
+        assert!(Condition::parse("temperature:Kitchen > warm").is_err());
+        assert!(Condition::parse("temperature:Kitchen > 24 for").is_err());
+    }
+}
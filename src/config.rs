@@ -0,0 +1,807 @@
+//! # config
+//!
+//! Layered configuration. Each setting starts from its built-in default and is overridden in turn
+//! by $HOME/.config/rusty_hue/config.json, by its environment variable, and by command line flags.
+//! The layer each value came from is kept so the effective configuration can be explained.
+//!
+//! The config file also remembers the bridge: pairing saves the token to it, and the address and
+//! id of a discovered bridge are saved so later runs skip discovery.
+
+use serde_json;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use bridges::Bridge;
+use files;
+use parse;
+
+/// A known setting with its environment variable, default value, and the values it takes.
+struct Definition {
+    key: &'static str,
+    env: &'static str,
+    default: Option<&'static str>,
+    secret: bool,
+    /// Whether a value is valid for the setting.
+    check: fn(&str) -> bool,
+    /// What a valid value is, for errors, e.x. "true or false".
+    expected: &'static str,
+}
+
+/// All known settings.
+const DEFINITIONS: [Definition; 31] = [
+    Definition {
+        key: "bridge",
+        env: "RUSTY_HUE_BRIDGE",
+        default: None,
+        secret: false,
+        check: is_set,
+        expected: "a bridge name from bridges.json",
+    },
+    Definition {
+        key: "bridge_ip",
+        env: "RUSTY_HUE_BRIDGE_IP",
+        default: None,
+        secret: false,
+        check: is_host,
+        expected: "an address, e.x. \"192.168.1.2\" or \"hue.local:8080\"",
+    },
+    Definition {
+        key: "bridge_id",
+        env: "RUSTY_HUE_BRIDGE_ID",
+        default: None,
+        secret: false,
+        check: is_set,
+        expected: "the id of a bridge",
+    },
+    Definition {
+        key: "token",
+        env: "RUSTY_HUE_TOKEN",
+        default: None,
+        secret: true,
+        check: is_set,
+        expected: "the token from pairing",
+    },
+    Definition {
+        key: "cache_file",
+        env: "RUSTY_HUE_CACHE_FILE",
+        default: Some("~/.cache/rusty_hue/state.json"),
+        secret: false,
+        check: is_file,
+        expected: "the path of a file",
+    },
+    Definition {
+        key: "cache_ttl",
+        env: "RUSTY_HUE_CACHE_TTL",
+        default: Some("60s"),
+        secret: false,
+        check: is_duration,
+        expected: "a duration, e.x. \"60s\"",
+    },
+    Definition {
+        key: "output",
+        env: "RUSTY_HUE_OUTPUT",
+        default: Some("changed"),
+        secret: false,
+        check: is_output,
+        expected: "changed, summary, or verbose",
+    },
+    Definition {
+        key: "unauthorized",
+        env: "RUSTY_HUE_UNAUTHORIZED",
+        default: Some("ask"),
+        secret: false,
+        check: is_unauthorized,
+        expected: "ask, pair, or exit",
+    },
+    Definition {
+        key: "ipc_socket",
+        env: "RUSTY_HUE_IPC_SOCKET",
+        default: Some("~/.config/rusty_hue/ipc.sock"),
+        secret: false,
+        check: is_file,
+        expected: "the path of a file",
+    },
+    Definition {
+        key: "battery_threshold",
+        env: "RUSTY_HUE_BATTERY_THRESHOLD",
+        default: Some("20"),
+        secret: false,
+        check: is_percentage,
+        expected: "a percentage from 0 to 100",
+    },
+    Definition {
+        key: "battery_webhook",
+        env: "RUSTY_HUE_BATTERY_WEBHOOK",
+        default: None,
+        secret: false,
+        check: is_url,
+        expected: "an http or https URL",
+    },
+    Definition {
+        key: "lang",
+        env: "RUSTY_HUE_LANG",
+        default: None,
+        secret: false,
+        check: is_set,
+        expected: "a language code, e.x. \"de\"",
+    },
+    Definition {
+        key: "strict",
+        env: "RUSTY_HUE_STRICT",
+        default: Some("false"),
+        secret: false,
+        check: is_flag,
+        expected: "true or false",
+    },
+    Definition {
+        key: "serve_address",
+        env: "RUSTY_HUE_SERVE_ADDRESS",
+        default: Some("127.0.0.1:8080"),
+        secret: false,
+        check: is_socket_address,
+        expected: "an address and port, e.x. \"127.0.0.1:8080\"",
+    },
+    Definition {
+        key: "proxy_address",
+        env: "RUSTY_HUE_PROXY_ADDRESS",
+        default: Some("0.0.0.0:8081"),
+        secret: false,
+        check: is_socket_address,
+        expected: "an address and port, e.x. \"0.0.0.0:8081\"",
+    },
+    Definition {
+        key: "verify",
+        env: "RUSTY_HUE_VERIFY",
+        default: Some("false"),
+        secret: false,
+        check: is_flag,
+        expected: "true or false",
+    },
+    Definition {
+        key: "verify_retries",
+        env: "RUSTY_HUE_VERIFY_RETRIES",
+        default: Some("2"),
+        secret: false,
+        check: is_count,
+        expected: "a whole number",
+    },
+    Definition {
+        key: "progress",
+        env: "RUSTY_HUE_PROGRESS",
+        default: Some("true"),
+        secret: false,
+        check: is_flag,
+        expected: "true or false",
+    },
+    Definition {
+        key: "color_names",
+        env: "RUSTY_HUE_COLOR_NAMES",
+        default: Some("true"),
+        secret: false,
+        check: is_flag,
+        expected: "true or false",
+    },
+    Definition {
+        key: "max_flash_rate",
+        env: "RUSTY_HUE_MAX_FLASH_RATE",
+        default: Some("3"),
+        secret: false,
+        check: is_rate,
+        expected: "a number of flashes a second above 0",
+    },
+    Definition {
+        key: "store",
+        env: "RUSTY_HUE_STORE",
+        default: Some("json"),
+        secret: false,
+        check: is_store,
+        expected: "json or sqlite",
+    },
+    Definition {
+        key: "timeout",
+        env: "RUSTY_HUE_TIMEOUT",
+        default: Some("30s"),
+        secret: false,
+        check: is_timeout,
+        expected: "a duration longer than 0s, e.x. \"30s\"",
+    },
+    Definition {
+        key: "retries",
+        env: "RUSTY_HUE_RETRIES",
+        default: Some("2"),
+        secret: false,
+        check: is_count,
+        expected: "a whole number",
+    },
+    Definition {
+        key: "no_cloud",
+        env: "RUSTY_HUE_NO_CLOUD",
+        default: Some("false"),
+        secret: false,
+        check: is_flag,
+        expected: "true or false",
+    },
+    Definition {
+        key: "parallel_requests",
+        env: "RUSTY_HUE_PARALLEL_REQUESTS",
+        default: None,
+        secret: false,
+        check: is_parallel,
+        expected: "a number of requests above 0",
+    },
+    Definition {
+        key: "frame_rate",
+        env: "RUSTY_HUE_FRAME_RATE",
+        default: None,
+        secret: false,
+        check: is_rate,
+        expected: "a number of light commands a second above 0",
+    },
+    Definition {
+        key: "transition",
+        env: "RUSTY_HUE_TRANSITION",
+        default: None,
+        secret: false,
+        check: is_duration,
+        expected: "a duration, e.x. \"400ms\"",
+    },
+    Definition {
+        key: "favorite_group",
+        env: "RUSTY_HUE_FAVORITE_GROUP",
+        default: None,
+        secret: false,
+        check: is_set,
+        expected: "the name or id of a group",
+    },
+    Definition {
+        key: "colors_file",
+        env: "RUSTY_HUE_COLORS_FILE",
+        default: Some("~/.config/rusty_hue/colors.json"),
+        secret: false,
+        check: is_file,
+        expected: "the path of a file",
+    },
+    Definition {
+        key: "api",
+        env: "RUSTY_HUE_API",
+        default: Some("auto"),
+        secret: false,
+        check: is_api,
+        expected: "auto, v1, or v2",
+    },
+    Definition {
+        key: "bridge_ca",
+        env: "RUSTY_HUE_BRIDGE_CA",
+        default: Some("~/.config/rusty_hue/bridge_ca.pem"),
+        secret: false,
+        check: is_file,
+        expected: "the path of a PEM file",
+    },
+];
+
+/// The layer a setting's value came from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Default,
+    File,
+    Environment(&'static str),
+    Flag,
+    /// A bridge in bridges.json, selected by name.
+    Bridge(String),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Source::Default => f.write_str("default"),
+            Source::File => f.write_str("config file"),
+            Source::Environment(name) => write!(f, "environment ({})", name),
+            Source::Flag => f.write_str("command line flag"),
+            Source::Bridge(ref name) => write!(f, "bridge '{}' in bridges.json", name),
+        }
+    }
+}
+
+/// The effective value of a setting and where it came from.
+#[derive(Debug)]
+pub struct Setting {
+    pub value: Option<String>,
+    pub source: Source,
+}
+
+/// The merged configuration.
+#[derive(Debug)]
+pub struct Config {
+    settings: BTreeMap<&'static str, Setting>,
+}
+
+impl Config {
+    /// Loads the configuration from the defaults, the config file, and the environment. Flags are
+    /// applied afterwards with `set_flag`.
+    pub fn load() -> Result<Config, Box<dyn Error>> {
+        let file = read_config_file()?;
+        Config::layered(&file, |name| env::var(name).ok())
+    }
+
+    /// Helper function for merging the default, file, and environment layers.
+    fn layered<F>(file: &BTreeMap<String, Value>, env: F) -> Result<Config, Box<dyn Error>>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        if let Some(key) = file.keys().find(|key| definition(key).is_none()) {
+            return Err(From::from(format!(
+                "Unknown setting '{}' in {}.",
+                key,
+                config_path()?.display()
+            )));
+        }
+
+        let mut settings = BTreeMap::new();
+
+        for def in DEFINITIONS.iter() {
+            let mut setting = Setting {
+                value: def.default.map(String::from),
+                source: Source::Default,
+            };
+
+            if let Some(value) = file.get(def.key) {
+                setting.value = match *value {
+                    Value::Null => None,
+                    Value::String(ref s) => Some(s.clone()),
+                    ref other => Some(other.to_string()),
+                };
+                setting.source = Source::File;
+            }
+
+            if let Some(value) = env(def.env) {
+                setting.value = Some(value);
+                setting.source = Source::Environment(def.env);
+            }
+
+            settings.insert(def.key, setting);
+        }
+
+        Ok(Config { settings })
+    }
+
+    /// Overrides a setting with a value given on the command line.
+    pub fn set_flag(&mut self, key: &str, value: &str) -> Result<(), Box<dyn Error>> {
+        self.set(key, value, Source::Flag)
+    }
+
+    /// Uses the address, token, and settings of a bridge from bridges.json, overriding any
+    /// others.
+    pub fn set_bridge(&mut self, bridge: &Bridge) -> Result<(), Box<dyn Error>> {
+        let source = Source::Bridge(bridge.name.clone());
+
+        for (key, value) in &bridge.settings {
+            if definition(key).is_none() || ["bridge", "bridge_ip", "token"].contains(&&key[..]) {
+                return Err(From::from(format!(
+                    "Unknown setting '{}' for bridge '{}' in bridges.json.",
+                    key, bridge.name
+                )));
+            }
+
+            let value = match *value {
+                Value::String(ref s) => s.clone(),
+                ref other => other.to_string(),
+            };
+            self.set(key, &value, source.clone())?;
+        }
+
+        self.set("bridge_ip", &bridge.ip, source.clone())?;
+        self.set("token", &bridge.token, source)
+    }
+
+    /// Saves the address and id of a discovered bridge to the config file, so later runs connect
+    /// to it without discovery, and uses them. Returns the path of the config file.
+    pub fn remember_bridge(
+        &mut self,
+        ip: &str,
+        id: Option<&str>,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        let path = save_settings(&[("bridge_ip", Some(ip)), ("bridge_id", id)])?;
+
+        self.set("bridge_ip", ip, Source::File)?;
+        if let Some(id) = id {
+            self.set("bridge_id", id, Source::File)?;
+        }
+        Ok(path)
+    }
+
+    /// Name of the bridge from bridges.json in use, if one was picked.
+    pub fn bridge_name(&self) -> Option<&str> {
+        match self.settings.get("token").map(|setting| &setting.source) {
+            Some(Source::Bridge(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Helper function to override a setting.
+    fn set(&mut self, key: &str, value: &str, source: Source) -> Result<(), Box<dyn Error>> {
+        match self.settings.get_mut(key) {
+            Some(setting) => {
+                setting.value = Some(value.to_string());
+                setting.source = source;
+                Ok(())
+            }
+            None => Err(From::from(format!("Unknown setting '{}'.", key))),
+        }
+    }
+
+    /// Returns the layer the value of a setting came from.
+    pub fn source(&self, key: &str) -> Option<&Source> {
+        self.settings.get(key).map(|setting| &setting.source)
+    }
+
+    /// Returns the effective value of a setting, if it has one.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.settings
+            .get(key)
+            .and_then(|setting| setting.value.as_deref())
+    }
+
+    /// Returns the effective value of a setting holding a path, expanding a leading "~/" to the
+    /// home directory.
+    pub fn path(&self, key: &str) -> Option<PathBuf> {
+        expand_path(self.get(key)?)
+    }
+
+    /// Checks the effective value of every setting, with the same checks as `check_value`, so a
+    /// bad value is reported with where it came from before it's used.
+    pub fn check(&self) -> Result<(), Box<dyn Error>> {
+        for def in DEFINITIONS.iter() {
+            let setting = &self.settings[def.key];
+            if let Some(ref value) = setting.value {
+                if !(def.check)(value) {
+                    return Err(From::from(format!(
+                        "Invalid value '{}' for '{}' from the {}, expected {}.",
+                        value, def.key, setting.source, def.expected
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Iterates over all settings in alphabetical order.
+    pub fn settings(&self) -> impl Iterator<Item = (&str, &Setting)> {
+        self.settings.iter().map(|(key, setting)| (*key, setting))
+    }
+}
+
+/// Formats a setting's value for display, hiding most of secret values such as the token.
+pub fn display_value(key: &str, value: Option<&str>) -> String {
+    match value {
+        None => String::from("(not set)"),
+        Some(value) if definition(key).is_some_and(|def| def.secret) => {
+            let shown: String = value.chars().take(4).collect();
+            format!("{}... (hidden)", shown)
+        }
+        Some(value) => value.to_string(),
+    }
+}
+
+/// Checks a value before it is saved for a setting, so a typo is caught when setting it rather
+/// than on the next run.
+pub fn check_value(key: &str, value: &str) -> Result<(), Box<dyn Error>> {
+    let def = match definition(key) {
+        Some(def) => def,
+        None => return Err(From::from(format!("Unknown setting '{}'.", key))),
+    };
+
+    if (def.check)(value) {
+        Ok(())
+    } else {
+        Err(From::from(format!(
+            "Invalid value '{}' for '{}', expected {}.",
+            value, key, def.expected
+        )))
+    }
+}
+
+/// Helper function to check a setting that takes any value but an empty one.
+fn is_set(value: &str) -> bool {
+    !value.trim().is_empty()
+}
+
+/// Helper function to check an address of the bridge: an IP address or a host name, either with
+/// an optional port.
+fn is_host(value: &str) -> bool {
+    if value.parse::<IpAddr>().is_ok() || value.parse::<SocketAddr>().is_ok() {
+        return true;
+    }
+
+    let (host, port) = match value.rsplit_once(':') {
+        Some((host, port)) => (host, Some(port)),
+        None => (value, None),
+    };
+    !host.is_empty()
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+        && port.is_none_or(|port| port.parse::<u16>().is_ok())
+}
+
+/// Helper function to check an address and port to listen on.
+fn is_socket_address(value: &str) -> bool {
+    value.parse::<SocketAddr>().is_ok()
+}
+
+/// Helper function to check the path of a file, which may not exist yet but can't be a directory.
+fn is_file(value: &str) -> bool {
+    is_set(value) && expand_path(value).is_some_and(|path| !path.is_dir())
+}
+
+/// Helper function to check a URL requests are sent to.
+fn is_url(value: &str) -> bool {
+    value.starts_with("http://") || value.starts_with("https://")
+}
+
+/// Helper function to check a duration, e.x. "400ms".
+fn is_duration(value: &str) -> bool {
+    parse::parse_duration(value).is_ok()
+}
+
+/// Helper function to check the timeout of requests, which has to be longer than 0s.
+fn is_timeout(value: &str) -> bool {
+    parse::parse_duration(value).is_ok_and(|timeout| timeout > Duration::from_secs(0))
+}
+
+/// Helper function to check "true" or "false".
+fn is_flag(value: &str) -> bool {
+    value == "true" || value == "false"
+}
+
+/// Helper function to check a whole number.
+fn is_count(value: &str) -> bool {
+    value.parse::<u32>().is_ok()
+}
+
+/// Helper function to check a percentage.
+fn is_percentage(value: &str) -> bool {
+    value
+        .parse::<u8>()
+        .is_ok_and(|percentage| percentage <= 100)
+}
+
+/// Helper function to check the number of requests sent at once, which can't be 0.
+fn is_parallel(value: &str) -> bool {
+    value.parse::<usize>().is_ok_and(|parallel| parallel > 0)
+}
+
+/// Helper function to check a rate a second, which has to be above 0.
+fn is_rate(value: &str) -> bool {
+    value
+        .parse::<f32>()
+        .is_ok_and(|rate| rate.is_finite() && rate > 0.0)
+}
+
+/// Helper function to check the output setting.
+fn is_output(value: &str) -> bool {
+    ["changed", "summary", "verbose"].contains(&value)
+}
+
+/// Helper function to check the unauthorized setting.
+fn is_unauthorized(value: &str) -> bool {
+    ["ask", "pair", "exit"].contains(&value)
+}
+
+/// Helper function to check the store setting.
+fn is_store(value: &str) -> bool {
+    ["json", "sqlite"].contains(&value)
+}
+
+/// Helper function to check the api setting.
+fn is_api(value: &str) -> bool {
+    ["auto", "v1", "v2"].contains(&value)
+}
+
+/// Helper function to expand a leading "~/" of a path to the home directory.
+fn expand_path(value: &str) -> Option<PathBuf> {
+    if let Some(rest) = value.strip_prefix("~/") {
+        env::home_dir().map(|home| home.join(rest))
+    } else {
+        Some(PathBuf::from(value))
+    }
+}
+
+/// Helper function to look up the definition of a known setting.
+fn definition(key: &str) -> Option<&'static Definition> {
+    DEFINITIONS.iter().find(|def| def.key == key)
+}
+
+/// Location of the config file: $HOME/.config/rusty_hue/config.json.
+pub fn config_path() -> Result<PathBuf, Box<dyn Error>> {
+    files::config_file("config.json")
+}
+
+/// Reads the settings stored in the config file. A missing file holds no settings.
+pub fn read_config_file() -> Result<BTreeMap<String, Value>, Box<dyn Error>> {
+    let mut f = match File::open(config_path()?) {
+        Ok(f) => f,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(From::from(e)),
+    };
+
+    let mut contents = String::new();
+    f.read_to_string(&mut contents)?;
+
+    let settings: BTreeMap<String, Value> = serde_json::from_str(&contents)?;
+
+    Ok(settings)
+}
+
+/// Changes settings in the config file, keeping the others. A setting without a value is left
+/// as is. Returns the path of the config file.
+pub fn save_settings(changes: &[(&str, Option<&str>)]) -> Result<PathBuf, Box<dyn Error>> {
+    let mut settings = read_config_file()?;
+
+    for &(key, value) in changes {
+        if definition(key).is_none() {
+            return Err(From::from(format!("Unknown setting '{}'.", key)));
+        }
+        if let Some(value) = value {
+            settings.insert(key.to_string(), Value::from(value));
+        }
+    }
+
+    let path = config_path()?;
+    files::write_atomic(&path, serde_json::to_string_pretty(&settings)?.as_bytes())?;
+    Ok(path)
+}
+
+/// Removes settings from the config file, so they fall back to their defaults. Returns the path
+/// of the config file.
+pub fn remove_settings(keys: &[&str]) -> Result<PathBuf, Box<dyn Error>> {
+    let mut settings = read_config_file()?;
+
+    for key in keys {
+        if definition(key).is_none() {
+            return Err(From::from(format!("Unknown setting '{}'.", key)));
+        }
+        settings.remove(*key);
+    }
+
+    let path = config_path()?;
+    files::write_atomic(&path, serde_json::to_string_pretty(&settings)?.as_bytes())?;
+    Ok(path)
+}
+
+/// Loads command aliases from $HOME/.config/rusty_hue/aliases.json, which maps each alias to the
+/// commands it runs in order, e.x. {"goodnight": ["-s 'all - room:Bedroom' off", "scene
+/// Nightlight"]}. A missing file means no aliases are defined.
+pub fn load_aliases() -> Result<BTreeMap<String, Vec<String>>, Box<dyn Error>> {
+    let mut f = match File::open(files::config_file("aliases.json")?) {
+        Ok(f) => f,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(From::from(e)),
+    };
+
+    let mut contents = String::new();
+    f.read_to_string(&mut contents)?;
+
+    let aliases: BTreeMap<String, Vec<String>> = serde_json::from_str(&contents)?;
+
+    Ok(aliases)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn layer_precedence() {
+        let mut file = BTreeMap::new();
+        file.insert(String::from("bridge_ip"), Value::from("10.0.0.2"));
+        file.insert(String::from("token"), Value::from("from-file"));
+
+        let mut config = Config::layered(&file, |name| match name {
+            "RUSTY_HUE_TOKEN" => Some(String::from("from-env")),
+            _ => None,
+        })
+        .unwrap();
+
+        assert_eq!(config.get("bridge_ip"), Some("10.0.0.2"));
+        assert_eq!(config.settings["bridge_ip"].source, Source::File);
+        assert_eq!(config.get("token"), Some("from-env"));
+        assert_eq!(
+            config.settings["token"].source,
+            Source::Environment("RUSTY_HUE_TOKEN")
+        );
+        assert_eq!(config.settings["ipc_socket"].source, Source::Default);
+
+        config.set_flag("bridge_ip", "10.0.0.3").unwrap();
+        assert_eq!(config.get("bridge_ip"), Some("10.0.0.3"));
+        assert_eq!(config.settings["bridge_ip"].source, Source::Flag);
+        assert!(config.set_flag("nope", "1").is_err());
+
+        let mut bridge = Bridge {
+            name: String::from("upstairs"),
+            id: None,
+            ip: String::from("10.0.0.4"),
+            token: String::from("from-bridge"),
+            settings: BTreeMap::new(),
+        };
+        bridge
+            .settings
+            .insert(String::from("cache_ttl"), Value::from("0s"));
+        config.set_bridge(&bridge).unwrap();
+        assert_eq!(config.get("bridge_ip"), Some("10.0.0.4"));
+        assert_eq!(config.get("cache_ttl"), Some("0s"));
+        assert_eq!(
+            config.settings["token"].source,
+            Source::Bridge(String::from("upstairs"))
+        );
+        assert_eq!(config.bridge_name(), Some("upstairs"));
+
+        bridge
+            .settings
+            .insert(String::from("token"), Value::from("sneaky"));
+        assert!(config.set_bridge(&bridge).is_err());
+    }
+
+    #[test]
+    fn unknown_file_setting() {
+        let mut file = BTreeMap::new();
+        file.insert(String::from("brige_ip"), Value::from("10.0.0.2"));
+
+        assert!(Config::layered(&file, |_| None).is_err());
+    }
+
+    #[test]
+    fn check_values() {
+        assert!(check_value("transition", "400ms").is_ok());
+        assert!(check_value("transition", "fast").is_err());
+        assert!(check_value("verify", "true").is_ok());
+        assert!(check_value("verify", "yes").is_err());
+        assert!(check_value("retries", "-1").is_err());
+        assert!(check_value("output", "verbose").is_ok());
+        assert!(check_value("colors_file", "~/colors.json").is_ok());
+        assert!(check_value("api", "v3").is_err());
+        assert!(check_value("transiton", "400ms").is_err());
+        assert!(check_value("parallel_requests", "0").is_err());
+        assert!(check_value("frame_rate", "inf").is_err());
+        assert!(check_value("timeout", "0s").is_err());
+        assert!(check_value("bridge_ip", "192.168.1.2").is_ok());
+        assert!(check_value("bridge_ip", "hue.local:8080").is_ok());
+        assert!(check_value("bridge_ip", "192.168.1.2:port").is_err());
+        assert!(check_value("bridge_ip", "http://192.168.1.2").is_err());
+        assert!(check_value("favorite_group", "Living room").is_ok());
+        assert!(check_value("favorite_group", " ").is_err());
+        assert!(check_value("colors_file", "").is_err());
+        assert!(check_value("colors_file", &env::temp_dir().to_string_lossy()).is_err());
+    }
+
+    #[test]
+    fn check_effective_values() {
+        let mut file = BTreeMap::new();
+        file.insert(String::from("retries"), Value::from(3));
+        let mut config = Config::layered(&file, |_| None).unwrap();
+        assert!(config.check().is_ok());
+
+        config.set_flag("parallel_requests", "0").unwrap();
+        let e = config.check().unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Invalid value '0' for 'parallel_requests' from the command line flag, expected a \
+             number of requests above 0."
+        );
+    }
+
+    #[test]
+    fn hide_secrets() {
+        assert_eq!(display_value("token", Some("abcdefgh")), "abcd... (hidden)");
+        assert_eq!(display_value("bridge_ip", Some("10.0.0.2")), "10.0.0.2");
+        assert_eq!(display_value("bridge_ip", None), "(not set)");
+    }
+}
@@ -0,0 +1,168 @@
+//! # decode
+//!
+//! Decoding of the bridge's JSON into data structures. By default decoding is lenient: unknown
+//! fields are ignored, missing optional fields are defaulted, and an entry of a collection that
+//! can't be decoded at all is skipped with a warning, so a new field in a firmware update doesn't
+//! break the tool. Strict mode, meant for development, instead fails on unknown fields and
+//! undecodable entries, printing the raw payload to stderr. The mode is given to each call, see
+//! `HueConfig::strict`.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+
+/// Decodes a value, e.x. a light, described by `what` in errors, e.x. "light 3". In strict mode,
+/// fields of the value that the data structure doesn't hold are an error, except the `known` ones
+/// the bridge sends but aren't needed, given by path, e.x. "state.mode".
+pub fn decode<T>(
+    value: &Value,
+    what: &str,
+    known: &[&str],
+    strict: bool,
+) -> Result<T, Box<dyn Error>>
+where
+    T: DeserializeOwned + Serialize,
+{
+    let decoded: T = match serde_json::from_value(value.clone()) {
+        Ok(decoded) => decoded,
+        Err(e) => return Err(rejected(value, what, &e.to_string(), strict)),
+    };
+
+    if strict {
+        let mut unknown = unknown_fields(value, &serde_json::to_value(&decoded)?, "");
+        unknown.retain(|path| !known.contains(&path.as_str()));
+        if !unknown.is_empty() {
+            let reason = format!("unknown field(s) '{}'", unknown.join("', '"));
+            return Err(rejected(value, what, &reason, strict));
+        }
+    }
+
+    Ok(decoded)
+}
+
+/// Decodes an object of values keyed by id, e.x. all groups, described by `what` in errors, e.x.
+/// "group", with `known` fields as for `decode`. Entries that can't be decoded are skipped with a
+/// warning, unless in strict mode.
+pub fn decode_map<T>(
+    value: &Value,
+    what: &str,
+    known: &[&str],
+    strict: bool,
+) -> Result<HashMap<String, T>, Box<dyn Error>>
+where
+    T: DeserializeOwned + Serialize,
+{
+    let entries = match value.as_object() {
+        Some(entries) => entries,
+        None => {
+            return Err(rejected(
+                value,
+                what,
+                "expected an object of entries by id",
+                strict,
+            ))
+        }
+    };
+
+    let mut decoded = HashMap::new();
+
+    for (id, entry) in entries {
+        match decode(entry, &format!("{} {}", what, id), known, strict) {
+            Ok(entry) => {
+                decoded.insert(id.clone(), entry);
+            }
+            Err(e) if strict => return Err(e),
+            Err(e) => esay!("{} Skipping it.", e),
+        }
+    }
+
+    Ok(decoded)
+}
+
+/// Helper function to build the error for a value that couldn't be decoded, printing the raw
+/// value first in strict mode.
+fn rejected(value: &Value, what: &str, reason: &str, strict: bool) -> Box<dyn Error> {
+    if strict {
+        esay!(
+            "Raw {} from the bridge:\n{}",
+            what,
+            serde_json::to_string_pretty(value).unwrap_or_default()
+        );
+    }
+
+    From::from(format!("Unexpected {} from the bridge: {}.", what, reason))
+}
+
+/// Paths of the fields of `raw` that aren't in `decoded`, e.x. "state.effect", looking into
+/// nested objects.
+fn unknown_fields(raw: &Value, decoded: &Value, prefix: &str) -> Vec<String> {
+    let (raw, decoded) = match (raw.as_object(), decoded.as_object()) {
+        (Some(raw), Some(decoded)) => (raw, decoded),
+        _ => return Vec::new(),
+    };
+
+    let mut unknown = Vec::new();
+
+    for (key, value) in raw {
+        let path = format!("{}{}", prefix, key);
+        match decoded.get(key) {
+            Some(decoded) => unknown.extend(unknown_fields(value, decoded, &(path + "."))),
+            None => unknown.push(path),
+        }
+    }
+
+    unknown
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct State {
+        on: bool,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Thing {
+        name: String,
+        state: State,
+    }
+
+    #[test]
+    fn find_unknown_fields() {
+        let raw = json!({"name": "a", "state": {"on": true, "dimming": 3}, "owner": "b"});
+        let decoded = json!({"name": "a", "state": {"on": true}});
+
+        assert_eq!(
+            unknown_fields(&raw, &decoded, ""),
+            vec!["owner", "state.dimming"]
+        );
+    }
+
+    #[test]
+    fn lenient_and_strict() {
+        let things = json!({
+            "1": {"name": "a", "state": {"on": true}, "new": 1},
+            "2": {"name": "b"}
+        });
+
+        let lenient: HashMap<String, Thing> = decode_map(&things, "thing", &[], false).unwrap();
+        assert_eq!(lenient.len(), 1);
+        assert_eq!(lenient["1"].name, "a");
+        assert!(decode_map::<Thing>(&things, "thing", &["new"], true).is_err());
+
+        let strict = decode::<Thing>(&things["1"], "thing 1", &[], true);
+        let known = decode::<Thing>(&things["1"], "thing 1", &["new"], true);
+
+        assert!(known.is_ok());
+
+        assert_eq!(
+            strict.unwrap_err().to_string(),
+            "Unexpected thing 1 from the bridge: unknown field(s) 'new'."
+        );
+    }
+}
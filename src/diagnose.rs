@@ -0,0 +1,138 @@
+//! # diagnose
+//!
+//! Network diagnostics for when the bridge can't be reached. Rather than a bare connection error,
+//! the bridge's address is resolved and its ports probed to tell apart a wrong address, a host
+//! that is down, and a port that is blocked.
+
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// How long to wait for each probe.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Outcome of probing the bridge.
+#[derive(Debug, PartialEq)]
+pub enum Diagnosis {
+    /// The bridge's address couldn't be resolved.
+    Unresolved(String),
+    /// Nothing answered on any port, so the host is down or the address belongs to another
+    /// network.
+    HostDown(String),
+    /// The host answered, but the API port refused or dropped the connection.
+    PortBlocked(String, u16),
+    /// The API port accepted a connection, so the failure wasn't at the network level.
+    Reachable(String),
+}
+
+impl fmt::Display for Diagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Diagnosis::Unresolved(ref host) => write!(
+                f,
+                "Could not resolve the bridge address '{}'. Check the bridge_ip setting.",
+                host
+            ),
+            Diagnosis::HostDown(ref host) => write!(
+                f,
+                "No response from the bridge at {}. Check that it's powered on and on this \
+                 network, or that its IP address hasn't changed (try --fresh).",
+                host
+            ),
+            Diagnosis::PortBlocked(ref host, port) => write!(
+                f,
+                "The host at {} is up but port {} is closed or blocked. Check that the address is \
+                 the bridge's and that no firewall is in the way.",
+                host, port
+            ),
+            Diagnosis::Reachable(ref host) => write!(
+                f,
+                "The bridge at {} accepts connections, so the network looks fine.",
+                host
+            ),
+        }
+    }
+}
+
+/// Probes the bridge at an address of the form "host" or "host:port" (port 80 by default).
+pub fn probe(address: &str) -> Diagnosis {
+    let (host, port) = split_address(address);
+
+    let addr = match (host, port).to_socket_addrs().map(|mut addrs| addrs.next()) {
+        Ok(Some(addr)) => addr,
+        _ => return Diagnosis::Unresolved(host.to_string()),
+    };
+
+    match connect(addr) {
+        Ok(()) => Diagnosis::Reachable(host.to_string()),
+        Err(ref e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+            Diagnosis::PortBlocked(host.to_string(), port)
+        }
+        Err(_) => {
+            // The bridge also listens on 443, so an answer there means only the API port is
+            // being dropped.
+            let other = if port == 443 { 80 } else { 443 };
+            match connect(SocketAddr::new(addr.ip(), other)) {
+                Err(ref e) if e.kind() != io::ErrorKind::ConnectionRefused => {
+                    Diagnosis::HostDown(host.to_string())
+                }
+                _ => Diagnosis::PortBlocked(host.to_string(), port),
+            }
+        }
+    }
+}
+
+/// Helper function to open and immediately close a TCP connection.
+fn connect(addr: SocketAddr) -> io::Result<()> {
+    TcpStream::connect_timeout(&addr, PROBE_TIMEOUT).map(|_| ())
+}
+
+/// Helper function to split "host:port" into its parts. IPv6 addresses must be bracketed to
+/// carry a port, e.x. "[fe80::1]:80".
+fn split_address(address: &str) -> (&str, u16) {
+    let address = address.trim();
+
+    if let Some(i) = address.rfind(':') {
+        let (host, port) = (&address[..i], &address[i + 1..]);
+        let bracketed = !host.contains(':') || (host.starts_with('[') && host.ends_with(']'));
+
+        if let (true, Ok(port)) = (bracketed, port.parse()) {
+            return (host.trim_start_matches('[').trim_end_matches(']'), port);
+        }
+    }
+
+    (address, 80)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn addresses() {
+        assert_eq!(split_address("192.168.1.2"), ("192.168.1.2", 80));
+        assert_eq!(split_address("127.0.0.1:8765"), ("127.0.0.1", 8765));
+        assert_eq!(split_address("[fe80::1]:8080"), ("fe80::1", 8080));
+        assert_eq!(split_address("fe80::1"), ("fe80::1", 80));
+    }
+
+    #[test]
+    fn probe_local_ports() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let address = format!("127.0.0.1:{}", port);
+
+        assert_eq!(
+            probe(&address),
+            Diagnosis::Reachable(String::from("127.0.0.1"))
+        );
+
+        drop(listener);
+        assert_eq!(
+            probe(&address),
+            Diagnosis::PortBlocked(String::from("127.0.0.1"), port)
+        );
+    }
+}
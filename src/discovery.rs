@@ -0,0 +1,462 @@
+//! # discovery
+//!
+//! Finds Hue bridges. Bridges are searched for on the local network with SSDP and mDNS at the same
+//! time, since older bridges only answer SSDP and newer ones only advertise `_hue._tcp` over mDNS.
+//! The meethue.com endpoint, which needs internet access, is only asked when neither finds one,
+//! and never with `no_cloud`, for networks that must not reach the internet.
+
+use reqwest;
+use serde_json;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use progress;
+use ssdp;
+
+/// Multicast address and port of mDNS.
+const MDNS_ADDRESS: &str = "224.0.0.251:5353";
+
+/// Service bridges advertise over mDNS.
+const HUE_SERVICE: &str = "_hue._tcp.local";
+
+/// How long to wait for bridges to answer an mDNS query.
+const MDNS_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// DNS record types used by mDNS discovery.
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+
+/// How a bridge was found.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    Ssdp,
+    Mdns,
+    Nupnp,
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Method::Ssdp => f.write_str("SSDP"),
+            Method::Mdns => f.write_str("mDNS"),
+            Method::Nupnp => f.write_str("meethue.com"),
+        }
+    }
+}
+
+/// A bridge that was found, with its id when the discovery method reports it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BridgeInfo {
+    pub id: Option<String>,
+    pub ip: String,
+    pub method: Method,
+}
+
+/// Finds the bridges on the network, searching with SSDP and mDNS at the same time and asking the
+/// meethue.com endpoint only if neither finds any and not `no_cloud`. A bridge found by both
+/// local methods is listed once.
+///
+/// # Example
+///
+/// ```no_run
+/// use rusty_hue::discovery;
+///
+/// for bridge in discovery::discover_bridges(false) {
+///     println!("{} (found with {})", bridge.ip, bridge.method);
+/// }
+/// ```
+pub fn discover_bridges(no_cloud: bool) -> Vec<BridgeInfo> {
+    let mut bridges = Vec::new();
+    search(no_cloud, |bridge| {
+        bridges.push(bridge);
+        true
+    });
+    bridges
+}
+
+/// Same as `discover_bridges`, but returns the first bridge that `wanted` accepts as soon as it
+/// answers, rather than waiting out the whole search.
+pub fn discover_bridge<F: Fn(&BridgeInfo) -> bool>(
+    no_cloud: bool,
+    wanted: F,
+) -> Option<BridgeInfo> {
+    let mut found = None;
+    search(no_cloud, |bridge| {
+        if wanted(&bridge) {
+            found = Some(bridge);
+        }
+        found.is_none()
+    });
+    found
+}
+
+/// Helper function to search for bridges, passing each one found to `found` until it returns
+/// false.
+fn search<F: FnMut(BridgeInfo) -> bool>(no_cloud: bool, mut found: F) {
+    progress::status("Searching for bridges with SSDP and mDNS...");
+    let (sender, receiver) = mpsc::channel();
+    let mdns_sender = sender.clone();
+    // Each search stops once nothing is listening for what it finds anymore. A search that
+    // fails, e.x. without a network, just finds nothing.
+    thread::spawn(move || {
+        let _ = mdns(MDNS_TIMEOUT, |bridge| mdns_sender.send(bridge).is_ok());
+    });
+    thread::spawn(move || {
+        let _ = ssdp::search(ssdp::SEARCH_TIMEOUT, |bridge| {
+            let bridge = BridgeInfo {
+                id: Some(bridge.id),
+                ip: bridge.ip,
+                method: Method::Ssdp,
+            };
+            sender.send(bridge).is_ok()
+        });
+    });
+
+    let searched = collect(receiver, &mut found);
+    if searched == Some(0) && !no_cloud {
+        progress::status("Asking meethue.com for bridges...");
+        for bridge in nupnp().unwrap_or_default() {
+            if !found(bridge) {
+                break;
+            }
+        }
+    }
+
+    progress::clear();
+}
+
+/// Passes the bridges received to `found` until it returns false, skipping those already
+/// received from another method. Returns how many bridges were received once every sender is
+/// done, or None if `found` stopped early.
+fn collect<F: FnMut(BridgeInfo) -> bool>(
+    receiver: Receiver<BridgeInfo>,
+    found: &mut F,
+) -> Option<usize> {
+    let mut ips: Vec<String> = Vec::new();
+
+    for bridge in receiver {
+        if ips.contains(&bridge.ip) {
+            continue;
+        }
+        ips.push(bridge.ip.clone());
+        if !found(bridge) {
+            return None;
+        }
+    }
+
+    Some(ips.len())
+}
+
+/// Asks the meethue.com/api/nupnp endpoint for the bridges registered from this network.
+fn nupnp() -> Result<Vec<BridgeInfo>, Box<dyn Error>> {
+    let body = reqwest::get("https://www.meethue.com/api/nupnp")?.text()?;
+    let json: Value = serde_json::from_str(&body)?;
+
+    Ok(json
+        .as_array()
+        .map(|bridges| {
+            bridges
+                .iter()
+                .filter_map(|bridge| {
+                    Some(BridgeInfo {
+                        id: bridge["id"].as_str().map(str::to_uppercase),
+                        ip: bridge["internalipaddress"].as_str()?.to_string(),
+                        method: Method::Nupnp,
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// Queries for bridges with mDNS, passing each one that answers within the timeout to `found`
+/// until it returns false. The query is sent from an ephemeral port, so responders answer it
+/// directly rather than to the multicast group.
+fn mdns<F: FnMut(BridgeInfo) -> bool>(
+    timeout: Duration,
+    mut found: F,
+) -> Result<(), Box<dyn Error>> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.send_to(&mdns_query(HUE_SERVICE), MDNS_ADDRESS)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut ips: Vec<String> = Vec::new();
+    let mut buffer = [0; 4096];
+
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        if remaining == Duration::from_secs(0) {
+            break;
+        }
+        socket.set_read_timeout(Some(remaining))?;
+
+        let (len, from) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(_) => break,
+        };
+
+        for bridge in parse_mdns_response(&buffer[..len], from) {
+            if !ips.contains(&bridge.ip) {
+                ips.push(bridge.ip.clone());
+                if !found(bridge) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds a DNS query for the instances of a service, e.x. "_hue._tcp.local".
+fn mdns_query(service: &str) -> Vec<u8> {
+    // Id, flags, one question, no answers.
+    let mut packet = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+
+    for label in service.split('.') {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+
+    packet.extend_from_slice(&TYPE_PTR.to_be_bytes());
+    // Class IN.
+    packet.extend_from_slice(&[0, 1]);
+
+    packet
+}
+
+/// Helper function to read a big endian u16 from a packet.
+fn read_u16(packet: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*packet.get(at)?, *packet.get(at + 1)?]))
+}
+
+/// Reads a possibly compressed domain name from a packet. Returns the name and the position just
+/// past it.
+fn read_name(packet: &[u8], mut at: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut end = None;
+
+    // Pointers could loop, so only so many are followed.
+    for _ in 0..64 {
+        let len = *packet.get(at)? as usize;
+
+        if len == 0 {
+            return Some((labels.join("."), end.unwrap_or(at + 1)));
+        }
+
+        if len & 0xc0 == 0xc0 {
+            let pointer = (read_u16(packet, at)? & 0x3fff) as usize;
+            end.get_or_insert(at + 2);
+            at = pointer;
+            continue;
+        }
+
+        let label = packet.get(at + 1..at + 1 + len)?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        at += 1 + len;
+    }
+
+    None
+}
+
+/// Parses an mDNS response, returning the bridges it describes. Each bridge is a PTR record for
+/// the Hue service naming an instance, whose SRV record names the host, whose A record holds the
+/// IP. The instance's TXT record holds the bridge id.
+fn parse_mdns_response(packet: &[u8], from: SocketAddr) -> Vec<BridgeInfo> {
+    let mut instances: Vec<String> = Vec::new();
+    let mut hosts: HashMap<String, String> = HashMap::new();
+    let mut ips: HashMap<String, Ipv4Addr> = HashMap::new();
+    let mut ids: HashMap<String, String> = HashMap::new();
+
+    let counts: Option<Vec<u16>> = (0..4).map(|i| read_u16(packet, 4 + i * 2)).collect();
+    let counts = match counts {
+        Some(counts) => counts,
+        None => return Vec::new(),
+    };
+
+    let mut at = 12;
+    for _ in 0..counts[0] {
+        match read_name(packet, at) {
+            // Type and class follow the name.
+            Some((_, end)) => at = end + 4,
+            None => return Vec::new(),
+        }
+    }
+
+    let records = counts[1] as usize + counts[2] as usize + counts[3] as usize;
+    for _ in 0..records {
+        let (name, end) = match read_name(packet, at) {
+            Some(read) => read,
+            None => break,
+        };
+        let (record_type, len) = match (read_u16(packet, end), read_u16(packet, end + 8)) {
+            (Some(record_type), Some(len)) => (record_type, len as usize),
+            _ => break,
+        };
+        let data_at = end + 10;
+        let data = match packet.get(data_at..data_at + len) {
+            Some(data) => data,
+            None => break,
+        };
+
+        match record_type {
+            TYPE_PTR if name.eq_ignore_ascii_case(HUE_SERVICE) => {
+                if let Some((instance, _)) = read_name(packet, data_at) {
+                    instances.push(instance);
+                }
+            }
+            TYPE_SRV => {
+                if let Some((host, _)) = read_name(packet, data_at + 6) {
+                    hosts.insert(name, host);
+                }
+            }
+            TYPE_A if len == 4 => {
+                ips.insert(name, Ipv4Addr::new(data[0], data[1], data[2], data[3]));
+            }
+            TYPE_TXT => {
+                let mut i = 0;
+                while let Some(&len) = data.get(i) {
+                    let entry = String::from_utf8_lossy(
+                        data.get(i + 1..i + 1 + len as usize).unwrap_or_default(),
+                    );
+                    if let Some(id) = entry.strip_prefix("bridgeid=") {
+                        ids.insert(name.clone(), id.to_uppercase());
+                    }
+                    i += 1 + len as usize;
+                }
+            }
+            _ => (),
+        }
+
+        at = data_at + len;
+    }
+
+    instances
+        .into_iter()
+        .map(|instance| {
+            let ip = hosts
+                .get(&instance)
+                .and_then(|host| ips.get(host))
+                .map_or_else(|| from.ip().to_string(), Ipv4Addr::to_string);
+
+            BridgeInfo {
+                id: ids.get(&instance).cloned(),
+                ip,
+                method: Method::Mdns,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Helper function to encode a DNS record.
+    fn record(name: &[u8], record_type: u16, data: &[u8]) -> Vec<u8> {
+        let mut bytes = name.to_vec();
+        bytes.extend_from_slice(&record_type.to_be_bytes());
+        bytes.extend_from_slice(&[0, 1, 0, 0, 0, 120]);
+        bytes.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn parse_mdns() {
+        // The service name sits right after the header, at offset 12, and is pointed back to.
+        let mut packet = vec![0, 0, 0x84, 0, 0, 0, 0, 4, 0, 0, 0, 0];
+        let service = mdns_query(HUE_SERVICE)[12..29].to_vec();
+        let instance = [&b"\x0eHue Bridge - 1\xc0\x0c"[..]].concat();
+        let host = b"\x06hue-01\x05local\x00".to_vec();
+
+        packet.extend(record(&service, TYPE_PTR, &instance));
+        // The instance name starts after the PTR record's name and header.
+        let instance_at = 12 + service.len() + 10;
+        let pointer = [0xc0, instance_at as u8];
+        packet.extend(record(
+            &pointer,
+            TYPE_SRV,
+            &[&[0, 0, 0, 0, 0, 80][..], &host].concat(),
+        ));
+        packet.extend(record(
+            &pointer,
+            TYPE_TXT,
+            b"\x19bridgeid=001788fffe000000\x0emodelid=BSB002",
+        ));
+        packet.extend(record(&host, TYPE_A, &[192, 168, 1, 2]));
+
+        let from: SocketAddr = "192.168.1.20:5353".parse().unwrap();
+
+        assert_eq!(
+            parse_mdns_response(&packet, from),
+            vec![BridgeInfo {
+                id: Some(String::from("001788FFFE000000")),
+                ip: String::from("192.168.1.2"),
+                method: Method::Mdns,
+            }]
+        );
+
+        // Truncated packets are ignored rather than misread.
+        assert!(parse_mdns_response(&packet[..40], from).is_empty());
+    }
+
+    #[test]
+    fn read_compressed_names() {
+        let packet = b"\x03abc\x05local\x00\x03def\xc0\x00\xc0\x11";
+
+        assert_eq!(read_name(packet, 0), Some((String::from("abc.local"), 11)));
+        assert_eq!(
+            read_name(packet, 11),
+            Some((String::from("def.abc.local"), 17))
+        );
+        // A pointer to itself.
+        assert_eq!(read_name(packet, 17), None);
+    }
+
+    #[test]
+    fn collect_bridges() {
+        let bridge = |ip: &str, method| BridgeInfo {
+            id: None,
+            ip: String::from(ip),
+            method,
+        };
+        let received = || {
+            let (sender, receiver) = mpsc::channel();
+            sender.send(bridge("192.168.1.2", Method::Ssdp)).unwrap();
+            sender.send(bridge("192.168.1.2", Method::Mdns)).unwrap();
+            sender.send(bridge("192.168.1.3", Method::Mdns)).unwrap();
+            receiver
+        };
+
+        // A bridge found by both methods is passed on once.
+        let mut all = Vec::new();
+        let count = collect(received(), &mut |bridge| {
+            all.push(bridge);
+            true
+        });
+        assert_eq!(count, Some(2));
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].method, Method::Ssdp);
+
+        // Stops at the first bridge wanted, without waiting for the senders to finish.
+        let (sender, receiver) = mpsc::channel();
+        sender.send(bridge("192.168.1.2", Method::Ssdp)).unwrap();
+        let mut first = None;
+        let count = collect(receiver, &mut |bridge| {
+            first = Some(bridge);
+            false
+        });
+        assert_eq!(count, None);
+        assert_eq!(first.unwrap().ip, "192.168.1.2");
+        drop(sender);
+    }
+}
@@ -0,0 +1,96 @@
+//! # drift
+//!
+//! Detects the bridge drifting from a desired-state file, for keeping lighting set up like
+//! infrastructure, e.x. checked from a cron job. The file is an import file (see `import`), which
+//! may also hold a "config" object of bridge settings, e.x.
+//! `{"config": {"name": "Home", "timezone": "Europe/Berlin"}, "groups": {...}}`. As with imports,
+//! only the resource types and fields the file mentions are compared.
+
+use serde_json::{Map, Value};
+use std::error::Error;
+
+use hue::Hue;
+use import::{Action, Change, Format};
+
+/// Resource of the bridge's settings, which isn't keyed by id.
+pub const SETTINGS: &str = "config";
+
+/// Computes the change needed for the bridge's settings to match the desired ones, if any.
+pub fn plan_settings(desired: &Map<String, Value>, live: &Value) -> Option<Change> {
+    let mut fields = Map::new();
+    let mut current = Map::new();
+    current.insert(String::from("name"), live["name"].clone());
+
+    for (key, value) in desired {
+        if live.get(key) != Some(value) {
+            fields.insert(key.clone(), value.clone());
+            current.insert(key.clone(), live[key].clone());
+        }
+    }
+
+    if fields.is_empty() {
+        return None;
+    }
+
+    Some(Change {
+        resource: SETTINGS,
+        id: String::new(),
+        action: Action::Update,
+        fields,
+        current,
+    })
+}
+
+impl Hue {
+    /// Plans the changes needed for the bridge to match a desired-state file, against fresh
+    /// state from the bridge. No changes means no drift.
+    pub fn plan_drift(&self, desired: &Map<String, Value>) -> Result<Vec<Change>, Box<dyn Error>> {
+        let mut file = desired.clone();
+        let settings = match file.remove(SETTINGS) {
+            Some(Value::Object(settings)) => Some(settings),
+            Some(_) => {
+                return Err(From::from(format!(
+                    "'{}' in the desired-state file must be an object of settings.",
+                    SETTINGS
+                )))
+            }
+            None => None,
+        };
+
+        let mut changes = self.plan_import(&file, Format::Bridge)?;
+
+        if let Some(settings) = settings {
+            let live = self.fetch(SETTINGS, false)?;
+            changes.extend(plan_settings(&settings, &live));
+        }
+
+        Ok(changes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn settings_drift() {
+        let live = json!({"name": "Hue", "timezone": "Europe/Berlin", "zigbeechannel": 25});
+        let desired = |value: Value| value.as_object().unwrap().clone();
+
+        assert_eq!(
+            plan_settings(&desired(json!({"timezone": "Europe/Berlin"})), &live),
+            None
+        );
+
+        let change = plan_settings(
+            &desired(json!({"name": "Home", "zigbeechannel": 25})),
+            &live,
+        )
+        .unwrap();
+        assert_eq!(change.fields, desired(json!({"name": "Home"})));
+        assert_eq!(
+            change.to_string(),
+            "  ~ config \"Hue\"\n      name: \"Hue\" -> \"Home\""
+        );
+    }
+}
@@ -0,0 +1,352 @@
+//! # events
+//!
+//! Listens to the bridge's event stream, which pushes changes to lights, groups, and sensors as
+//! they happen. The stream survives bridge reboots and network blips: it reconnects with a
+//! jittered backoff, and since events sent while it was down are lost, the state of the lights is
+//! fetched again after every reconnection. Like the v2 API, the stream is only served over HTTPS
+//! and is only listened to over connections verified against the bridge's root CA.
+
+use serde_json;
+use serde_json::Value;
+use std::cmp;
+use std::error::Error;
+use std::io::prelude::*;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hue::https::{Https, Response};
+use hue::Hue;
+
+/// Shortest delay before reconnecting.
+const MIN_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest delay before reconnecting, however long the bridge has been gone.
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// How long the stream may stay silent before it's assumed dead. The bridge doesn't send
+/// keep-alives, so a quiet stream is reconnected now and then even when nothing is wrong.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How long `check_event_stream` waits for the stream to open.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// State of the connection to the event stream, reported to the caller as it changes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionState {
+    /// Connecting to the bridge, after the given number of failed attempts.
+    Connecting(u32),
+    Connected,
+    /// Reconnected after the stream was lost, and the state of the lights fetched again since
+    /// events may have been missed.
+    Resynced,
+    /// The stream was lost and will be reconnected after a delay.
+    Disconnected {
+        error: String,
+        retry_in: Duration,
+    },
+}
+
+/// An event sent by the bridge. The data is usually a list of changed resources.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub id: Option<String>,
+    pub data: Value,
+}
+
+/// Delay before each reconnection attempt, doubling from `MIN_DELAY` up to `MAX_DELAY`. Delays
+/// are jittered so clients cut off together don't all reconnect at once.
+#[derive(Debug, Default)]
+pub struct Backoff {
+    attempts: u32,
+}
+
+impl Backoff {
+    /// Number of failed attempts since the last successful connection.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Records a failed attempt and returns how long to wait before the next one.
+    pub fn next_delay(&mut self) -> Duration {
+        let ceiling = cmp::min(MIN_DELAY * 2u32.pow(cmp::min(self.attempts, 6)), MAX_DELAY);
+        self.attempts += 1;
+
+        // Somewhere between half and all of the ceiling.
+        ceiling / 2 + ceiling.mul_f32(jitter() / 2.0)
+    }
+
+    /// Records a successful connection.
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+    }
+}
+
+/// Helper function for a pseudo-random number between 0 and 1, good enough to spread out
+/// reconnections.
+fn jitter() -> f32 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.subsec_nanos())
+        .unwrap_or(0);
+
+    (nanos % 1000) as f32 / 1000.0
+}
+
+/// Parser for the server-sent events format, fed one line at a time.
+#[derive(Debug, Default)]
+struct Parser {
+    id: Option<String>,
+    data: Vec<String>,
+}
+
+impl Parser {
+    /// Parses a line, returning an event when the line completes one.
+    fn line(&mut self, line: &str) -> Option<Event> {
+        let line = line.trim_end_matches('\r');
+
+        if line.is_empty() {
+            if self.data.is_empty() {
+                return None;
+            }
+
+            let data = self.data.join("\n");
+            self.data.clear();
+
+            return Some(Event {
+                id: self.id.clone(),
+                data: serde_json::from_str(&data).unwrap_or(Value::String(data)),
+            });
+        }
+
+        // Lines starting with a colon are comments.
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = match line.find(':') {
+            Some(i) => (&line[..i], &line[i + 1..]),
+            None => (line, ""),
+        };
+        let value = value.strip_prefix(' ').unwrap_or(value);
+
+        match field {
+            "id" => self.id = Some(value.to_string()),
+            "data" => self.data.push(value.to_string()),
+            _ => (),
+        }
+
+        None
+    }
+}
+
+/// Whether an event adds, deletes, or renames a resource, after which the stored state of the
+/// lights no longer matches the bridge.
+fn changes_entities(event: &Event) -> bool {
+    let containers = match event.data.as_array() {
+        Some(containers) => containers,
+        None => return false,
+    };
+
+    containers
+        .iter()
+        .any(|container| match container["type"].as_str() {
+            Some("add") | Some("delete") => true,
+            Some("update") => container["data"].as_array().is_some_and(|resources| {
+                resources.iter().any(|r| r["metadata"]["name"].is_string())
+            }),
+            _ => false,
+        })
+}
+
+impl Hue {
+    /// Opens the event stream and closes it again, to check that the bridge serves it and
+    /// accepts the API token.
+    pub fn check_event_stream(&self) -> Result<(), Box<dyn Error>> {
+        let response = self.open_event_stream(&self.https()?, None, CHECK_TIMEOUT)?;
+
+        match response.status {
+            401 => Err(From::from("The bridge rejected the API token.")),
+            404 => Err(From::from(
+                "The bridge doesn't serve an event stream. Its firmware may be too old.",
+            )),
+            status if !(200..300).contains(&status) => Err(From::from(format!(
+                "The bridge answered with status {}.",
+                status
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Helper function to open the event stream over a verified connection, resuming after the
+    /// event with the given id. The connection gives up when the stream is silent for `timeout`.
+    fn open_event_stream(
+        &self,
+        https: &Https,
+        last_id: Option<&str>,
+        timeout: Duration,
+    ) -> Result<Response, Box<dyn Error>> {
+        let mut headers = vec![
+            ("hue-application-key", self.token()),
+            ("Accept", "text/event-stream"),
+        ];
+        if let Some(id) = last_id {
+            headers.push(("Last-Event-ID", id));
+        }
+
+        https.send("GET", "/eventstream/clip/v2", &headers, "", timeout)
+    }
+
+    /// Listens to the bridge's event stream, calling `on_event` for each event until it returns
+    /// false. Lost connections are retried indefinitely, with `on_state` told of each change in
+    /// the connection. Only a rejected API token or a bridge without an event stream ends
+    /// listening with an error.
+    pub fn listen<S, E>(&mut self, mut on_state: S, mut on_event: E) -> Result<(), Box<dyn Error>>
+    where
+        S: FnMut(&ConnectionState),
+        E: FnMut(&Hue, &Event) -> bool,
+    {
+        let https = self.https()?;
+
+        let mut backoff = Backoff::default();
+        let mut last_id: Option<String> = None;
+        let mut connected_before = false;
+
+        loop {
+            on_state(&ConnectionState::Connecting(backoff.attempts()));
+
+            let error = match self.open_event_stream(&https, last_id.as_deref(), IDLE_TIMEOUT) {
+                Ok(ref response) if response.status == 401 => {
+                    return Err(From::from(
+                        "The bridge rejected the API token, so events can't be received.",
+                    ));
+                }
+                Ok(ref response) if response.status == 404 => {
+                    return Err(From::from(
+                        "The bridge doesn't serve an event stream. Its firmware may be too old.",
+                    ));
+                }
+                Ok(ref response) if !(200..300).contains(&response.status) => {
+                    format!("The bridge answered with status {}.", response.status)
+                }
+                Ok(response) => {
+                    backoff.reset();
+                    on_state(&ConnectionState::Connected);
+
+                    // The stream is open before state is fetched again, so no change falls in
+                    // between.
+                    let resynced = if connected_before {
+                        self.refresh()
+                            .map(|()| on_state(&ConnectionState::Resynced))
+                    } else {
+                        Ok(())
+                    };
+                    connected_before = true;
+
+                    match resynced {
+                        Ok(()) => {
+                            let mut parser = Parser::default();
+                            let mut error = String::from("The bridge closed the event stream.");
+
+                            for line in response.body.lines() {
+                                let line = match line {
+                                    Ok(line) => line,
+                                    Err(e) => {
+                                        error = e.to_string();
+                                        break;
+                                    }
+                                };
+
+                                if let Some(event) = parser.line(&line) {
+                                    if event.id.is_some() {
+                                        last_id = event.id.clone();
+                                    }
+                                    // Keep names and indices current, so lookups by name made
+                                    // by the caller find renamed lights and skip deleted ones.
+                                    if changes_entities(&event) {
+                                        if let Err(e) = self.refresh() {
+                                            error = e.to_string();
+                                            break;
+                                        }
+                                    }
+                                    if !on_event(self, &event) {
+                                        return Ok(());
+                                    }
+                                }
+                            }
+
+                            error
+                        }
+                        Err(e) => e.to_string(),
+                    }
+                }
+                Err(e) => e.to_string(),
+            };
+
+            let retry_in = backoff.next_delay();
+            on_state(&ConnectionState::Disconnected { error, retry_in });
+            thread::sleep(retry_in);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_events() {
+        let mut parser = Parser::default();
+        let lines = [
+            ": hi",
+            "",
+            "id: 1700000000:0",
+            "data: [{\"type\": \"update\",",
+            "data:  \"data\": []}]",
+            "",
+            "data: not json\r",
+            "\r",
+        ];
+
+        let events: Vec<Event> = lines.iter().filter_map(|line| parser.line(line)).collect();
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].id, Some(String::from("1700000000:0")));
+        assert_eq!(events[0].data[0]["type"], "update");
+        // The id carries over to later events until the bridge sends a new one.
+        assert_eq!(events[1].id, Some(String::from("1700000000:0")));
+        assert_eq!(events[1].data, Value::String(String::from("not json")));
+    }
+
+    #[test]
+    fn entity_changes() {
+        let event = |data: Value| Event { id: None, data };
+
+        assert!(changes_entities(&event(
+            json!([{"type": "delete", "data": [{"type": "light"}]}])
+        )));
+        assert!(changes_entities(&event(json!([
+            {"type": "update", "data": [{"type": "light", "metadata": {"name": "Lamp"}}]}
+        ]))));
+        assert!(!changes_entities(&event(json!([
+            {"type": "update", "data": [{"type": "light", "on": {"on": true}}]}
+        ]))));
+        assert!(!changes_entities(&event(Value::String(String::from("hi")))));
+    }
+
+    #[test]
+    fn backoff_delays() {
+        let mut backoff = Backoff::default();
+
+        let first = backoff.next_delay();
+        assert!(first >= MIN_DELAY / 2 && first <= MIN_DELAY);
+
+        for _ in 0..10 {
+            assert!(backoff.next_delay() <= MAX_DELAY);
+        }
+        assert!(backoff.next_delay() >= MAX_DELAY / 2);
+        assert_eq!(backoff.attempts(), 12);
+
+        backoff.reset();
+        assert!(backoff.next_delay() <= MIN_DELAY);
+    }
+}
@@ -0,0 +1,142 @@
+//! # files
+//!
+//! Helpers for the files kept in $HOME/.config/rusty_hue. All writes go through `write_atomic`, or
+//! `append_line` for logs, so a daemon and the command line tool running at the same time can't
+//! corrupt each other's files. Files only written by hand use `replace_file` instead.
+
+use std::env;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Location of a file in the configuration directory, e.x. `config_file("colors.json")`.
+pub fn config_file(name: &str) -> Result<PathBuf, Box<dyn Error>> {
+    match env::home_dir() {
+        Some(path) => Ok(path.join(".config/rusty_hue").join(name)),
+        None => Err(From::from("Failed to get home directory.")),
+    }
+}
+
+/// Helper function to build a sibling path of a file, e.x. "colors.json" -> ".colors.json.lock".
+fn sibling(path: &Path, prefix: &str, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(prefix);
+    name.push(path.file_name().unwrap_or_default());
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Replaces the contents of a file without ever leaving it partially written. The contents are
+/// written and synced to a temporary file in the same directory, which is then renamed over the
+/// target. Writers hold an exclusive lock on a sibling lock file for the duration, so concurrent
+/// writers are serialized rather than interleaved. Missing parent directories are created.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(sibling(path, ".", ".lock"))?;
+    lock.lock()?;
+
+    let result = replace_file(path, contents);
+
+    lock.unlock()?;
+    result
+}
+
+/// Same as `write_atomic`, but without the lock, for files only written by hand and not by
+/// daemons, e.x. exports, so no lock file is left next to them.
+pub fn replace_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let temp = sibling(path, ".", &format!(".{}.tmp", process::id()));
+
+    let result = File::create(&temp)
+        .and_then(|mut f| {
+            f.write_all(contents)?;
+            f.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp, path));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+
+    result
+}
+
+/// Appends a line to a file, e.x. an entry of a log, creating the file and missing parent
+/// directories. Writers hold the same lock as `write_atomic`, so lines aren't interleaved.
+pub fn append_line(path: &Path, line: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(sibling(path, ".", ".lock"))?;
+    lock.lock()?;
+
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut f| f.write_all(format!("{}\n", line).as_bytes()));
+
+    lock.unlock()?;
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn write_and_replace() {
+        let dir = env::temp_dir().join(format!("rusty_hue_files_{}", process::id()));
+        let path = dir.join("colors.json");
+
+        write_atomic(&path, b"{}").unwrap();
+        write_atomic(&path, b"{\"red\": 1}").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{\"red\": 1}");
+
+        let leftovers: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().ends_with(".tmp"))
+            .collect();
+        assert!(leftovers.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replace_without_lock() {
+        let dir = env::temp_dir().join(format!("rusty_hue_replace_{}", process::id()));
+        let path = dir.join("export.json");
+
+        replace_file(&path, b"{}").unwrap();
+
+        let names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name())
+            .collect();
+        assert_eq!(names, vec!["export.json"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,115 @@
+//! # fixtures
+//!
+//! Samples of what bridges send for each resource, across firmware versions, for tests. Every
+//! sample is decoded and compared against a golden file in src/fixtures/golden, so a change to
+//! decoding that users would notice fails a test first. When a change is intended, run the tests
+//! with RUSTY_HUE_UPDATE_GOLDEN=1 to rewrite the golden files, and review their diff.
+
+use serde::Serialize;
+use serde_json;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Lights of a bridge on firmware before capabilities were reported, e.x. 1.16.
+pub const LIGHTS_V1: &str = include_str!("fixtures/lights_v1.json");
+/// Lights of a bridge on firmware 1.50 and later, with capabilities, configs, and a plug.
+pub const LIGHTS_V2: &str = include_str!("fixtures/lights_v2.json");
+/// Rooms, zones, an entertainment area, a light group, and a group missing its lights.
+pub const GROUPS: &str = include_str!("fixtures/groups.json");
+/// A version 1 scene and version 2 group and light scenes.
+pub const SCENES: &str = include_str!("fixtures/scenes.json");
+/// A motion sensor with its light level and temperature sensors, dimmer switches, and sensors
+/// that aren't read.
+pub const SENSORS: &str = include_str!("fixtures/sensors.json");
+/// Error bodies of the bridge, one of each type handled and one that isn't.
+pub const ERRORS: &str = include_str!("fixtures/errors.json");
+
+/// Reads a sample as JSON.
+pub fn parse(fixture: &str) -> Value {
+    serde_json::from_str(fixture).expect("fixture isn't valid JSON")
+}
+
+/// Pretty JSON of decoded resources keyed by id, in order of id so the output is stable.
+pub fn to_golden_json<T: Serialize>(resources: HashMap<String, T>) -> String {
+    let resources: BTreeMap<String, T> = resources.into_iter().collect();
+    serde_json::to_string_pretty(&resources).unwrap() + "\n"
+}
+
+/// Compares output against the golden file of a name, e.x. "lights_v1.json", or rewrites the
+/// golden file when RUSTY_HUE_UPDATE_GOLDEN is set.
+pub fn assert_golden(name: &str, actual: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("src/fixtures/golden")
+        .join(name);
+
+    if env::var_os("RUSTY_HUE_UPDATE_GOLDEN").is_some() {
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&path).unwrap_or_default();
+    assert!(
+        expected == actual,
+        "{} doesn't match its golden file, rerun with RUSTY_HUE_UPDATE_GOLDEN=1 if the change is \
+         intended.\n--- expected\n{}\n+++ actual\n{}",
+        name,
+        expected,
+        actual
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use decode;
+    use groups::Group;
+    use hue::api::decode_lights;
+    use hue::HueError;
+    use scenes::Scene;
+    use sensors;
+
+    #[test]
+    fn lights() {
+        for &(name, fixture) in &[("lights_v1.json", LIGHTS_V1), ("lights_v2.json", LIGHTS_V2)] {
+            let lights = decode_lights(&parse(fixture), false).unwrap();
+            assert_golden(name, &to_golden_json(lights));
+        }
+    }
+
+    #[test]
+    fn groups() {
+        let groups: HashMap<String, Group> =
+            decode::decode_map(&parse(GROUPS), "group", &[], false).unwrap();
+        assert_golden("groups.json", &to_golden_json(groups));
+    }
+
+    #[test]
+    fn scenes() {
+        let scenes: HashMap<String, Scene> =
+            decode::decode_map(&parse(SCENES), "scene", &[], false).unwrap();
+        assert_golden("scenes.json", &to_golden_json(scenes));
+    }
+
+    #[test]
+    fn sensors() {
+        let sensors = sensors::sensors(&parse(SENSORS), false).unwrap();
+        assert_golden("sensors.txt", &format!("{:#?}\n", sensors));
+    }
+
+    #[test]
+    fn errors() {
+        let errors: String = parse(ERRORS)
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| {
+                let error = HueError::from_bridge(&item["error"], "10.0.0.2");
+                format!("{:?}\n    {}\n", error, error)
+            })
+            .collect();
+        assert_golden("errors.txt", &errors);
+    }
+}
@@ -0,0 +1,152 @@
+//! # gamut
+//!
+//! Renders a light's color gamut, the triangle of colors it can produce in the CIE xy color
+//! space, with a point such as the light's current color marked. Colors outside the triangle are
+//! moved to its edge by the light, which explains why a color may not look as requested.
+
+use colors::{ColorGamut, GamutPoint};
+
+/// Extent of the xy plane drawn, which covers every Hue gamut.
+const X_MAX: f32 = 0.8;
+const Y_MAX: f32 = 0.9;
+
+/// Renders the gamut as text, `width` characters wide. Inside of the gamut is drawn with `.`, its
+/// edges with `#`, its vertices with `R`, `G`, and `B`, and the marked point with `X` when it's
+/// inside the gamut or `O` when it's outside.
+pub fn render_text(gamut: &ColorGamut, point: Option<(f32, f32)>, width: usize) -> String {
+    // Terminal characters are about twice as tall as they are wide.
+    let height = (width as f32 * Y_MAX / X_MAX / 2.0).round() as usize;
+    let cell = |column: usize, row: usize| {
+        (
+            (column as f32 + 0.5) / width as f32 * X_MAX,
+            (1.0 - (row as f32 + 0.5) / height as f32) * Y_MAX,
+        )
+    };
+    let inside = |(x, y): (f32, f32)| gamut.point_in_gamut(&GamutPoint::new(x, y));
+    let position = |(x, y): (f32, f32)| {
+        (
+            ((x / X_MAX * width as f32) as usize).min(width - 1),
+            (((1.0 - y / Y_MAX) * height as f32) as usize).min(height - 1),
+        )
+    };
+
+    let mut grid = vec![vec![' '; width]; height];
+
+    for (row, line) in grid.iter_mut().enumerate() {
+        for (column, c) in line.iter_mut().enumerate() {
+            if !inside(cell(column, row)) {
+                continue;
+            }
+
+            let edge = column == 0
+                || row == 0
+                || column + 1 == width
+                || row + 1 == height
+                || !inside(cell(column - 1, row))
+                || !inside(cell(column + 1, row))
+                || !inside(cell(column, row - 1))
+                || !inside(cell(column, row + 1));
+
+            *c = if edge { '#' } else { '.' };
+        }
+    }
+
+    for (vertex, label) in gamut.vertices().iter().zip(&['R', 'G', 'B']) {
+        let (column, row) = position(*vertex);
+        grid[row][column] = *label;
+    }
+
+    if let Some(point) = point {
+        let (column, row) = position(point);
+        grid[row][column] = if inside(point) { 'X' } else { 'O' };
+    }
+
+    let mut output = String::new();
+    for line in grid {
+        output += line.iter().collect::<String>().trim_end();
+        output.push('\n');
+    }
+
+    output.trim_start_matches('\n').to_string()
+}
+
+/// Renders the gamut as an SVG image, with the marked point drawn as a circle.
+pub fn render_svg(gamut: &ColorGamut, point: Option<(f32, f32)>) -> String {
+    let scale = 500.0;
+    let (width, height) = (X_MAX * scale, Y_MAX * scale);
+    let project = |(x, y): (f32, f32)| (x * scale, height - y * scale);
+
+    let vertices: Vec<String> = gamut
+        .vertices()
+        .iter()
+        .map(|vertex| {
+            let (x, y) = project(*vertex);
+            format!("{:.1},{:.1}", x, y)
+        })
+        .collect();
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n\
+         <rect width=\"{w}\" height=\"{h}\" fill=\"white\"/>\n\
+         <polygon points=\"{points}\" fill=\"#eeeeee\" stroke=\"black\" stroke-width=\"2\"/>\n",
+        w = width,
+        h = height,
+        points = vertices.join(" ")
+    );
+
+    for (vertex, (label, color)) in
+        gamut
+            .vertices()
+            .iter()
+            .zip(&[("R", "red"), ("G", "green"), ("B", "blue")])
+    {
+        let (x, y) = project(*vertex);
+        svg += &format!(
+            "<text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-family=\"sans-serif\">{}</text>\n",
+            x + 6.0,
+            y - 6.0,
+            color,
+            label
+        );
+    }
+
+    if let Some(point) = point {
+        let (x, y) = project(point);
+        let inside = gamut.point_in_gamut(&GamutPoint::new(point.0, point.1));
+        svg += &format!(
+            "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"6\" fill=\"{}\" stroke=\"black\"/>\n",
+            x,
+            y,
+            if inside { "black" } else { "none" }
+        );
+    }
+
+    svg += "</svg>\n";
+    svg
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use colors;
+
+    #[test]
+    fn text_marks_point() {
+        let inside = render_text(&colors::COLOR_GAMUT_B, Some((0.4, 0.4)), 40);
+        assert!(inside.contains('X'));
+        assert!(inside.contains('R') && inside.contains('G') && inside.contains('B'));
+
+        let outside = render_text(&colors::COLOR_GAMUT_B, Some((0.1, 0.8)), 40);
+        assert!(outside.contains('O'));
+        assert!(!outside.contains('X'));
+    }
+
+    #[test]
+    fn svg_has_triangle() {
+        let svg = render_svg(&colors::COLOR_GAMUT_A, None);
+
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("<polygon points=\"352.0,302.0 107.6,94.7 69.0,410.0\""));
+        assert!(!svg.contains("<circle"));
+    }
+}
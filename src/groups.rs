@@ -0,0 +1,372 @@
+//! # groups
+//!
+//! Collection of data structures and methods for working with groups (rooms, zones, etc.) of
+//! lights on the Hue bridge.
+
+use serde_json;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::time::Duration;
+
+use colors;
+use decode;
+use files;
+use hue;
+use hue::api::Request;
+use hue::Hue;
+use journal;
+use journal::Activation;
+use state::LightStateUpdate;
+
+/// Represents a group of lights. Matches the JSON data fields to allow for serialization.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Group {
+    pub name: String,
+    pub lights: Vec<String>,
+    #[serde(rename = "type")]
+    pub group_type: String,
+    #[serde(default)]
+    pub class: Option<String>,
+    /// Mean brightness of the group's reachable lights that are powered on. This is derived from
+    /// the member lights when the groups are fetched, not reported by the bridge, and is `None`
+    /// when no member light is on.
+    #[serde(skip)]
+    pub derived_bri: Option<u8>,
+}
+
+/// Fields of a group the bridge sends that aren't decoded.
+const KNOWN_GROUP_FIELDS: [&str; 8] = [
+    "action",
+    "state",
+    "recycle",
+    "sensors",
+    "presence",
+    "lightlevel",
+    "stream",
+    "locations",
+];
+
+impl Hue {
+    /// Retrieves all groups from the bridge, keyed by their id, with the derived brightness of
+    /// each group computed from its member lights.
+    pub fn get_groups(&self) -> Result<HashMap<String, Group>, Box<dyn Error>> {
+        let mut groups: HashMap<String, Group> = decode::decode_map(
+            &self.fetch("groups", true)?,
+            "group",
+            &KNOWN_GROUP_FIELDS,
+            self.config().strict,
+        )?;
+
+        for group in groups.values_mut() {
+            group.derived_bri = mean_brightness(&self.lit_brightness(&group.lights));
+        }
+
+        Ok(groups)
+    }
+
+    /// Maps the index of each light that belongs to a room to the room's name. A light can be
+    /// in at most one room, though it may be in any number of zones.
+    pub fn rooms_by_light(&self) -> Result<HashMap<String, String>, Box<dyn Error>> {
+        let mut rooms = HashMap::new();
+
+        for group in self.get_groups()?.into_values() {
+            if group.group_type == "Room" {
+                for light in group.lights {
+                    rooms.insert(light, group.name.clone());
+                }
+            }
+        }
+
+        Ok(rooms)
+    }
+
+    /// Finds a group given either its id or its name.
+    pub fn find_group(&self, group: &str) -> Result<(String, Group), Box<dyn Error>> {
+        let mut groups = self.get_groups()?;
+
+        if let Some(found) = groups.remove(group) {
+            return Ok((group.to_string(), found));
+        }
+
+        groups
+            .into_iter()
+            .find(|(_, g)| g.name == group)
+            .ok_or_else(|| From::from(format!("No room or group with name '{}' found.", group)))
+    }
+
+    /// Recalls a scene on a group, with an optional transition. The activation is recorded in
+    /// the journal.
+    pub fn recall_scene(
+        &self,
+        group: &str,
+        scene: &str,
+        fade: Option<Duration>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut update = LightStateUpdate::new().scene(scene);
+        if let Some(fade) = fade {
+            update = update.transition(hue::transition_time(fade));
+        }
+
+        self.set_group_state(group, &update)?;
+
+        // The scene was recalled either way, so a journal that can't be written isn't an error.
+        let recorded = self
+            .config()
+            .store
+            .open()
+            .and_then(|store| journal::record(&*store, &Activation::now(group, scene)));
+        if let Err(e) = recorded {
+            esay!("Failed to record the scene in the journal: {}", e);
+        }
+        Ok(())
+    }
+
+    /// Turns a room on with its default scene from `default_scenes`, or turns its lights on in
+    /// their last state when it has none. Returns the name of the scene recalled, if any.
+    pub fn room_on(
+        &self,
+        room: &str,
+        default_scenes: &HashMap<String, String>,
+        fade: Option<Duration>,
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        let (id, group) = self.find_group(room)?;
+
+        let scene = default_scenes
+            .get(&group.name)
+            .or_else(|| default_scenes.get(&id));
+
+        match scene {
+            Some(scene) => {
+                let (scene_id, scene) = self.find_scene(scene)?;
+                self.recall_scene(&id, &scene_id, fade)?;
+                Ok(Some(scene.name))
+            }
+            None => {
+                self.set_power_by_indices(&group.lights, true, fade)?;
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl Hue {
+    /// Changes the state of all lights of a group, given by id, in one request. When automations
+    /// leave locked lights alone and the group has some, its other lights are changed one by one
+    /// instead, as the bridge can't leave lights of a group out.
+    pub fn set_group_state(
+        &self,
+        id: &str,
+        update: &LightStateUpdate,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.config().respect_locks {
+            let lights: Vec<String> = if id == "0" {
+                self.lights.keys().cloned().collect()
+            } else {
+                self.get_groups()?
+                    .remove(id)
+                    .map(|group| group.lights)
+                    .unwrap_or_default()
+            };
+            let unlocked = self.unlocked(&lights)?;
+
+            if unlocked.len() < lights.len() {
+                if update.scene.is_some() {
+                    return Err(From::from(format!(
+                        "Not recalling the scene on group {}, some of its lights are locked.",
+                        id
+                    )));
+                }
+
+                let changes: Vec<Request> = unlocked
+                    .iter()
+                    .map(|index| Request::light_state(index, update))
+                    .collect();
+                return self.put_all(&changes);
+            }
+        }
+
+        self.send_change(&Request::group_action(id, update))
+    }
+
+    /// Sets all lights of a group, given by name or id, to a color, e.x. "tomato" or "#ff8800",
+    /// and turns them on in one request. The brightness of the color is used unless one is
+    /// given. Lights with a multiplier in `scales` are set one by one instead, see
+    /// `set_group_scaled`.
+    pub fn set_group_color(
+        &self,
+        group: &str,
+        color: &str,
+        bri: Option<u8>,
+        scales: &HashMap<String, f32>,
+    ) -> Result<(), Box<dyn Error>> {
+        let rgb = colors::parse_color(color, &self.colors_file()?)?;
+        let (id, group) = self.find_group(group)?;
+
+        // The bridge moves the color into each light's gamut.
+        let xy = colors::XY::from_rgb(&rgb);
+        let bri = bri.unwrap_or(xy.brightness);
+        let update = LightStateUpdate::new().on(true).xy(xy.x, xy.y);
+        self.set_group_scaled(&id, &group, update, bri, scales)
+    }
+
+    /// Sets the brightness of all lights of a group, given by name or id, turning them on. Lights
+    /// with a multiplier in `scales` are set one by one instead, see `set_group_scaled`.
+    pub fn set_group_brightness(
+        &self,
+        group: &str,
+        bri: u8,
+        scales: &HashMap<String, f32>,
+    ) -> Result<(), Box<dyn Error>> {
+        let (id, group) = self.find_group(group)?;
+
+        self.set_group_scaled(&id, &group, LightStateUpdate::new().on(true), bri, scales)
+    }
+
+    /// Helper function to change a group with the given brightness. A group action gives all of
+    /// its lights the same brightness, so when any of them has a multiplier in `scales` each
+    /// light is changed on its own, with its brightness scaled.
+    fn set_group_scaled(
+        &self,
+        id: &str,
+        group: &Group,
+        update: LightStateUpdate,
+        bri: u8,
+        scales: &HashMap<String, f32>,
+    ) -> Result<(), Box<dyn Error>> {
+        let scale = |index: &String| self.brightness_scale(scales, index);
+        if group.lights.iter().all(|index| scale(index) == 1.0) {
+            return self.set_group_state(id, &update.bri(bri));
+        }
+
+        let changes: Vec<Request> = group
+            .lights
+            .iter()
+            .map(|index| {
+                let bri = scale_brightness(bri, scale(index));
+                Request::light_state(index, &update.clone().bri(bri))
+            })
+            .collect();
+        self.put_all(&changes)
+    }
+
+    /// Toggles a group, given by name or id. If any of its lights is on, turns them all off,
+    /// otherwise turns them all on. Returns the new power state.
+    pub fn toggle_group(&self, group: &str) -> Result<bool, Box<dyn Error>> {
+        let (id, group) = self.find_group(group)?;
+        let any_on = !self.lit_brightness(&group.lights).is_empty();
+
+        self.set_group_state(&id, &LightStateUpdate::new().on(!any_on))?;
+
+        Ok(!any_on)
+    }
+}
+
+/// Loads the default scene of each room from $HOME/.config/rusty_hue/default_scenes.json, which
+/// maps room names or ids to scene names or ids. A missing file means no defaults are set.
+pub fn load_default_scenes() -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let mut f = match File::open(files::config_file("default_scenes.json")?) {
+        Ok(f) => f,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(From::from(e)),
+    };
+
+    let mut contents = String::new();
+    f.read_to_string(&mut contents)?;
+
+    let scenes: HashMap<String, String> = serde_json::from_str(&contents)?;
+
+    Ok(scenes)
+}
+
+/// Loads per-light brightness multipliers from $HOME/.config/rusty_hue/brightness_scale.json,
+/// which maps light names or indices to a multiplier, e.x. {"Strip": 0.6}. A missing file means
+/// no light is scaled.
+pub fn load_brightness_scales() -> Result<HashMap<String, f32>, Box<dyn Error>> {
+    let mut f = match File::open(files::config_file("brightness_scale.json")?) {
+        Ok(f) => f,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(From::from(e)),
+    };
+
+    let mut contents = String::new();
+    f.read_to_string(&mut contents)?;
+
+    let scales: HashMap<String, f32> = serde_json::from_str(&contents)?;
+
+    if let Some((light, scale)) = scales.iter().find(|(_, &scale)| scale <= 0.0) {
+        return Err(From::from(format!(
+            "Invalid brightness scale {} for '{}', expected a number above 0.",
+            scale, light
+        )));
+    }
+
+    Ok(scales)
+}
+
+/// Scales a brightness by a multiplier, keeping it within the range lights accept.
+pub fn scale_brightness(bri: u8, scale: f32) -> u8 {
+    (f32::from(bri) * scale).round().clamp(1.0, 254.0) as u8
+}
+
+impl Hue {
+    /// The brightness multiplier for the light at the given index, looked up by its name and then
+    /// its index, or 1 when it has none.
+    pub fn brightness_scale(&self, scales: &HashMap<String, f32>, index: &str) -> f32 {
+        self.light_name(index)
+            .and_then(|name| scales.get(name))
+            .or_else(|| scales.get(index))
+            .cloned()
+            .unwrap_or(1.0)
+    }
+}
+
+/// Rounded mean of a set of brightness values, or `None` if there are none.
+pub fn mean_brightness(values: &[u8]) -> Option<u8> {
+    if values.is_empty() {
+        return None;
+    }
+
+    let total: u32 = values.iter().map(|&bri| u32::from(bri)).sum();
+
+    Some((total as f32 / values.len() as f32).round() as u8)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn make_group() {
+        let data = r#"{
+            "name": "Living room",
+            "lights": ["1", "2", "3"],
+            "type": "Room",
+            "class": "Living room",
+            "state": { "all_on": false, "any_on": true },
+            "action": { "on": true, "bri": 254 }
+        }"#;
+
+        let group: Group = serde_json::from_str(data).unwrap();
+
+        assert_eq!(group.group_type, "Room");
+        assert_eq!(group.lights.len(), 3);
+        assert_eq!(group.derived_bri, None);
+    }
+
+    #[test]
+    fn scaled_brightness() {
+        assert_eq!(scale_brightness(200, 0.6), 120);
+        assert_eq!(scale_brightness(200, 1.5), 254);
+        assert_eq!(scale_brightness(1, 0.1), 1);
+    }
+
+    #[test]
+    fn mean_of_brightness() {
+        assert_eq!(mean_brightness(&[]), None);
+        assert_eq!(mean_brightness(&[254]), Some(254));
+        assert_eq!(mean_brightness(&[100, 201]), Some(151));
+    }
+}
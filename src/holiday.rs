@@ -0,0 +1,219 @@
+//! # holiday
+//!
+//! Built-in holiday effects played with the animation engine: a Halloween candle flicker, a
+//! Christmas twinkle alternating red and green, and a New Year strobe. Each preset's colors,
+//! brightness, and speed can be changed. The twinkle and strobe repeat a fixed cycle and can be
+//! exported to the bridge; the flicker is random and needs this tool running.
+
+use std::error::Error;
+use std::time::Duration;
+
+use animation::{Animation, LightFrame};
+use colors;
+use colors::RGB;
+use shuffle::Rng;
+
+/// How an effect moves.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Effect {
+    /// Each light keeps a color and wavers in brightness, like a candle.
+    Flicker,
+    /// Neighbouring lights alternate colors, swapping every step, and one light sparkles.
+    Twinkle,
+    /// All lights flash together, changing color with each flash.
+    Strobe,
+}
+
+/// A holiday preset. Built-in presets are found with `preset` and their fields can be changed
+/// before playing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Holiday {
+    pub effect: Effect,
+    pub colors: Vec<RGB>,
+    /// Lowest and highest brightness.
+    pub bri: (u8, u8),
+    pub interval: Duration,
+}
+
+/// Names of the built-in presets.
+pub const PRESETS: [&str; 3] = ["halloween", "christmas", "newyear"];
+
+/// Returns a built-in preset by name.
+pub fn preset(name: &str) -> Result<Holiday, Box<dyn Error>> {
+    let rgb = |r, g, b| RGB { r, g, b };
+
+    match name {
+        "halloween" => Ok(Holiday {
+            effect: Effect::Flicker,
+            colors: vec![rgb(255, 100, 0), rgb(130, 0, 200)],
+            bri: (50, 200),
+            interval: Duration::from_millis(400),
+        }),
+        "christmas" => Ok(Holiday {
+            effect: Effect::Twinkle,
+            colors: vec![rgb(230, 20, 20), rgb(20, 180, 40)],
+            bri: (100, 254),
+            interval: Duration::from_secs(1),
+        }),
+        "newyear" => Ok(Holiday {
+            effect: Effect::Strobe,
+            colors: vec![rgb(255, 255, 255), rgb(255, 190, 60), rgb(200, 200, 255)],
+            bri: (20, 254),
+            interval: Duration::from_millis(500),
+        }),
+        _ => Err(From::from(format!(
+            "Unknown holiday '{}'. Available holidays: {}.",
+            name,
+            PRESETS.join(", ")
+        ))),
+    }
+}
+
+impl Holiday {
+    /// Checks the preset after its fields were changed.
+    pub fn check(&self) -> Result<(), Box<dyn Error>> {
+        if self.colors.is_empty() {
+            return Err(From::from("A holiday effect needs at least one color."));
+        }
+
+        if self.bri.0 > self.bri.1 {
+            return Err(From::from("The lowest brightness is above the highest."));
+        }
+
+        Ok(())
+    }
+
+    /// Helper function for the xy coordinates of the nth color.
+    fn xy(&self, n: usize) -> (f32, f32) {
+        let xy = colors::XY::from_rgb(&self.colors[n % self.colors.len()]);
+        (xy.x, xy.y)
+    }
+}
+
+impl Animation for Holiday {
+    fn frame(&mut self, step: u32, lights: &[String], rng: &mut Rng) -> Vec<LightFrame> {
+        let (low, high) = self.bri;
+        let step = step as usize;
+
+        match self.effect {
+            Effect::Flicker => lights
+                .iter()
+                .enumerate()
+                .map(|(i, index)| LightFrame {
+                    index: index.clone(),
+                    xy: Some(self.xy(i)),
+                    bri: rng.between(low, high),
+                    transition: self.interval / 2,
+                })
+                .collect(),
+            Effect::Twinkle => {
+                let sparkle = rng.below(lights.len());
+                lights
+                    .iter()
+                    .enumerate()
+                    .map(|(i, index)| LightFrame {
+                        index: index.clone(),
+                        xy: Some(self.xy(i + step)),
+                        bri: if i == sparkle { high } else { low },
+                        transition: self.interval / 4,
+                    })
+                    .collect()
+            }
+            Effect::Strobe => {
+                // Flashes on even steps and falls dark on odd ones.
+                let flash = step.is_multiple_of(2);
+                lights
+                    .iter()
+                    .map(|index| LightFrame {
+                        index: index.clone(),
+                        xy: Some(self.xy(step / 2)),
+                        bri: if flash { high } else { low },
+                        transition: Duration::from_secs(0),
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// The twinkle is exported without its random sparkle, and the strobe as it plays.
+    fn cycle(&self, lights: &[String]) -> Result<Vec<Vec<LightFrame>>, String> {
+        let steps = match self.effect {
+            Effect::Flicker => return Err(String::from("it picks random brightnesses")),
+            Effect::Twinkle => self.colors.len(),
+            Effect::Strobe => self.colors.len() * 2,
+        };
+
+        Ok((0..steps)
+            .map(|step| {
+                let mut frames = self
+                    .clone()
+                    .frame(step as u32, lights, &mut Rng::with_seed(0));
+                if self.effect == Effect::Twinkle {
+                    for frame in &mut frames {
+                        frame.bri = self.bri.1;
+                    }
+                }
+                frames
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lights() -> Vec<String> {
+        vec![String::from("1"), String::from("2"), String::from("3")]
+    }
+
+    #[test]
+    fn presets() {
+        for name in PRESETS.iter() {
+            assert!(preset(name).unwrap().check().is_ok());
+        }
+        assert!(preset("arbor day").is_err());
+    }
+
+    #[test]
+    fn twinkle_alternates() {
+        let mut christmas = preset("christmas").unwrap();
+        let mut rng = Rng::with_seed(7);
+
+        let first = christmas.frame(0, &lights(), &mut rng);
+        let second = christmas.frame(1, &lights(), &mut rng);
+
+        assert_eq!(first.len(), 3);
+        assert_ne!(first[0].xy, first[1].xy);
+        assert_eq!(first[0].xy, second[1].xy);
+        assert_eq!(first.iter().filter(|frame| frame.bri == 254).count(), 1);
+    }
+
+    #[test]
+    fn exported_cycles() {
+        let christmas = preset("christmas").unwrap();
+        let cycle = christmas.cycle(&lights()).unwrap();
+
+        assert_eq!(cycle.len(), 2);
+        assert_eq!(cycle[0][0].xy, cycle[1][1].xy);
+        assert!(cycle.iter().flatten().all(|frame| frame.bri == 254));
+
+        assert_eq!(
+            preset("newyear").unwrap().cycle(&lights()).unwrap().len(),
+            6
+        );
+        assert!(preset("halloween").unwrap().cycle(&lights()).is_err());
+    }
+
+    #[test]
+    fn strobe_flashes() {
+        let mut newyear = preset("newyear").unwrap();
+        let mut rng = Rng::with_seed(7);
+
+        let flash = newyear.frame(0, &lights(), &mut rng);
+        let dark = newyear.frame(1, &lights(), &mut rng);
+
+        assert!(flash.iter().all(|frame| frame.bri == 254));
+        assert!(dark.iter().all(|frame| frame.bri == 20));
+    }
+}
@@ -0,0 +1,198 @@
+//! # aio
+//!
+//! An asynchronous client for the bridge, for applications that run an event loop, e.x. a home
+//! automation daemon, and would otherwise need a thread per request. Requests return futures to
+//! be run on a tokio runtime, and fail with a `HueError`. Unlike `hue::Hue`, the client doesn't
+//! discover the bridge or keep the lights' state: it takes the bridge's address and token, and
+//! each call is one request.
+
+use futures::Future;
+use reqwest::async::Client;
+use reqwest::Method as HttpMethod;
+use serde_json::Value;
+use std::collections::HashMap;
+
+use hue::api::{decode_lights, read_response, Method, Request};
+use hue::{HueConfig, HueError, Light};
+use state::LightStateUpdate;
+
+/// Asynchronous client for a bridge.
+#[derive(Debug, Clone)]
+pub struct Hue {
+    ip: String,
+    token: String,
+    client: Client,
+}
+
+impl Hue {
+    /// A client for the bridge at the given IP, using the given API token.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// extern crate futures;
+    /// extern crate rusty_hue;
+    /// extern crate tokio;
+    ///
+    /// use futures::Future;
+    /// use rusty_hue::hue::aio::Hue;
+    /// use rusty_hue::state::LightStateUpdate;
+    ///
+    /// fn main() {
+    ///     let hue = Hue::new("192.168.1.2", "<token>");
+    ///     let on = LightStateUpdate::new().on(true);
+    ///
+    ///     tokio::run(
+    ///         hue.set_state("1", &on)
+    ///             .and_then(move |_| hue.get_lights())
+    ///             .map(|lights| println!("{} light(s)", lights.len()))
+    ///             .map_err(|e| eprintln!("{}", e)),
+    ///     );
+    /// }
+    /// ```
+    pub fn new(ip: &str, token: &str) -> Hue {
+        Hue::with_client(ip, token, Client::new())
+    }
+
+    /// Same as `new`, but with the timeouts of the given `HueConfig`. Requests aren't retried,
+    /// which is left to the caller's event loop.
+    pub fn with_config(ip: &str, token: &str, config: &HueConfig) -> Result<Hue, HueError> {
+        let client = Client::builder()
+            .connect_timeout(config.connect_timeout)
+            .timeout(config.request_timeout)
+            .build()
+            .map_err(|e| HueError::Network(e.to_string()))?;
+
+        Ok(Hue::with_client(ip, token, client))
+    }
+
+    /// Same as `new`, but makes every request with the given client, e.x. one built with custom
+    /// timeouts or a proxy. Clones of the client share its open connections.
+    pub fn with_client(ip: &str, token: &str, client: Client) -> Hue {
+        Hue {
+            ip: ip.to_string(),
+            token: token.to_string(),
+            client,
+        }
+    }
+
+    /// Gets a resource such as "lights" or "groups/1" from the bridge.
+    pub fn get(&self, resource: &str) -> impl Future<Item = Value, Error = HueError> {
+        self.request(Request::get(resource))
+    }
+
+    /// Gets all lights, by index.
+    pub fn get_lights(&self) -> impl Future<Item = HashMap<String, Light>, Error = HueError> {
+        self.get("lights").and_then(|json| {
+            decode_lights(&json, false).map_err(|e| match e.downcast_ref::<HueError>() {
+                Some(e) => e.clone(),
+                None => HueError::Parse(e.to_string()),
+            })
+        })
+    }
+
+    /// Changes the state of a single light given its index.
+    pub fn set_state(
+        &self,
+        index: &str,
+        update: &LightStateUpdate,
+    ) -> impl Future<Item = (), Error = HueError> {
+        self.request(Request::light_state(index, update))
+            .map(|_| ())
+    }
+
+    /// Changes the action of a group given its id, e.x. to recall a scene on it.
+    pub fn set_group_state(
+        &self,
+        id: &str,
+        update: &LightStateUpdate,
+    ) -> impl Future<Item = (), Error = HueError> {
+        self.request(Request::group_action(id, update)).map(|_| ())
+    }
+
+    /// Helper function to send a request and read the bridge's answer, turning the errors the
+    /// bridge reports into a `HueError`.
+    fn request(&self, request: Request) -> impl Future<Item = Value, Error = HueError> {
+        let method = match request.method {
+            Method::Get => HttpMethod::GET,
+            Method::Put => HttpMethod::PUT,
+        };
+        let mut builder = self
+            .client
+            .request(method, &request.url(&self.ip, &self.token));
+        if let Some(body) = request.body {
+            builder = builder.body(body);
+        }
+
+        let ip = self.ip.clone();
+        builder
+            .send()
+            .and_then(|mut response| response.text())
+            .map_err(|e| HueError::Network(e.to_string()))
+            .and_then(move |text| read_response(&text, &ip))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn get_lights() {
+        let bridge = mock::bridge();
+        let hue = Hue::new(bridge.address(), mock::TOKEN);
+
+        let lights = Runtime::new().unwrap().block_on(hue.get_lights()).unwrap();
+
+        assert_eq!(lights.len(), 2);
+        assert_eq!(lights["1"].name(), "Desk");
+        assert!(lights["1"].supports_color());
+        assert!(!lights["2"].supports_color());
+    }
+
+    #[test]
+    fn set_state() {
+        let bridge = mock::bridge();
+        let hue = Hue::new(bridge.address(), mock::TOKEN);
+        let mut runtime = Runtime::new().unwrap();
+
+        runtime
+            .block_on(hue.set_state("1", &LightStateUpdate::new().on(false)))
+            .unwrap();
+        runtime
+            .block_on(hue.set_group_state("0", &LightStateUpdate::new().scene("abc")))
+            .unwrap();
+
+        let changes = bridge.changes();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(
+            (&*changes[0].method, &*changes[0].path),
+            ("PUT", "lights/1/state")
+        );
+        assert_eq!(mock::body(&changes[0]), json!({"on": false}));
+        assert_eq!(
+            (&*changes[1].method, &*changes[1].path),
+            ("PUT", "groups/0/action")
+        );
+        assert_eq!(mock::body(&changes[1]), json!({"scene": "abc"}));
+    }
+
+    #[test]
+    fn bridge_errors() {
+        let bridge = mock::bridge();
+        let hue = Hue::new(bridge.address(), mock::TOKEN);
+        bridge.answer(
+            "PUT",
+            "lights/9/state",
+            vec![json!([{"error": {"type": 3, "address": "/lights/9/state",
+                "description": "resource, /lights/9/state, not available"}}])],
+        );
+
+        let error = Runtime::new()
+            .unwrap()
+            .block_on(hue.set_state("9", &LightStateUpdate::new().on(true)));
+        assert_eq!(error, Err(HueError::LightNotFound("9".to_string())));
+    }
+}
@@ -0,0 +1,203 @@
+//! # api
+//!
+//! The core of talking to the bridge, shared by the blocking `hue::Hue` and the asynchronous
+//! `aio::Hue` so the two can't drift apart: the requests operations make, and reading the
+//! bridge's answers, errors and lights included. Nothing here sends a request, which is left to
+//! each client with its own transport.
+
+use serde_json;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+
+use decode;
+use hue::{HueError, Light};
+use state;
+use state::LightStateUpdate;
+
+/// Fields of a light the bridge sends that aren't decoded.
+const KNOWN_LIGHT_FIELDS: [&str; 7] = [
+    "capabilities.certified",
+    "swupdate",
+    "productname",
+    "productid",
+    "swconfigid",
+    "luminaireuniqueid",
+    "state.mode",
+];
+
+/// Method of a request to the bridge.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    Get,
+    Put,
+}
+
+/// A request to the bridge, e.x. to change the state of a light, before it's sent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: Method,
+    /// The resource, relative to the API token, e.x. "lights/1/state".
+    pub resource: String,
+    /// JSON body of a change.
+    pub body: Option<String>,
+}
+
+impl Request {
+    /// Gets a resource such as "lights" or "groups/1".
+    pub fn get(resource: &str) -> Request {
+        Request {
+            method: Method::Get,
+            resource: resource.to_string(),
+            body: None,
+        }
+    }
+
+    /// Changes the state of a single light given its index.
+    pub fn light_state(index: &str, update: &LightStateUpdate) -> Request {
+        Request {
+            method: Method::Put,
+            resource: format!("lights/{}/state", index),
+            body: Some(
+                update
+                    .or_transition(state::default_transition())
+                    .to_string(),
+            ),
+        }
+    }
+
+    /// Changes the action of a group given its id, with group "0" being all lights.
+    pub fn group_action(id: &str, update: &LightStateUpdate) -> Request {
+        Request {
+            method: Method::Put,
+            resource: format!("groups/{}/action", id),
+            body: Some(
+                update
+                    .or_transition(state::default_transition())
+                    .to_string(),
+            ),
+        }
+    }
+
+    /// Index of the light whose state the request changes, if any.
+    pub fn light(&self) -> Option<&str> {
+        match self.resource.split('/').collect::<Vec<&str>>()[..] {
+            ["lights", index, "state"] => Some(index),
+            _ => None,
+        }
+    }
+
+    /// Full address of the request on the bridge at an IP.
+    pub fn url(&self, ip: &str, token: &str) -> String {
+        format!("http://{}{}", ip, path(token, &self.resource))
+    }
+}
+
+/// Address of a resource relative to the bridge, e.x. "/api/<token>/groups/1/action".
+pub fn path(token: &str, resource: &str) -> String {
+    format!("/api/{}/{}", token, resource)
+}
+
+/// Reads a response from the bridge as JSON.
+pub fn parse_response(body: &str) -> Result<Value, HueError> {
+    serde_json::from_str(body).map_err(|e| HueError::Parse(e.to_string()))
+}
+
+/// Reads the answer to a request: the bridge's JSON, or the first error the bridge at an IP
+/// reported in it.
+pub fn read_response(body: &str, ip: &str) -> Result<Value, HueError> {
+    let response = parse_response(body)?;
+
+    match bridge_error(&response, ip) {
+        Some(error) => Err(error),
+        None => Ok(response),
+    }
+}
+
+/// The first error in a bridge response, if any. The bridge reports errors with a successful
+/// status code and a body such as
+/// `[{"error": {"type": 3, "address": "...", "description": "..."}}]`.
+pub fn bridge_error(response: &Value, ip: &str) -> Option<HueError> {
+    response
+        .as_array()?
+        .iter()
+        .find(|item| item["error"].is_object())
+        .map(|item| HueError::from_bridge(&item["error"], ip))
+}
+
+/// Decodes the lights the bridge returns, by index. Indices have gaps where lights were deleted,
+/// which is common once third party lights have been added and removed. A light that can't be
+/// decoded is skipped unless `strict`, see `decode::decode_map`.
+pub fn decode_lights(json: &Value, strict: bool) -> Result<HashMap<String, Light>, Box<dyn Error>> {
+    let mut lights = match json.as_object() {
+        Some(lights) => lights.clone(),
+        None => {
+            return Err(Box::new(HueError::Parse(
+                "the bridge did not return any lights".to_string(),
+            )))
+        }
+    };
+
+    for light in lights.values_mut() {
+        // Only the light's own "type" is renamed, not e.x. "colorgamuttype" or a name such as
+        // "Prototype".
+        if let Some(fields) = light.as_object_mut() {
+            if let Some(light_type) = fields.remove("type") {
+                fields.insert(String::from("light_type"), light_type);
+            }
+        }
+    }
+
+    decode::decode_map(&Value::Object(lights), "light", &KNOWN_LIGHT_FIELDS, strict)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn requests() {
+        let request = Request::light_state("3", &LightStateUpdate::new().on(true));
+
+        assert_eq!(request.method, Method::Put);
+        assert_eq!(request.body, Some(String::from(r#"{"on":true}"#)));
+        assert_eq!(request.light(), Some("3"));
+        assert_eq!(
+            request.url("10.0.0.2", "abc"),
+            "http://10.0.0.2/api/abc/lights/3/state"
+        );
+        assert_eq!(
+            Request::group_action("0", &LightStateUpdate::new()).light(),
+            None
+        );
+    }
+
+    #[test]
+    fn read_errors() {
+        assert!(
+            read_response(r#"[{"success": {"/lights/1/state/on": true}}]"#, "10.0.0.2").is_ok()
+        );
+        assert_eq!(
+            read_response(
+                r#"[{"error": {"type": 1, "address": "/", "description": "unauthorized user"}}]"#,
+                "10.0.0.2"
+            ),
+            Err(HueError::Unauthorized(String::from("10.0.0.2")))
+        );
+        assert!(read_response("<html>", "10.0.0.2").is_err());
+    }
+
+    #[test]
+    fn skip_undecodable_lights() {
+        let json = json!({
+            "1": {"type": "Dimmable light", "name": "Desk"},
+            "2": {"type": "Dimmable light", "name": 5}
+        });
+
+        let lights = decode_lights(&json, false).unwrap();
+        assert_eq!(lights.len(), 1);
+        assert_eq!(lights["1"].light_type, "Dimmable light");
+
+        assert!(decode_lights(&json, true).is_err());
+    }
+}
@@ -0,0 +1,277 @@
+//! # https
+//!
+//! Connections to the bridge over HTTPS, which the v2 API and the event stream are only served
+//! over. The bridge's certificate is signed by the Hue bridge root CA and names the bridge by its
+//! id, and a connection is only made when both check out: the CA is read from the file of the
+//! bridge_ca setting, no other CA is trusted, and the name has to match the id of the bridge_id
+//! setting or, without one, the id the bridge reports in its config. Nothing falls back to a
+//! connection that isn't verified, so without the CA the v2 API and the event stream are simply
+//! unavailable.
+
+use native_tls::{Certificate, TlsConnector};
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::time::Duration;
+
+use hue::Answer;
+use throttle;
+
+/// Port the bridge serves HTTPS on.
+const PORT: u16 = 443;
+
+/// Makes requests to a bridge over connections verified against its root CA.
+#[derive(Debug)]
+pub(crate) struct Https {
+    host: String,
+    bridge_id: String,
+    connector: TlsConnector,
+    connect_timeout: Duration,
+}
+
+impl Https {
+    /// Verifies connections to the bridge at `ip` with the root CA in the PEM file `ca`, and
+    /// expects its certificate to name `bridge_id`.
+    pub fn new(
+        ip: &str,
+        bridge_id: &str,
+        ca: &Path,
+        connect_timeout: Duration,
+    ) -> Result<Https, Box<dyn Error>> {
+        let pem = fs::read(ca).map_err(|e| {
+            format!(
+                "Failed to read the bridge's root CA from {}: {}",
+                ca.display(),
+                e
+            )
+        })?;
+        let connector = TlsConnector::builder()
+            .add_root_certificate(Certificate::from_pem(&pem)?)
+            .disable_built_in_roots(true)
+            .build()?;
+
+        // The bridge is reached by IP, any port given is that of its plain HTTP API.
+        let host = match ip.rsplit_once(':') {
+            Some((host, _)) => host,
+            None => ip,
+        };
+
+        Ok(Https {
+            host: host.to_string(),
+            // Certificates name the bridge by its id in lower case, e.x. "001788fffe4b5a6c".
+            bridge_id: bridge_id.to_lowercase(),
+            connector,
+            connect_timeout,
+        })
+    }
+
+    /// Sends a request, e.x. ("GET", "/clip/v2/resource/light"), and returns the answer with its
+    /// body left to be read. The connection gives up when the bridge is silent for `timeout`.
+    pub fn send(
+        &self,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &str,
+        timeout: Duration,
+    ) -> Result<Response, Box<dyn Error>> {
+        let address = (self.host.as_str(), PORT)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| format!("Failed to resolve {}.", self.host))?;
+        let stream = TcpStream::connect_timeout(&address, self.connect_timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+
+        let mut stream = self
+            .connector
+            .connect(&self.bridge_id, stream)
+            .map_err(|e| {
+                format!(
+                    "Failed to verify the bridge's certificate for {}: {}",
+                    self.bridge_id, e
+                )
+            })?;
+
+        let mut request = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Length: {}\r\n",
+            method,
+            path,
+            self.host,
+            body.len()
+        );
+        for &(name, value) in headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        request.push_str("\r\n");
+        request.push_str(body);
+        stream.write_all(request.as_bytes())?;
+        stream.flush()?;
+
+        read_response(BufReader::new(stream))
+    }
+}
+
+/// An answer of the bridge, with its body read as it arrives, e.x. line by line for the event
+/// stream.
+pub(crate) struct Response {
+    pub status: u16,
+    headers: Vec<(String, String)>,
+    pub body: Box<dyn BufRead + Send>,
+}
+
+impl Response {
+    /// Value of a header, whatever the case of its name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Reads the whole body as text.
+    pub fn text(mut self) -> io::Result<String> {
+        let mut text = String::new();
+        self.body.read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    /// Reads the whole answer, for `Hue::send`.
+    pub fn answer(self) -> io::Result<Answer> {
+        let status = self.status;
+        let retry_after = self.header("Retry-After").and_then(throttle::retry_after);
+
+        Ok(Answer {
+            status,
+            retry_after,
+            body: self.text()?,
+        })
+    }
+}
+
+/// Helper function to read the status line and headers of an answer, leaving the body to be read
+/// in whichever of the three ways the bridge sends it: chunked, with its length, or until the
+/// connection is closed.
+fn read_response<R: BufRead + Send + 'static>(mut reader: R) -> Result<Response, Box<dyn Error>> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let status = line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| format!("Failed to read the bridge's answer: {}", line.trim()))?;
+
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    let mut response = Response {
+        status,
+        headers,
+        body: Box::new(io::empty()),
+    };
+    let chunked = response
+        .header("Transfer-Encoding")
+        .is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked"));
+    let length = response
+        .header("Content-Length")
+        .and_then(|length| length.parse().ok());
+
+    response.body = match length {
+        _ if chunked => Box::new(BufReader::new(Chunked {
+            inner: reader,
+            left: 0,
+            done: false,
+        })),
+        Some(length) => Box::new(reader.take(length)),
+        None => Box::new(reader),
+    };
+    Ok(response)
+}
+
+/// Reads a body sent in chunks, each preceded by its size in hex and ended by an empty chunk.
+struct Chunked<R> {
+    inner: R,
+    /// Bytes left in the current chunk.
+    left: u64,
+    done: bool,
+}
+
+impl<R: BufRead> Read for Chunked<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.left == 0 {
+            let mut line = String::new();
+            self.inner.read_line(&mut line)?;
+            let size = line.split(';').next().unwrap_or_default().trim();
+            self.left = u64::from_str_radix(size, 16)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid chunk size"))?;
+            if self.left == 0 {
+                self.done = true;
+                return Ok(0);
+            }
+        }
+
+        let max = buf.len().min(self.left as usize);
+        let read = self.inner.read(&mut buf[..max])?;
+        if read == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        self.left -= read as u64;
+
+        // Every chunk ends with a line break of its own.
+        if self.left == 0 {
+            self.inner.read_line(&mut String::new())?;
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Helper function to read an answer from text.
+    fn response(text: &str) -> Response {
+        read_response(Cursor::new(text.as_bytes().to_vec())).unwrap()
+    }
+
+    #[test]
+    fn read_responses() {
+        let answer = response(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 2\r\n\r\n{}extra",
+        );
+        assert_eq!(answer.status, 200);
+        assert_eq!(answer.header("content-type"), Some("application/json"));
+        assert_eq!(answer.text().unwrap(), "{}");
+
+        let answer = response(
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 2\r\n\
+             Transfer-Encoding: chunked\r\n\r\n4\r\n{\"a\"\r\n3;x=y\r\n: 1\r\n1\r\n}\r\n0\r\n\r\n",
+        );
+        assert_eq!(answer.status, 429);
+        assert_eq!(answer.header("Retry-After"), Some("2"));
+        assert_eq!(answer.text().unwrap(), "{\"a\": 1}");
+
+        let answer = response("HTTP/1.1 200 OK\r\n\r\nid: 1\ndata: []\n\n");
+        let lines: Vec<String> = answer.body.lines().map(Result::unwrap).collect();
+        assert_eq!(lines, ["id: 1", "data: []", ""]);
+
+        assert!(read_response(Cursor::new(b"garbage\r\n\r\n".to_vec())).is_err());
+    }
+}
@@ -0,0 +1,551 @@
+//! # v2
+//!
+//! Client for the bridge's newer API at /clip/v2/resource, where resources are addressed by UUID.
+//! It's only served over HTTPS, verified as described in `https`, with the API token sent in the
+//! "hue-application-key" header. Every resource also carries its address in the v1 API, e.x.
+//! "/lights/3", which is how lights are matched with the indices used everywhere else.
+//!
+//! Changes to single lights go through this API when the bridge serves it, and through the v1 API
+//! otherwise or when a change can't be expressed in it, e.x. hue and saturation. Groups and scenes
+//! are still only changed through the v1 API.
+
+use serde::de::DeserializeOwned;
+use serde_json;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::time::Duration;
+
+use cache;
+use files;
+
+use hue::https::Https;
+use hue::{Answer, Hue, HueError};
+use state::LightStateUpdate;
+
+/// First firmware of the bridge to serve the v2 API, and with it the event stream.
+const FIRMWARE: u64 = 1_948_086_000;
+
+/// How long what was found out about the v2 API of a bridge is trusted, see `Known`.
+const KNOWN_FOR: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Which API changes to single lights go through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Api {
+    /// The v2 API when the bridge serves it and its certificate can be verified, the v1 API
+    /// otherwise.
+    #[default]
+    Auto,
+    V1,
+    /// The v2 API, failing when it can't be used.
+    V2,
+}
+
+impl Api {
+    /// Parses the name of an API, "auto", "v1", or "v2".
+    pub fn parse(api: &str) -> Result<Api, Box<dyn Error>> {
+        match api {
+            "auto" => Ok(Api::Auto),
+            "v1" => Ok(Api::V1),
+            "v2" => Ok(Api::V2),
+            _ => Err(From::from(format!(
+                "Unknown API '{}', expected 'auto', 'v1', or 'v2'.",
+                api
+            ))),
+        }
+    }
+}
+
+/// What was found out about the v2 API of a bridge, kept between runs in
+/// $HOME/.config/rusty_hue/v2.json, keyed by bridge IP. Runs within `KNOWN_FOR` of each other
+/// neither ask the bridge for its lights again nor wait again on a v2 API that couldn't be
+/// reached.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct Known {
+    /// When it was found out, in seconds since the unix epoch.
+    checked: u64,
+    /// Why the v2 API couldn't be reached, or None when it could.
+    unreachable: Option<String>,
+    /// Id of each light by its v1 index.
+    lights: BTreeMap<String, String>,
+}
+
+/// Helper function to load what was found out about the v2 API of the bridge at an IP, if it's
+/// still trusted.
+fn load_known(ip: &str) -> Option<Known> {
+    let path = files::config_file("v2.json").ok()?;
+    let contents = fs::read_to_string(path).ok()?;
+    let mut all: BTreeMap<String, Known> = serde_json::from_str(&contents).ok()?;
+
+    all.remove(ip)
+        .filter(|known| cache::now().saturating_sub(known.checked) < KNOWN_FOR.as_secs())
+}
+
+/// Helper function to save what was found out about the v2 API of the bridge at an IP, keeping
+/// that of other bridges.
+fn save_known(ip: &str, known: &Known) -> Result<(), Box<dyn Error>> {
+    let path = files::config_file("v2.json")?;
+    let mut all: BTreeMap<String, Known> = match fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents)?,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+        Err(e) => return Err(From::from(e)),
+    };
+    all.insert(ip.to_string(), known.clone());
+
+    files::write_atomic(&path, serde_json::to_string_pretty(&all)?.as_bytes())?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Metadata {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct On {
+    pub on: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct Dimming {
+    /// In percent, 0-100.
+    pub brightness: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct Color {
+    pub xy: Point,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct ColorTemperature {
+    /// None when the light isn't showing a color temperature.
+    pub mirek: Option<u16>,
+}
+
+/// A light, e.x. from GET /clip/v2/resource/light.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Light {
+    pub id: String,
+    /// Address in the v1 API, e.x. "/lights/3".
+    pub id_v1: Option<String>,
+    pub metadata: Metadata,
+    pub on: On,
+    pub dimming: Option<Dimming>,
+    pub color: Option<Color>,
+    pub color_temperature: Option<ColorTemperature>,
+}
+
+impl Light {
+    /// Index of the light in the v1 API, e.x. "3".
+    pub fn index(&self) -> Option<&str> {
+        self.id_v1
+            .as_ref()
+            .and_then(|id| id.strip_prefix("/lights/"))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorDescription {
+    description: String,
+}
+
+/// Every answer of the v2 API has this shape, with errors next to whatever data there is.
+#[derive(Debug, Deserialize)]
+struct Response<T> {
+    #[serde(default)]
+    errors: Vec<ErrorDescription>,
+    #[serde(default = "Vec::new")]
+    data: Vec<T>,
+}
+
+/// Reads an answer of the v2 API, returning its data or the errors the bridge reported.
+pub fn parse<T: DeserializeOwned>(body: &str) -> Result<Vec<T>, Box<dyn Error>> {
+    let response: Response<T> = serde_json::from_str(body)?;
+
+    if response.errors.is_empty() {
+        return Ok(response.data);
+    }
+
+    let errors: Vec<&str> = response
+        .errors
+        .iter()
+        .map(|e| e.description.as_str())
+        .collect();
+    Err(From::from(format!(
+        "The bridge answered: {}",
+        errors.join(", ")
+    )))
+}
+
+/// Converts a change to a light into a body for PUT /clip/v2/resource/light/<id>, or None if it
+/// has fields with no counterpart, e.x. hue and saturation, steps, alerts, and effects.
+///
+/// # Example
+///
+/// ```
+/// #[macro_use]
+/// extern crate serde_json;
+/// extern crate rusty_hue;
+///
+/// use rusty_hue::hue::v2;
+/// use rusty_hue::state::LightStateUpdate;
+///
+/// # fn main() {
+/// let update = LightStateUpdate::new().on(true).ct(300);
+///
+/// assert_eq!(
+///     v2::light_body(&update),
+///     Some(json!({"on": {"on": true}, "color_temperature": {"mirek": 300}}))
+/// );
+/// # }
+/// ```
+pub fn light_body(update: &LightStateUpdate) -> Option<Value> {
+    let unsupported = update.hue.is_some()
+        || update.sat.is_some()
+        || update.bri_inc.is_some()
+        || update.hue_inc.is_some()
+        || update.ct_inc.is_some()
+        || update.alert.is_some()
+        || update.effect.is_some()
+        || update.scene.is_some();
+    if unsupported {
+        return None;
+    }
+
+    let mut body = Map::new();
+    if let Some(on) = update.on {
+        body.insert(String::from("on"), json!({ "on": on }));
+    }
+    if let Some(bri) = update.bri {
+        let brightness = round(f32::from(bri) / 254.0 * 100.0).min(100.0);
+        body.insert(String::from("dimming"), json!({ "brightness": brightness }));
+    }
+    if let Some([x, y]) = update.xy {
+        body.insert(
+            String::from("color"),
+            json!({"xy": {"x": round(x), "y": round(y)}}),
+        );
+    }
+    if let Some(ct) = update.ct {
+        body.insert(String::from("color_temperature"), json!({ "mirek": ct }));
+    }
+    if let Some(transition) = update.transition {
+        let duration = u32::from(transition) * 100;
+        body.insert(String::from("dynamics"), json!({ "duration": duration }));
+    }
+
+    Some(Value::Object(body))
+}
+
+/// Helper function to round a number to the 4 decimals the bridge keeps, so 0.3 isn't sent as
+/// 0.30000001192092896.
+fn round(n: f32) -> f64 {
+    (f64::from(n) * 10_000.0).round() / 10_000.0
+}
+
+/// Makes requests of the v2 API of a bridge, which are sent through `Hue::send` like those of
+/// the v1 API.
+#[derive(Debug)]
+pub struct Client {
+    https: Https,
+    key: String,
+    timeout: Duration,
+}
+
+impl Client {
+    /// A client for the bridge behind the verified connections, authorized with an API token of
+    /// the v1 API, giving up on requests after `timeout`.
+    pub(crate) fn new(https: Https, key: &str, timeout: Duration) -> Client {
+        Client {
+            https,
+            key: key.to_string(),
+            timeout,
+        }
+    }
+
+    /// Sends a request for a resource, e.x. ("GET", "light") or ("PUT", "light/<id>"), and reads
+    /// the answer.
+    fn answer(&self, method: &str, resource: &str, body: &str) -> Result<Answer, Box<dyn Error>> {
+        let headers = [
+            ("hue-application-key", self.key.as_str()),
+            ("Content-Type", "application/json"),
+        ];
+        let path = format!("/clip/v2/resource/{}", resource);
+
+        Ok(self
+            .https
+            .send(method, &path, &headers, body, self.timeout)?
+            .answer()?)
+    }
+}
+
+/// A client for the v2 API along with the id of each light by its v1 index.
+#[derive(Debug)]
+pub(crate) struct Bridge {
+    pub client: Client,
+    pub lights: BTreeMap<String, String>,
+}
+
+impl Bridge {
+    /// Connects to the v2 API, failing when the bridge doesn't serve it or its certificate can't
+    /// be verified. The lights are only asked for when they aren't `Known`, and with `api` "auto"
+    /// a v2 API known to be unreachable isn't tried again.
+    fn connect(hue: &Hue, api: Api) -> Result<Bridge, Box<dyn Error>> {
+        let client = Client::new(hue.https()?, hue.token(), hue.config().request_timeout);
+
+        match load_known(&hue.ip) {
+            Some(Known {
+                unreachable: Some(e),
+                ..
+            }) if api == Api::Auto => return Err(From::from(e)),
+            Some(Known {
+                unreachable: None,
+                lights,
+                ..
+            }) => return Ok(Bridge { client, lights }),
+            _ => (),
+        }
+
+        // With "auto" the v1 API is there to fall back to, so an unreachable v2 API isn't waited
+        // on again and again.
+        let lights = hue.request_v2::<Light>(&client, "GET", "light", "", api == Api::V2);
+        let unreachable = match lights {
+            Err(ref e) => match e.downcast_ref::<HueError>() {
+                Some(&HueError::Network(_)) => Some(e.to_string()),
+                // Anything else, e.x. a rejected token, is reported every time.
+                _ => return Err(lights.unwrap_err()),
+            },
+            Ok(_) => None,
+        };
+        let lights: BTreeMap<String, String> = lights
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|light| {
+                light
+                    .index()
+                    .map(|index| (index.to_string(), light.id.clone()))
+            })
+            .collect();
+
+        let known = Known {
+            checked: cache::now(),
+            unreachable: unreachable.clone(),
+            lights: lights.clone(),
+        };
+        // What was found out is only an optimization, so failing to save it isn't an error.
+        let _ = save_known(&hue.ip, &known);
+
+        match unreachable {
+            Some(e) => Err(From::from(e)),
+            None => Ok(Bridge { client, lights }),
+        }
+    }
+}
+
+impl Hue {
+    /// Whether the bridge's firmware serves the v2 API, judging by its (cached) config.
+    pub fn supports_v2(&self) -> bool {
+        self.fetch("config", true)
+            .ok()
+            .and_then(|config| config["swversion"].as_str().and_then(|v| v.parse().ok()))
+            .is_some_and(|version: u64| version >= FIRMWARE)
+    }
+
+    /// The v2 API of the bridge when changes should go through it. It's connected to once, on
+    /// first use, and when that fails changes go through the v1 API instead, unless
+    /// `HueConfig::api` is `Api::V2`.
+    pub(crate) fn v2(&self) -> Result<Option<&Bridge>, Box<dyn Error>> {
+        let api = self.config.api;
+        if api == Api::V1 {
+            return Ok(None);
+        }
+
+        let bridge = self.v2.get_or_init(|| {
+            if api == Api::Auto && !self.supports_v2() {
+                return Err(String::from(
+                    "The bridge's firmware doesn't serve the v2 API.",
+                ));
+            }
+            self.profile
+                .time("v2 connect", || Bridge::connect(self, api))
+                .map_err(|e| format!("Failed to reach the v2 API of the bridge: {}", e))
+        });
+
+        match *bridge {
+            Ok(ref bridge) => Ok(Some(bridge)),
+            Err(ref e) if api == Api::V2 => Err(From::from(e.clone())),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Helper function to send a request of the v2 API through `send`, so it's paced, retried with
+    /// `retry`, and sampled like those of the v1 API, returning the answer's data. A rejected API
+    /// token is `HueError::Unauthorized`, as with the v1 API.
+    fn request_v2<T: DeserializeOwned>(
+        &self,
+        client: &Client,
+        method: &str,
+        resource: &str,
+        body: &str,
+        retry: bool,
+    ) -> Result<Vec<T>, Box<dyn Error>> {
+        let url = format!("https://{}/clip/v2/resource/{}", self.ip, resource);
+        let answer = self.send(method, &url, retry, || {
+            client.answer(method, resource, body)
+        })?;
+
+        if answer.status == 401 || answer.status == 403 {
+            return Err(Box::new(HueError::Unauthorized(self.ip.clone())));
+        }
+        parse(&answer.body)
+    }
+
+    /// Sends a change to a single light through the v2 API, or returns None when it should go
+    /// through the v1 API instead.
+    pub(crate) fn set_light_state_v2(
+        &self,
+        index: &str,
+        update: &LightStateUpdate,
+    ) -> Option<Result<(), Box<dyn Error>>> {
+        let bridge = match self.v2() {
+            Ok(Some(bridge)) => bridge,
+            Ok(None) => return None,
+            Err(e) => return Some(Err(e)),
+        };
+        let id = bridge.lights.get(index)?;
+        let body = light_body(update)?;
+
+        let result = self
+            .request_v2::<Value>(
+                &bridge.client,
+                "PUT",
+                &format!("light/{}", id),
+                &body.to_string(),
+                true,
+            )
+            .and_then(|_| self.invalidate_cache());
+
+        if result.is_ok() {
+            self.applied
+                .record(&format!("lights/{}/state", index), &update.to_string());
+        }
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hue::HueConfig;
+    use mock;
+    use mock::MockBridge;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn resources() {
+        let body = r#"{"errors": [], "data": [{
+            "id": "3a6710fa-4474-4eba-b533-5e6e72968feb",
+            "id_v1": "/lights/3",
+            "type": "light",
+            "metadata": {"name": "Desk", "archetype": "sultan_bulb"},
+            "on": {"on": true},
+            "dimming": {"brightness": 50.2, "min_dim_level": 0.2},
+            "color": {"xy": {"x": 0.4573, "y": 0.41}, "gamut_type": "C"},
+            "color_temperature": {"mirek": null, "mirek_valid": false}
+        }]}"#;
+
+        let lights: Vec<Light> = parse(body).unwrap();
+        assert_eq!(lights.len(), 1);
+        assert_eq!(lights[0].index(), Some("3"));
+        assert_eq!(lights[0].metadata.name, "Desk");
+        assert!(lights[0].on.on);
+        assert_eq!(lights[0].dimming, Some(Dimming { brightness: 50.2 }));
+        assert_eq!(
+            lights[0].color_temperature,
+            Some(ColorTemperature { mirek: None })
+        );
+
+        let body = r#"{"errors": [{"description": "unauthorized user"}], "data": []}"#;
+        let error = parse::<Light>(body).unwrap_err();
+        assert_eq!(error.to_string(), "The bridge answered: unauthorized user");
+    }
+
+    #[test]
+    fn light_bodies() {
+        let update = LightStateUpdate::new()
+            .on(true)
+            .bri(254)
+            .xy(0.3, 0.5)
+            .transition(4);
+        assert_eq!(
+            light_body(&update),
+            Some(json!({
+                "on": {"on": true},
+                "dimming": {"brightness": 100.0},
+                "color": {"xy": {"x": 0.3, "y": 0.5}},
+                "dynamics": {"duration": 400}
+            }))
+        );
+
+        assert_eq!(light_body(&LightStateUpdate::new().hue(1000)), None);
+        assert_eq!(light_body(&LightStateUpdate::new().bri_inc(-20)), None);
+    }
+
+    #[test]
+    fn api_fallback() {
+        let update = LightStateUpdate::new().bri(100);
+        let missing_ca = env::temp_dir().join(format!("rusty_hue_ca_{}.pem", process::id()));
+        let connect = |bridge: &MockBridge, api| {
+            let config = HueConfig {
+                api,
+                bridge_ca: Some(missing_ca.clone()),
+                ..HueConfig::default()
+            };
+            Hue::with_config(Some(bridge.address()), Some(mock::TOKEN), None, config).unwrap()
+        };
+        let bridge_with_firmware = |version: &str| {
+            MockBridge::start(json!({
+                "lights": mock::lights(),
+                "config": {"swversion": version, "bridgeid": "001788FFFE000000"}
+            }))
+        };
+
+        let bridge = bridge_with_firmware("1948086000");
+        connect(&bridge, Api::V1)
+            .set_light_state("1", &update)
+            .unwrap();
+        assert_eq!(bridge.changes()[0].path, "lights/1/state");
+        assert!(!bridge.requests().iter().any(|r| r.path == "config"));
+
+        // Firmware from before the v2 API.
+        let bridge = bridge_with_firmware("1941132000");
+        connect(&bridge, Api::Auto)
+            .set_light_state("1", &update)
+            .unwrap();
+        assert_eq!(bridge.changes()[0].path, "lights/1/state");
+
+        // Firmware with the v2 API but no root CA to verify the bridge with.
+        let bridge = bridge_with_firmware("1948086000");
+        connect(&bridge, Api::Auto)
+            .set_light_state("1", &update)
+            .unwrap();
+        assert_eq!(bridge.changes()[0].path, "lights/1/state");
+
+        let bridge = bridge_with_firmware("1948086000");
+        let error = connect(&bridge, Api::V2)
+            .set_light_state("1", &update)
+            .unwrap_err();
+        assert!(error.to_string().contains("root CA"));
+        assert!(bridge.changes().is_empty());
+    }
+}
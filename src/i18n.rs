@@ -0,0 +1,182 @@
+//! # i18n
+//!
+//! Hooks for translating the messages printed by the command line tool. Messages are written in
+//! English and looked up in a catalog for the user's language, which maps each English message to
+//! its translation, e.x. {"Turning lights {}...": "Lichter werden {}geschaltet..."}. Each `{}` is
+//! filled in with the message's arguments in order. Catalogs are read from
+//! $HOME/.config/rusty_hue/lang/<lang>.json, or from /usr/share/rusty_hue/lang/<lang>.json where
+//! packagers install them. Messages without a translation are printed in English. The `say!` and
+//! `esay!` macros print a translated message to stdout and stderr.
+
+use serde_json;
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use files;
+
+/// Where packagers install catalogs.
+const SYSTEM_CATALOGS: &str = "/usr/share/rusty_hue/lang";
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+/// Translations of messages, keyed by the English message.
+#[derive(Debug, Default)]
+pub struct Catalog {
+    messages: HashMap<String, String>,
+}
+
+impl Catalog {
+    /// Loads the catalog of a language, e.x. "de_DE.UTF-8", trying the language with its region
+    /// and then without, e.x. "de_DE" and then "de". English, and languages without a catalog,
+    /// give an empty catalog.
+    pub fn load(lang: &str) -> Result<Catalog, Box<dyn Error>> {
+        for name in catalog_names(lang) {
+            for path in catalog_paths(&name)? {
+                let mut f = match File::open(&path) {
+                    Ok(f) => f,
+                    Err(ref e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(From::from(e)),
+                };
+
+                let mut contents = String::new();
+                f.read_to_string(&mut contents)?;
+
+                let messages: HashMap<String, String> = serde_json::from_str(&contents)
+                    .map_err(|e| format!("Invalid catalog {}: {}", path.display(), e))?;
+                return Catalog::from_messages(messages);
+            }
+        }
+
+        Ok(Catalog::default())
+    }
+
+    /// Creates a catalog from translations keyed by the English message. A translation must keep
+    /// the number of `{}` of its message.
+    pub fn from_messages(messages: HashMap<String, String>) -> Result<Catalog, Box<dyn Error>> {
+        if let Some((message, _)) = messages.iter().find(|(message, translation)| {
+            message.matches("{}").count() != translation.matches("{}").count()
+        }) {
+            return Err(From::from(format!(
+                "The translation of '{}' doesn't fill in the same number of values.",
+                message
+            )));
+        }
+
+        Ok(Catalog { messages })
+    }
+
+    /// Translates a message, filling in its arguments.
+    pub fn format(&self, message: &str, args: &[&dyn fmt::Display]) -> String {
+        let template = self.messages.get(message).map_or(message, String::as_str);
+        let mut args = args.iter();
+
+        template
+            .split("{}")
+            .enumerate()
+            .fold(String::new(), |mut out, (i, part)| {
+                if i > 0 {
+                    if let Some(arg) = args.next() {
+                        out.push_str(&arg.to_string());
+                    }
+                }
+                out.push_str(part);
+                out
+            })
+    }
+}
+
+/// The user's language from the environment, as gettext looks for it: LC_ALL, LC_MESSAGES, and
+/// then LANG.
+pub fn detect_language() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|lang| !lang.is_empty())
+}
+
+/// Helper function for the catalog names to try for a language, most specific first. The C and
+/// POSIX locales and English have no catalog.
+fn catalog_names(lang: &str) -> Vec<String> {
+    let lang = lang.split(['.', '@']).next().unwrap_or_default();
+    let base = lang.split('_').next().unwrap_or_default();
+
+    if base.is_empty() || ["C", "POSIX", "en"].contains(&base) {
+        return Vec::new();
+    }
+
+    let mut names = vec![lang.to_string()];
+    if base != lang {
+        names.push(base.to_string());
+    }
+    names
+}
+
+/// Helper function for the paths a catalog is looked for at, the user's first.
+fn catalog_paths(name: &str) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let file = format!("{}.json", name);
+
+    Ok(vec![
+        files::config_file("lang")?.join(&file),
+        PathBuf::from(SYSTEM_CATALOGS).join(file),
+    ])
+}
+
+/// Sets the catalog messages are translated with. Only the first catalog set is used.
+pub fn set_catalog(catalog: Catalog) {
+    let _ = CATALOG.set(catalog);
+}
+
+/// Translates a message with the catalog that was set, filling in its arguments, e.x.
+/// `tr("Turning {} lights {}...", &[&3, &"on"])`.
+pub fn tr(message: &str, args: &[&dyn fmt::Display]) -> String {
+    match CATALOG.get() {
+        Some(catalog) => catalog.format(message, args),
+        None => Catalog::default().format(message, args),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn translate() {
+        let mut messages = HashMap::new();
+        messages.insert(
+            String::from("Turning {} lights {}..."),
+            String::from("{} Lampen werden {}geschaltet..."),
+        );
+        let catalog = Catalog::from_messages(messages).unwrap();
+
+        assert_eq!(
+            catalog.format("Turning {} lights {}...", &[&3, &"ein"]),
+            "3 Lampen werden eingeschaltet..."
+        );
+        assert_eq!(
+            catalog.format("Light '{}' has been powered on.", &[&"Desk"]),
+            "Light 'Desk' has been powered on."
+        );
+
+        let mut broken = HashMap::new();
+        broken.insert(
+            String::from("Toggling {}..."),
+            String::from("Umschalten..."),
+        );
+        assert!(Catalog::from_messages(broken).is_err());
+    }
+
+    #[test]
+    fn language_names() {
+        assert_eq!(catalog_names("de_DE.UTF-8"), vec!["de_DE", "de"]);
+        assert_eq!(catalog_names("fr"), vec!["fr"]);
+        assert!(catalog_names("en_US.UTF-8").is_empty());
+        assert!(catalog_names("C").is_empty());
+    }
+}
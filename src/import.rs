@@ -0,0 +1,706 @@
+//! # import
+//!
+//! Applies a file describing the desired lights, groups, and scenes to the bridge. The file uses
+//! the same JSON layout as the bridge, e.x. `{"groups": {"1": {"name": "Living", ...}}}`, and only
+//! the resource types and fields it mentions are compared. Changes are computed as a plan against
+//! the live bridge first so they can be reviewed before anything is applied.
+//!
+//! `export` writes the bridge in the same layout, keeping only the fields an import can set, so
+//! the bridge can be backed up and restored, or set up the same on another.
+//!
+//! Exports of other tools are mapped onto the bridge by light name instead, see `Format`. They
+//! only create and update groups and scenes, leaving the rest of the bridge alone.
+
+use serde_json;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+use hue::{Hue, LightConfig};
+
+/// Resource types that can be imported, in the order they are applied. Lights can only be
+/// updated, since the bridge adds and removes lights itself.
+pub const RESOURCES: [&str; 3] = ["lights", "groups", "scenes"];
+
+/// Fields of each resource type kept in exports, besides the config of lights.
+const EXPORT_FIELDS: [(&str, &[&str]); 3] = [
+    ("lights", &["name"]),
+    ("groups", &["name", "type", "class", "lights"]),
+    (
+        "scenes",
+        &["name", "type", "group", "lights", "lightstates"],
+    ),
+];
+
+/// Fields of a light state kept from the scenes of other tools.
+const SCENE_STATE_FIELDS: [&str; 7] = ["on", "bri", "ct", "xy", "hue", "sat", "effect"];
+
+/// Layout of an import file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// The bridge's own layout, keyed by id.
+    Bridge,
+    /// A Hue Essentials export, with rooms, zones, and scenes naming their lights, e.x.
+    /// `{"rooms": [{"name": "Living", "lights": ["Desk"]}], "scenes": [{"name": "Relax",
+    /// "lights": {"Desk": {"on": true, "bri": 144, "ct": 447}}}]}`.
+    HueEssentials,
+}
+
+impl Format {
+    /// Parses a format given on the command line: "bridge" or "hue-essentials".
+    pub fn parse(value: &str) -> Result<Format, Box<dyn Error>> {
+        match value {
+            "bridge" => Ok(Format::Bridge),
+            "hue-essentials" => Ok(Format::HueEssentials),
+            _ => Err(From::from(format!(
+                "Invalid format '{}', expected bridge or hue-essentials.",
+                value
+            ))),
+        }
+    }
+}
+
+/// What a planned change does to a resource.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Create,
+    Update,
+    Delete,
+}
+
+/// A single change to a resource on the bridge.
+#[derive(Debug, PartialEq)]
+pub struct Change {
+    pub resource: &'static str,
+    /// Id of the resource on the bridge, or in the import file for resources to create. Empty
+    /// for resources that aren't keyed by id, e.x. the bridge's settings.
+    pub id: String,
+    pub action: Action,
+    /// Fields to send to the bridge. Empty for deletions.
+    pub fields: Map<String, Value>,
+    /// Current values on the bridge of the fields being changed, and the name of the resource.
+    pub current: Map<String, Value>,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (symbol, name) = match self.action {
+            Action::Create => ('+', &self.fields["name"]),
+            Action::Update => ('~', &self.current["name"]),
+            Action::Delete => ('-', &self.current["name"]),
+        };
+
+        if self.id.is_empty() {
+            write!(f, "  {} {} {}", symbol, self.resource, name)?;
+        } else {
+            write!(f, "  {} {}/{} {}", symbol, self.resource, self.id, name)?;
+        }
+
+        for (key, value) in &self.fields {
+            match self.current.get(key) {
+                Some(current) if self.action == Action::Update => {
+                    write!(f, "\n      {}: {} -> {}", key, current, value)?
+                }
+                _ => write!(f, "\n      {}: {}", key, value)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Number of changes in a plan with the given action, e.x. the resources to create.
+pub fn count(changes: &[Change], action: Action) -> usize {
+    changes.iter().filter(|c| c.action == action).count()
+}
+
+/// Reads an import file.
+pub fn read_import_file(path: &Path) -> Result<Map<String, Value>, Box<dyn Error>> {
+    let mut f = File::open(path)?;
+    let mut contents = String::new();
+    f.read_to_string(&mut contents)?;
+
+    match serde_json::from_str(&contents)? {
+        Value::Object(desired) => Ok(desired),
+        _ => Err(From::from(format!(
+            "{} must contain a JSON object keyed by resource type.",
+            path.display()
+        ))),
+    }
+}
+
+/// Builds an import file from the live resources, keeping the fields an import can set. Of the
+/// config of lights, only the fields the bridge lets be changed are kept.
+pub fn export(live: &BTreeMap<&str, Value>) -> Map<String, Value> {
+    let mut file = Map::new();
+
+    for &(resource, keep) in EXPORT_FIELDS.iter() {
+        let resources = match live.get(resource).and_then(Value::as_object) {
+            Some(resources) => resources,
+            None => continue,
+        };
+
+        let mut exported = Map::new();
+        for (id, value) in resources {
+            let mut fields: Map<String, Value> = keep
+                .iter()
+                .filter_map(|&key| value.get(key).map(|field| (key.to_string(), field.clone())))
+                .collect();
+
+            if resource == "lights" {
+                let config: LightConfig =
+                    serde_json::from_value(value["config"].clone()).unwrap_or_default();
+                let config = config.writable();
+                if !config.is_empty() {
+                    fields.insert(String::from("config"), Value::Object(config));
+                }
+            }
+
+            exported.insert(id.clone(), Value::Object(fields));
+        }
+        file.insert(resource.to_string(), Value::Object(exported));
+    }
+
+    file
+}
+
+/// Computes the changes needed to turn the live resources into the desired ones. Resource types
+/// missing from `desired` are left alone.
+pub fn plan(
+    desired: &Map<String, Value>,
+    live: &BTreeMap<&str, Value>,
+) -> Result<Vec<Change>, Box<dyn Error>> {
+    if let Some(key) = desired
+        .keys()
+        .find(|key| !RESOURCES.contains(&key.as_str()))
+    {
+        return Err(From::from(format!(
+            "Unknown resource type '{}' in import file, expected one of: {}.",
+            key,
+            RESOURCES.join(", ")
+        )));
+    }
+
+    let mut changes = Vec::new();
+
+    for &resource in RESOURCES.iter() {
+        let wanted = match desired.get(resource) {
+            Some(Value::Object(wanted)) => wanted,
+            Some(_) => {
+                return Err(From::from(format!(
+                    "'{}' in import file must be an object keyed by id.",
+                    resource
+                )))
+            }
+            None => continue,
+        };
+        let empty = Map::new();
+        let existing = live
+            .get(resource)
+            .and_then(|value| value.as_object())
+            .unwrap_or(&empty);
+
+        for (id, fields) in wanted {
+            let fields = match *fields {
+                Value::Object(ref fields) => fields,
+                _ => {
+                    return Err(From::from(format!(
+                        "{}/{} in import file must be an object.",
+                        resource, id
+                    )))
+                }
+            };
+
+            match existing.get(id) {
+                Some(current) => {
+                    let mut changed = Map::new();
+                    let mut before = Map::new();
+                    before.insert("name".to_string(), current["name"].clone());
+
+                    for (key, value) in fields {
+                        // Only the fields of a light's config that are given are compared, as
+                        // some can't be changed.
+                        if resource == "lights" && key == "config" {
+                            if let Some(value) = changed_fields(&current[key], value) {
+                                changed.insert(key.clone(), value);
+                                before.insert(key.clone(), current[key].clone());
+                            }
+                        } else if current.get(key) != Some(value) {
+                            changed.insert(key.clone(), value.clone());
+                            before.insert(key.clone(), current[key].clone());
+                        }
+                    }
+
+                    if !changed.is_empty() {
+                        changes.push(Change {
+                            resource,
+                            id: id.clone(),
+                            action: Action::Update,
+                            fields: changed,
+                            current: before,
+                        });
+                    }
+                }
+                None if resource == "lights" => {
+                    return Err(From::from(format!(
+                        "Light {} in import file doesn't exist on the bridge.",
+                        id
+                    )))
+                }
+                None => {
+                    if !fields.contains_key("name") {
+                        return Err(From::from(format!(
+                            "{}/{} in import file needs a name to be created.",
+                            resource, id
+                        )));
+                    }
+
+                    changes.push(Change {
+                        resource,
+                        id: id.clone(),
+                        action: Action::Create,
+                        fields: fields.clone(),
+                        current: Map::new(),
+                    });
+                }
+            }
+        }
+
+        if resource == "lights" {
+            continue;
+        }
+
+        for (id, current) in existing {
+            if !wanted.contains_key(id) {
+                let mut before = Map::new();
+                before.insert("name".to_string(), current["name"].clone());
+
+                changes.push(Change {
+                    resource,
+                    id: id.clone(),
+                    action: Action::Delete,
+                    fields: Map::new(),
+                    current: before,
+                });
+            }
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Helper function to find the fields of `wanted` that differ from `current`, going into objects
+/// so fields `wanted` leaves out aren't compared.
+fn changed_fields(current: &Value, wanted: &Value) -> Option<Value> {
+    match (current, wanted) {
+        (Value::Object(current), Value::Object(wanted)) => {
+            let changed: Map<String, Value> = wanted
+                .iter()
+                .filter_map(|(key, value)| {
+                    let current = current.get(key).unwrap_or(&Value::Null);
+                    changed_fields(current, value).map(|value| (key.clone(), value))
+                })
+                .collect();
+
+            if changed.is_empty() {
+                None
+            } else {
+                Some(Value::Object(changed))
+            }
+        }
+        _ if current == wanted => None,
+        _ => Some(wanted.clone()),
+    }
+}
+
+/// Helper function to find the id of a resource on the bridge by name.
+fn id_by_name(live: &BTreeMap<&str, Value>, resource: &str, name: &str) -> Option<String> {
+    live.get(resource)?
+        .as_object()?
+        .iter()
+        .find(|(_, value)| value["name"] == name)
+        .map(|(id, _)| id.clone())
+}
+
+/// Helper function to find the index of a light on the bridge by name, for the error naming what
+/// the light belongs to, e.x. "room 'Living'".
+fn light_by_name(
+    live: &BTreeMap<&str, Value>,
+    name: &Value,
+    owner: &str,
+) -> Result<String, Box<dyn Error>> {
+    name.as_str()
+        .and_then(|name| id_by_name(live, "lights", name))
+        .ok_or_else(|| From::from(format!("Light {} of {} isn't on the bridge.", name, owner)))
+}
+
+/// Maps a Hue Essentials export onto the live lights, groups, and scenes, giving the desired
+/// groups and scenes in the bridge's layout. Rooms, zones, and scenes named like one on the
+/// bridge update it, and the others are created. Scenes are created as light scenes, holding
+/// the lights they set.
+pub fn from_hue_essentials(
+    export: &Map<String, Value>,
+    live: &BTreeMap<&str, Value>,
+) -> Result<Map<String, Value>, Box<dyn Error>> {
+    let entries = |key: &str| -> Vec<Value> {
+        export
+            .get(key)
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default()
+    };
+    let name_of = |entry: &Value, kind: &str| -> Result<String, Box<dyn Error>> {
+        match entry["name"].as_str() {
+            Some(name) => Ok(name.to_string()),
+            None => Err(From::from(format!("A {} in the export has no name.", kind))),
+        }
+    };
+    let mut created = 0;
+    let mut key_for = |resource: &str, name: &str| match id_by_name(live, resource, name) {
+        Some(id) => (id, false),
+        None => {
+            created += 1;
+            (format!("new-{}", created), true)
+        }
+    };
+
+    let mut groups = Map::new();
+    for (kind, group_type) in [("rooms", "Room"), ("zones", "Zone")].iter() {
+        for group in entries(kind) {
+            let name = name_of(&group, "group")?;
+            let owner = format!("'{}'", name);
+            let lights = match group["lights"].as_array() {
+                Some(lights) => lights
+                    .iter()
+                    .map(|light| light_by_name(live, light, &owner).map(Value::from))
+                    .collect::<Result<Vec<Value>, _>>()?,
+                None => Vec::new(),
+            };
+
+            let (id, new) = key_for("groups", &name);
+            let mut fields = Map::new();
+            if new {
+                fields.insert("name".to_string(), Value::from(name));
+                fields.insert("type".to_string(), Value::from(*group_type));
+            }
+            fields.insert("lights".to_string(), Value::Array(lights));
+            groups.insert(id, Value::Object(fields));
+        }
+    }
+
+    let mut scenes = Map::new();
+    for scene in entries("scenes") {
+        let name = name_of(&scene, "scene")?;
+        let owner = format!("scene '{}'", name);
+        let mut lights = Vec::new();
+        let mut lightstates = Map::new();
+
+        if let Some(states) = scene["lights"].as_object() {
+            for (light, state) in states {
+                let index = light_by_name(live, &Value::from(light.as_str()), &owner)?;
+                let state: Map<String, Value> = SCENE_STATE_FIELDS
+                    .iter()
+                    .filter_map(|field| Some((field.to_string(), state.get(*field)?.clone())))
+                    .collect();
+                lights.push(Value::from(index.as_str()));
+                lightstates.insert(index, Value::Object(state));
+            }
+        }
+
+        let (id, new) = key_for("scenes", &name);
+        let mut fields = Map::new();
+        if new {
+            fields.insert("name".to_string(), Value::from(name));
+            fields.insert("type".to_string(), Value::from("LightScene"));
+        }
+        fields.insert("lights".to_string(), Value::Array(lights));
+        fields.insert("lightstates".to_string(), Value::Object(lightstates));
+        scenes.insert(id, Value::Object(fields));
+    }
+
+    let mut desired = Map::new();
+    desired.insert("groups".to_string(), Value::Object(groups));
+    desired.insert("scenes".to_string(), Value::Object(scenes));
+    Ok(desired)
+}
+
+impl Hue {
+    /// Plans the changes needed for the bridge to match an import file of the given format,
+    /// comparing against fresh state from the bridge rather than the state cache. Files from
+    /// other tools never delete anything.
+    pub fn plan_import(
+        &self,
+        file: &Map<String, Value>,
+        format: Format,
+    ) -> Result<Vec<Change>, Box<dyn Error>> {
+        let resources: Vec<&str> = RESOURCES
+            .iter()
+            .cloned()
+            .filter(|resource| format != Format::Bridge || file.contains_key(*resource))
+            .collect();
+        let live = self.fetch_resources(&resources)?;
+
+        match format {
+            Format::Bridge => plan(file, &live),
+            Format::HueEssentials => {
+                let mut changes = plan(&from_hue_essentials(file, &live)?, &live)?;
+                changes.retain(|change| change.action != Action::Delete);
+                Ok(changes)
+            }
+        }
+    }
+
+    /// Builds an import file of the bridge's lights, groups, and scenes, see `export`.
+    pub fn export_resources(&self) -> Result<Map<String, Value>, Box<dyn Error>> {
+        Ok(export(&self.fetch_resources(&RESOURCES)?))
+    }
+
+    /// Helper function to fetch resource types fresh from the bridge. The bridge lists scenes
+    /// without their light states, so those are fetched scene by scene.
+    fn fetch_resources<'a>(
+        &self,
+        resources: &[&'a str],
+    ) -> Result<BTreeMap<&'a str, Value>, Box<dyn Error>> {
+        let mut live = BTreeMap::new();
+
+        for &resource in resources {
+            let mut fetched = self.fetch(resource, false)?;
+            if resource == "scenes" {
+                for (id, scene) in fetched.as_object_mut().into_iter().flatten() {
+                    let stored = self.fetch(&format!("scenes/{}", id), false)?;
+                    if let Some(states) = stored.get("lightstates") {
+                        scene["lightstates"] = states.clone();
+                    }
+                }
+            }
+            live.insert(resource, fetched);
+        }
+
+        Ok(live)
+    }
+
+    /// Applies a single planned change to the bridge.
+    pub fn apply_change(&self, change: &Change) -> Result<(), Box<dyn Error>> {
+        match change.action {
+            Action::Create => {
+                let url = self.api_url(change.resource);
+                let body = Value::Object(change.fields.clone()).to_string();
+                self.post(&url, body)?;
+            }
+            Action::Update => {
+                let path = if change.id.is_empty() {
+                    change.resource.to_string()
+                } else {
+                    format!("{}/{}", change.resource, change.id)
+                };
+                let mut fields = change.fields.clone();
+
+                // How a light is set up is changed apart from the rest of it.
+                if change.resource == "lights" {
+                    if let Some(config) = fields.remove("config") {
+                        let url = self.api_url(&format!("{}/config", path));
+                        self.put(&url, config.to_string())?;
+                    }
+                }
+
+                // The light states of a scene are changed one light at a time.
+                if let Some(Value::Object(states)) = fields.remove("lightstates") {
+                    for (light, state) in states {
+                        let url = self.api_url(&format!("{}/lightstates/{}", path, light));
+                        self.put(&url, state.to_string())?;
+                    }
+                }
+
+                if !fields.is_empty() {
+                    self.put(&self.api_url(&path), Value::Object(fields).to_string())?;
+                }
+            }
+            Action::Delete => {
+                let url = self.api_url(&format!("{}/{}", change.resource, change.id));
+                self.delete(&url)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock;
+    use mock::MockBridge;
+
+    fn object(data: &str) -> Map<String, Value> {
+        match serde_json::from_str(data).unwrap() {
+            Value::Object(map) => map,
+            _ => panic!("not an object"),
+        }
+    }
+
+    #[test]
+    fn plan_changes() {
+        let desired = object(
+            r#"{
+                "lights": { "1": { "name": "Desk" }, "2": { "name": "Reading lamp" } },
+                "groups": { "new": { "name": "Office", "lights": ["1"] } }
+            }"#,
+        );
+
+        let mut live = BTreeMap::new();
+        live.insert(
+            "lights",
+            Value::Object(object(
+                r#"{ "1": { "name": "Desk" }, "2": { "name": "Lamp" }, "3": { "name": "Strip" } }"#,
+            )),
+        );
+        live.insert(
+            "groups",
+            Value::Object(object(
+                r#"{ "1": { "name": "Living", "lights": ["1", "2"] } }"#,
+            )),
+        );
+
+        let changes = plan(&desired, &live).unwrap();
+        let actions: Vec<_> = changes
+            .iter()
+            .map(|c| (c.resource, c.id.as_str(), c.action))
+            .collect();
+
+        assert_eq!(
+            actions,
+            vec![
+                ("lights", "2", Action::Update),
+                ("groups", "new", Action::Create),
+                ("groups", "1", Action::Delete),
+            ]
+        );
+        assert_eq!(
+            changes[0].to_string(),
+            "  ~ lights/2 \"Lamp\"\n      name: \"Lamp\" -> \"Reading lamp\""
+        );
+        assert_eq!(count(&changes, Action::Create), 1);
+        assert_eq!(count(&changes, Action::Delete), 1);
+    }
+
+    #[test]
+    fn hue_essentials_by_light_name() {
+        let export = object(
+            r#"{
+                "rooms": [{ "name": "Living", "lights": ["Desk", "Lamp"] }],
+                "zones": [{ "name": "Reading", "lights": ["Lamp"] }],
+                "scenes": [{ "name": "Relax", "lights": { "Desk": { "on": true, "bri": 144, "id": 7 } } }]
+            }"#,
+        );
+
+        let mut live = BTreeMap::new();
+        live.insert(
+            "lights",
+            Value::Object(object(
+                r#"{ "1": { "name": "Desk" }, "2": { "name": "Lamp" } }"#,
+            )),
+        );
+        live.insert(
+            "groups",
+            Value::Object(object(r#"{ "4": { "name": "Living", "lights": ["1"] } }"#)),
+        );
+
+        let desired = from_hue_essentials(&export, &live).unwrap();
+
+        assert_eq!(
+            Value::Object(desired),
+            serde_json::from_str::<Value>(
+                r#"{
+                    "groups": {
+                        "4": { "lights": ["1", "2"] },
+                        "new-1": { "name": "Reading", "type": "Zone", "lights": ["2"] }
+                    },
+                    "scenes": {
+                        "new-2": {
+                            "name": "Relax",
+                            "type": "LightScene",
+                            "lights": ["1"],
+                            "lightstates": { "1": { "on": true, "bri": 144 } }
+                        }
+                    }
+                }"#
+            )
+            .unwrap()
+        );
+
+        let export = object(r#"{ "rooms": [{ "name": "Office", "lights": ["Strip"] }] }"#);
+        assert!(from_hue_essentials(&export, &live).is_err());
+    }
+
+    #[test]
+    fn plan_errors() {
+        let live = BTreeMap::new();
+
+        assert!(plan(&object(r#"{ "sensors": {} }"#), &live).is_err());
+        assert!(plan(
+            &object(r#"{ "lights": { "9": { "name": "New" } } }"#),
+            &live
+        )
+        .is_err());
+        assert!(plan(&object(r#"{ "groups": { "1": { "lights": [] } } }"#), &live).is_err());
+    }
+
+    #[test]
+    fn light_configs() {
+        let mut live = BTreeMap::new();
+        live.insert(
+            "lights",
+            Value::Object(object(
+                r#"{ "1": { "name": "Desk", "config": { "archetype": "sultanbulb",
+                    "direction": "omnidirectional", "startup": { "mode": "safety",
+                    "configured": true } } } }"#,
+            )),
+        );
+
+        let exported = export(&live);
+        assert_eq!(
+            Value::Object(exported.clone()),
+            json!({"lights": {"1": {"name": "Desk", "config": {"archetype": "sultanbulb",
+                "startup": {"mode": "safety"}}}}})
+        );
+        assert_eq!(plan(&exported, &live).unwrap(), vec![]);
+
+        let desired = object(
+            r#"{ "lights": { "1": { "config": { "startup": { "mode": "powerfail" } } } } }"#,
+        );
+        let changes = plan(&desired, &live).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            Value::Object(changes[0].fields.clone()),
+            json!({"config": {"startup": {"mode": "powerfail"}}})
+        );
+    }
+
+    #[test]
+    fn exported_scenes_keep_light_states() {
+        let scene = json!({"name": "Relax", "type": "LightScene", "lights": ["1"]});
+        let mut stored = scene.clone();
+        stored["lightstates"] = json!({"1": {"on": true, "bri": 144, "ct": 447}});
+        let bridge = MockBridge::start(json!({
+            "lights": mock::lights(),
+            "groups": {},
+            "scenes": {"1": stored}
+        }));
+        // As the bridge does, scenes are listed without their light states.
+        bridge.answer("GET", "scenes", vec![json!({ "1": scene })]);
+        let hue = bridge.hue();
+
+        let file = hue.export_resources().unwrap();
+        assert_eq!(
+            file["scenes"]["1"]["lightstates"],
+            json!({"1": {"on": true, "bri": 144, "ct": 447}})
+        );
+
+        // So the export imports back without changes.
+        assert!(hue.plan_import(&file, Format::Bridge).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,223 @@
+//! # ipc
+//!
+//! A small local socket protocol for hotkey daemons and Stream Deck style plugins, where
+//! spawning a new process per keypress is too slow. Each request is a single line of JSON, e.x.
+//! `{"command": "toggle", "light": "Desk"}`, answered by a single line of JSON such as
+//! `{"ok":true,"on":false}`. Requests without a light apply to all lights.
+
+use serde_json;
+use std::error::Error;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+
+#[cfg(unix)]
+use std::sync::Arc;
+#[cfg(unix)]
+use std::thread;
+
+#[cfg(unix)]
+use std::fs;
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+
+use hue::{Hue, HueError};
+use parse;
+
+/// A single request read from the socket.
+#[derive(Debug, Deserialize)]
+pub struct IpcRequest {
+    pub command: String,
+    #[serde(default)]
+    pub light: Option<String>,
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub fade: Option<String>,
+}
+
+/// The reply written back for each request.
+#[derive(Debug, Serialize)]
+pub struct IpcResponse {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl IpcResponse {
+    fn success(on: Option<bool>) -> IpcResponse {
+        IpcResponse {
+            ok: true,
+            on,
+            error: None,
+        }
+    }
+
+    fn failure(error: String) -> IpcResponse {
+        IpcResponse {
+            ok: false,
+            on: None,
+            error: Some(error),
+        }
+    }
+}
+
+/// Runs a single request against the bridge. Returns the new power state for commands that
+/// change it.
+pub fn handle_request(hue: &mut Hue, request: &IpcRequest) -> Result<Option<bool>, Box<dyn Error>> {
+    let index = match request.light {
+        Some(ref light) => Some(resolve_light(hue, light)?),
+        None => None,
+    };
+
+    let fade = match request.fade {
+        Some(ref fade) => Some(parse::parse_duration(fade)?),
+        None => None,
+    };
+
+    match request.command.as_ref() {
+        "toggle" => {
+            hue.refresh()?;
+            let on = match index {
+                Some(index) => hue.toggle_by_index(&index)?,
+                None => hue.toggle_lights()?,
+            };
+            Ok(Some(on))
+        }
+        "on" | "off" => {
+            let power = request.command == "on";
+            hue.refresh()?;
+            match index {
+                Some(index) => hue.set_power_by_index(&index, power, fade)?,
+                None => hue.set_power_all(power, fade)?,
+            }
+            Ok(Some(power))
+        }
+        "color" => {
+            let color = match request.color {
+                Some(ref color) => color,
+                None => return Err(From::from("The color command requires a color.")),
+            };
+            match index {
+                Some(index) => hue.set_color_by_index_and_color(&index, color)?,
+                None => hue.set_all_by_color(color)?,
+            }
+            Ok(None)
+        }
+        command => Err(From::from(format!("Unknown command '{}'.", command))),
+    }
+}
+
+/// Helper function to find the index of a light by name or index. Lights may have been added or
+/// renamed since the server started, so state is fetched again before giving up.
+fn resolve_light(hue: &mut Hue, light: &str) -> Result<String, Box<dyn Error>> {
+    if let Some(index) = hue.light_index(light) {
+        return Ok(index.to_string());
+    }
+
+    hue.refresh()?;
+    match hue.light_index(light) {
+        Some(index) => Ok(index.to_string()),
+        None => Err(Box::new(HueError::LightNotFound(light.to_string()))),
+    }
+}
+
+/// Answers newline separated JSON requests from a single client until it disconnects. The Hue
+/// system is only held while a request runs, so clients connected at once take turns per request.
+pub fn handle_connection<S: Read + Write>(hue: &Mutex<Hue>, stream: S) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<IpcRequest>(&line) {
+            Ok(request) => {
+                // A request that panicked leaves nothing half done worth refusing others over.
+                let mut hue = hue.lock().unwrap_or_else(PoisonError::into_inner);
+                match handle_request(&mut hue, &request) {
+                    Ok(on) => IpcResponse::success(on),
+                    Err(e) => IpcResponse::failure(e.to_string()),
+                }
+            }
+            Err(e) => IpcResponse::failure(format!("Invalid request: {}", e)),
+        };
+
+        let stream = reader.get_mut();
+        writeln!(stream, "{}", serde_json::to_string(&response)?)?;
+        stream.flush()?;
+    }
+}
+
+/// Listens on a unix socket at the given path, serving each client on its own thread so an idle
+/// or slow client doesn't hold up the others. A stale socket left at the path by a previous run is
+/// replaced.
+#[cfg(unix)]
+pub fn serve(hue: Hue, path: &Path) -> Result<(), Box<dyn Error>> {
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(From::from(format!(
+                "'{}' already exists and is not a socket.",
+                path.display()
+            )));
+        }
+        fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+
+    let hue = Arc::new(Mutex::new(hue));
+
+    for stream in listener.incoming().flatten() {
+        let hue = Arc::clone(&hue);
+        thread::spawn(move || {
+            // A client hanging up mid-request shouldn't bring the server down.
+            let _ = handle_connection(&hue, stream);
+        });
+    }
+
+    Ok(())
+}
+
+/// Local sockets are currently only implemented for unix platforms.
+#[cfg(not(unix))]
+pub fn serve(_hue: Hue, _path: &Path) -> Result<(), Box<dyn Error>> {
+    Err(From::from("IPC mode is only supported on unix platforms."))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_request() {
+        let request: IpcRequest =
+            serde_json::from_str(r#"{"command": "toggle", "light": "Desk"}"#).unwrap();
+
+        assert_eq!(request.command, "toggle");
+        assert_eq!(request.light, Some(String::from("Desk")));
+        assert_eq!(request.color, None);
+    }
+
+    #[test]
+    fn serialize_response() {
+        let ok = serde_json::to_string(&IpcResponse::success(Some(true))).unwrap();
+        let err = serde_json::to_string(&IpcResponse::failure(String::from("Nope"))).unwrap();
+
+        assert_eq!(ok, r#"{"ok":true,"on":true}"#);
+        assert_eq!(err, r#"{"ok":false,"error":"Nope"}"#);
+    }
+}
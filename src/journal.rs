@@ -0,0 +1,196 @@
+//! # journal
+//!
+//! A local journal of the scenes recalled with this tool, kept as the "journal" history of the
+//! store, by default $HOME/.config/rusty_hue/journal.jsonl with one activation per line, e.x.
+//! {"at":"2018-06-01T21:30:00","group":"1","scene":"abc"}. It shows which scenes are used and
+//! when, and gives presence simulation the times rooms are really turned on.
+
+use serde_json;
+use std::collections::HashMap;
+use std::error::Error;
+use time;
+
+use parse::TimeOfDay;
+use store::Store;
+
+/// Fewest activations of a room before its usual time is learned from them.
+const MIN_LEARNED: usize = 3;
+
+/// Earliest activation taken as an evening one when learning a room's usual time, in minutes
+/// since midnight.
+const EVENING: u32 = 16 * 60;
+
+/// A scene recalled on a group.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Activation {
+    /// Local time of the activation, e.x. "2018-06-01T21:30:00".
+    pub at: String,
+    pub group: String,
+    pub scene: String,
+}
+
+impl Activation {
+    /// An activation of a scene on a group now.
+    pub fn now(group: &str, scene: &str) -> Activation {
+        Activation {
+            at: time::now()
+                .strftime("%Y-%m-%dT%H:%M:%S")
+                .unwrap()
+                .to_string(),
+            group: group.to_string(),
+            scene: scene.to_string(),
+        }
+    }
+
+    /// Minutes since midnight of the activation, `None` if its time can't be read.
+    pub fn minutes(&self) -> Option<u32> {
+        let (_, at) = self.at.split_once('T')?;
+        let mut parts = at.split(':').map(str::parse::<u32>);
+
+        match (parts.next(), parts.next()) {
+            (Some(Ok(hour)), Some(Ok(minute))) if hour < 24 && minute < 60 => {
+                Some(hour * 60 + minute)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Name of the journal's history in the store.
+const HISTORY: &str = "journal";
+
+/// Appends an activation to the journal in a store.
+pub fn record(store: &dyn Store, activation: &Activation) -> Result<(), Box<dyn Error>> {
+    store.append(HISTORY, &serde_json::to_value(activation)?)
+}
+
+/// Loads the journal from a store, oldest first. Activations that can't be read are skipped. A
+/// missing journal is empty.
+pub fn load(store: &dyn Store) -> Result<Vec<Activation>, Box<dyn Error>> {
+    Ok(store
+        .history(HISTORY)?
+        .into_iter()
+        .filter_map(|record| serde_json::from_value(record).ok())
+        .collect())
+}
+
+/// How often a scene was activated and when.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneUsage {
+    pub scene: String,
+    pub count: usize,
+    /// Median time of day of the activations.
+    pub typical: Option<TimeOfDay>,
+    /// Time of the latest activation.
+    pub last: String,
+}
+
+/// Helper function for the median of minutes since midnight, as a time of day.
+fn median(mut minutes: Vec<u32>) -> Option<TimeOfDay> {
+    if minutes.is_empty() {
+        return None;
+    }
+
+    minutes.sort_unstable();
+    let median = minutes[minutes.len() / 2];
+
+    Some(TimeOfDay {
+        hour: (median / 60) as u8,
+        minute: (median % 60) as u8,
+        second: 0,
+    })
+}
+
+/// Usage of each scene in the journal, most used first.
+pub fn scene_usage(activations: &[Activation]) -> Vec<SceneUsage> {
+    let mut by_scene: HashMap<&str, Vec<&Activation>> = HashMap::new();
+    for activation in activations {
+        by_scene
+            .entry(activation.scene.as_str())
+            .or_default()
+            .push(activation);
+    }
+
+    let mut usage: Vec<SceneUsage> = by_scene
+        .into_iter()
+        .map(|(scene, activations)| SceneUsage {
+            scene: scene.to_string(),
+            count: activations.len(),
+            typical: median(activations.iter().filter_map(|a| a.minutes()).collect()),
+            last: activations
+                .iter()
+                .map(|a| a.at.as_str())
+                .max()
+                .unwrap_or_default()
+                .to_string(),
+        })
+        .collect();
+
+    usage.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.scene.cmp(&b.scene)));
+    usage
+}
+
+/// The usual evening time each group is turned on with a scene, in minutes since midnight, for
+/// groups activated often enough in the evening to tell.
+pub fn learned_evenings(activations: &[Activation]) -> HashMap<String, u32> {
+    let mut by_group: HashMap<&str, Vec<u32>> = HashMap::new();
+    for activation in activations {
+        if let Some(minutes) = activation.minutes().filter(|&minutes| minutes >= EVENING) {
+            by_group
+                .entry(activation.group.as_str())
+                .or_default()
+                .push(minutes);
+        }
+    }
+
+    by_group
+        .into_iter()
+        .filter(|(_, minutes)| minutes.len() >= MIN_LEARNED)
+        .filter_map(|(group, minutes)| {
+            let typical = median(minutes)?;
+            Some((group.to_string(), typical.seconds() / 60))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn activation(at: &str, group: &str, scene: &str) -> Activation {
+        Activation {
+            at: at.to_string(),
+            group: group.to_string(),
+            scene: scene.to_string(),
+        }
+    }
+
+    fn journal() -> Vec<Activation> {
+        vec![
+            activation("2018-06-01T21:30:00", "1", "relax"),
+            activation("2018-06-02T22:10:00", "1", "relax"),
+            activation("2018-06-03T21:00:00", "1", "relax"),
+            activation("2018-06-03T07:00:00", "2", "bright"),
+            activation("2018-06-04T19:45:00", "2", "bright"),
+        ]
+    }
+
+    #[test]
+    fn usage_by_scene() {
+        let usage = scene_usage(&journal());
+
+        assert_eq!(usage.len(), 2);
+        assert_eq!(usage[0].scene, "relax");
+        assert_eq!(usage[0].count, 3);
+        assert_eq!(usage[0].typical.unwrap().to_string(), "21:30:00");
+        assert_eq!(usage[0].last, "2018-06-03T21:00:00");
+    }
+
+    #[test]
+    fn learned_evening_times() {
+        let learned = learned_evenings(&journal());
+
+        assert_eq!(learned.len(), 1);
+        assert_eq!(learned["1"], 21 * 60 + 30);
+    }
+}
@@ -1,9 +1,99 @@
+extern crate futures;
+extern crate native_tls;
 extern crate reqwest;
+#[cfg(feature = "sqlite")]
+extern crate rusqlite;
 extern crate serde;
-extern crate serde_json;
+extern crate time;
+#[cfg(test)]
+extern crate tokio;
 
 #[macro_use]
 extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
+
+/// Prints a message translated with the message catalog, see `i18n::tr`, e.x.
+/// `say!("Toggling light '{}'...", name)`.
+#[macro_export]
+macro_rules! say {
+    ($message:expr) => {
+        println!("{}", $crate::i18n::tr($message, &[]))
+    };
+    ($message:expr, $($arg:expr),+ $(,)?) => {
+        println!("{}", $crate::i18n::tr($message, &[$(&$arg),+]))
+    };
+}
+
+/// Same as `say!`, but prints to stderr, for progress and failures that aren't the output of the
+/// command.
+#[macro_export]
+macro_rules! esay {
+    ($message:expr) => {
+        eprintln!("{}", $crate::i18n::tr($message, &[]))
+    };
+    ($message:expr, $($arg:expr),+ $(,)?) => {
+        eprintln!("{}", $crate::i18n::tr($message, &[$(&$arg),+]))
+    };
+}
 
+pub mod alert;
+pub mod animation;
+#[cfg(feature = "streaming")]
+pub mod area;
+pub mod batteries;
+#[cfg(feature = "streaming")]
+pub mod bench;
+pub mod bridges;
+pub mod bus;
+pub mod cache;
+pub mod circadian;
 pub mod colors;
+pub mod condition;
+pub mod config;
+pub mod decode;
+pub mod diagnose;
+pub mod discovery;
+pub mod drift;
+pub mod events;
+pub mod files;
+#[cfg(test)]
+mod fixtures;
+pub mod gamut;
+pub mod groups;
+pub mod holiday;
 pub mod hue;
+pub mod i18n;
+pub mod import;
+#[cfg(feature = "server")]
+pub mod ipc;
+pub mod journal;
+pub mod lock;
+#[cfg(test)]
+mod mock;
+pub mod nightlight;
+pub mod pairing;
+pub mod parse;
+#[cfg(feature = "picker")]
+pub mod picker;
+pub mod poweron;
+pub mod profile;
+pub mod progress;
+#[cfg(feature = "server")]
+pub mod proxy;
+pub mod report;
+pub mod scenes;
+pub mod schedules;
+pub mod selector;
+pub mod selftest;
+pub mod sensors;
+#[cfg(feature = "server")]
+pub mod serve;
+pub mod shuffle;
+pub mod ssdp;
+pub mod state;
+pub mod store;
+pub mod throttle;
+pub mod tuning;
+pub mod vacation;
+pub mod verify;
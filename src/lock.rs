@@ -0,0 +1,197 @@
+//! # lock
+//!
+//! Manual overrides of automations. A light locked with `lock`, e.x. a desk lamp turned up for
+//! reading, is left alone by the automations, `when`, `nightlight watch`, `holiday`, `vacation`,
+//! and `shuffle --every`, until it is unlocked or the lock expires. Commands run by hand still
+//! change it. Locks are kept in $HOME/.config/rusty_hue/locks.json, keyed by light index.
+
+use serde_json;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+use time;
+
+use cache;
+use files;
+use hue::{Hue, HueError};
+
+/// How long a light stays locked unless another duration is given.
+pub const DEFAULT_LOCK_DURATION: Duration = Duration::from_secs(60 * 60);
+
+/// A locked light.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lock {
+    /// Name of the light, for display.
+    pub name: String,
+    /// When the lock expires, in seconds since the unix epoch.
+    pub until: u64,
+}
+
+impl Lock {
+    /// When the lock expires in local time, e.x. "2024-01-05 14:30".
+    pub fn expiry(&self) -> String {
+        time::at(time::Timespec::new(self.until as i64, 0))
+            .strftime("%Y-%m-%d %H:%M")
+            .map(|at| at.to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// Locks keyed by light index, with when locks.json was last modified as they were loaded.
+type Loaded = (Option<SystemTime>, BTreeMap<String, Lock>);
+
+/// The locks as last loaded, so locks.json is only read again once it changes rather than for
+/// every change of a light, e.x. each frame of an animation.
+#[derive(Debug, Default)]
+pub struct Locks {
+    loaded: Mutex<Option<Loaded>>,
+}
+
+impl Locks {
+    /// The indices among the given that aren't locked.
+    fn unlocked(&self, indices: &[String]) -> Result<Vec<String>, Box<dyn Error>> {
+        let modified = fs::metadata(files::config_file("locks.json")?)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+
+        let mut loaded = self.loaded.lock().unwrap_or_else(PoisonError::into_inner);
+        let locks = match *loaded {
+            Some((at, ref mut locks)) if at == modified => locks,
+            _ => &mut loaded.insert((modified, load_locks()?)).1,
+        };
+        // Locks expire while the file stays the same.
+        drop_expired(locks, cache::now());
+
+        Ok(indices
+            .iter()
+            .filter(|index| !locks.contains_key(*index))
+            .cloned()
+            .collect())
+    }
+}
+
+/// Loads the locks that haven't expired yet, keyed by light index. A missing file means no light
+/// is locked.
+pub fn load_locks() -> Result<BTreeMap<String, Lock>, Box<dyn Error>> {
+    let mut f = match File::open(files::config_file("locks.json")?) {
+        Ok(f) => f,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(From::from(e)),
+    };
+
+    let mut contents = String::new();
+    f.read_to_string(&mut contents)?;
+
+    let mut locks: BTreeMap<String, Lock> = serde_json::from_str(&contents)?;
+    drop_expired(&mut locks, cache::now());
+    Ok(locks)
+}
+
+/// Saves the locks keyed by light index.
+fn save_locks(locks: &BTreeMap<String, Lock>) -> Result<(), Box<dyn Error>> {
+    let path = files::config_file("locks.json")?;
+    files::write_atomic(&path, serde_json::to_string_pretty(locks)?.as_bytes())?;
+    Ok(())
+}
+
+/// Helper function to drop the locks expired at a time, in seconds since the unix epoch.
+fn drop_expired(locks: &mut BTreeMap<String, Lock>, now: u64) {
+    locks.retain(|_, lock| lock.until > now);
+}
+
+/// Unlocks every light, returning how many were locked.
+pub fn unlock_all() -> Result<usize, Box<dyn Error>> {
+    let count = load_locks()?.len();
+    save_locks(&BTreeMap::new())?;
+    Ok(count)
+}
+
+impl Hue {
+    /// Whether the light at an index is locked and changes to it are to be skipped. Always false
+    /// unless locks are respected, see `HueConfig::respect_locks`.
+    pub(crate) fn is_locked(&self, index: &str) -> Result<bool, Box<dyn Error>> {
+        Ok(self.unlocked(&[index.to_string()])?.is_empty())
+    }
+
+    /// The indices among the given that aren't locked, all of them unless locks are respected.
+    pub(crate) fn unlocked(&self, indices: &[String]) -> Result<Vec<String>, Box<dyn Error>> {
+        if !self.config().respect_locks {
+            return Ok(indices.to_vec());
+        }
+
+        self.locks.unlocked(indices)
+    }
+
+    /// Locks a light, by name or index, against the automations for a duration. Locking a locked
+    /// light sets a new expiry.
+    pub fn lock_light(&self, light: &str, duration: Duration) -> Result<Lock, Box<dyn Error>> {
+        let index = match self.light_index(light) {
+            Some(index) => index.to_string(),
+            None => return Err(Box::new(HueError::LightNotFound(light.to_string()))),
+        };
+
+        let lock = Lock {
+            name: self.light_name(&index).unwrap_or(light).to_string(),
+            until: cache::now() + duration.as_secs(),
+        };
+
+        let mut locks = load_locks()?;
+        locks.insert(index, lock.clone());
+        save_locks(&locks)?;
+
+        Ok(lock)
+    }
+
+    /// Unlocks a light, by name or index. Returns false if it wasn't locked.
+    pub fn unlock_light(&self, light: &str) -> Result<bool, Box<dyn Error>> {
+        let index = match self.light_index(light) {
+            Some(index) => index.to_string(),
+            None => return Err(Box::new(HueError::LightNotFound(light.to_string()))),
+        };
+
+        let mut locks = load_locks()?;
+        if locks.remove(&index).is_none() {
+            return Ok(false);
+        }
+
+        save_locks(&locks)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock;
+
+    #[test]
+    fn locks_expire() {
+        let lock = |until| Lock {
+            name: String::from("Desk"),
+            until,
+        };
+        let mut locks = BTreeMap::new();
+        locks.insert(String::from("1"), lock(100));
+        locks.insert(String::from("2"), lock(200));
+
+        drop_expired(&mut locks, 100);
+        assert_eq!(locks.keys().collect::<Vec<_>>(), vec!["2"]);
+        drop_expired(&mut locks, 250);
+        assert!(locks.is_empty());
+    }
+
+    #[test]
+    fn unlocked_unless_respected() {
+        let bridge = mock::bridge();
+        let hue = bridge.hue();
+        let indices = vec![String::from("1"), String::from("2")];
+
+        assert_eq!(hue.unlocked(&indices).unwrap(), indices);
+        assert!(!hue.is_locked("1").unwrap());
+    }
+}
@@ -1,158 +1,4024 @@
 #[macro_use]
 extern crate clap;
 
+#[macro_use]
 extern crate rusty_hue;
-use rusty_hue::hue::Hue;
+extern crate serde_json;
+extern crate time;
 
-fn main() {
-    let matches = clap_app!(RustyHue =>
+use rusty_hue::cache::CacheSettings;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::process;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rusty_hue::alert::{AlertMode, Effect};
+use rusty_hue::animation;
+use rusty_hue::batteries;
+use rusty_hue::batteries::BatteryAlerts;
+use rusty_hue::bridges;
+use rusty_hue::bus;
+use rusty_hue::bus::{Backend, HueEvents};
+use rusty_hue::circadian;
+use rusty_hue::colors;
+use rusty_hue::condition::Condition;
+use rusty_hue::config;
+use rusty_hue::config::Config;
+use rusty_hue::discovery;
+use rusty_hue::events::ConnectionState;
+use rusty_hue::files;
+use rusty_hue::gamut;
+use rusty_hue::groups;
+use rusty_hue::holiday;
+use rusty_hue::hue;
+use rusty_hue::hue::v2;
+use rusty_hue::hue::{ColorCapability, Hue, HueConfig, HueError};
+use rusty_hue::i18n;
+use rusty_hue::i18n::Catalog;
+use rusty_hue::import;
+use rusty_hue::import::Action;
+#[cfg(feature = "server")]
+use rusty_hue::ipc;
+use rusty_hue::journal;
+use rusty_hue::lock;
+use rusty_hue::nightlight;
+use rusty_hue::pairing;
+use rusty_hue::parse;
+#[cfg(feature = "picker")]
+use rusty_hue::picker;
+use rusty_hue::poweron;
+use rusty_hue::poweron::Preset;
+use rusty_hue::progress;
+use rusty_hue::progress::Progress;
+#[cfg(feature = "server")]
+use rusty_hue::proxy;
+use rusty_hue::report;
+use rusty_hue::report::{Report, Verbosity};
+use rusty_hue::scenes::{Scene, SceneLightChange};
+use rusty_hue::schedules;
+use rusty_hue::schedules::{Schedule, ScheduleChange};
+use rusty_hue::selector::{Glob, Selector};
+use rusty_hue::selftest::Requirements;
+use rusty_hue::sensors::Readings;
+#[cfg(feature = "server")]
+use rusty_hue::serve;
+use rusty_hue::shuffle;
+use rusty_hue::shuffle::Rng;
+use rusty_hue::state;
+use rusty_hue::store;
+use rusty_hue::vacation;
+use rusty_hue::vacation::Slot;
+
+/// The command line interface. Commands other than the built-in ones are looked up as aliases.
+fn app() -> clap::App<'static, 'static> {
+    let app = clap_app!(RustyHue =>
+        (@setting AllowExternalSubcommands)
         (version: "0.4")
         (author: "Richard Mills <scripts.richard@gmail.com>")
         (about: "Control your Hue lights from the command line.")
-        (@arg index: -i --index +takes_value "Select light by its index.")
-        (@arg name: -n --name +takes_value "Select light by its name.")
+        (@arg index: -i --index +takes_value conflicts_with[name]
+            "Select light by its index, or lights by a list (e.x. '1,3,5').")
+        (@arg name: -n --name +takes_value
+            "Select light by its name, or lights by a list or pattern (e.x. 'Desk,Lamp' or 'Living*').")
+        (@arg ip: --ip +takes_value "IP address of the bridge, skipping discovery.")
+        (@arg bridge: --bridge +takes_value conflicts_with[ip] "Use a bridge from bridges.json by name or id.")
+        (@arg fresh: --fresh "Fetch state from the bridge instead of the state cache.")
+        (@arg select: -s --select +takes_value
+            "Select lights by expression (e.x. 'room:Living + Desk - tag:accent').")
+        (@arg room: --room +takes_value conflicts_with[select group index name]
+            "Select the lights of a room by its name or id.")
+        (@arg group: --group +takes_value conflicts_with[select index name]
+            "Select the lights of any group (room, zone, ...) by its name or id.")
+        (@arg all: --all conflicts_with[select room group index name] "Select every light.")
+        (@arg vendor: --vendor +takes_value
+            "Only select lights by a manufacturer (e.x. 'innr'), alone or with --select.")
+        (@arg summary: --summary conflicts_with[verbose]
+            "Only print per-room counts for commands on many lights.")
+        (@arg lang: --lang +takes_value "Language of messages, e.x. 'de' (default: from LANG).")
+        (@arg json: --json
+            "Print info, sensors, batteries, and scene list as JSON for scripts, never translated.")
+        (@arg strict: --strict "Fail on unexpected data from the bridge, printing it, instead of ignoring it.")
+        (@arg verify: --verify
+            "Check that lights reached the state they were sent afterwards, sending it again if not.")
+        (@arg no_progress: --("no-progress")
+            "Don't show progress of slow commands, e.x. for scripts. Only shown on a terminal.")
+        (@arg profile: --profile "Print the time taken by each step of the command afterwards.")
+        (@arg timeout: --timeout +takes_value "Give up on a request to the bridge after this long (default: '30s').")
+        (@arg i_understand_strobe: --("i-understand-strobe")
+            "Let effects flash lights faster than max_flash_rate (default: 3 a second). Fast flashing can trigger seizures.")
+        (@arg verbose: -v --verbose "Print every light's state before and after for commands on many lights.")
         (@subcommand color =>
             (about: "Set color by name (i.e. 'red').")
             (version: "0.1")
-            (@arg COLOR: +required "Color to be set.")
+            (@arg COLOR: +required "Color to be set: a name from colors.json, a CSS color name, hex, e.x. '#ff8800', or 'hsv:30,100,80'. A comma separated list spreads its colors across the lights.")
+            (@arg adaptive: -a --adaptive "Shift the color warmer and dimmer for the time of day.")
+            (@arg whites: -w --whites
+                "Set lights that only show whites to the white nearest the color instead of skipping them.")
+        )
+        (@subcommand colors =>
+            (about: "Manage the named colors in colors.json.")
+            (version: "0.1")
+            (@subcommand add =>
+                (about: "Name a color, e.x. 'colors add brand #ff8800'.")
+                (version: "0.1")
+                (@arg NAME: +required "Name of the color.")
+                (@arg COLOR: +required "The color as hex, e.x. '#ff8800', or 'r,g,b', e.x. '255,136,0'.")
+                (@arg replace: -r --replace "Replace the color if the name is taken.")
+            )
+            (@subcommand list =>
+                (about: "Print the named colors.")
+                (version: "0.1")
+            )
+            (@subcommand remove =>
+                (about: "Forget a named color.")
+                (version: "0.1")
+                (@arg NAME: +required "Name of the color.")
+            )
+            (@subcommand import =>
+                (about: "Add the colors of another file in the layout of colors.json.")
+                (version: "0.1")
+                (@arg FILE: +required "File to add the colors of.")
+                (@arg replace: -r --replace "Replace colors whose names are taken.")
+            )
         )
         (@subcommand rgb =>
             (about: "Set color by rgb (e.x. '233,222,123').")
             (version: "0.1")
-            (@arg RGB: +required "RGB to be set.")
+            (@arg RGB: +required "RGB to be set, each 0-255, or as hex (e.x. '#e9de7b').")
+        )
+        (@subcommand xy =>
+            (about: "Set color by CIE xy coordinates (e.x. '0.32 0.33').")
+            (version: "0.1")
+            (@arg X: +required "x coordinate, between 0 and 1.")
+            (@arg Y: +required "y coordinate, between 0 and 1.")
+            (@arg bri: -b --bri +takes_value "Brightness (1-254 or percentage, e.x. '80%').")
+            (@arg clamp: --clamp "Move colors outside a light's gamut to the closest color it can produce.")
+        )
+        (@subcommand on =>
+            (about: "Turn lights on.")
+            (version: "0.1")
+            (@arg fade: -f --fade +takes_value "Fade in from minimum brightness (e.x. '60s').")
+            (@arg adaptive: -a --adaptive conflicts_with[fade]
+                "Use the white and brightness of the circadian curve for the time of day.")
+            (@arg room: -r --room +takes_value
+                "Turn on a room with its default scene from default_scenes.json, if it has one.")
+            (@arg all: --all conflicts_with[room] "Turn on every light, ignoring any light selection.")
+        )
+        (@subcommand off =>
+            (about: "Turn lights off.")
+            (version: "0.1")
+            (@arg fade: -f --fade +takes_value "Dim to minimum brightness before turning off (e.x. '60s').")
+            (@arg all: --all "Turn off every light, ignoring any light selection.")
+        )
+        (@subcommand ct =>
+            (about: "Set white color temperature by mireds, Kelvin, or name (i.e. 'warm').")
+            (version: "0.1")
+            (@arg TEMPERATURE: +required
+                "Mireds, Kelvin (e.x. '2700K'), or one of candle, warm, neutral, cool, daylight.")
+        )
+        (@subcommand bri =>
+            (about: "Set brightness without changing color.")
+            (version: "0.1")
+            (@arg BRIGHTNESS: +required "Brightness (1-254 or percentage, e.x. '50%').")
+        )
+        (@subcommand dim =>
+            (about: "Make lights brighter or dimmer by a step, e.x. 'dim -25' or 'dim +10%'.")
+            (version: "0.1")
+            (@setting AllowLeadingHyphen)
+            (@arg STEP: +required +allow_hyphen_values
+                "Change of brightness, signed, in steps of 254 or as a percentage (e.x. '-10%').")
+        )
+        (@subcommand alert =>
+            (about: "Blink lights, e.x. to tell which light is which.")
+            (version: "0.1")
+            (@arg MODE: possible_value[select lselect none] default_value[select]
+                "'select' blinks once, 'lselect' for 15 seconds, 'none' stops blinking.")
+        )
+        (@subcommand effect =>
+            (about: "Start or stop an effect on color lights.")
+            (version: "0.1")
+            (@arg EFFECT: +required possible_value[colorloop none]
+                "'colorloop' cycles through all colors, 'none' stops it.")
+        )
+        (@subcommand batteries =>
+            (about: "List the battery levels of sensors and switches, lowest first.")
+            (version: "0.1")
+            (@arg watch: -w --watch "Keep checking, alerting when a battery drops below the threshold.")
+            (@arg threshold: -t --threshold +takes_value "Battery percentage to alert below (default: 20).")
+            (@arg interval: --interval +takes_value requires[watch] "Time between checks (default: '1h').")
+            (@arg webhook: --webhook +takes_value requires[watch] "URL to post alerts to as JSON.")
+        )
+        (@subcommand config =>
+            (about: "Inspect and change the settings in the config file.")
+            (version: "0.1")
+            (@subcommand show =>
+                (about: "Print the settings stored in the config file.")
+                (version: "0.1")
+                (@arg effective: -e --effective
+                    "Print every setting after merging defaults, config file, environment, and flags.")
+            )
+            (@subcommand get =>
+                (about: "Print the effective value of a setting and where it came from.")
+                (version: "0.1")
+                (@arg KEY: +required "Name of the setting, e.x. 'transition'.")
+            )
+            (@subcommand set =>
+                (about: "Store a setting in the config file, e.x. 'config set transition 400ms'.")
+                (version: "0.1")
+                (@arg KEY: +required "Name of the setting, e.x. 'transition'.")
+                (@arg VALUE: +required "Value of the setting.")
+            )
+            (@subcommand unset =>
+                (about: "Remove a setting from the config file, going back to its default.")
+                (version: "0.1")
+                (@arg KEY: +required "Name of the setting.")
+            )
+            (@subcommand path =>
+                (about: "Print the location of the config file.")
+                (version: "0.1")
+            )
+        )
+        (@subcommand drift =>
+            (about: "Compare the bridge against a desired-state file, exiting with 1 on drift.")
+            (version: "0.1")
+            (@arg desired: -d --desired +takes_value +required
+                "JSON file in the import layout, plus optional bridge settings under \"config\".")
+            (@arg fix: --fix "Change the bridge to match the file, asking first if that deletes anything.")
+            (@arg auto_approve: --("auto-approve") requires[fix]
+                "Fix without asking for confirmation, even when resources are deleted.")
+        )
+        (@subcommand events =>
+            (about: "Print events from the bridge as they happen, reconnecting when the stream is lost.")
+            (version: "0.1")
+        )
+        (@subcommand export =>
+            (about: "Write the bridge's lights, groups, and scenes as a file for import.")
+            (version: "0.1")
+            (@arg FILE: "File to write to (default: print the file).")
+        )
+        (@subcommand gamut =>
+            (about: "Draw a light's color gamut with its current color marked.")
+            (version: "0.1")
+            (@arg LIGHT: +required "Name or index of the light.")
+            (@arg svg: --svg +takes_value "Write the drawing to an SVG file instead.")
+        )
+        (@subcommand group =>
+            (about: "Control all lights of a room or group with one request, or list the groups.")
+            (version: "0.1")
+            (@arg GROUP: "Name or id of the group (default: the favorite_group setting, or list all groups).")
+            (@arg color: -c --color +takes_value "Color name from the colors file.")
+            (@arg bri: -b --bri +takes_value "Brightness (1-254 or percentage, e.x. '40%').")
+            (@arg toggle: -t --toggle conflicts_with[color bri]
+                "Turn the group off if any of its lights is on, else on.")
+        )
+        (@subcommand holiday =>
+            (about: "Play a holiday effect on the lights: halloween, christmas, or newyear.")
+            (version: "0.1")
+            (@arg HOLIDAY: required_unless[clear] "Name of the holiday effect.")
+            (@arg color: -c --color +takes_value +multiple number_of_values(1)
+                "Color to use instead of the effect's, by name or 'r,g,b'. Repeat for more colors.")
+            (@arg bri: -b --bri +takes_value "Brightness range, e.x. '20%-80%'.")
+            (@arg interval: --interval +takes_value "Time between steps of the effect, e.x. '500ms'.")
+            (@arg duration: -d --duration +takes_value "Stop after this long (default: until interrupted).")
+            (@arg export: --export conflicts_with[duration]
+                "Play the effect with timers on the bridge, which keep running without this tool.")
+            (@arg clear: --clear conflicts_with[export] "Remove the timers of exported effects.")
+        )
+        (@subcommand import =>
+            (about: "Make the bridge match a file of lights, groups, and scenes, after showing the plan.")
+            (version: "0.1")
+            (@arg FILE: +required "JSON file in the bridge's layout, e.x. {\"groups\": {...}}.")
+            (@arg format: --format +takes_value
+                "Layout of the file: bridge (default), or hue-essentials to map another tool's export by light name.")
+            (@arg auto_approve: --("auto-approve") "Apply the plan without asking for confirmation.")
         )
         (@subcommand info =>
             (about: "Displays information about Hue lights.")
             (version: "0.1")
         )
+        (@subcommand lock =>
+            (about: "Keep automations from changing a light for a while, or list the locked lights.")
+            (version: "0.1")
+            (@arg LIGHT: "Name or index of the light (default: list the locked lights).")
+            (@arg duration: --("for") +takes_value requires[LIGHT] "How long to lock the light (default: '1h').")
+        )
+        (@subcommand unlock =>
+            (about: "Let automations change a locked light again.")
+            (version: "0.1")
+            (@arg LIGHT: required_unless[all] "Name or index of the light.")
+            (@arg all: --all conflicts_with[LIGHT] "Unlock every light.")
+        )
+        (@subcommand nightlight =>
+            (about: "Cap the brightness and color temperature of rooms, e.x. a bedroom at night.")
+            (version: "0.1")
+            (@subcommand on =>
+                (about: "Cap a room, bringing its lights within the cap.")
+                (version: "0.1")
+                (@arg room: -r --room +takes_value +required "Name or id of the room.")
+                (@arg bri: -b --bri +takes_value "Highest brightness (1-254 or percentage, default: '20%').")
+                (@arg ct: --ct +takes_value "Coolest white color temperature, mireds or name (default: 454).")
+            )
+            (@subcommand off =>
+                (about: "Lift the cap of a room.")
+                (version: "0.1")
+                (@arg room: -r --room +takes_value +required "Name or id of the room.")
+            )
+            (@subcommand list =>
+                (about: "List the capped rooms.")
+                (version: "0.1")
+            )
+            (@subcommand watch =>
+                (about: "Keep the lights of capped rooms within their caps until interrupted.")
+                (version: "0.1")
+                (@arg interval: --interval +takes_value "Time between checks (default: '5s').")
+            )
+        )
+        (@subcommand pair =>
+            (about: "Pair with the bridge for a new API token, after pressing its link button.")
+            (version: "0.1")
+            (alias: "register")
+            (@arg device_type: -d --("device-type") +takes_value
+                "Name to pair as, shown in the Hue app (default: 'rusty_hue#cli').")
+        )
+        (@subcommand poweron =>
+            (about: "Set lights to a preset when powered on at the wall, for bulbs that can't be set up to.")
+            (version: "0.1")
+            (@subcommand set =>
+                (about: "Set the preset of a light.")
+                (version: "0.1")
+                (@arg LIGHT: +required "Name or index of the light.")
+                (@arg bri: -b --bri +takes_value "Brightness (1-254 or percentage).")
+                (@arg ct: --ct +takes_value conflicts_with[color] "White color temperature, mireds, Kelvin, or name.")
+                (@arg color: -c --color +takes_value "Color, e.x. 'tomato' or '#ff8800'.")
+            )
+            (@subcommand remove =>
+                (about: "Remove the preset of a light.")
+                (version: "0.1")
+                (@arg LIGHT: +required "Name or index of the light.")
+            )
+            (@subcommand list =>
+                (about: "List the presets.")
+                (version: "0.1")
+            )
+            (@subcommand watch =>
+                (about: "Set lights to their preset as soon as they're powered on, until interrupted.")
+                (version: "0.1")
+                (@arg interval: --interval +takes_value "Time between checks (default: '2s').")
+            )
+        )
         (@subcommand rename =>
             (about: "Change a light's configuration.")
             (version: "0.1")
             (@arg INDEX: +required "Index of light to set value.")
             (@arg NAME: +required "New name value for light.")
         )
-    ).get_matches();
+        (@subcommand schedule =>
+            (about: "Create, list, change, and delete schedules stored on the bridge.")
+            (version: "0.1")
+            (@subcommand list =>
+                (about: "List the schedules on the bridge.")
+                (version: "0.1")
+            )
+            (@subcommand add =>
+                (about: "Schedule a change to a light or group at a time of day.")
+                (version: "0.1")
+                (@arg at: --at +takes_value +required "Time of day, e.x. '07:00' or '7am'.")
+                (@arg days: --days +takes_value
+                    "Days the schedule repeats: daily, weekdays, weekends, or e.x. 'mon,wed' (default: once).")
+                (@arg light: -l --light +takes_value +required "Name or index of a light, or name or id of a group.")
+                (@arg color: -c --color +takes_value conflicts_with[off]
+                    "Color name from the colors file, or white color temperature (e.x. 'warm').")
+                (@arg bri: -b --bri +takes_value conflicts_with[off] "Brightness (1-254 or percentage, e.x. '80%').")
+                (@arg off: --off "Turn off instead of on.")
+                (@arg fade: --fade +takes_value conflicts_with[off]
+                    "Fade in over this long, reaching the state at the time, e.x. '30m' for a wake-up light.")
+                (@arg name: --name +takes_value "Name of the schedule (default: the light and time).")
+            )
+            (@subcommand update =>
+                (about: "Change the name, time, or status of a schedule.")
+                (version: "0.1")
+                (@arg ID: +required "Id of the schedule.")
+                (@arg at: --at +takes_value "New time of day.")
+                (@arg days: --days +takes_value requires[at] "Days the schedule repeats (default: once).")
+                (@arg name: --name +takes_value "New name.")
+                (@arg enable: --enable conflicts_with[disable] "Enable the schedule.")
+                (@arg disable: --disable "Disable the schedule.")
+            )
+            (@subcommand delete =>
+                (about: "Delete a schedule.")
+                (version: "0.1")
+                (@arg ID: +required "Id of the schedule.")
+            )
+        )
+        (@subcommand watch =>
+            (about: "Print changes to lights, groups, sensors, and switches as they happen.")
+            (version: "0.1")
+            (@arg poll: --poll +takes_value
+                "Poll at this interval (e.x. '2s') instead, for bridges without an event stream.")
+        )
+        (@subcommand when =>
+            (about: "Run a command whenever a condition on sensors starts to hold, until interrupted.")
+            (version: "0.1")
+            (@arg CONDITION: +required
+                "Condition, e.x. 'lightlevel:Office < 20 for 5m' or 'temperature:Kitchen > 24 hysteresis 1'.")
+            (@arg COMMAND: +required "Command to run, e.x. 'on', with the global flags given before 'when'.")
+            (@arg otherwise: --else +takes_value "Command to run when the condition stops holding, e.x. 'off'.")
+            (@arg interval: --interval +takes_value "Time between checks of the sensors (default: '5s').")
+        )
+        (@subcommand sensors =>
+            (about: "Print the readings of motion sensors, temperature sensors, and dimmer switches.")
+            (version: "0.1")
+        )
+        (@subcommand shuffle =>
+            (about: "Give a room's lights random colors from a palette, once or periodically.")
+            (version: "0.1")
+            (@arg room: -r --room +takes_value +required "Name or id of the room.")
+            (@arg palette: -p --palette +takes_value +required
+                "Palette name: cozy, party, holiday, ocean, or one from palettes.json.")
+            (@arg every: -e --every +takes_value "Shuffle again after each interval (e.x. '10m') until interrupted.")
+            (@arg bri: -b --bri +takes_value "Brightness range (default: '30%-70%').")
+        )
+        (@subcommand stats =>
+            (about: "Show how the lights are used, from the local journal.")
+            (version: "0.1")
+            (@arg api: --api "Show how the bridge answers requests and the pacing learned from it.")
+            (@subcommand scenes =>
+                (about: "How often each scene was recalled and at what time of day, most used first.")
+                (version: "0.1")
+            )
+        )
+        (@subcommand vacation =>
+            (about: "Simulate presence while away by turning rooms on and off at realistic, varying times.")
+            (version: "0.1")
+            (@arg room: -r --room +takes_value +multiple number_of_values(1)
+                "Room to include, by name or id. Repeat for more rooms (default: all rooms).")
+            (@arg days: -d --days +takes_value "Number of days, starting today (default: 7 with --schedule, else until interrupted).")
+            (@arg schedule: --schedule conflicts_with[clear]
+                "Create schedules on the bridge instead, which run without this tool.")
+            (@arg clear: --clear "Remove the schedules created by --schedule.")
+        )
+        (@subcommand scene =>
+            (about: "Recall, schedule, and manage scenes stored on the bridge.")
+            (version: "0.1")
+            (@setting ArgsNegateSubcommands)
+            (@arg SCENE: "Name or id of a scene to recall, e.x. 'Relax', as with 'scene recall'.")
+            (@arg at: --at +takes_value requires[SCENE]
+                "Schedule the scene on the bridge at a time of day instead (e.x. '21:30' or '7pm').")
+            (@arg days: --days +takes_value requires[at]
+                "Days the schedule repeats: daily, weekdays, weekends, or e.x. 'mon,wed' (default: once).")
+            (@subcommand list =>
+                (about: "List the scenes stored on the bridge.")
+                (version: "0.1")
+            )
+            (@subcommand recall =>
+                (about: "Recall a scene by name or id, or schedule it with --at.")
+                (version: "0.1")
+                (visible_alias: "on")
+                (@arg SCENE: +required "Name or id of the scene, e.x. 'Relax'.")
+                (@arg at: --at +takes_value
+                    "Schedule the scene on the bridge at a time of day instead (e.x. '21:30' or '7pm').")
+                (@arg days: --days +takes_value requires[at]
+                    "Days the schedule repeats: daily, weekdays, weekends, or e.x. 'mon,wed' (default: once).")
+                (@arg diff: --diff conflicts_with[at]
+                    "Show what recalling the scene would change for each light, without recalling it.")
+            )
+            (@subcommand create =>
+                (about: "Store the current state of the lights as a new scene, all lights unless selected.")
+                (version: "0.1")
+                (@arg NAME: +required "Name of the new scene.")
+            )
+            (@subcommand edit =>
+                (about: "Modify the state of a single light stored in a scene.")
+                (version: "0.1")
+                (@arg SCENE: +required "Name or id of the scene to edit.")
+                (@arg light: -l --light +takes_value +required "Name or index of the light.")
+                (@arg bri: -b --bri +takes_value "Brightness (1-254 or percentage, e.x. '30%').")
+                (@arg color: -c --color +takes_value "Color name from the colors file.")
+                (@arg on: --on conflicts_with[off] "Light is on when the scene is recalled.")
+                (@arg off: --off "Light is off when the scene is recalled.")
+            )
+        )
+    );
+
+    #[cfg(feature = "picker")]
+    let app = app.subcommand(
+        clap_app!(@subcommand pick =>
+            (about: "Pick a color interactively, with the lights following along, and print it.")
+            (version: "0.1")
+            (@arg SELECTOR: "Lights to pick for, e.x. 'room:Living + Desk' (default: the selected lights).")
+        )
+    );
+
+    #[cfg(feature = "server")]
+    let app = app.subcommand(
+        clap_app!(@subcommand ipc =>
+            (about: "Serve newline separated JSON commands over a local socket for hotkey tools.")
+            (version: "0.1")
+            (@arg socket: -s --socket +takes_value "Socket path (default: ~/.config/rusty_hue/ipc.sock).")
+        )
+    );
+
+    #[cfg(feature = "server")]
+    let app = app.subcommand(
+        clap_app!(@subcommand proxy =>
+            (about: "Serve a limited Hue API backed by the bridge, giving each client its own token and lights.")
+            (version: "0.1")
+            (@arg listen: -l --listen +takes_value "Address to listen on (default: '0.0.0.0:8081').")
+            (@subcommand add =>
+                (about: "Add a client and print its token.")
+                (version: "0.1")
+                (@arg NAME: +required "Name of the client, e.x. 'tablet'.")
+                (@arg lights: --lights +takes_value +required +multiple number_of_values(1)
+                    "Light the client may use, by name or index. Repeat for more lights.")
+            )
+            (@subcommand remove =>
+                (about: "Remove a client, revoking its token.")
+                (version: "0.1")
+                (@arg NAME: +required "Name of the client.")
+            )
+            (@subcommand list =>
+                (about: "List the clients and their lights.")
+                (version: "0.1")
+            )
+        )
+    );
+
+    #[cfg(feature = "server")]
+    let app = app.subcommand(
+        clap_app!(@subcommand serve =>
+            (about: "Serve a read-only live dashboard of the lights and rooms over HTTP.")
+            (version: "0.1")
+            (@arg listen: -l --listen +takes_value "Address to listen on (default: '127.0.0.1:8080', '0.0.0.0:8080' to open it from other devices).")
+            (@arg interval: --interval +takes_value "Time between fetches of the bridge's state (default: '2s').")
+        )
+    );
+
+    #[cfg(feature = "streaming")]
+    let app = app.subcommand(
+        clap_app!(@subcommand area =>
+            (about: "Set up entertainment areas.")
+            (version: "0.1")
+            (@subcommand auto =>
+                (about: "Create an entertainment area from a room's lights that can stream, at default positions.")
+                (version: "0.1")
+                (@arg room: -r --room +takes_value +required "Name or id of the room.")
+                (@arg name: -n --name +takes_value "Name of the area (default: the room's name and 'area').")
+            )
+        )
+    );
+
+    #[cfg(feature = "streaming")]
+    let app = app.subcommand(
+        clap_app!(@subcommand stream =>
+            (about: "Tools for entertainment areas.")
+            (version: "0.1")
+            (@subcommand bench =>
+                (about: "Send test frames to an entertainment area and measure the update rate and drops.")
+                (version: "0.1")
+                (@arg area: -a --area +takes_value +required "Name or id of the entertainment area.")
+                (@arg rate: -r --rate +takes_value "Target frames per second (default: 25).")
+                (@arg duration: -d --duration +takes_value "How long to send frames for (default: '10s').")
+            )
+        )
+    );
+
+    app
+}
+
+fn main() {
+    let start = Instant::now();
+    let matches = app().get_matches();
+
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            say!("Failed to load configuration: {}", e);
+            process::exit(1);
+        }
+    };
+
+    if let Some(bridge) = matches.value_of("bridge") {
+        config.set_flag("bridge", bridge).unwrap();
+    }
+
+    if let Some(bridge) = config.get("bridge").map(String::from) {
+        let selected = bridges::load_bridges()
+            .and_then(|bridges| bridges::find_bridge(&bridges, &bridge).cloned())
+            .and_then(|selected| config.set_bridge(&selected));
+        // Pairing with a bridge that isn't listed yet adds it.
+        if let Err(e) = selected {
+            if matches.subcommand_name() != Some("pair") {
+                println!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    if let Some(ip) = matches.value_of("ip") {
+        config.set_flag("bridge_ip", ip).unwrap();
+    }
+
+    if let Some(lang) = matches.value_of("lang") {
+        config.set_flag("lang", lang).unwrap();
+    }
+    let lang = config
+        .get("lang")
+        .map(String::from)
+        .or_else(i18n::detect_language);
+    if let Some(lang) = lang {
+        match Catalog::load(&lang) {
+            Ok(catalog) => i18n::set_catalog(catalog),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    if matches.is_present("strict") {
+        config.set_flag("strict", "true").unwrap();
+    }
+
+    if matches.is_present("verify") {
+        config.set_flag("verify", "true").unwrap();
+    }
+
+    if matches.is_present("no_progress") {
+        config.set_flag("progress", "false").unwrap();
+    }
+
+    if let Some(timeout) = matches.value_of("timeout") {
+        config.set_flag("timeout", timeout).unwrap();
+    }
+
+    if matches.is_present("summary") {
+        config.set_flag("output", "summary").unwrap();
+    } else if matches.is_present("verbose") {
+        config.set_flag("output", "verbose").unwrap();
+    }
+
+    if let Some(socket) = matches
+        .subcommand_matches("ipc")
+        .and_then(|matches| matches.value_of("socket"))
+    {
+        config.set_flag("ipc_socket", socket).unwrap();
+    }
+
+    if let Some(address) = matches
+        .subcommand_matches("serve")
+        .and_then(|matches| matches.value_of("listen"))
+    {
+        config.set_flag("serve_address", address).unwrap();
+    }
+
+    if let Some(address) = matches
+        .subcommand_matches("proxy")
+        .and_then(|matches| matches.value_of("listen"))
+    {
+        config.set_flag("proxy_address", address).unwrap();
+    }
+
+    if let Some(matches) = matches.subcommand_matches("batteries") {
+        if let Some(threshold) = matches.value_of("threshold") {
+            config.set_flag("battery_threshold", threshold).unwrap();
+        }
+        if let Some(webhook) = matches.value_of("webhook") {
+            config.set_flag("battery_webhook", webhook).unwrap();
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("config") {
+        subcommand_config(&config, matches);
+        return;
+    }
+
+    // Settings are checked once the config command had a chance to fix them.
+    let hue_config = match hue_config(&config) {
+        Ok(hue_config) => HueConfig {
+            profile: matches.is_present("profile"),
+            respect_locks: is_automation(&matches),
+            ..hue_config
+        },
+        Err(e) => {
+            println!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    if let Some(matches) = matches.subcommand_matches("colors") {
+        subcommand_colors(&hue_config, matches);
+        return;
+    }
+
+    progress::set_enabled(config.get("progress") == Some("true"));
+    colors::set_color_names(config.get("color_names") == Some("true"));
+
+    if let Some(rate) = config
+        .get("max_flash_rate")
+        .and_then(|rate| rate.parse().ok())
+    {
+        animation::set_max_flash_rate(Some(rate));
+    }
+    if matches.is_present("i_understand_strobe") {
+        animation::set_max_flash_rate(None);
+    }
+
+    if let Some(duration) = config
+        .get("transition")
+        .and_then(|transition| parse::parse_duration(transition).ok())
+    {
+        state::set_default_transition(Some(hue::transition_time(duration)));
+    }
+
+    let verbosity = match Verbosity::parse(config.get("output").unwrap_or("changed")) {
+        Ok(verbosity) => verbosity,
+        Err(e) => {
+            println!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let cache = match cache_settings(&config, &matches) {
+        Ok(cache) => cache,
+        Err(e) => {
+            println!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    // A discovered bridge is remembered in the config file, so later runs skip discovery.
+    if config.get("bridge_ip").is_none() {
+        if let Err(e) = discover_bridge(&mut config) {
+            println!("{}", e);
+            process::exit(1);
+        }
+    }
+
+    if let Some(pair_matches) = matches.subcommand_matches("pair") {
+        let ip = config.get("bridge_ip").unwrap().to_string();
+        let device_type = pair_matches
+            .value_of("device_type")
+            .unwrap_or(pairing::DEFAULT_DEVICE_TYPE);
+        pair_bridge(&config, &ip, device_type);
+        return;
+    }
+
+    let mut connected = Hue::with_config(
+        config.get("bridge_ip"),
+        config.get("token"),
+        cache.clone(),
+        hue_config.clone(),
+    );
+
+    // A remembered bridge that stopped answering may have been given another address.
+    let unreachable = match connected {
+        Err(ref e) => matches!(e.downcast_ref(), Some(HueError::Network(_))),
+        Ok(_) => false,
+    };
+    if unreachable && config.source("bridge_ip") == Some(&config::Source::File) {
+        let old_ip = config.get("bridge_ip").unwrap().to_string();
+        esay!(
+            "The bridge at {} isn't answering, searching for it again...",
+            old_ip
+        );
+        match discover_bridge(&mut config) {
+            Ok(ip) if ip != old_ip => {
+                connected = Hue::with_config(
+                    Some(&ip),
+                    config.get("token"),
+                    cache.clone(),
+                    hue_config.clone(),
+                )
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    let mut hue = match connected {
+        Ok(hue) => hue,
+        Err(e) => {
+            let ip = match e.downcast_ref::<HueError>() {
+                Some(HueError::Unauthorized(ip)) => ip.clone(),
+                _ => {
+                    println!("{}", e);
+                    process::exit(1);
+                }
+            };
+
+            let token = recover_unauthorized(&config, &ip);
+            Hue::with_config(Some(&ip), Some(&token), cache, hue_config).unwrap_or_else(|e| {
+                println!("{}", e);
+                process::exit(1);
+            })
+        }
+    };
+
+    // Daemons check everything they need before starting, rather than failing hours later.
+    if let Some(requirements) = daemon_requirements(&matches) {
+        let test = hue.self_test(&requirements);
+        if !test.passed() {
+            eprint!("{}", test);
+            esay!("Not starting, fix the failed checks first.");
+            exit(&hue, 1);
+        }
+    }
+
+    match matches.subcommand() {
+        ("batteries", Some(sub)) if sub.is_present("watch") => {
+            subcommand_batteries_watch(&hue, &config, sub);
+            return;
+        }
+
+        ("events", _) => {
+            subcommand_events(hue);
+            return;
+        }
+
+        ("watch", Some(sub)) => {
+            subcommand_watch(hue, sub);
+            return;
+        }
+
+        #[cfg(feature = "server")]
+        ("ipc", _) => {
+            subcommand_ipc(hue, &config);
+            return;
+        }
+
+        #[cfg(feature = "server")]
+        ("serve", Some(sub)) => {
+            subcommand_serve(hue, &config, sub);
+            return;
+        }
+
+        #[cfg(feature = "server")]
+        ("proxy", Some(sub)) if sub.subcommand_name().is_none() => {
+            subcommand_proxy_serve(hue, &config);
+            return;
+        }
+
+        ("when", Some(sub)) => {
+            subcommand_when(&mut hue, sub, verbosity);
+            return;
+        }
+
+        _ => {
+            let verify = config.get("verify") == Some("true");
+            if verify {
+                hue.record_changes();
+            }
+
+            if !run_command(&hue, &matches, verbosity) {
+                subcommand_alias(&mut hue, &matches, verbosity);
+            }
+
+            if verify {
+                verify_changes(&hue, &config);
+            }
+        }
+    }
+
+    if matches.is_present("profile") {
+        eprint!("{}", hue.profile().render(start.elapsed()));
+    }
+
+    // What was learned about the bridge is only an optimization, so failing to save it is ignored.
+    let _ = hue.save_performance();
+
+    let throttled = hue.throttled_requests();
+    if throttled > 0 {
+        esay!(
+            "{} request(s) were throttled by the overloaded bridge and retried.",
+            throttled
+        );
+    }
+}
+
+/// Whether the command is an automation, which leaves lights locked with 'lock' alone, rather
+/// than a change made by hand.
+fn is_automation(matches: &clap::ArgMatches) -> bool {
+    match matches.subcommand() {
+        ("when", _) | ("holiday", _) => true,
+        ("nightlight", Some(sub)) | ("poweron", Some(sub)) => {
+            sub.subcommand_name() == Some("watch")
+        }
+        ("shuffle", Some(sub)) => sub.is_present("every"),
+        ("vacation", Some(sub)) => !sub.is_present("schedule") && !sub.is_present("clear"),
+        _ => false,
+    }
+}
+
+/// What the command needs from the bridge if it runs as a daemon, `None` if it doesn't.
+fn daemon_requirements(matches: &clap::ArgMatches) -> Option<Requirements> {
+    let mut requirements = Requirements::default();
+
+    match matches.subcommand() {
+        ("batteries", Some(sub)) if sub.is_present("watch") => {}
+        ("events", _) => requirements.event_stream = true,
+        ("watch", Some(sub)) => requirements.event_stream = !sub.is_present("poll"),
+        ("ipc", _) | ("serve", _) => {}
+        ("proxy", Some(sub)) if sub.subcommand_name().is_none() => {
+            requirements.proxy_clients = true
+        }
+        ("when", Some(sub)) => {
+            // An invalid condition is reported by 'when' itself.
+            if let Ok(condition) = Condition::parse(sub.value_of("CONDITION").unwrap_or("")) {
+                requirements.sensors = condition
+                    .references()
+                    .into_iter()
+                    .filter(|(source, _)| ["motion", "temperature", "lightlevel"].contains(source))
+                    .map(|(_, name)| name.to_string())
+                    .collect();
+            }
+
+            // So do the scenes and groups its commands use.
+            let steps = [sub.value_of("COMMAND"), sub.value_of("otherwise")];
+            for step in steps.iter().flatten() {
+                if let Ok(step) = parse_step(&[String::from("rusty_hue")], step) {
+                    match step.subcommand() {
+                        ("scene", Some(scene)) => requirements.scenes.extend(
+                            scene
+                                .subcommand_matches("recall")
+                                .unwrap_or(scene)
+                                .value_of("SCENE")
+                                .map(String::from),
+                        ),
+                        ("group", Some(group)) => requirements
+                            .groups
+                            .extend(group.value_of("GROUP").map(String::from)),
+                        ("on", Some(on)) if on.is_present("room") => {
+                            requirements.default_scenes = true;
+                            requirements
+                                .groups
+                                .extend(on.value_of("room").map(String::from));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        ("nightlight", Some(sub)) if sub.subcommand_name() == Some("watch") => {
+            requirements.nightlights = true
+        }
+        ("poweron", Some(sub)) if sub.subcommand_name() == Some("watch") => {}
+        ("shuffle", Some(sub)) if sub.is_present("every") => {
+            requirements.groups = sub.value_of("room").into_iter().map(String::from).collect()
+        }
+        ("holiday", Some(sub)) if !sub.is_present("export") && !sub.is_present("clear") => {}
+        ("vacation", Some(sub)) if !sub.is_present("schedule") && !sub.is_present("clear") => {
+            requirements.groups = sub
+                .values_of("room")
+                .into_iter()
+                .flatten()
+                .map(String::from)
+                .collect()
+        }
+        _ => return None,
+    }
+
+    Some(requirements)
+}
+
+/// Exits with a code, saving what was learned about the bridge first, which the end of `main`
+/// otherwise does.
+fn exit(hue: &Hue, code: i32) -> ! {
+    // What was learned about the bridge is only an optimization, so failing to save it is ignored.
+    let _ = hue.save_performance();
+    process::exit(code)
+}
+
+/// Checks that the lights changed by the command reached the state they were sent, reporting
+/// those that didn't after the configured number of retries.
+fn verify_changes(hue: &Hue, config: &Config) {
+    let retries = match config.get("verify_retries").unwrap_or("2").parse::<u32>() {
+        Ok(retries) => retries,
+        Err(_) => {
+            say!("Invalid verify_retries, expected a number of retries.");
+            exit(hue, 1);
+        }
+    };
+
+    match hue.verify_changes(retries) {
+        Ok(ref unverified) if unverified.is_empty() => (),
+        Ok(unverified) => {
+            say!(
+                "{} light(s) didn't reach the state they were sent:",
+                unverified.len()
+            );
+            for light in unverified {
+                println!("  {}: {}", light.name, light.fields.join(", "));
+            }
+            exit(hue, 1);
+        }
+        Err(e) => {
+            say!("Failed to verify the changes: {}", e);
+            exit(hue, 1);
+        }
+    }
+}
+
+/// Runs a built-in command with an open connection to the bridge. Returns false if the command
+/// isn't one, e.x. an alias.
+fn run_command(hue: &Hue, matches: &clap::ArgMatches, verbosity: Verbosity) -> bool {
+    match matches.subcommand_name() {
+        Some("alert") => {
+            subcommand_alert(hue, matches, verbosity);
+        }
+
+        Some("batteries") => {
+            subcommand_batteries(hue, matches.is_present("json"));
+        }
+
+        Some("bri") => {
+            subcommand_bri(hue, matches, verbosity);
+        }
+
+        Some("dim") => {
+            subcommand_dim(hue, matches, verbosity);
+        }
+
+        Some("color") => {
+            subcommand_color(hue, matches, verbosity);
+        }
+
+        Some("xy") => {
+            subcommand_xy(hue, matches, verbosity);
+        }
+
+        Some("on") => {
+            subcommand_power(hue, matches, true, verbosity);
+        }
+
+        Some("off") => {
+            subcommand_power(hue, matches, false, verbosity);
+        }
+
+        Some("ct") => {
+            subcommand_ct(hue, matches, verbosity);
+        }
+
+        Some("drift") => {
+            subcommand_drift(hue, matches);
+        }
+
+        Some("effect") => {
+            subcommand_effect(hue, matches, verbosity);
+        }
+
+        Some("export") => {
+            subcommand_export(hue, matches);
+        }
+
+        Some("gamut") => {
+            subcommand_gamut(hue, matches);
+        }
+
+        Some("group") => {
+            subcommand_group(hue, matches);
+        }
+
+        Some("holiday") => {
+            subcommand_holiday(hue, matches);
+        }
+
+        Some("import") => {
+            subcommand_import(hue, matches);
+        }
+
+        Some("info") => {
+            if matches.is_present("json") {
+                let lights: serde_json::Map<String, serde_json::Value> = hue
+                    .lights()
+                    .map(|(index, light)| (index.to_string(), light.to_json()))
+                    .collect();
+                print_json(&serde_json::Value::Object(lights));
+            } else {
+                hue.print_info(verbosity == Verbosity::Verbose);
+            }
+        }
+
+        Some("lock") => {
+            subcommand_lock(hue, matches);
+        }
+
+        Some("unlock") => {
+            subcommand_unlock(hue, matches);
+        }
+
+        Some("nightlight") => {
+            subcommand_nightlight(hue, matches);
+        }
+
+        Some("rename") => {
+            if let Some(matches) = matches.subcommand_matches("rename") {
+                let index = matches.value_of("INDEX");
+                let name = matches.value_of("NAME");
+
+                // Any other condition besides the below will be caught by the arg parser.
+                if let (Some(index), Some(name)) = (index, name) {
+                    hue.rename_light(index, name).unwrap();
+                }
+            }
+        }
+
+        Some("rgb") => {
+            subcommand_rgb(hue, matches, verbosity);
+        }
+
+        Some("scene") => {
+            subcommand_scene(hue, matches);
+        }
+
+        Some("schedule") => {
+            subcommand_schedule(hue, matches);
+        }
+
+        Some("sensors") => {
+            subcommand_sensors(hue, matches.is_present("json"));
+        }
+
+        Some("shuffle") => {
+            subcommand_shuffle(hue, matches);
+        }
+
+        Some("vacation") => {
+            subcommand_vacation(hue, matches);
+        }
+
+        Some("stats") => {
+            subcommand_stats(hue, matches);
+        }
+
+        #[cfg(feature = "streaming")]
+        Some("stream") => {
+            subcommand_stream(hue, matches);
+        }
+
+        #[cfg(feature = "streaming")]
+        Some("area") => {
+            subcommand_area(hue, matches);
+        }
+
+        #[cfg(feature = "server")]
+        Some("proxy") => {
+            subcommand_proxy(hue, matches);
+        }
+
+        #[cfg(feature = "picker")]
+        Some("pick") => {
+            subcommand_pick(hue, matches);
+        }
+
+        Some("poweron") => {
+            subcommand_poweron(hue, matches);
+        }
+
+        None => {
+            subcommand_toggle(hue, matches);
+        }
+
+        Some(_) => return false,
+    }
+
+    true
+}
+
+/// Commands that can't be run on behalf of another command, e.x. from an alias, as they run until
+/// interrupted or need the terminal.
+const STANDALONE_COMMANDS: [&str; 7] =
+    ["colors", "config", "events", "ipc", "pair", "watch", "when"];
+
+/// Parses a command run on behalf of another, e.x. from an alias, with the global flags the other
+/// was run with. Only built-in commands are run, so aliases can't refer to each other.
+fn parse_step(
+    global: &[String],
+    command: &str,
+) -> Result<clap::ArgMatches<'static>, Box<dyn Error>> {
+    parse::split_words(command).and_then(|words| {
+        app()
+            .unset_setting(clap::AppSettings::AllowExternalSubcommands)
+            .get_matches_from_safe(global.iter().cloned().chain(words))
+            .map_err(|e| From::from(e.message))
+    })
+}
+
+/// Runs the commands of an alias from $HOME/.config/rusty_hue/aliases.json in order, sharing one
+/// connection to the bridge. Each command is given the global flags the alias was run with, and
+/// every command is checked before any is run.
+fn subcommand_alias(hue: &mut Hue, matches: &clap::ArgMatches, verbosity: Verbosity) {
+    let (name, alias_matches) = matches.subcommand();
+
+    let commands = match config::load_aliases() {
+        Ok(mut aliases) => match aliases.remove(name) {
+            Some(commands) => commands,
+            None => {
+                say!("Unknown command or alias '{}'.", name);
+                exit(hue, 1);
+            }
+        },
+        Err(e) => {
+            say!("Failed to load aliases: {}", e);
+            exit(hue, 1);
+        }
+    };
+
+    if alias_matches.is_some_and(|matches| matches.is_present("")) {
+        say!("Alias '{}' doesn't take arguments.", name);
+        exit(hue, 1);
+    }
+
+    // The global flags are everything before the alias name, which ends the command line.
+    let args: Vec<String> = env::args().collect();
+    let global = &args[..args.len() - 1];
+
+    let mut steps = Vec::new();
+    for command in &commands {
+        match parse_step(global, command) {
+            Ok(ref step) if STANDALONE_COMMANDS.contains(&step.subcommand_name().unwrap_or("")) => {
+                say!("'{}' can't be run from alias '{}'.", command, name);
+                exit(hue, 1);
+            }
+            Ok(step) => steps.push(step),
+            Err(e) => {
+                say!("Invalid command '{}' in alias '{}':\n{}", command, name, e);
+                exit(hue, 1);
+            }
+        }
+    }
+
+    for step in &steps {
+        // Earlier commands change the lights, so state is fetched again for each one.
+        if let Err(e) = hue.refresh() {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+
+        run_command(hue, step, verbosity);
+    }
+}
+
+/// Checks the sensors until interrupted, running a command each time a condition starts to hold
+/// and, if given, another each time it stops. Thresholds need the condition kept between checks,
+/// so a single condition is evaluated throughout.
+fn subcommand_when(hue: &mut Hue, matches: &clap::ArgMatches, verbosity: Verbosity) {
+    let expression = matches.value_of("CONDITION").unwrap();
+    let condition = match Condition::parse(expression) {
+        Ok(condition) => condition,
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    };
+
+    // The global flags are everything before "when", and apply to the commands run.
+    let args: Vec<String> = env::args().collect();
+    let global = &args[..args.iter().position(|arg| arg == "when").unwrap_or(1)];
+
+    let mut commands = Vec::new();
+    for command in [matches.value_of("COMMAND"), matches.value_of("otherwise")].iter() {
+        let step = match *command {
+            Some(command) => command,
+            None => {
+                commands.push(None);
+                continue;
+            }
+        };
+
+        match parse_step(global, step) {
+            Ok(ref parsed)
+                if STANDALONE_COMMANDS.contains(&parsed.subcommand_name().unwrap_or("")) =>
+            {
+                say!("'{}' can't be run from 'when'.", step);
+                exit(hue, 1);
+            }
+            Ok(parsed) => commands.push(Some(parsed)),
+            Err(e) => {
+                say!("Invalid command '{}':\n{}", step, e);
+                exit(hue, 1);
+            }
+        }
+    }
+
+    let interval = match parse::parse_duration(matches.value_of("interval").unwrap_or("5s")) {
+        Ok(interval) => interval,
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    };
+
+    esay!(
+        "Checking '{}' every {} seconds...",
+        expression,
+        interval.as_secs_f32()
+    );
+
+    let mut held = false;
+    loop {
+        match hue.sensors() {
+            Ok(sensors) => {
+                let holds = condition.evaluate(&Readings { sensors });
+                if holds != held {
+                    held = holds;
+                    let step = if holds { &commands[0] } else { &commands[1] };
+
+                    if let Some(step) = step {
+                        if holds {
+                            say!("'{}' holds.", expression);
+                        } else {
+                            say!("'{}' no longer holds.", expression);
+                        }
+                        match hue.refresh() {
+                            Ok(()) => {
+                                run_command(hue, step, verbosity);
+                            }
+                            Err(e) => eprintln!("{}", e),
+                        }
+                    }
+                }
+            }
+            Err(e) => esay!("Failed to read the sensors: {}", e),
+        }
+
+        thread::sleep(interval);
+    }
+}
+
+/// Handles the bridge rejecting the API token, as set by the `unauthorized` setting: "ask" offers
+/// to pair again when run from a terminal, "pair" always pairs again, and "exit" prints how to.
+/// Returns the new token after pairing.
+fn recover_unauthorized(config: &Config, ip: &str) -> String {
+    let interactive = io::stdin().is_terminal() && io::stdout().is_terminal();
+
+    let pair = match config.get("unauthorized").unwrap_or("ask") {
+        "pair" => true,
+        "ask" if interactive => {
+            print!(
+                "{}",
+                i18n::tr(
+                    "The bridge at {} rejected the API token. Pair with it again now? [y/N] ",
+                    &[&ip]
+                )
+            );
+            io::stdout().flush().unwrap();
+
+            let mut answer = String::new();
+            io::stdin().read_line(&mut answer).unwrap();
+            matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+        }
+        "ask" | "exit" => false,
+        other => {
+            say!(
+                "Invalid unauthorized setting '{}', expected ask, pair, or exit.",
+                other
+            );
+            process::exit(1);
+        }
+    };
+
+    if !pair {
+        say!(
+            "The bridge at {} rejected the API token.\n{}",
+            ip,
+            pairing::recovery_steps(ip)
+        );
+        process::exit(1);
+    }
+
+    pair_bridge(config, ip, pairing::DEFAULT_DEVICE_TYPE)
+}
+
+/// Discovers the bridge, preferring the one with the id of the bridge_id setting, and remembers
+/// its address in the config file. Returns its address.
+fn discover_bridge(config: &mut Config) -> Result<String, Box<dyn Error>> {
+    let no_cloud = config.get("no_cloud") == Some("true");
+    let found = discovery::discover_bridge(no_cloud, |bridge| match config.get("bridge_id") {
+        Some(id) => bridge
+            .id
+            .as_ref()
+            .is_some_and(|b| b.eq_ignore_ascii_case(id)),
+        None => true,
+    });
+
+    let bridge = match found {
+        Some(bridge) => bridge,
+        // Fails the same way as discovery without a config file.
+        None if config.get("bridge_id").is_none() => return hue::get_hue_ip(no_cloud),
+        None => {
+            return Err(Box::new(HueError::DiscoveryFailed(Some(format!(
+                "None of the bridges found has the id {} of the bridge_id setting.",
+                config.get("bridge_id").unwrap()
+            )))))
+        }
+    };
+
+    // Failing to save only means discovering again next time.
+    if let Err(e) = config.remember_bridge(&bridge.ip, bridge.id.as_deref()) {
+        esay!("Failed to remember the bridge at {}: {}", bridge.ip, e);
+    }
+    Ok(bridge.ip)
+}
+
+/// Pairs with the bridge and saves the new token, exiting if the link button isn't pressed in
+/// time.
+fn pair_bridge(config: &Config, ip: &str, device_type: &str) -> String {
+    say!(
+        "Press the link button on the bridge at {} within {} seconds...",
+        ip,
+        pairing::LINK_TIMEOUT.as_secs()
+    );
+
+    let token = match pairing::pair(ip, device_type, pairing::LINK_TIMEOUT) {
+        Ok(token) => token,
+        Err(e) => {
+            println!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    // A bridge picked by name keeps its token in bridges.json rather than config.json.
+    let name = config.bridge_name().or_else(|| config.get("bridge"));
+    let saved = match name {
+        Some(name) => bridges::save_bridge(name, ip, &token),
+        None => pairing::save_token(&token),
+    };
+    match saved {
+        Ok(path) => say!("Paired, the new token is saved to {}.", path.display()),
+        Err(e) => say!("Paired, but the new token could not be saved: {}", e),
+    }
+
+    if let (None, Some(config::Source::Environment(variable))) = (name, config.source("token")) {
+        say!(
+            "Note: {} overrides config.json, so replace it as well.",
+            variable
+        );
+    }
+
+    token
+}
+
+#[cfg(feature = "server")]
+fn subcommand_ipc(hue: Hue, config: &Config) {
+    let socket = config.path("ipc_socket").unwrap();
+
+    say!("Listening on {}...", socket.display());
+    if let Err(e) = ipc::serve(hue, &socket) {
+        println!("{}", e);
+    }
+}
+
+#[cfg(feature = "server")]
+fn subcommand_proxy_serve(hue: Hue, config: &Config) {
+    let address = config.get("proxy_address").unwrap();
+
+    say!(
+        "Serving the Hue API for proxy clients on http://{}/api...",
+        address
+    );
+    if let Err(e) = proxy::serve(hue, address) {
+        println!("{}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(feature = "server")]
+fn subcommand_proxy(hue: &Hue, matches: &clap::ArgMatches) {
+    let matches = matches.subcommand_matches("proxy").unwrap();
+
+    match matches.subcommand() {
+        ("add", Some(sub)) => {
+            let name = sub.value_of("NAME").unwrap();
+            let mut lights = Vec::new();
+            for light in sub.values_of("lights").unwrap() {
+                match hue.light_index(light) {
+                    Some(index) => lights.push(index.to_string()),
+                    None => {
+                        say!("No light with name '{}' found.", light);
+                        exit(hue, 1);
+                    }
+                }
+            }
+
+            match proxy::add_client(name, &lights) {
+                Ok(token) => say!(
+                    "Added client '{}' with token {}\nUse it as the username of a bridge at this \
+                     computer's address.",
+                    name,
+                    token
+                ),
+                Err(e) => {
+                    println!("{}", e);
+                    exit(hue, 1);
+                }
+            }
+        }
+        ("remove", Some(sub)) => {
+            let name = sub.value_of("NAME").unwrap();
+            match proxy::remove_client(name) {
+                Ok(true) => say!("Removed client '{}'.", name),
+                Ok(false) => {
+                    say!("No client named '{}'.", name);
+                    exit(hue, 1);
+                }
+                Err(e) => {
+                    println!("{}", e);
+                    exit(hue, 1);
+                }
+            }
+        }
+        _ => {
+            let clients = proxy::load_clients().unwrap_or_else(|e| {
+                say!("Failed to load the proxy clients: {}", e);
+                exit(hue, 1);
+            });
+            if clients.is_empty() {
+                say!("No proxy clients, add one with 'proxy add'.");
+            }
+            for client in clients.values() {
+                let lights: Vec<&str> = client
+                    .lights
+                    .iter()
+                    .map(|index| hue.light_name(index).unwrap_or(index))
+                    .collect();
+                println!("{:<16} {}", client.name, lights.join(", "));
+            }
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+fn subcommand_serve(hue: Hue, config: &Config, matches: &clap::ArgMatches) {
+    let address = config.get("serve_address").unwrap();
+    let interval = match parse::parse_duration(matches.value_of("interval").unwrap_or("2s")) {
+        Ok(interval) => interval,
+        Err(e) => {
+            println!("{}", e);
+            exit(&hue, 1);
+        }
+    };
+
+    say!("Serving the dashboard on http://{}/...", address);
+    if let Err(e) = serve::serve(hue, address, interval) {
+        println!("{}", e);
+        process::exit(1);
+    }
+}
+
+/// Prints a value as pretty JSON, e.x. for `--json`.
+fn print_json(value: &serde_json::Value) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(value).unwrap_or_default()
+    );
+}
+
+fn subcommand_batteries(hue: &Hue, json: bool) {
+    let batteries = match hue.batteries() {
+        Ok(batteries) => batteries,
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    };
+
+    if json {
+        print_json(&serde_json::to_value(&batteries).unwrap_or_default());
+        return;
+    }
+
+    if batteries.is_empty() {
+        say!("No battery powered sensors or switches found.");
+        return;
+    }
+
+    let width = batteries.iter().map(|b| b.name.len()).max().unwrap_or(0);
+    for battery in batteries {
+        println!(
+            "{:>4}%  {:<width$}  {}",
+            battery.level,
+            battery.name,
+            battery.kind,
+            width = width
+        );
+    }
+}
+
+fn subcommand_sensors(hue: &Hue, json: bool) {
+    let sensors = match hue.sensors() {
+        Ok(sensors) => sensors,
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    };
+
+    if json {
+        print_json(&serde_json::to_value(&sensors).unwrap_or_default());
+        return;
+    }
+
+    if sensors.is_empty() {
+        say!("No motion sensors, temperature sensors, or dimmer switches found.");
+        return;
+    }
+
+    let width = sensors.iter().map(|s| s.name.len()).max().unwrap_or(0);
+    for sensor in sensors {
+        let mut line = format!("{:<width$}  {}", sensor.name, sensor.reading, width = width);
+        if let Some(at) = sensor.lastupdated {
+            line.push_str(&i18n::tr(" (at {})", &[&at.replace('T', " ")]));
+        }
+        if let Some(level) = sensor.battery {
+            line.push_str(&i18n::tr(", battery {}%", &[&level]));
+        }
+        println!("{}", line);
+    }
+}
+
+/// Checks battery levels until interrupted, alerting once for each battery that drops below the
+/// threshold. Alerts are printed and, if a webhook is configured, posted to it.
+fn subcommand_batteries_watch(hue: &Hue, config: &Config, matches: &clap::ArgMatches) {
+    let threshold = config.get("battery_threshold").unwrap_or("20");
+    let threshold: u8 = match threshold.trim_end_matches('%').parse() {
+        Ok(threshold) if threshold <= 100 => threshold,
+        _ => {
+            say!(
+                "Invalid battery threshold '{}', expected a percentage.",
+                threshold
+            );
+            exit(hue, 1);
+        }
+    };
+    let interval = match parse::parse_duration(matches.value_of("interval").unwrap_or("1h")) {
+        Ok(interval) => interval,
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    };
+    let webhook = config.get("battery_webhook");
+
+    let mut alerts = BatteryAlerts::new(threshold);
+    esay!("Checking batteries every {} seconds...", interval.as_secs());
+
+    loop {
+        match hue.batteries() {
+            Ok(batteries) => {
+                for battery in alerts.check(&batteries) {
+                    let alert = i18n::tr(
+                        "The battery of '{}' is at {}%, below {}%.",
+                        &[&battery.name, &battery.level, &threshold],
+                    );
+                    println!("{}", alert);
+                    if let Some(url) = webhook {
+                        if let Err(e) = batteries::send_webhook(url, battery, &alert) {
+                            esay!("Failed to post alert to webhook: {}", e);
+                        }
+                    }
+                }
+            }
+            Err(e) => esay!("Failed to check batteries: {}", e),
+        }
+
+        thread::sleep(interval);
+    }
+}
+
+fn subcommand_events(mut hue: Hue) {
+    let result = hue.listen(
+        |state| match *state {
+            ConnectionState::Connecting(0) => esay!("Connecting to the event stream..."),
+            ConnectionState::Connecting(_) => esay!("Reconnecting to the event stream..."),
+            ConnectionState::Connected => esay!("Connected."),
+            ConnectionState::Resynced => esay!("State refreshed after reconnecting."),
+            ConnectionState::Disconnected {
+                ref error,
+                retry_in,
+            } => esay!(
+                "Event stream lost: {} Retrying in {} seconds.",
+                error,
+                format!("{:.1}", retry_in.as_secs_f32())
+            ),
+        },
+        |_, event| {
+            println!("{}", event.data);
+            true
+        },
+    );
+
+    if let Err(e) = result {
+        println!("{}", e);
+        exit(&hue, 1);
+    }
+}
+
+fn subcommand_watch(hue: Hue, matches: &clap::ArgMatches) {
+    let backend = match matches.value_of("poll").map(parse::parse_duration) {
+        Some(Ok(interval)) => Backend::Polling(interval),
+        Some(Err(e)) => {
+            println!("{}", e);
+            exit(&hue, 1);
+        }
+        None => Backend::Stream,
+    };
+
+    let names = hue.resource_names().unwrap_or_else(|e| {
+        println!("{}", e);
+        exit(&hue, 1);
+    });
+
+    esay!("Watching for changes, press Ctrl-C to stop.");
+    for event in HueEvents::subscribe(hue, backend) {
+        let (message, values) = bus::describe(&event, &names);
+        let values: Vec<&dyn fmt::Display> =
+            values.iter().map(|v| v as &dyn fmt::Display).collect();
+        println!(
+            "{} {}",
+            time::now().strftime("%H:%M:%S").unwrap(),
+            i18n::tr(message, &values)
+        );
+    }
+}
+
+fn subcommand_gamut(hue: &Hue, matches: &clap::ArgMatches) {
+    let matches = matches.subcommand_matches("gamut").unwrap();
+    let light = matches.value_of("LIGHT").unwrap();
+
+    let index = match hue.light_index(light) {
+        Some(index) => index,
+        None => {
+            say!("No light with index or name '{}' found.", light);
+            exit(hue, 1);
+        }
+    };
+
+    let model = hue.light_model(index).unwrap_or_default();
+    let gamut = match hue.light_gamut(index) {
+        Some(gamut) => gamut,
+        None => {
+            say!(
+                "The color gamut of light '{}' ({}) is not known.",
+                light,
+                model
+            );
+            exit(hue, 1);
+        }
+    };
+    let xy = hue.light_state(index).and_then(|state| state.xy());
+
+    if let Some(path) = matches.value_of("svg") {
+        let svg = gamut::render_svg(&gamut, xy);
+        match fs::write(path, svg) {
+            Ok(()) => say!("Gamut of light '{}' written to {}.", light, path),
+            Err(e) => say!("Failed to write {}: {}", path, e),
+        }
+        return;
+    }
+
+    print!("{}", gamut::render_text(&gamut, xy, 60));
+    say!("Gamut of light '{}' ({}).", light, model);
+    if let Some((x, y)) = xy {
+        let inside = gamut.point_in_gamut(&colors::GamutPoint::new(x, y));
+        let xy = format!("[{:.4}, {:.4}]", x, y);
+        if inside {
+            say!("Current color: {}, inside the gamut.", xy);
+        } else {
+            say!("Current color: {}, outside the gamut.", xy);
+        }
+    }
+}
+
+fn subcommand_drift(hue: &Hue, matches: &clap::ArgMatches) {
+    let matches = matches.subcommand_matches("drift").unwrap();
+    let path = Path::new(matches.value_of("desired").unwrap());
+
+    let changes = match import::read_import_file(path).and_then(|file| hue.plan_drift(&file)) {
+        Ok(changes) => changes,
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    };
+
+    if changes.is_empty() {
+        say!("No drift, the bridge matches {}.", path.display());
+        return;
+    }
+
+    for change in &changes {
+        println!("{}", change);
+    }
+    // Resources to delete are extra, on the bridge but not in the file.
+    println!();
+    say!(
+        "Drift: {} missing, {} changed, {} extra.",
+        import::count(&changes, Action::Create),
+        import::count(&changes, Action::Update),
+        import::count(&changes, Action::Delete)
+    );
+
+    if !matches.is_present("fix") {
+        exit(hue, 1);
+    }
+
+    // Resources only on the bridge are deleted, which can't be undone.
+    let deletes = changes.iter().any(|change| change.action == Action::Delete);
+    if deletes && !matches.is_present("auto_approve") && !approved() {
+        say!("Fix cancelled.");
+        exit(hue, 1);
+    }
+
+    for change in &changes {
+        if let Err(e) = hue.apply_change(change) {
+            say!("Failed to fix {}/{}: {}", change.resource, change.id, e);
+            exit(hue, 1);
+        }
+    }
+
+    say!("Fixed {} difference(s).", changes.len());
+}
+
+fn subcommand_export(hue: &Hue, matches: &clap::ArgMatches) {
+    let matches = matches.subcommand_matches("export").unwrap();
+
+    let file = match hue
+        .export_resources()
+        .and_then(|file| Ok(serde_json::to_string_pretty(&file)?))
+    {
+        Ok(file) => file + "\n",
+        Err(e) => {
+            say!("Failed to export the bridge: {}", e);
+            exit(hue, 1);
+        }
+    };
+
+    match matches.value_of("FILE") {
+        Some(path) => match files::replace_file(Path::new(path), file.as_bytes()) {
+            Ok(()) => say!("Bridge exported to {}.", path),
+            Err(e) => {
+                say!("Failed to write {}: {}", path, e);
+                exit(hue, 1);
+            }
+        },
+        None => print!("{}", file),
+    }
+}
+
+fn subcommand_import(hue: &Hue, matches: &clap::ArgMatches) {
+    let matches = matches.subcommand_matches("import").unwrap();
+    let path = Path::new(matches.value_of("FILE").unwrap());
+
+    let format = import::Format::parse(matches.value_of("format").unwrap_or("bridge"));
+
+    let changes = match format.and_then(|format| {
+        import::read_import_file(path).and_then(|file| hue.plan_import(&file, format))
+    }) {
+        Ok(changes) => changes,
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    };
+
+    if changes.is_empty() {
+        say!("No changes. The bridge already matches {}.", path.display());
+        return;
+    }
+
+    for change in &changes {
+        println!("{}", change);
+    }
+    println!();
+    say!(
+        "Plan: {} to create, {} to update, {} to delete.",
+        import::count(&changes, Action::Create),
+        import::count(&changes, Action::Update),
+        import::count(&changes, Action::Delete)
+    );
+
+    if !matches.is_present("auto_approve") && !approved() {
+        say!("Import cancelled.");
+        return;
+    }
+
+    let mut progress = Progress::new(changes.len());
+    for change in &changes {
+        progress.step(&format!("{}/{}", change.resource, change.id));
+        if let Err(e) = hue.apply_change(change) {
+            drop(progress);
+            say!("Failed to apply {}/{}: {}", change.resource, change.id, e);
+            exit(hue, 1);
+        }
+    }
+
+    say!("Import complete, {} change(s) applied.", changes.len());
+}
+
+/// Asks whether to apply the changes shown, accepting only "yes".
+fn approved() -> bool {
+    print!(
+        "\n{}",
+        i18n::tr("Apply these changes? Only 'yes' will be accepted: ", &[])
+    );
+    io::stdout().flush().unwrap();
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).unwrap();
+    answer.trim() == "yes"
+}
+
+fn subcommand_config(config: &Config, matches: &clap::ArgMatches) {
+    if let Some(matches) = matches.subcommand_matches("show") {
+        if matches.is_present("effective") {
+            for (key, setting) in config.settings() {
+                println!(
+                    "{} = {}\t# {}",
+                    key,
+                    config::display_value(key, setting.value.as_deref()),
+                    setting.source
+                );
+            }
+            return;
+        }
+
+        match config::read_config_file() {
+            Ok(settings) => {
+                for (key, value) in settings {
+                    let value = match value {
+                        serde_json::Value::String(s) => s,
+                        other => other.to_string(),
+                    };
+                    println!("{} = {}", key, config::display_value(&key, Some(&value)));
+                }
+            }
+            Err(e) => println!("{}", e),
+        }
+    } else if let Some(matches) = matches.subcommand_matches("get") {
+        let key = matches.value_of("KEY").unwrap();
+        match config.source(key) {
+            Some(source) => println!(
+                "{} = {}\t# {}",
+                key,
+                config::display_value(key, config.get(key)),
+                source
+            ),
+            None => {
+                say!("Unknown setting '{}'.", key);
+                process::exit(1);
+            }
+        }
+    } else if let Some(matches) = matches.subcommand_matches("set") {
+        let key = matches.value_of("KEY").unwrap();
+        let value = matches.value_of("VALUE").unwrap();
+
+        let saved = config::check_value(key, value)
+            .and_then(|_| config::save_settings(&[(key, Some(value))]));
+        match saved {
+            Ok(path) => {
+                say!(
+                    "Set {} to {} in {}.",
+                    key,
+                    config::display_value(key, Some(value)),
+                    path.display()
+                );
+                if let Some(config::Source::Environment(name)) = config.source(key) {
+                    say!("Note: {} is set and takes precedence.", name);
+                }
+            }
+            Err(e) => {
+                println!("{}", e);
+                process::exit(1);
+            }
+        }
+    } else if let Some(matches) = matches.subcommand_matches("unset") {
+        let key = matches.value_of("KEY").unwrap();
+        match config::remove_settings(&[key]) {
+            Ok(path) => say!("Removed {} from {}.", key, path.display()),
+            Err(e) => {
+                println!("{}", e);
+                process::exit(1);
+            }
+        }
+    } else if matches.subcommand_matches("path").is_some() {
+        match config::config_path() {
+            Ok(path) => println!("{}", path.display()),
+            Err(e) => {
+                println!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+}
+
+fn subcommand_colors(hue_config: &HueConfig, matches: &clap::ArgMatches) {
+    let colors_file = colors::colors_file(hue_config.colors_file.as_deref());
+    if let Err(e) = colors_file.and_then(|path| colors_command(&path, matches)) {
+        println!("{}", e);
+        process::exit(1);
+    }
+}
+
+/// Helper function to run a colors subcommand on the colors file at `path`. A missing file holds no
+/// colors, leaving only the built-in ones.
+fn colors_command(path: &Path, matches: &clap::ArgMatches) -> Result<(), Box<dyn Error>> {
+    match matches.subcommand() {
+        ("add", Some(matches)) => {
+            let name = matches.value_of("NAME").unwrap();
+            let rgb = colors::parse_rgb(matches.value_of("COLOR").unwrap())?;
+
+            colors::add_color(path, name, rgb.clone(), matches.is_present("replace"))?;
+            say!(
+                "Saved '{}' as {} in {}.",
+                name,
+                colors::to_hex(&rgb),
+                path.display()
+            );
+        }
+        ("remove", Some(matches)) => {
+            let name = matches.value_of("NAME").unwrap();
+
+            colors::remove_color(path, name)?;
+            say!("Removed '{}' from {}.", name, path.display());
+        }
+        ("import", Some(matches)) => {
+            let file = Path::new(matches.value_of("FILE").unwrap());
+            let merge = colors::import_colors(path, file, matches.is_present("replace"))?;
+
+            say!(
+                "Added {}, replaced {}, and kept {} color(s) in {}.",
+                merge.added.len(),
+                merge.replaced.len(),
+                merge.kept.len(),
+                path.display()
+            );
+            if !merge.kept.is_empty() {
+                say!(
+                    "Kept the current colors of: {}. Use --replace to take those of the file.",
+                    merge.kept.join(", ")
+                );
+            }
+        }
+        _ => {
+            let named = colors::saved_colors(path)?;
+            let mut all = colors::default_colors();
+            all.extend(named.clone());
+
+            let mut sorted: Vec<_> = all.iter().collect();
+            sorted.sort_by(|a, b| a.0.cmp(b.0));
+            let width = sorted.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+            for (name, rgb) in sorted {
+                println!(
+                    "{:<width$}  {}  {:>3}, {:>3}, {:>3}{}",
+                    name,
+                    colors::to_hex(rgb),
+                    rgb.r,
+                    rgb.g,
+                    rgb.b,
+                    if named.contains_key(name) {
+                        ""
+                    } else {
+                        "  (built in)"
+                    },
+                    width = width
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn subcommand_scene(hue: &Hue, global: &clap::ArgMatches) {
+    if let Some(matches) = global.subcommand_matches("scene") {
+        if let Some(matches) = matches.subcommand_matches("edit") {
+            subcommand_scene_edit(hue, matches);
+        } else if matches.subcommand_matches("list").is_some() {
+            subcommand_scene_list(hue, global.is_present("json"));
+        } else if let Some(name) = matches
+            .subcommand_matches("create")
+            .and_then(|matches| matches.value_of("NAME"))
+        {
+            subcommand_scene_create(hue, global, name);
+        } else if let Some(matches) = matches
+            .subcommand_matches("recall")
+            .or_else(|| matches.value_of("SCENE").map(|_| matches))
+        {
+            let scene = matches.value_of("SCENE").unwrap();
+            if matches.is_present("diff") {
+                subcommand_scene_diff(hue, scene);
+                return;
+            }
+
+            match matches.value_of("at") {
+                Some(at) => subcommand_scene_schedule(hue, scene, at, matches.value_of("days")),
+                None => subcommand_scene_recall(hue, scene),
+            }
+        }
+    }
+}
+
+fn subcommand_scene_list(hue: &Hue, json: bool) {
+    let scenes = match hue.list_scenes() {
+        Ok(scenes) => scenes,
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    };
+
+    if json {
+        let scenes: BTreeMap<String, Scene> = scenes.into_iter().collect();
+        print_json(&serde_json::to_value(&scenes).unwrap_or_default());
+        return;
+    }
+
+    for (id, scene) in scenes {
+        match scene.group {
+            Some(group) => say!("{}: {} (group {})", id, scene.name, group),
+            None => say!(
+                "{}: {} (lights {})",
+                id,
+                scene.name,
+                scene.lights.join(", ")
+            ),
+        }
+    }
+}
+
+fn subcommand_scene_create(hue: &Hue, matches: &clap::ArgMatches, name: &str) {
+    let indices = target_lights(hue, matches);
+
+    let result = hue
+        .capture_lightstates(&indices)
+        .and_then(|states| hue.create_scene(name, &states));
+
+    match result {
+        Ok(id) => say!(
+            "Scene '{}' has been created from {} light(s) as scene {}.",
+            name,
+            indices.len(),
+            id
+        ),
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    }
+}
+
+fn subcommand_scene_recall(hue: &Hue, scene: &str) {
+    match hue.activate_scene(scene) {
+        Ok(()) => say!("Scene '{}' has been recalled.", scene),
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    }
+}
+
+fn subcommand_scene_diff(hue: &Hue, scene: &str) {
+    let diffs = match hue.diff_scene(scene) {
+        Ok(diffs) => diffs,
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    };
+
+    let rooms = hue.rooms_by_light().unwrap_or_default();
+    let mut report = Report::new();
+
+    for diff in diffs {
+        let room = rooms
+            .get(&diff.index)
+            .map_or(report::UNASSIGNED, String::as_str);
+        let name = hue.light_name(&diff.index).unwrap_or(&diff.index);
+        report.record(room, name, Some(diff.before), Some(diff.after), Ok(()));
+    }
+
+    say!("Recalling '{}' would change (dry run):", scene);
+    print!("{}", report.render(Verbosity::Verbose));
+}
+
+fn subcommand_scene_schedule(hue: &Hue, scene: &str, at: &str, days: Option<&str>) {
+    let at = match parse::parse_time_of_day(at) {
+        Ok(at) => at,
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    };
+
+    let days = match days.map(schedules::parse_days) {
+        Some(Ok(days)) => Some(days),
+        Some(Err(e)) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+        None => None,
+    };
+
+    match hue.schedule_scene(scene, &at, days) {
+        Ok(id) => say!(
+            "Scene '{}' scheduled at {} ({}) as schedule {}.",
+            scene,
+            at,
+            schedules::localtime(&at, days),
+            id
+        ),
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    }
+}
+
+fn subcommand_lock(hue: &Hue, matches: &clap::ArgMatches) {
+    let matches = matches.subcommand_matches("lock").unwrap();
+
+    let light = match matches.value_of("LIGHT") {
+        Some(light) => light,
+        None => {
+            match lock::load_locks() {
+                Ok(locks) => {
+                    if locks.is_empty() {
+                        say!("No lights are locked.");
+                    }
+                    for lock in locks.values() {
+                        say!("{}: locked until {}", lock.name, lock.expiry());
+                    }
+                }
+                Err(e) => {
+                    println!("{}", e);
+                    exit(hue, 1);
+                }
+            }
+            return;
+        }
+    };
+
+    let duration = match matches.value_of("duration").map(parse::parse_duration) {
+        None => lock::DEFAULT_LOCK_DURATION,
+        Some(Ok(duration)) => duration,
+        Some(Err(e)) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    };
+
+    match hue.lock_light(light, duration) {
+        Ok(lock) => say!(
+            "'{}' is locked against automations until {}.",
+            lock.name,
+            lock.expiry()
+        ),
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    }
+}
+
+fn subcommand_unlock(hue: &Hue, matches: &clap::ArgMatches) {
+    let matches = matches.subcommand_matches("unlock").unwrap();
+
+    let result = match matches.value_of("LIGHT") {
+        Some(light) => hue.unlock_light(light).map(|unlocked| {
+            if unlocked {
+                say!("'{}' has been unlocked.", light);
+            } else {
+                say!("'{}' isn't locked.", light);
+            }
+        }),
+        None => lock::unlock_all().map(|count| say!("Unlocked {} light(s).", count)),
+    };
+
+    if let Err(e) = result {
+        println!("{}", e);
+        exit(hue, 1);
+    }
+}
+
+fn subcommand_nightlight(hue: &Hue, matches: &clap::ArgMatches) {
+    let matches = matches.subcommand_matches("nightlight").unwrap();
+
+    let result = match matches.subcommand() {
+        ("on", Some(matches)) => subcommand_nightlight_on(hue, matches),
+        ("off", Some(matches)) => {
+            let room = matches.value_of("room").unwrap();
+            hue.nightlight_off(room).map(|lifted| {
+                if lifted {
+                    say!("The cap of '{}' has been lifted.", room);
+                } else {
+                    say!("'{}' isn't capped.", room);
+                }
+            })
+        }
+        ("list", _) => nightlight::load_nightlights().map(|nightlights| {
+            if nightlights.is_empty() {
+                say!("No rooms are capped.");
+            }
+            for cap in nightlights.values() {
+                say!(
+                    "{}: brightness {}, color temperature {}",
+                    cap.room,
+                    cap.bri,
+                    cap.ct
+                );
+            }
+        }),
+        ("watch", Some(matches)) => subcommand_nightlight_watch(hue, matches),
+        _ => {
+            println!("{}", matches.usage());
+            Ok(())
+        }
+    };
+
+    if let Err(e) = result {
+        println!("{}", e);
+        exit(hue, 1);
+    }
+}
+
+fn subcommand_nightlight_on(hue: &Hue, matches: &clap::ArgMatches) -> Result<(), Box<dyn Error>> {
+    let room = matches.value_of("room").unwrap();
+    let bri = match matches.value_of("bri") {
+        Some(bri) => parse::parse_brightness(bri)?,
+        None => nightlight::DEFAULT_MAX_BRI,
+    };
+    let ct = match matches.value_of("ct") {
+        Some(ct) => colors::parse_temperature(ct, &colors::load_temperatures()?)?,
+        None => nightlight::DEFAULT_MIN_CT,
+    };
+
+    let cap = hue.nightlight_on(room, bri, ct)?;
+    say!(
+        "'{}' is capped at brightness {} and color temperature {}.",
+        cap.room,
+        cap.bri,
+        cap.ct
+    );
+    say!("Run 'nightlight watch' to keep other apps from raising it.");
+    Ok(())
+}
+
+#[cfg(feature = "picker")]
+fn subcommand_pick(hue: &Hue, matches: &clap::ArgMatches) {
+    let indices = match matches
+        .subcommand_matches("pick")
+        .unwrap()
+        .value_of("SELECTOR")
+    {
+        Some(selector) => match Selector::parse(selector).and_then(|s| hue.resolve(&s)) {
+            Ok(indices) => indices.into_iter().collect(),
+            Err(e) => {
+                println!("{}", e);
+                exit(hue, 1);
+            }
+        },
+        None => match selected_lights(hue, matches) {
+            Some(indices) => indices,
+            None => {
+                say!("Give the lights to pick a color for, e.x. 'pick Desk'.");
+                exit(hue, 1);
+            }
+        },
+    };
+
+    match hue.pick(&indices) {
+        Ok(Some(rgb)) => {
+            say!("Picked {}.", picker::describe(&rgb));
+            say!(
+                "To name it, run: rusty_hue colors add <name> '{}'",
+                colors::to_hex(&rgb)
+            );
+        }
+        Ok(None) => say!("Picking cancelled, the lights were set back."),
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    }
+}
+
+fn subcommand_poweron(hue: &Hue, matches: &clap::ArgMatches) {
+    let matches = matches.subcommand_matches("poweron").unwrap();
+
+    let result = match matches.subcommand() {
+        ("set", Some(matches)) => subcommand_poweron_set(hue, matches),
+        ("remove", Some(matches)) => {
+            let light = matches.value_of("LIGHT").unwrap();
+            hue.remove_power_on_preset(light).map(|removed| {
+                if removed {
+                    say!("The power-on preset of '{}' has been removed.", light);
+                } else {
+                    say!("'{}' has no power-on preset.", light);
+                }
+            })
+        }
+        ("list", _) => poweron::load_presets().map(|presets| {
+            if presets.is_empty() {
+                say!("No lights have a power-on preset.");
+            }
+            for preset in presets.values() {
+                println!("{}: {}", preset.name, preset.update());
+            }
+        }),
+        ("watch", Some(matches)) => subcommand_poweron_watch(hue, matches),
+        _ => {
+            println!("{}", matches.usage());
+            Ok(())
+        }
+    };
+
+    if let Err(e) = result {
+        println!("{}", e);
+        exit(hue, 1);
+    }
+}
+
+fn subcommand_poweron_set(hue: &Hue, matches: &clap::ArgMatches) -> Result<(), Box<dyn Error>> {
+    let light = matches.value_of("LIGHT").unwrap();
+    let mut preset = Preset::default();
+
+    if let Some(bri) = matches.value_of("bri") {
+        preset.bri = Some(parse::parse_brightness(bri)?);
+    }
+    if let Some(ct) = matches.value_of("ct") {
+        preset.ct = Some(colors::parse_temperature(
+            ct,
+            &colors::load_temperatures()?,
+        )?);
+    }
+    if let Some(color) = matches.value_of("color") {
+        let xy = colors::XY::from_rgb(&colors::parse_color(color, &hue.colors_file()?)?);
+        preset.xy = Some((xy.x, xy.y));
+        preset.bri = preset.bri.or(Some(xy.brightness));
+    }
+
+    let preset = hue.set_power_on_preset(light, preset)?;
+    say!(
+        "'{}' will be set to {} when powered on.",
+        preset.name,
+        preset.update()
+    );
+    say!("Run 'poweron watch' to apply it.");
+    Ok(())
+}
+
+/// Checks the lights until interrupted, setting those powered on at the wall to their preset.
+/// The presets are reloaded on every check, so presets set or removed meanwhile are picked up.
+fn subcommand_poweron_watch(hue: &Hue, matches: &clap::ArgMatches) -> Result<(), Box<dyn Error>> {
+    let interval = parse::parse_duration(matches.value_of("interval").unwrap_or("2s"))?;
+    let mut reachable = BTreeMap::new();
+
+    esay!(
+        "Watching for lights powered on every {} seconds...",
+        interval.as_secs_f32()
+    );
+
+    loop {
+        let result = poweron::load_presets()
+            .and_then(|presets| hue.apply_power_on_presets(&presets, &mut reachable));
+        match result {
+            Ok(applied) => {
+                for (name, result) in applied {
+                    match result {
+                        Ok(()) => say!("Set '{}' to its power-on preset.", name),
+                        Err(e) => esay!("Failed to set '{}' to its power-on preset: {}", name, e),
+                    }
+                }
+            }
+            Err(e) => esay!("Failed to apply power-on presets: {}", e),
+        }
+
+        thread::sleep(interval);
+    }
+}
+
+/// Checks the capped rooms until interrupted, bringing back down any light raised above its cap.
+/// The caps are reloaded on every check, so rooms capped or lifted meanwhile are picked up.
+fn subcommand_nightlight_watch(
+    hue: &Hue,
+    matches: &clap::ArgMatches,
+) -> Result<(), Box<dyn Error>> {
+    let interval = parse::parse_duration(matches.value_of("interval").unwrap_or("5s"))?;
+
+    esay!(
+        "Enforcing night lights every {} seconds...",
+        interval.as_secs_f32()
+    );
+
+    loop {
+        let result = nightlight::load_nightlights()
+            .and_then(|nightlights| hue.enforce_nightlights(&nightlights));
+        match result {
+            Ok(changed) => {
+                for name in changed {
+                    say!("Brought '{}' back within its night light cap.", name);
+                }
+            }
+            Err(e) => esay!("Failed to enforce night lights: {}", e),
+        }
+
+        thread::sleep(interval);
+    }
+}
+
+fn subcommand_schedule(hue: &Hue, matches: &clap::ArgMatches) {
+    let matches = matches.subcommand_matches("schedule").unwrap();
+
+    let result = match matches.subcommand() {
+        ("list", _) => {
+            subcommand_schedule_list(hue);
+            Ok(())
+        }
+        ("add", Some(matches)) => subcommand_schedule_add(hue, matches),
+        ("update", Some(matches)) => subcommand_schedule_update(hue, matches),
+        ("delete", Some(matches)) => {
+            let id = matches.value_of("ID").unwrap();
+            hue.delete_schedule(id)
+                .map(|()| say!("Schedule {} has been deleted.", id))
+        }
+        _ => {
+            println!("{}", matches.usage());
+            Ok(())
+        }
+    };
+
+    if let Err(e) = result {
+        println!("{}", e);
+        exit(hue, 1);
+    }
+}
+
+fn subcommand_schedule_list(hue: &Hue) {
+    let schedules = match hue.list_schedules() {
+        Ok(schedules) => schedules,
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    };
+
+    if schedules.is_empty() {
+        say!("No schedules on the bridge.");
+    }
+
+    for (id, schedule) in schedules {
+        println!(
+            "{}: {}, {}{}",
+            id,
+            schedule.name,
+            schedules::describe_localtime(&schedule.localtime),
+            if schedule.status == "disabled" {
+                " (disabled)"
+            } else {
+                ""
+            }
+        );
+    }
+}
+
+/// Helper function to parse the --at and --days arguments into a `localtime`.
+fn schedule_localtime(matches: &clap::ArgMatches) -> Result<Option<String>, Box<dyn Error>> {
+    let at = match matches.value_of("at") {
+        Some(at) => parse::parse_time_of_day(at)?,
+        None => return Ok(None),
+    };
+    let days = match matches.value_of("days") {
+        Some(days) => Some(schedules::parse_days(days)?),
+        None => None,
+    };
+
+    Ok(Some(schedules::localtime(&at, days)))
+}
+
+fn subcommand_schedule_add(hue: &Hue, matches: &clap::ArgMatches) -> Result<(), Box<dyn Error>> {
+    let light = matches.value_of("light").unwrap();
+    let localtime = schedule_localtime(matches)?.unwrap();
+    let bri = match matches.value_of("bri") {
+        Some(bri) => Some(parse::parse_brightness(bri)?),
+        None => None,
+    };
+
+    let body = schedules::action_body(
+        !matches.is_present("off"),
+        bri,
+        matches.value_of("color"),
+        &hue.colors_file()?,
+    )?;
+    let name = match matches.value_of("name") {
+        Some(name) => name.to_string(),
+        None => format!("{} {}", light, matches.value_of("at").unwrap()),
+    };
+
+    if let Some(fade) = matches.value_of("fade") {
+        let fade = parse::parse_duration(fade)?;
+        let at = parse::parse_time_of_day(matches.value_of("at").unwrap())?;
+        let days = match matches.value_of("days") {
+            Some(days) => Some(schedules::parse_days(days)?),
+            None => None,
+        };
+
+        let ids = hue.schedule_fade_in(&name, light, &body, &at, days, fade)?;
+        say!(
+            "Schedules {} created, fading in to reach the state {}.",
+            ids.join(" and "),
+            schedules::describe_localtime(&localtime)
+        );
+        return Ok(());
+    }
+
+    let id = hue.create_schedule(&Schedule {
+        name,
+        description: String::new(),
+        command: hue.schedule_command(light, &body)?,
+        localtime: localtime.clone(),
+        status: String::from("enabled"),
+        autodelete: None,
+    })?;
+
+    say!(
+        "Schedule {} created, {}.",
+        id,
+        schedules::describe_localtime(&localtime)
+    );
+    Ok(())
+}
+
+fn subcommand_schedule_update(hue: &Hue, matches: &clap::ArgMatches) -> Result<(), Box<dyn Error>> {
+    let id = matches.value_of("ID").unwrap();
+    let change = ScheduleChange {
+        name: matches.value_of("name").map(String::from),
+        localtime: schedule_localtime(matches)?,
+        enabled: match (matches.is_present("enable"), matches.is_present("disable")) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        },
+    };
+
+    hue.update_schedule(id, &change)?;
+    say!("Schedule {} has been updated.", id);
+    Ok(())
+}
+
+fn subcommand_scene_edit(hue: &Hue, matches: &clap::ArgMatches) {
+    let scene = matches.value_of("SCENE").unwrap();
+    let light = matches.value_of("light").unwrap();
+    let mut change = SceneLightChange::default();
+
+    if matches.is_present("on") {
+        change.on = Some(true);
+    } else if matches.is_present("off") {
+        change.on = Some(false);
+    }
+
+    if let Some(bri) = matches.value_of("bri") {
+        match parse::parse_brightness(bri) {
+            Ok(bri) => change.bri = Some(bri),
+            Err(e) => {
+                println!("{}", e);
+                exit(hue, 1);
+            }
+        }
+    }
+
+    if let Some(color) = matches.value_of("color") {
+        match hue
+            .colors_file()
+            .and_then(|path| colors::parse_color(color, &path))
+        {
+            Ok(rgb) => change.rgb = Some(rgb),
+            Err(e) => {
+                println!("{}", e);
+                exit(hue, 1);
+            }
+        }
+    }
+
+    say!("Updating light '{}' in scene '{}'...", light, scene);
+    match hue.edit_scene_lightstate(scene, light, &change) {
+        Ok(()) => say!("Scene '{}' has been updated.", scene),
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    }
+}
+
+fn subcommand_holiday(hue: &Hue, matches: &clap::ArgMatches) {
+    let lights = target_lights(hue, matches);
+    let matches = matches.subcommand_matches("holiday").unwrap();
+
+    if matches.is_present("clear") {
+        match hue.clear_exported() {
+            Ok(count) => say!("Removed {} timer(s) of exported effects.", count),
+            Err(e) => {
+                println!("{}", e);
+                exit(hue, 1);
+            }
+        }
+        return;
+    }
 
-    let hue = Hue::new().unwrap();
+    let holiday = holiday::preset(matches.value_of("HOLIDAY").unwrap()).and_then(|mut holiday| {
+        if let Some(colors) = matches.values_of("color") {
+            let named = hue
+                .colors_file()
+                .and_then(|path| colors::load_colors(&path))
+                .unwrap_or_else(|_| colors::default_colors());
+            holiday.colors = colors
+                .map(|color| shuffle::resolve_color(color, &named))
+                .collect::<Result<Vec<_>, _>>()?;
+        }
+        if let Some(bri) = matches.value_of("bri") {
+            holiday.bri = shuffle::parse_brightness_range(bri)?;
+        }
+        match matches.value_of("interval") {
+            Some(interval) => holiday.interval = parse::parse_duration(interval)?,
+            // The preset's speed is slowed down for more lights than the bridge can keep up with.
+            None => holiday.interval = holiday.interval.max(hue.min_interval(lights.len())),
+        }
+        holiday.check()?;
+        Ok(holiday)
+    });
+    let duration = matches.value_of("duration").map(parse::parse_duration);
 
-    match matches.subcommand_name() {
-        Some("color") => {
-            subcommand_color(&hue, &matches);
-            return;
+    let (mut holiday, duration) = match (holiday, duration) {
+        (Ok(holiday), None) => (holiday, None),
+        (Ok(holiday), Some(Ok(duration))) => (holiday, Some(duration)),
+        (Err(e), _) | (_, Some(Err(e))) => {
+            println!("{}", e);
+            exit(hue, 1);
         }
+    };
 
-        Some("info") => {
-            hue.print_info();
-            return;
+    let interval = holiday.interval;
+    if let Some(rate) = animation::max_flash_rate() {
+        if interval < animation::min_flash_gap(rate) && !matches.is_present("export") {
+            say!(
+                "Flashes faster than {} a second are slowed into fades, pass --i-understand-strobe \
+                 to allow them.",
+                rate
+            );
         }
+    }
 
-        Some("rename") => {
-            if let Some(matches) = matches.subcommand_matches("rename") {
-                let index = matches.value_of("INDEX");
-                let name = matches.value_of("NAME");
+    if matches.is_present("export") {
+        let name = matches.value_of("HOLIDAY").unwrap();
+        match hue.export(name, &holiday, &lights, interval) {
+            Ok(count) => say!(
+                "Created {} timer(s) on the bridge playing '{}', remove them with 'holiday --clear'.",
+                count, name
+            ),
+            Err(e) => {
+                println!("{}", e);
+                exit(hue, 1);
+            }
+        }
+        return;
+    }
 
-                match (index, name) {
-                    (Some(index), Some(name)) => {
-                        hue.rename_light(index, name).unwrap();
-                    }
-                    _ => (), // Any other condition besides the above will be caught by the arg parser.
+    if let Err(e) = hue.play(&mut holiday, &lights, interval, duration) {
+        println!("{}", e);
+        exit(hue, 1);
+    }
+}
+
+fn subcommand_stats(hue: &Hue, matches: &clap::ArgMatches) {
+    let matches = matches.subcommand_matches("stats").unwrap();
+    if matches.is_present("api") {
+        print_api_stats(hue);
+        return;
+    }
+    if matches.subcommand_matches("scenes").is_none() {
+        println!("{}", matches.usage());
+        return;
+    }
+
+    let activations = hue
+        .config()
+        .store
+        .open()
+        .and_then(|store| journal::load(&*store))
+        .unwrap_or_else(|e| {
+            say!("Failed to load the journal: {}", e);
+            exit(hue, 1);
+        });
+    if activations.is_empty() {
+        say!("No scenes have been recalled with rusty_hue yet.");
+        return;
+    }
+
+    // Scenes deleted from the bridge are shown by id.
+    let names: HashMap<String, String> = hue
+        .list_scenes()
+        .map(|scenes| {
+            scenes
+                .into_iter()
+                .map(|(id, scene)| (id, scene.name))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let usage = journal::scene_usage(&activations);
+    let name = |scene: &str| {
+        names
+            .get(scene)
+            .cloned()
+            .unwrap_or_else(|| scene.to_string())
+    };
+    let width = usage
+        .iter()
+        .map(|u| name(&u.scene).len())
+        .max()
+        .unwrap_or(0);
+
+    for scene in usage {
+        let typical = scene
+            .typical
+            .map_or(String::from("?"), |at| at.to_string()[..5].to_string());
+        say!(
+            "{}  {} time(s), usually at {}, last {}",
+            format!("{:<width$}", name(&scene.scene), width = width),
+            format!("{:>4}", scene.count),
+            typical,
+            scene.last.replace('T', " ")
+        );
+    }
+}
+
+/// Prints how the bridge has been answering requests, and the pacing used for it, learned or
+/// pinned in the configuration.
+fn print_api_stats(hue: &Hue) {
+    let performance = hue.performance();
+    let source = |pinned: bool| {
+        let source = if pinned {
+            "pinned"
+        } else if performance.learned() {
+            "learned"
+        } else {
+            "default, too few requests seen"
+        };
+        i18n::tr(source, &[])
+    };
+
+    say!("Requests seen:     {}", performance.samples);
+    say!(
+        "Latency:           {} ms",
+        format!("{:.0}", performance.latency_ms)
+    );
+    say!(
+        "Drop rate:         {}%",
+        format!("{:.1}", performance.drop_rate * 100.0)
+    );
+    say!(
+        "Parallel requests: {} ({})",
+        hue.parallelism(),
+        source(hue.config().parallel_requests.is_some())
+    );
+    say!(
+        "Frame rate:        {} light commands a second ({})",
+        format!("{:.1}", 1.0 / hue.time_per_command().as_secs_f64()),
+        source(hue.config().frame_rate.is_some())
+    );
+}
+
+fn subcommand_shuffle(hue: &Hue, matches: &clap::ArgMatches) {
+    let matches = matches.subcommand_matches("shuffle").unwrap();
+    let room = matches.value_of("room").unwrap();
+    let palette = matches.value_of("palette").unwrap();
+
+    let named = hue
+        .colors_file()
+        .and_then(|path| colors::load_colors(&path))
+        .unwrap_or_else(|_| colors::default_colors());
+    let palettes = shuffle::load_palettes(&named).unwrap_or_else(|e| {
+        say!("Failed to load palettes: {}", e);
+        exit(hue, 1);
+    });
+    let colors = match palettes.get(palette) {
+        Some(colors) => colors,
+        None => {
+            let mut names: Vec<&String> = palettes.keys().collect();
+            names.sort();
+            say!(
+                "Unknown palette '{}'. Available palettes: {}.",
+                palette,
+                names
+                    .iter()
+                    .map(|name| name.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            );
+            exit(hue, 1);
+        }
+    };
+
+    let bri = shuffle::parse_brightness_range(matches.value_of("bri").unwrap_or("30%-70%"));
+    let every = matches.value_of("every").map(parse::parse_duration);
+    let (bri, every) = match (bri, every) {
+        (Ok(bri), None) => (bri, None),
+        (Ok(bri), Some(Ok(every))) => (bri, Some(every)),
+        (Err(e), _) | (_, Some(Err(e))) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    };
+
+    let mut rng = Rng::from_time();
+    loop {
+        match hue.shuffle_room(room, colors, bri, &mut rng) {
+            Ok(count) => say!(
+                "Shuffled {} light(s) in '{}' with '{}'.",
+                count,
+                room,
+                palette
+            ),
+            Err(e) => {
+                println!("{}", e);
+                // Only give up on the first shuffle; later failures may be a passing blip.
+                if every.is_none() {
+                    exit(hue, 1);
                 }
             }
-            return;
         }
 
-        Some("rgb") => {
-            return;
+        match every {
+            Some(every) => thread::sleep(every),
+            None => return,
+        }
+    }
+}
+
+fn subcommand_vacation(hue: &Hue, matches: &clap::ArgMatches) {
+    let matches = matches.subcommand_matches("vacation").unwrap();
+
+    if matches.is_present("clear") {
+        match hue.clear_vacation() {
+            Ok(count) => say!("Removed {} vacation schedule(s).", count),
+            Err(e) => {
+                println!("{}", e);
+                exit(hue, 1);
+            }
+        }
+        return;
+    }
+
+    let names: Vec<&str> = matches
+        .values_of("room")
+        .map_or(Vec::new(), Iterator::collect);
+    let rooms = hue.vacation_rooms(&names).unwrap_or_else(|e| {
+        println!("{}", e);
+        exit(hue, 1);
+    });
+    if rooms.is_empty() {
+        say!("No rooms to simulate presence in.");
+        exit(hue, 1);
+    }
+
+    let days = match matches.value_of("days").map(str::parse::<u32>) {
+        Some(Ok(days)) if days > 0 => Some(days),
+        Some(_) => {
+            say!("Invalid number of days, expected at least 1.");
+            exit(hue, 1);
+        }
+        None => None,
+    };
+    let mut rng = Rng::from_time();
+    let learned = hue
+        .config()
+        .store
+        .open()
+        .and_then(|store| journal::load(&*store))
+        .map(|activations| journal::learned_evenings(&activations))
+        .unwrap_or_default();
+
+    if matches.is_present("schedule") {
+        let slots = vacation::plan(&rooms, days.unwrap_or(7), &learned, &mut rng);
+        for slot in &slots {
+            say!(
+                "Day {}: {} on at {}, off at {}",
+                slot.day + 1,
+                slot.room,
+                slot.on,
+                slot.off
+            );
+        }
+        match hue.schedule_vacation(&slots) {
+            Ok(count) => say!(
+                "Created {} schedule(s) on the bridge, remove them with 'vacation --clear'.",
+                count
+            ),
+            Err(e) => {
+                println!("{}", e);
+                exit(hue, 1);
+            }
+        }
+        return;
+    }
+
+    let mut day = 0;
+    let mut midnight =
+        Instant::now() - Duration::from_secs(u64::from(vacation::seconds_since_midnight()));
+    while days.is_none_or(|days| day < days) {
+        // Each day is planned on its own, so the times differ from day to day.
+        let mut events: Vec<(u32, bool, Slot)> = Vec::new();
+        for slot in vacation::plan(&rooms, 1, &learned, &mut rng) {
+            events.push((slot.on_seconds(), true, slot.clone()));
+            events.push((slot.off_seconds(), false, slot));
+        }
+        events.sort_by_key(|&(at, _, _)| at);
+
+        for (at, on, slot) in events {
+            // Times count from the midnight starting the planned day, so rooms turned off past
+            // midnight are turned off before the next day is planned.
+            let at = midnight + Duration::from_secs(u64::from(at));
+            match at.checked_duration_since(Instant::now()) {
+                Some(wait) => thread::sleep(wait),
+                None => continue,
+            }
+
+            let state = if on { "on" } else { "off" };
+            match hue.play_slot(&slot, on) {
+                Ok(()) => say!("Turned {} {}.", slot.room, state),
+                Err(e) => esay!("Failed to turn {} {}: {}", slot.room, state, e),
+            }
         }
 
+        midnight += Duration::from_secs(24 * 3600);
+        day += 1;
+    }
+}
+
+#[cfg(feature = "streaming")]
+fn subcommand_area(hue: &Hue, matches: &clap::ArgMatches) {
+    let matches = match matches
+        .subcommand_matches("area")
+        .and_then(|matches| matches.subcommand_matches("auto"))
+    {
+        Some(matches) => matches,
+        None => {
+            say!("Missing area command, e.x. 'area auto --room Living'.");
+            exit(hue, 1);
+        }
+    };
+
+    match hue.auto_area(matches.value_of("room").unwrap(), matches.value_of("name")) {
+        Ok(area) => {
+            say!(
+                "Created entertainment area '{}' (group {}) with {} light(s):",
+                area.name,
+                area.id,
+                area.lights.len()
+            );
+            for (index, [x, y, z]) in &area.lights {
+                println!(
+                    "  {:<20} x {:>5.2}, y {:>5.2}, z {:>5.2}",
+                    hue.light_name(index).unwrap_or(index),
+                    x,
+                    y,
+                    z
+                );
+            }
+        }
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    }
+}
+
+#[cfg(feature = "streaming")]
+fn subcommand_stream(hue: &Hue, matches: &clap::ArgMatches) {
+    let matches = match matches
+        .subcommand_matches("stream")
+        .and_then(|matches| matches.subcommand_matches("bench"))
+    {
+        Some(matches) => matches,
+        None => {
+            say!("Missing stream command, e.x. 'stream bench --area TV'.");
+            exit(hue, 1);
+        }
+    };
+
+    let area = matches.value_of("area").unwrap();
+    let rate = matches.value_of("rate").unwrap_or("25");
+    let rate: u32 = match rate.parse() {
+        Ok(rate) if rate > 0 => rate,
         _ => {
-            subcommand_toggle(&hue, &matches);
+            say!("Invalid rate '{}', expected frames per second.", rate);
+            exit(hue, 1);
+        }
+    };
+    let duration = match parse::parse_duration(matches.value_of("duration").unwrap_or("10s")) {
+        Ok(duration) => duration,
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    };
+
+    say!(
+        "Sending {} frames per second to '{}' for {} seconds...",
+        rate,
+        area,
+        duration.as_secs()
+    );
+    match hue.bench_area(area, rate, duration) {
+        Ok(result) => print!("{}", result.render()),
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
         }
     }
 }
 
-fn subcommand_color(hue: &Hue, matches: &clap::ArgMatches) {
+fn subcommand_color(hue: &Hue, matches: &clap::ArgMatches, verbosity: Verbosity) {
     let index = matches.value_of("index");
     let name = matches.value_of("name");
+    let selected = selected_lights(hue, matches);
+
+    if let Some(sub_matches) = matches.subcommand_matches("color") {
+        if let Some(color) = sub_matches.value_of("COLOR") {
+            if sub_matches.is_present("adaptive") {
+                let indices = selected.unwrap_or_else(|| target_lights(hue, matches));
+                subcommand_color_adaptive(hue, &indices, color, verbosity);
+                return;
+            }
+
+            // A list of colors is spread across the lights, so it always needs all of them.
+            let list = color.contains(',')
+                && hue
+                    .colors_file()
+                    .and_then(|path| colors::parse_color(color, &path))
+                    .is_err();
+            let indices = match selected {
+                None if list => Some(target_lights(hue, matches)),
+                indices => indices,
+            };
+            if let Some(indices) = indices {
+                let whites = sub_matches.is_present("whites");
+                subcommand_color_lights(hue, &indices, color, whites, verbosity);
+                return;
+            }
 
-    if let Some(matches) = matches.subcommand_matches("color") {
-        if let Some(color) = matches.value_of("COLOR") {
             match (index, name) {
                 (None, None) => {
-                    println!("Setting all lights to {}...", color);
-                    match hue.set_all_by_color(color) {
+                    say!("Setting all lights to {}...", color);
+                    let scales = brightness_scales(&hue.reachable_indices());
+                    match hue.set_all_by_scaled_color(color, &scales) {
                         Ok(()) => (),
-                        Err(_) => println!("No color: {} in configuration file.", color),
+                        Err(e) => println!("{}", e),
                     }
                 }
                 (None, Some(name)) => {
-                    println!("Setting light '{}' to {}...", name, color);
-                    hue.set_color_by_name_and_color(name, color).unwrap();
+                    say!("Setting light '{}' to {}...", name, color);
+                    if let Err(e) = hue.set_color_by_name_and_color(name, color) {
+                        println!("{}", e);
+                        exit(hue, 1);
+                    }
                 }
-                (Some(index), None) => {
-                    println!("Setting light at index: {} to {}", index, color);
-                    hue.set_color_by_index_and_color(index, color).unwrap();
+                (Some(index), _) => {
+                    say!("Setting light at index: {} to {}", index, color);
+                    if let Err(e) = hue.set_color_by_index_and_color(index, color) {
+                        println!("{}", e);
+                        exit(hue, 1);
+                    }
                 }
-                (Some(index), Some(name)) => {
-                    println!("Setting light at index: {} to {}", index, color);
-                    hue.set_color_by_index_and_color(index, color).unwrap();
+            }
+        }
+    }
+}
+
+/// Sets several lights to a color, or a list of colors spread across them in order of index,
+/// starting over when there are more lights than colors. Lights that can't show colors are
+/// checked for up front and skipped, or given the nearest white with `whites` if they can show
+/// whites, and the report says what was done to each light.
+fn subcommand_color_lights(
+    hue: &Hue,
+    indices: &[String],
+    color: &str,
+    whites: bool,
+    verbosity: Verbosity,
+) {
+    let rgbs = match hue
+        .colors_file()
+        .and_then(|path| colors::parse_colors(color, &path))
+    {
+        Ok(rgbs) => rgbs,
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    };
+
+    let mut targets = Vec::new();
+    let mut skipped = Vec::new();
+    for index in indices {
+        match hue.color_capability(index) {
+            Some(ColorCapability::Color) => targets.push(index.clone()),
+            Some(ColorCapability::White) if whites => targets.push(index.clone()),
+            Some(ColorCapability::White) => skipped.push((
+                index.clone(),
+                "only shows whites, use --whites for the nearest white",
+            )),
+            _ => skipped.push((index.clone(), "can't show colors")),
+        }
+    }
+
+    if targets.is_empty() {
+        say!("None of the {} light(s) can show colors.", indices.len());
+        exit(hue, 1);
+    }
+
+    targets.sort_by_key(|index| index.parse::<u32>().unwrap_or(u32::MAX));
+    let colors: HashMap<&str, &colors::RGB> = targets
+        .iter()
+        .map(String::as_str)
+        .zip(rgbs.iter().cycle())
+        .collect();
+
+    say!("Setting {} lights to {}...", targets.len(), color);
+    let scales = brightness_scales(&targets);
+    for_each_light_skipping(hue, &targets, &skipped, verbosity, |index| {
+        let rgb = colors[index];
+        let scale = hue.brightness_scale(&scales, index);
+        match hue.color_capability(index) {
+            Some(ColorCapability::White) => hue.set_white_for_rgb(index, rgb, scale).map(|_| ()),
+            _ => hue.set_scaled_color_by_index(index, rgb, scale),
+        }
+    });
+}
+
+fn subcommand_color_adaptive(hue: &Hue, indices: &[String], color: &str, verbosity: Verbosity) {
+    let rgb = match hue
+        .colors_file()
+        .and_then(|path| colors::parse_color(color, &path))
+    {
+        Ok(rgb) => circadian::adapt(&rgb, &circadian::now()),
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    };
+
+    say!(
+        "Setting {} lights to {} adapted for the time of day...",
+        indices.len(),
+        color
+    );
+    let scales = brightness_scales(indices);
+    for_each_light(hue, indices, verbosity, |index| {
+        hue.set_scaled_color_by_index(index, &rgb, hue.brightness_scale(&scales, index))
+    });
+}
+
+fn subcommand_rgb(hue: &Hue, matches: &clap::ArgMatches, verbosity: Verbosity) {
+    let value = matches
+        .subcommand_matches("rgb")
+        .and_then(|matches| matches.value_of("RGB"))
+        .unwrap();
+    let rgb = match colors::parse_rgb(value) {
+        Ok(rgb) => rgb,
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    };
+
+    let indices = target_lights(hue, matches);
+    let (targets, skipped): (Vec<String>, Vec<String>) = indices
+        .iter()
+        .cloned()
+        .partition(|index| hue.color_capability(index) == Some(ColorCapability::Color));
+    if targets.is_empty() {
+        say!("None of the {} light(s) can show colors.", indices.len());
+        exit(hue, 1);
+    }
+    let skipped: Vec<(String, &str)> = skipped
+        .into_iter()
+        .map(|index| (index, "can't show colors"))
+        .collect();
+
+    say!(
+        "Setting {} lights to {}, {}, {}...",
+        targets.len(),
+        rgb.r,
+        rgb.g,
+        rgb.b
+    );
+    let scales = brightness_scales(&targets);
+    for_each_light_skipping(hue, &targets, &skipped, verbosity, |index| {
+        hue.set_scaled_color_by_index(index, &rgb, hue.brightness_scale(&scales, index))
+    });
+}
+
+fn subcommand_xy(hue: &Hue, matches: &clap::ArgMatches, verbosity: Verbosity) {
+    let sub_matches = matches.subcommand_matches("xy").unwrap();
+    let coordinate = |name| {
+        let value = sub_matches.value_of(name).unwrap();
+        match value.parse::<f32>() {
+            Ok(c) if (0.0..=1.0).contains(&c) => c,
+            _ => {
+                say!(
+                    "Invalid {} coordinate '{}', expected a number between 0 and 1.",
+                    name,
+                    value
+                );
+                exit(hue, 1);
+            }
+        }
+    };
+    let (x, y) = (coordinate("X"), coordinate("Y"));
+
+    let bri = match sub_matches.value_of("bri").map(parse::parse_brightness) {
+        Some(Ok(bri)) => Some(bri),
+        Some(Err(e)) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+        None => None,
+    };
+    let clamp = sub_matches.is_present("clamp");
+
+    let indices = target_lights(hue, matches);
+    say!("Setting {} lights to [{}, {}]...", indices.len(), x, y);
+    let scales = brightness_scales(&indices);
+    for_each_light(hue, &indices, verbosity, |index| {
+        let bri =
+            bri.map(|bri| groups::scale_brightness(bri, hue.brightness_scale(&scales, index)));
+        hue.set_xy_by_index(index, x, y, bri, clamp)
+    });
+}
+
+fn subcommand_alert(hue: &Hue, matches: &clap::ArgMatches, verbosity: Verbosity) {
+    let mode = matches
+        .subcommand_matches("alert")
+        .and_then(|matches| matches.value_of("MODE"))
+        .map(AlertMode::parse)
+        .unwrap()
+        .unwrap();
+
+    let indices = target_lights(hue, matches);
+    say!("Setting alert '{}' on {} lights...", mode, indices.len());
+    for_each_light(hue, &indices, verbosity, |index| {
+        hue.set_alert_by_index(index, mode)
+    });
+}
+
+fn subcommand_effect(hue: &Hue, matches: &clap::ArgMatches, verbosity: Verbosity) {
+    let effect = matches
+        .subcommand_matches("effect")
+        .and_then(|matches| matches.value_of("EFFECT"))
+        .map(Effect::parse)
+        .unwrap()
+        .unwrap();
+
+    let indices = target_lights(hue, matches);
+    say!("Setting effect '{}' on {} lights...", effect, indices.len());
+    for_each_light(hue, &indices, verbosity, |index| {
+        hue.set_effect_by_index(index, effect)
+    });
+}
+
+fn subcommand_bri(hue: &Hue, matches: &clap::ArgMatches, verbosity: Verbosity) {
+    let value = matches
+        .subcommand_matches("bri")
+        .and_then(|matches| matches.value_of("BRIGHTNESS"))
+        .unwrap();
+
+    let bri = match parse::parse_brightness(value) {
+        Ok(bri) => bri,
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    };
+
+    let indices = target_lights(hue, matches);
+    say!(
+        "Setting {} lights to brightness {}...",
+        indices.len(),
+        value
+    );
+    let scales = brightness_scales(&indices);
+    for_each_light(hue, &indices, verbosity, |index| {
+        let bri = groups::scale_brightness(bri, hue.brightness_scale(&scales, index));
+        hue.set_brightness_by_index(index, bri)
+    });
+}
+
+fn subcommand_dim(hue: &Hue, matches: &clap::ArgMatches, verbosity: Verbosity) {
+    let value = matches
+        .subcommand_matches("dim")
+        .and_then(|matches| matches.value_of("STEP"))
+        .unwrap();
+
+    let step = match parse::parse_brightness_step(value) {
+        Ok(step) => step,
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    };
+
+    // The bridge only changes the brightness of lights that are on.
+    let indices = target_lights(hue, matches);
+    let (targets, skipped): (Vec<String>, Vec<String>) = indices
+        .iter()
+        .cloned()
+        .partition(|index| hue.light_state(index).is_some_and(|state| state.on()));
+    let skipped: Vec<(String, &str)> = skipped.into_iter().map(|index| (index, "is off")).collect();
+
+    say!(
+        "Changing the brightness of {} lights by {}...",
+        targets.len(),
+        value
+    );
+    for_each_light_skipping(hue, &targets, &skipped, verbosity, |index| {
+        hue.step_brightness_by_index(index, step)
+    });
+}
+
+fn subcommand_ct(hue: &Hue, matches: &clap::ArgMatches, verbosity: Verbosity) {
+    let index = matches.value_of("index");
+    let name = matches.value_of("name");
+
+    let temperature = matches
+        .subcommand_matches("ct")
+        .and_then(|matches| matches.value_of("TEMPERATURE"))
+        .unwrap();
+    let temperatures = match colors::load_temperatures() {
+        Ok(temperatures) => temperatures,
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    };
+
+    let mired = match colors::parse_temperature(temperature, &temperatures) {
+        Ok(mired) => mired,
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    };
+
+    if let Some(indices) = selected_lights(hue, matches) {
+        say!("Setting {} lights to {}...", indices.len(), temperature);
+        for_each_light(hue, &indices, verbosity, |index| {
+            hue.set_ct_by_index(index, mired)
+        });
+        return;
+    }
+
+    let result = match (index, name) {
+        (None, None) => {
+            say!("Setting all lights to {}...", temperature);
+            hue.set_ct_all(mired)
+        }
+        (None, Some(name)) => {
+            say!("Setting light '{}' to {}...", name, temperature);
+            hue.set_ct_by_name(name, mired)
+        }
+        (Some(index), _) => {
+            say!("Setting light at index: {} to {}", index, temperature);
+            hue.set_ct_by_index(index, mired)
+        }
+    };
+
+    if let Err(e) = result {
+        println!("{}", e);
+        exit(hue, 1);
+    }
+}
+
+fn subcommand_power(hue: &Hue, matches: &clap::ArgMatches, power: bool, verbosity: Verbosity) {
+    let state = if power { "on" } else { "off" };
+    let word = i18n::tr(state, &[]);
+    let all = matches
+        .subcommand_matches(state)
+        .is_some_and(|matches| matches.is_present("all"));
+    let (index, name) = if all {
+        (None, None)
+    } else {
+        (matches.value_of("index"), matches.value_of("name"))
+    };
+
+    let fade = match matches
+        .subcommand_matches(state)
+        .and_then(|matches| matches.value_of("fade"))
+    {
+        Some(fade) => match parse::parse_duration(fade) {
+            Ok(fade) => Some(fade),
+            Err(e) => {
+                println!("{}", e);
+                exit(hue, 1);
+            }
+        },
+        None => None,
+    };
+
+    if let Some(fade) = fade {
+        say!("Fading over {} seconds...", fade.as_secs());
+    }
+
+    if let Some(room) = matches
+        .subcommand_matches(state)
+        .and_then(|matches| matches.value_of("room"))
+    {
+        subcommand_room_on(hue, room, fade);
+        return;
+    }
 
-                    println!("Setting light '{}' to {}...", name, color);
-                    hue.set_color_by_name_and_color(name, color).unwrap();
+    if power
+        && matches
+            .subcommand_matches(state)
+            .is_some_and(|matches| matches.is_present("adaptive"))
+    {
+        let point = circadian::now();
+        let indices = target_lights(hue, matches);
+
+        say!(
+            "Turning {} lights on at {} mireds and {}% brightness...",
+            indices.len(),
+            point.mired,
+            (point.brightness * 100.0).round()
+        );
+        let scales = brightness_scales(&indices);
+        for_each_light(hue, &indices, verbosity, |index| {
+            let bri = groups::scale_brightness(point.bri(), hue.brightness_scale(&scales, index));
+            hue.set_white_by_index(index, point.mired, bri)
+        });
+        return;
+    }
+
+    if let Some(indices) = selected_lights(hue, matches).filter(|_| !all) {
+        say!("Turning {} lights {}...", indices.len(), word);
+        if fade.is_some() {
+            // Fading lights are dimmed together, so they can't be reported on one by one.
+            if let Err(e) = hue.set_power_by_indices(&indices, power, fade) {
+                println!("{}", e);
+                exit(hue, 1);
+            }
+            say!("Lights have been powered {}.", word);
+        } else {
+            for_each_light(hue, &indices, verbosity, |index| {
+                hue.set_power_by_index(index, power, None)
+            });
+        }
+        return;
+    }
+
+    match (index, name) {
+        (None, None) => {
+            say!("Turning lights {}...", word);
+            match hue.set_power_all(power, fade) {
+                Ok(()) => say!("Lights have been powered {}.", word),
+                Err(e) => {
+                    println!("{}", e);
+                    exit(hue, 1);
+                }
+            }
+        }
+        (None, Some(name)) => {
+            say!("Turning light '{}' {}...", name, word);
+            match hue.set_power_by_name(name, power, fade) {
+                Ok(()) => say!("Light '{}' has been powered {}.", name, word),
+                Err(e) => {
+                    println!("{}", e);
+                    exit(hue, 1);
+                }
+            }
+        }
+        (Some(index), _) => {
+            say!("Turning light at index: {} {}...", index, word);
+            match hue.set_power_by_index(index, power, fade) {
+                Ok(()) => say!("Light at index: {} powered {}.", index, word),
+                Err(e) => {
+                    println!("{}", e);
+                    exit(hue, 1);
                 }
             }
         }
     }
 }
 
+fn subcommand_group(hue: &Hue, matches: &clap::ArgMatches) {
+    let matches = matches.subcommand_matches("group").unwrap();
+
+    let acts = ["color", "bri", "toggle"]
+        .iter()
+        .any(|&arg| matches.is_present(arg));
+    let group = match (matches.value_of("GROUP"), &hue.config().favorite_group) {
+        (Some(group), _) => group,
+        (None, Some(favorite)) if acts => favorite,
+        (None, _) if acts => {
+            say!("Give the group, or set a favorite_group to act on by default.");
+            exit(hue, 1);
+        }
+        (None, _) => {
+            print_groups(hue);
+            return;
+        }
+    };
+
+    let result = if matches.is_present("toggle") {
+        say!("Toggling group '{}'...", group);
+        hue.toggle_group(group).map(|on| {
+            say!(
+                "Group '{}' has been powered {}.",
+                group,
+                if on { "on" } else { "off" }
+            )
+        })
+    } else {
+        let bri = matches.value_of("bri").map(parse::parse_brightness);
+        let scales = || match hue.find_group(group) {
+            Ok((_, found)) => brightness_scales(&found.lights),
+            Err(_) => HashMap::new(),
+        };
+        match (matches.value_of("color"), bri) {
+            (_, Some(Err(e))) => Err(e),
+            (Some(color), bri) => {
+                say!("Setting group '{}' to {}...", group, color);
+                hue.set_group_color(group, color, bri.and_then(Result::ok), &scales())
+            }
+            (None, Some(Ok(bri))) => {
+                say!("Setting group '{}' to brightness {}...", group, bri);
+                hue.set_group_brightness(group, bri, &scales())
+            }
+            (None, None) => {
+                say!("Turning group '{}' on...", group);
+                hue.find_group(group).and_then(|(id, _)| {
+                    hue.set_group_state(&id, &state::LightStateUpdate::new().on(true))
+                })
+            }
+        }
+    };
+
+    if let Err(e) = result {
+        println!("{}", e);
+        exit(hue, 1);
+    }
+}
+
+/// Prints the groups on the bridge with their type and lights, ordered by id.
+fn print_groups(hue: &Hue) {
+    let groups = match hue.get_groups() {
+        Ok(groups) => groups,
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    };
+
+    let mut ids: Vec<&String> = groups.keys().collect();
+    ids.sort_by_key(|id| id.parse::<u32>().unwrap_or(u32::MAX));
+
+    for id in ids {
+        let group = &groups[id];
+        say!(
+            "{}: {} ({}), lights {}",
+            id,
+            group.name,
+            group.group_type,
+            group.lights.join(", ")
+        );
+    }
+}
+
+fn subcommand_room_on(hue: &Hue, room: &str, fade: Option<Duration>) {
+    let default_scenes = match groups::load_default_scenes() {
+        Ok(default_scenes) => default_scenes,
+        Err(e) => {
+            say!("Failed to load default scenes: {}", e);
+            exit(hue, 1);
+        }
+    };
+
+    say!("Turning room '{}' on...", room);
+    match hue.room_on(room, &default_scenes, fade) {
+        Ok(Some(scene)) => say!("Room '{}' has been set to scene '{}'.", room, scene),
+        Ok(None) => say!("Room '{}' has been powered on.", room),
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+    }
+}
+
 fn subcommand_toggle(hue: &Hue, matches: &clap::ArgMatches) {
     let index = matches.value_of("index");
     let name = matches.value_of("name");
 
+    // A whole room or group is toggled with a single request to the group.
+    let group = selector_arg(matches)
+        .and_then(Result::ok)
+        .and_then(|selector| hue.resolve_group(&selector).ok())
+        .and_then(|id| id);
+    if let Some(id) = group {
+        say!("Toggling lights...");
+        let toggled = if id == "0" {
+            hue.toggle_lights()
+        } else {
+            hue.toggle_group(&id)
+        };
+        match toggled {
+            Ok(true) => say!("Lights have been powered on."),
+            Ok(false) => say!("Lights have been powered off."),
+            Err(e) => {
+                println!("{}", e);
+                exit(hue, 1);
+            }
+        }
+        return;
+    }
+
+    if let Some(indices) = selected_lights(hue, matches) {
+        say!("Toggling {} lights...", indices.len());
+        match hue.toggle_by_indices(&indices) {
+            Ok(true) => say!("Lights have been powered on."),
+            Ok(false) => say!("Lights have been powered off."),
+            Err(e) => {
+                println!("{}", e);
+                exit(hue, 1);
+            }
+        }
+        return;
+    }
+
     match (index, name) {
         (None, None) => {
-            println!("Toggling lights...");
+            say!("Toggling lights...");
             if hue.toggle_lights().unwrap() {
-                println!("Lights have been powered on.");
+                say!("Lights have been powered on.");
             } else {
-                println!("Lights have been powered off.");
+                say!("Lights have been powered off.");
             }
         }
         (None, Some(name)) => {
-            println!("Toggling light '{}'...", name);
+            say!("Toggling light '{}'...", name);
             match hue.toggle_by_name(name) {
                 Ok(on) => {
                     if on {
-                        println!("Light '{}' has been powered on.", name);
+                        say!("Light '{}' has been powered on.", name);
                     } else {
-                        println!("Light '{}' has been powered off.", name);
+                        say!("Light '{}' has been powered off.", name);
                     }
                 }
-                Err(_) => println!("No light with name: '{}'", name),
+                Err(_) => say!("No light with name: '{}'", name),
             }
         }
         (Some(index), None) => {
-            println!("Toggling light at index: {}...", index);
+            say!("Toggling light at index: {}...", index);
             if hue.toggle_by_index(index).unwrap() {
-                println!("Light at index: {} powered on.", index);
+                say!("Light at index: {} powered on.", index);
             } else {
-                println!("Light at index: {} powered off.", index);
+                say!("Light at index: {} powered off.", index);
             }
         }
         (Some(index), Some(name)) => {
-            println!("Toggling light at index: {}...", index);
+            say!("Toggling light at index: {}...", index);
             if hue.toggle_by_index(index).unwrap() {
-                println!("Light at index: {} powered on.", index);
+                say!("Light at index: {} powered on.", index);
             } else {
-                println!("Light at index: {} powered off.", index);
+                say!("Light at index: {} powered off.", index);
             }
 
-            println!("Toggling light '{}'...", name);
+            say!("Toggling light '{}'...", name);
             if hue.toggle_by_name(name).unwrap() {
-                println!("Light '{}' has been powered on.", name);
+                say!("Light '{}' has been powered on.", name);
             } else {
-                println!("Light '{}' has been powered off.", name);
+                say!("Light '{}' has been powered off.", name);
+            }
+        }
+    }
+}
+
+/// Builds the timeouts, retries, and any pinned pacing of requests to the bridge from the
+/// configuration, once every setting is checked with `Config::check`. Connecting may take at most
+/// the default connect timeout, or the whole timeout if that's shorter.
+fn hue_config(config: &Config) -> Result<HueConfig, Box<dyn Error>> {
+    config.check()?;
+
+    let defaults = HueConfig::default();
+    let timeout = parse::parse_duration(config.get("timeout").unwrap_or("30s"))?;
+
+    Ok(HueConfig {
+        connect_timeout: defaults.connect_timeout.min(timeout),
+        request_timeout: timeout,
+        retries: config
+            .get("retries")
+            .map_or(Ok(defaults.retries), str::parse)?,
+        parallel_requests: config
+            .get("parallel_requests")
+            .map(str::parse)
+            .transpose()?,
+        frame_rate: config.get("frame_rate").map(str::parse).transpose()?,
+        favorite_group: config.get("favorite_group").map(String::from),
+        strict: config.get("strict") == Some("true"),
+        no_cloud: config.get("no_cloud") == Some("true"),
+        store: store::Backend::parse(config.get("store").unwrap_or("json"))?,
+        colors_file: config.path("colors_file"),
+        bridge_ca: config.path("bridge_ca"),
+        bridge_id: config.get("bridge_id").map(String::from),
+        api: v2::Api::parse(config.get("api").unwrap_or("auto"))?,
+        ..defaults
+    })
+}
+
+/// Builds the state cache settings from the configuration. Commands that act on the current state
+/// of the lights, such as toggling, always fetch fresh state.
+fn cache_settings(
+    config: &Config,
+    matches: &clap::ArgMatches,
+) -> Result<Option<CacheSettings>, Box<dyn Error>> {
+    let path = match config.path("cache_file") {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let ttl = parse::parse_duration(config.get("cache_ttl").unwrap_or("0"))?;
+
+    let needs_state = matches!(
+        matches.subcommand_name(),
+        Some("on") | Some("off") | Some("dim") | Some("group") | Some("ipc") | None
+    ) || matches
+        .subcommand_matches("batteries")
+        .is_some_and(|matches| matches.is_present("watch"));
+
+    Ok(Some(CacheSettings {
+        path,
+        ttl,
+        fresh: matches.is_present("fresh") || needs_state,
+    }))
+}
+
+/// Applies a command to each selected light, to several at once as `Hue::in_parallel` does. The
+/// outcome is reported by room when there are several lights, and the process exits with an error
+/// if any of them failed.
+fn for_each_light<F>(hue: &Hue, indices: &[String], verbosity: Verbosity, command: F)
+where
+    F: Fn(&str) -> Result<(), Box<dyn Error>> + Sync,
+{
+    for_each_light_skipping(hue, indices, &[], verbosity, command)
+}
+
+/// Same as `for_each_light`, also reporting the lights left alone, given by index with the reason.
+fn for_each_light_skipping<F>(
+    hue: &Hue,
+    indices: &[String],
+    skipped: &[(String, &str)],
+    verbosity: Verbosity,
+    command: F,
+) where
+    F: Fn(&str) -> Result<(), Box<dyn Error>> + Sync,
+{
+    if let ([ref index], []) = (indices, skipped) {
+        if let Err(e) = command(index) {
+            println!("{}", e);
+            exit(hue, 1);
+        }
+        return;
+    }
+
+    // Lights are changed a few at a time, as many as the bridge takes at once.
+    let progress = Mutex::new(Progress::new(indices.len()));
+    let results = hue.in_parallel(indices, |index| {
+        progress
+            .lock()
+            .unwrap()
+            .step(hue.light_name(index).unwrap_or(index));
+        command(index)
+    });
+    drop(progress);
+
+    let rooms = hue.rooms_by_light().unwrap_or_default();
+    let after = hue.fetch_light_states().unwrap_or_default();
+    let mut report = Report::new();
+
+    for (index, result) in indices.iter().zip(results) {
+        let room = rooms.get(index).map_or(report::UNASSIGNED, String::as_str);
+        let name = hue.light_name(index).unwrap_or(index);
+        let before = hue.light_state(index).map(|state| state.to_string());
+        let after = after.get(index).map(|state| state.to_string());
+
+        report.record(room, name, before, after, result);
+    }
+
+    for (index, reason) in skipped {
+        let room = rooms.get(index).map_or(report::UNASSIGNED, String::as_str);
+        report.skip(room, hue.light_name(index).unwrap_or(index), reason);
+    }
+
+    print!("{}", report.render(verbosity));
+    if report.failures() > 0 {
+        exit(hue, 1);
+    }
+}
+
+/// Brightness multipliers to balance lights in commands on several lights, or none for a single
+/// light so it gets the brightness as requested.
+fn brightness_scales(indices: &[String]) -> HashMap<String, f32> {
+    if indices.len() < 2 {
+        return HashMap::new();
+    }
+
+    match groups::load_brightness_scales() {
+        Ok(scales) => scales,
+        Err(e) => {
+            println!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Lights targeted by the global selection arguments: the selection expression, the index or
+/// name of a single light, or otherwise every reachable light.
+fn target_lights(hue: &Hue, matches: &clap::ArgMatches) -> Vec<String> {
+    if let Some(indices) = selected_lights(hue, matches) {
+        return indices;
+    }
+
+    let light = matches
+        .value_of("index")
+        .or_else(|| matches.value_of("name"));
+
+    match light {
+        Some(light) => match hue.light_index(light) {
+            Some(index) => vec![index.to_string()],
+            None => {
+                say!("No light with index or name '{}' found.", light);
+                exit(hue, 1);
             }
+        },
+        None => hue.reachable_indices(),
+    }
+}
+
+/// The selector given by the global selection arguments, if any: the --select expression,
+/// --room, --group, lists or patterns of lights given to --index and --name, or --all, narrowed to
+/// a manufacturer by --vendor.
+fn selector_arg(matches: &clap::ArgMatches) -> Option<Result<Selector, Box<dyn Error>>> {
+    let vendor = matches.value_of("vendor");
+    let selector = if let Some(room) = matches.value_of("room") {
+        Ok(Selector::Room(room.to_string()))
+    } else if let Some(group) = matches.value_of("group") {
+        Ok(Selector::Group(group.to_string()))
+    } else if let Some(expression) = matches.value_of("select") {
+        Selector::parse(expression)
+    } else if let Some(lights) = light_list(matches) {
+        Selector::from_list(&lights)
+    } else if matches.is_present("all") || vendor.is_some() {
+        Ok(Selector::All)
+    } else {
+        return None;
+    };
+
+    Some(selector.map(|selector| match vendor {
+        // Selectors have no intersection, so lights not by the vendor are taken away instead.
+        Some(vendor) => Selector::Difference(
+            Box::new(selector),
+            Box::new(Selector::Difference(
+                Box::new(Selector::All),
+                Box::new(Selector::Vendor(vendor.to_string())),
+            )),
+        ),
+        None => selector,
+    }))
+}
+
+/// Lights given to --index or --name, if they're more than a single light, e.x. '-i 1,3,5',
+/// '-n Desk,Lamp', or a pattern such as '-n Living*'.
+fn light_list(matches: &clap::ArgMatches) -> Option<String> {
+    let lights = matches
+        .value_of("index")
+        .or_else(|| matches.value_of("name"))?;
+
+    if !lights.contains(',') && !Glob::is_pattern(lights) {
+        return None;
+    }
+    Some(lights.to_string())
+}
+
+/// Resolves the global selection arguments, if given, to the indices of the selected lights.
+/// Exits when the selection is invalid so no lights are changed by mistake.
+fn selected_lights(hue: &Hue, matches: &clap::ArgMatches) -> Option<Vec<String>> {
+    match selector_arg(matches)?.and_then(|selector| hue.resolve(&selector)) {
+        Ok(indices) => Some(indices.into_iter().collect()),
+        Err(e) => {
+            println!("{}", e);
+            exit(hue, 1);
         }
     }
 }
@@ -0,0 +1,236 @@
+//! # mock
+//!
+//! A bridge for tests, answering on a local port and recording the requests it's sent, so code
+//! that talks to the bridge can be tested without one on the network.
+
+use serde_json;
+use serde_json::Value;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use hue::Hue;
+
+/// Token the mock bridge is used with.
+pub const TOKEN: &str = "token";
+
+/// A request the mock bridge was sent, with the path after the token, e.x. "lights/1/state".
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recorded {
+    pub method: String,
+    pub path: String,
+    pub body: String,
+}
+
+/// Answers to the requests the bridge is sent, by method and path. Requests without one are
+/// answered as the bridge answers a successful change.
+type Answers = Vec<(String, String, Vec<Value>)>;
+
+#[derive(Debug, Default)]
+struct Shared {
+    resources: Value,
+    answers: Answers,
+    /// Requests, by method and path, that are recorded but never answered.
+    hang_ups: Vec<(String, String)>,
+    requests: Vec<Recorded>,
+}
+
+/// A bridge serving a fixed state on a local port until it's dropped.
+#[derive(Debug)]
+pub struct MockBridge {
+    address: String,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl MockBridge {
+    /// Starts a bridge answering GETs with the part of `resources` at the path, e.x.
+    /// `resources["lights"]["1"]` for "lights/1".
+    pub fn start(resources: Value) -> MockBridge {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let shared = Arc::new(Mutex::new(Shared {
+            resources,
+            ..Shared::default()
+        }));
+
+        let served = shared.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let shared = served.clone();
+                thread::spawn(move || serve(stream, &shared));
+            }
+        });
+
+        MockBridge { address, shared }
+    }
+
+    /// Answers the requests with the method and path with each of `answers` in turn, repeating
+    /// the last, e.x. an error the bridge reports.
+    pub fn answer(&self, method: &str, path: &str, answers: Vec<Value>) {
+        self.shared
+            .lock()
+            .unwrap()
+            .answers
+            .push((method.to_string(), path.to_string(), answers));
+    }
+
+    /// Hangs up on requests with the method and path once they're recorded, as if the answer was
+    /// lost on the way back after the bridge acted on them.
+    pub fn hang_up(&self, method: &str, path: &str) {
+        self.shared
+            .lock()
+            .unwrap()
+            .hang_ups
+            .push((method.to_string(), path.to_string()));
+    }
+
+    /// Address of the bridge, e.x. "127.0.0.1:40000".
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Connects to the bridge.
+    pub fn hue(&self) -> Hue {
+        Hue::connect(Some(&self.address), Some(TOKEN)).unwrap()
+    }
+
+    /// The requests sent so far other than GETs, in order.
+    pub fn changes(&self) -> Vec<Recorded> {
+        self.requests()
+            .into_iter()
+            .filter(|request| request.method != "GET")
+            .collect()
+    }
+
+    /// The requests sent so far, in order.
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.shared.lock().unwrap().requests.clone()
+    }
+}
+
+/// Helper function to answer the requests of a connection until the client hangs up.
+fn serve(stream: TcpStream, shared: &Mutex<Shared>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut stream = stream;
+
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            return;
+        }
+
+        let mut length = 0;
+        let mut header = String::new();
+        while reader.read_line(&mut header).unwrap_or(0) > 2 {
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    length = value.trim().parse().unwrap_or(0);
+                }
+            }
+            header.clear();
+        }
+
+        let mut body = vec![0; length];
+        if reader.read_exact(&mut body).is_err() {
+            return;
+        }
+
+        let mut parts = line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let target = parts.next().unwrap_or_default();
+        let path = target
+            .trim_start_matches(&format!("/api/{}", TOKEN))
+            .trim_matches('/')
+            .to_string();
+
+        let reply = {
+            let mut shared = shared.lock().unwrap();
+            shared.requests.push(Recorded {
+                method: method.clone(),
+                path: path.clone(),
+                body: String::from_utf8_lossy(&body).into_owned(),
+            });
+            if shared.hang_ups.contains(&(method.clone(), path.clone())) {
+                return;
+            }
+            answer(&mut shared, &method, &path)
+        };
+
+        let reply = reply.to_string();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            reply.len(),
+            reply
+        );
+        if stream.write_all(response.as_bytes()).is_err() {
+            return;
+        }
+    }
+}
+
+/// Helper function for the answer to a request.
+fn answer(shared: &mut Shared, method: &str, path: &str) -> Value {
+    let found = shared
+        .answers
+        .iter_mut()
+        .find(|(m, p, _)| m == method && p == path);
+    if let Some((_, _, answers)) = found {
+        return if answers.len() > 1 {
+            answers.remove(0)
+        } else {
+            answers[0].clone()
+        };
+    }
+
+    match method {
+        "GET" => {
+            let pointer = format!("/{}", path);
+            shared
+                .resources
+                .pointer(&pointer)
+                .cloned()
+                .unwrap_or_else(|| {
+                    json!([{"error": {"type": 3, "address": pointer,
+                    "description": "resource not available"}}])
+                })
+        }
+        "POST" => json!([{"success": {"id": "1"}}]),
+        _ => json!([{"success": {}}]),
+    }
+}
+
+/// Lights for tests: "1" an extended color light named "Desk" that can stream, "2" a dimmable
+/// light named "Lamp", both reachable.
+pub fn lights() -> Value {
+    json!({
+        "1": {
+            "type": "Extended color light",
+            "name": "Desk",
+            "modelid": "LCT015",
+            "capabilities": {
+                "control": {"colorgamuttype": "C", "ct": {"min": 153, "max": 500}},
+                "streaming": {"renderer": true, "proxy": true}
+            },
+            "state": {"on": true, "bri": 200, "hue": 0, "sat": 0, "xy": [0.3, 0.3], "ct": 300,
+                      "colormode": "xy", "reachable": true}
+        },
+        "2": {
+            "type": "Dimmable light",
+            "name": "Lamp",
+            "modelid": "LWB010",
+            "state": {"on": false, "bri": 100, "reachable": true}
+        }
+    })
+}
+
+/// Starts a bridge with the test `lights` and no other resources.
+pub fn bridge() -> MockBridge {
+    MockBridge::start(json!({ "lights": lights(), "groups": {}, "scenes": {}, "schedules": {} }))
+}
+
+/// Parses the body of a recorded request.
+pub fn body(request: &Recorded) -> Value {
+    serde_json::from_str(&request.body).unwrap()
+}
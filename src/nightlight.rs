@@ -0,0 +1,204 @@
+//! # nightlight
+//!
+//! Caps on the brightness and color temperature of rooms, e.x. a child's bedroom at night. While
+//! a room is capped, lights turned on brighter or cooler than its cap, by this tool or by any
+//! other app or automation, are brought back down by `nightlight watch`. Caps are kept in
+//! $HOME/.config/rusty_hue/nightlights.json until they are lifted.
+
+use serde_json;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+
+use files;
+use hue::Hue;
+use state::LightStateUpdate;
+
+/// Brightness a room is capped at unless another is given, about 20%.
+pub const DEFAULT_MAX_BRI: u8 = 51;
+
+/// Coolest color temperature a room is capped at unless another is given, in mireds (2200K).
+pub const DEFAULT_MIN_CT: u16 = 454;
+
+/// The cap of a room.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Nightlight {
+    /// Name of the room, for display.
+    pub room: String,
+    /// Highest brightness, 1-254.
+    pub bri: u8,
+    /// Coolest color temperature in mireds. Lights showing anything cooler, or a color, are set
+    /// to it.
+    pub ct: u16,
+}
+
+/// Loads the capped rooms keyed by group id. A missing file means no room is capped.
+pub fn load_nightlights() -> Result<BTreeMap<String, Nightlight>, Box<dyn Error>> {
+    let mut f = match File::open(files::config_file("nightlights.json")?) {
+        Ok(f) => f,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(From::from(e)),
+    };
+
+    let mut contents = String::new();
+    f.read_to_string(&mut contents)?;
+
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// Saves the capped rooms keyed by group id.
+pub fn save_nightlights(nightlights: &BTreeMap<String, Nightlight>) -> Result<(), Box<dyn Error>> {
+    let path = files::config_file("nightlights.json")?;
+    files::write_atomic(&path, serde_json::to_string_pretty(nightlights)?.as_bytes())?;
+    Ok(())
+}
+
+/// The changes that bring a light within a cap. `None` if the light is off or already within the
+/// cap.
+pub fn correction(cap: &Nightlight, light: &Value) -> Option<LightStateUpdate> {
+    let state = &light["state"];
+    if state["on"] != true {
+        return None;
+    }
+
+    let mut update = LightStateUpdate::new();
+
+    if state["bri"]
+        .as_u64()
+        .is_some_and(|bri| bri > u64::from(cap.bri))
+    {
+        update = update.bri(cap.bri);
+    }
+
+    // Lights without color temperatures can only be dimmed.
+    if let Some(ct) = state["ct"].as_u64() {
+        if state["colormode"] != "ct" || ct < u64::from(cap.ct) {
+            update = update.ct(cap.ct);
+        }
+    }
+
+    if update.is_empty() {
+        None
+    } else {
+        Some(update)
+    }
+}
+
+impl Hue {
+    /// Caps a room, by name or id, and brings its lights within the cap right away.
+    pub fn nightlight_on(
+        &self,
+        room: &str,
+        bri: u8,
+        ct: u16,
+    ) -> Result<Nightlight, Box<dyn Error>> {
+        let (id, group) = self.find_group(room)?;
+        let cap = Nightlight {
+            room: group.name,
+            bri: bri.max(1),
+            ct,
+        };
+
+        let mut nightlights = load_nightlights()?;
+        nightlights.insert(id, cap.clone());
+        save_nightlights(&nightlights)?;
+
+        self.enforce_nightlights(&nightlights)?;
+        Ok(cap)
+    }
+
+    /// Lifts the cap of a room, by name or id. Returns false if it wasn't capped.
+    pub fn nightlight_off(&self, room: &str) -> Result<bool, Box<dyn Error>> {
+        let (id, _) = self.find_group(room)?;
+
+        let mut nightlights = load_nightlights()?;
+        if nightlights.remove(&id).is_none() {
+            return Ok(false);
+        }
+
+        save_nightlights(&nightlights)?;
+        Ok(true)
+    }
+
+    /// Brings the lights of the capped rooms within their caps. Returns the names of the lights
+    /// that were changed. A light that fails to change is reported on stderr, and the others are
+    /// still brought within their caps.
+    pub fn enforce_nightlights(
+        &self,
+        nightlights: &BTreeMap<String, Nightlight>,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        if nightlights.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let lights = self.fetch("lights", false)?;
+        let groups = self.fetch("groups", false)?;
+        let mut changed = Vec::new();
+
+        for (id, cap) in nightlights {
+            let members = groups[id.as_str()]["lights"].as_array().cloned();
+
+            for index in members.unwrap_or_default().iter().filter_map(Value::as_str) {
+                let light = &lights[index];
+                if let Some(update) = correction(cap, light) {
+                    let name = light["name"].as_str().unwrap_or(index);
+                    match self.set_light_state(index, &update) {
+                        Ok(()) => changed.push(name.to_string()),
+                        Err(e) => esay!("Failed to cap light '{}': {}", name, e),
+                    }
+                }
+            }
+        }
+
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cap() -> Nightlight {
+        Nightlight {
+            room: String::from("Bedroom"),
+            bri: 50,
+            ct: 454,
+        }
+    }
+
+    #[test]
+    fn corrections() {
+        let bright = json!({"state": {"on": true, "bri": 200, "ct": 454, "colormode": "ct"}});
+        assert_eq!(
+            correction(&cap(), &bright),
+            Some(LightStateUpdate::new().bri(50))
+        );
+
+        let cool = json!({"state": {"on": true, "bri": 20, "ct": 250, "colormode": "ct"}});
+        assert_eq!(
+            correction(&cap(), &cool),
+            Some(LightStateUpdate::new().ct(454))
+        );
+
+        let colored = json!({"state": {"on": true, "bri": 20, "ct": 500, "colormode": "xy"}});
+        assert_eq!(
+            correction(&cap(), &colored),
+            Some(LightStateUpdate::new().ct(454))
+        );
+
+        let dimmable = json!({"state": {"on": true, "bri": 254}});
+        assert_eq!(
+            correction(&cap(), &dimmable),
+            Some(LightStateUpdate::new().bri(50))
+        );
+
+        let within = json!({"state": {"on": true, "bri": 50, "ct": 500, "colormode": "ct"}});
+        assert_eq!(correction(&cap(), &within), None);
+
+        let off = json!({"state": {"on": false, "bri": 254, "ct": 153, "colormode": "ct"}});
+        assert_eq!(correction(&cap(), &off), None);
+    }
+}
@@ -0,0 +1,145 @@
+//! # pairing
+//!
+//! Pairs with the bridge to get a new API token, which requires pressing the link button on the
+//! bridge. Needed whenever the bridge rejects the stored token, e.x. after a factory reset or
+//! after the token was removed in the Hue app.
+
+use reqwest;
+use serde_json;
+use serde_json::Value;
+use std::error::Error;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use config;
+use hue;
+use hue::Hue;
+
+/// How long the bridge accepts pairing requests after its link button is pressed.
+pub const LINK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Device type the command line tool pairs as, shown in the Hue app's list of paired apps.
+pub const DEFAULT_DEVICE_TYPE: &str = "rusty_hue#cli";
+
+/// Longest device type the bridge accepts.
+const MAX_DEVICE_TYPE_LEN: usize = 40;
+
+/// Bridge error type for a pairing request made before the link button was pressed.
+const LINK_BUTTON_NOT_PRESSED: u64 = 101;
+
+/// Checks a device type, which names the paired app in the form "app#device", e.x.
+/// "rusty_hue#kitchen-pi".
+fn check_device_type(device_type: &str) -> Result<(), Box<dyn Error>> {
+    if device_type.is_empty() || device_type.len() > MAX_DEVICE_TYPE_LEN {
+        return Err(From::from(format!(
+            "Invalid device type '{}', expected 1 to {} characters.",
+            device_type, MAX_DEVICE_TYPE_LEN
+        )));
+    }
+
+    Ok(())
+}
+
+/// Asks the bridge at the given IP for a new API token once, pairing as the given device type.
+/// Returns `None` if the link button hasn't been pressed yet.
+pub fn request_token(ip: &str, device_type: &str) -> Result<Option<String>, Box<dyn Error>> {
+    check_device_type(device_type)?;
+
+    let client = reqwest::Client::new();
+    let body = json!({ "devicetype": device_type });
+    let response: Value = serde_json::from_str(
+        &client
+            .post(&format!("http://{}/api", ip))
+            .body(body.to_string())
+            .send()?
+            .text()?,
+    )?;
+
+    if response[0]["error"]["type"].as_u64() == Some(LINK_BUTTON_NOT_PRESSED) {
+        return Ok(None);
+    }
+
+    if let Some(error) = hue::bridge_error(&response, ip) {
+        return Err(Box::new(error));
+    }
+
+    match response[0]["success"]["username"].as_str() {
+        Some(token) => Ok(Some(token.to_string())),
+        None => Err(From::from("The bridge did not return a token.")),
+    }
+}
+
+/// Pairs with the bridge at the given IP as the given device type, retrying until the link button
+/// is pressed or the timeout runs out. Returns the new API token.
+pub fn pair(ip: &str, device_type: &str, timeout: Duration) -> Result<String, Box<dyn Error>> {
+    let start = Instant::now();
+
+    loop {
+        if let Some(token) = request_token(ip, device_type)? {
+            return Ok(token);
+        }
+
+        if start.elapsed() >= timeout {
+            return Err(From::from(format!(
+                "The link button on the bridge at {} was not pressed within {} seconds.",
+                ip,
+                timeout.as_secs()
+            )));
+        }
+
+        thread::sleep(Duration::from_secs(1));
+    }
+}
+
+/// Saves an API token as the token setting in $HOME/.config/rusty_hue/config.json, where it's
+/// read from by default. Returns the path of the config file.
+pub fn save_token(token: &str) -> Result<PathBuf, Box<dyn Error>> {
+    config::save_settings(&[("token", Some(token))])
+}
+
+impl Hue {
+    /// Registers with the bridge at the given IP as a new app, e.x. "rusty_hue#kitchen-pi",
+    /// waiting up to `LINK_TIMEOUT` for its link button to be pressed. The new API token is saved
+    /// where it's read from by default, so `Hue::new` connects with it afterwards.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use rusty_hue::hue::{self, Hue};
+    ///
+    /// let ip = hue::get_hue_ip(false).unwrap();
+    /// println!("Press the link button on the bridge...");
+    /// let token = Hue::register(&ip, "rusty_hue#example").unwrap();
+    /// ```
+    pub fn register(ip: &str, device_type: &str) -> Result<String, Box<dyn Error>> {
+        let token = pair(ip, device_type, LINK_TIMEOUT)?;
+        save_token(&token)?;
+        Ok(token)
+    }
+}
+
+/// Steps to pair again without an interactive prompt, e.x. from a script or a service.
+pub fn recovery_steps(ip: &str) -> String {
+    format!(
+        "To pair again:\n  \
+         1. Press the link button on the bridge.\n  \
+         2. Within {} seconds, run: rusty_hue --ip {} pair\n\
+         The new token is saved to ~/.config/rusty_hue/config.json. If the token is set with \
+         RUSTY_HUE_TOKEN, replace it there instead.",
+        LINK_TIMEOUT.as_secs(),
+        ip
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn device_types() {
+        assert!(check_device_type(DEFAULT_DEVICE_TYPE).is_ok());
+        assert!(check_device_type("").is_err());
+        assert!(check_device_type(&"x".repeat(41)).is_err());
+    }
+}
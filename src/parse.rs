@@ -0,0 +1,384 @@
+//! # parse
+//!
+//! Parsers for the human friendly values accepted on the command line and in config files, such as
+//! brightness ("30%"), durations ("90s", "1h30m"), and times of day ("7:30pm", "19:30"). Shared by
+//! every feature taking these values so they are accepted, and rejected, the same way everywhere.
+
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+/// Parses a brightness given either as a bridge value (1-254) or as a percentage (e.x. "30%").
+/// Percentages are of the bridge's range, so 0% is its lowest brightness, 1.
+pub fn parse_brightness(value: &str) -> Result<u8, Box<dyn Error>> {
+    let value = value.trim();
+
+    if value.ends_with('%') {
+        let percent: f32 = match value.trim_end_matches('%').trim().parse() {
+            Ok(percent) => percent,
+            Err(_) => return Err(From::from(format!("Invalid brightness '{}'.", value))),
+        };
+
+        if !(0.0..=100.0).contains(&percent) {
+            return Err(From::from(format!(
+                "Brightness '{}' must be between 0% and 100%.",
+                value
+            )));
+        }
+
+        return Ok((percent / 100.0 * 254.0).round().max(1.0) as u8);
+    }
+
+    match value.parse::<u8>() {
+        Ok(bri) if (1..=254).contains(&bri) => Ok(bri),
+        _ => Err(From::from(format!(
+            "Brightness '{}' must be between 1 and 254 or a percentage.",
+            value
+        ))),
+    }
+}
+
+/// Parses a change of brightness, signed, given either in bridge steps (e.x. "-25") or as a
+/// percentage of full brightness (e.x. "+10%").
+pub fn parse_brightness_step(value: &str) -> Result<i16, Box<dyn Error>> {
+    let value = value.trim();
+
+    let (sign, magnitude) = if let Some(magnitude) = value.strip_prefix('+') {
+        (1, magnitude)
+    } else if let Some(magnitude) = value.strip_prefix('-') {
+        (-1, magnitude)
+    } else {
+        return Err(From::from(format!(
+            "Invalid brightness step '{}', expected '+' or '-' and a change, e.x. '+10%' or '-25'.",
+            value
+        )));
+    };
+
+    Ok(sign * i16::from(parse_brightness(magnitude)?))
+}
+
+/// Parses a duration such as "90s", "5 min", "1h30m", "1.5h", "250ms", or a plain number of
+/// seconds.
+///
+/// # Example
+///
+/// ```
+/// use rusty_hue::parse;
+/// use std::time::Duration;
+///
+/// assert_eq!(parse::parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+/// ```
+pub fn parse_duration(value: &str) -> Result<Duration, Box<dyn Error>> {
+    let trimmed = value.trim().to_lowercase();
+    let invalid = |reason: String| -> Box<dyn Error> {
+        From::from(format!("Invalid duration '{}': {}.", value.trim(), reason))
+    };
+
+    if trimmed.is_empty() {
+        return Err(invalid(String::from(
+            "nothing given, e.x. '90s' or '1h30m'",
+        )));
+    }
+
+    if let Ok(seconds) = trimmed.parse::<f64>() {
+        return to_duration(seconds).ok_or_else(|| invalid(String::from("out of range")));
+    }
+
+    let mut seconds = 0.0;
+    let mut rest = trimmed.as_str();
+
+    while !rest.is_empty() {
+        let number_end = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let (number, after) = rest.split_at(number_end);
+        let after = after.trim_start();
+
+        let unit_end = after
+            .find(|c: char| !c.is_alphabetic())
+            .unwrap_or(after.len());
+        let (unit, after) = after.split_at(unit_end);
+
+        if number.is_empty() {
+            return Err(invalid(format!("expected a number before '{}'", rest)));
+        }
+
+        let number: f64 = number
+            .parse()
+            .map_err(|_| invalid(format!("'{}' is not a number", number)))?;
+
+        let scale = match unit {
+            "" => {
+                return Err(invalid(format!(
+                    "missing unit after '{}' (use h, m, s, or ms)",
+                    number
+                )))
+            }
+            "h" | "hr" | "hrs" | "hour" | "hours" => 3600.0,
+            "m" | "min" | "mins" | "minute" | "minutes" => 60.0,
+            "s" | "sec" | "secs" | "second" | "seconds" => 1.0,
+            "ms" => 0.001,
+            unit => {
+                return Err(invalid(format!(
+                    "unknown unit '{}' (use h, m, s, or ms)",
+                    unit
+                )))
+            }
+        };
+
+        seconds += number * scale;
+        rest = after.trim_start();
+    }
+
+    to_duration(seconds).ok_or_else(|| invalid(String::from("out of range")))
+}
+
+/// Helper function to convert fractional seconds to a duration, rounded to the millisecond.
+fn to_duration(seconds: f64) -> Option<Duration> {
+    if !seconds.is_finite() || seconds < 0.0 || seconds > u32::MAX as f64 {
+        return None;
+    }
+
+    Some(Duration::from_millis((seconds * 1000.0).round() as u64))
+}
+
+/// A time of day on a 24 hour clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeOfDay {
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl TimeOfDay {
+    /// Seconds since midnight.
+    pub fn seconds(&self) -> u32 {
+        u32::from(self.hour) * 3600 + u32::from(self.minute) * 60 + u32::from(self.second)
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:{:02}:{:02}", self.hour, self.minute, self.second)
+    }
+}
+
+/// Parses a time of day on either a 12 or 24 hour clock, e.x. "7:30pm", "7 pm", "19:30",
+/// "19h30", "19.30", "noon", or "midnight".
+///
+/// # Example
+///
+/// ```
+/// use rusty_hue::parse;
+///
+/// let time = parse::parse_time_of_day("7:30pm").unwrap();
+///
+/// assert_eq!(time.to_string(), "19:30:00");
+/// ```
+pub fn parse_time_of_day(value: &str) -> Result<TimeOfDay, Box<dyn Error>> {
+    let trimmed = value.trim().to_lowercase();
+    let invalid = |reason: &str| -> Box<dyn Error> {
+        From::from(format!("Invalid time '{}': {}.", value.trim(), reason))
+    };
+
+    match trimmed.as_str() {
+        "noon" => {
+            return Ok(TimeOfDay {
+                hour: 12,
+                minute: 0,
+                second: 0,
+            })
+        }
+        "midnight" => {
+            return Ok(TimeOfDay {
+                hour: 0,
+                minute: 0,
+                second: 0,
+            })
+        }
+        "" => return Err(invalid("nothing given, e.x. '7:30pm' or '19:30'")),
+        _ => (),
+    }
+
+    let (clock, meridiem) = split_meridiem(&trimmed);
+
+    let parts: Vec<&str> = clock.split(&[':', '.', 'h'][..]).collect();
+    if parts.len() > 3 || parts.iter().skip(1).any(|part| part.len() != 2) {
+        return Err(invalid(
+            "expected hours and two digit minutes, e.x. '7:30pm' or '19:30'",
+        ));
+    }
+
+    let mut numbers = Vec::new();
+    for part in &parts {
+        match part.parse::<u8>() {
+            Ok(number) => numbers.push(number),
+            Err(_) => {
+                return Err(invalid(
+                    "expected hours and minutes, e.x. '7:30pm' or '19:30'",
+                ))
+            }
+        }
+    }
+
+    let mut hour = numbers[0];
+    let minute = numbers.get(1).cloned().unwrap_or(0);
+    let second = numbers.get(2).cloned().unwrap_or(0);
+
+    match meridiem {
+        Some(pm) => {
+            if !(1..=12).contains(&hour) {
+                return Err(invalid("hour must be between 1 and 12 with am/pm"));
+            }
+            hour %= 12;
+            if pm {
+                hour += 12;
+            }
+        }
+        None => {
+            if hour > 23 {
+                return Err(invalid("hour must be between 0 and 23"));
+            }
+        }
+    }
+
+    if minute > 59 || second > 59 {
+        return Err(invalid("minutes and seconds must be between 0 and 59"));
+    }
+
+    Ok(TimeOfDay {
+        hour,
+        minute,
+        second,
+    })
+}
+
+/// Helper function to split an am/pm suffix from a time. Returns `Some(true)` for pm.
+fn split_meridiem(value: &str) -> (&str, Option<bool>) {
+    for &(suffix, pm) in &[("a.m.", false), ("p.m.", true), ("am", false), ("pm", true)] {
+        if let Some(clock) = value.strip_suffix(suffix) {
+            return (clock.trim_end(), Some(pm));
+        }
+    }
+
+    (value, None)
+}
+
+/// Splits a command line into words the way a shell would, with single or double quotes keeping
+/// spaces within a word, e.x. `scene 'Movie night'` -> ["scene", "Movie night"].
+pub fn split_words(command: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+
+    for c in command.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    if quote.is_some() {
+        return Err(From::from(format!("Unclosed quote in '{}'.", command)));
+    }
+    words.extend(word);
+
+    Ok(words)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn brightness_values() {
+        assert_eq!(parse_brightness("200").unwrap(), 200);
+        assert_eq!(parse_brightness("30%").unwrap(), 76);
+        assert_eq!(parse_brightness("100%").unwrap(), 254);
+        assert_eq!(parse_brightness("0%").unwrap(), 1);
+        assert!(parse_brightness("0").is_err());
+        assert!(parse_brightness("255").is_err());
+        assert!(parse_brightness("120%").is_err());
+        assert!(parse_brightness("bright").is_err());
+    }
+
+    #[test]
+    fn brightness_steps() {
+        assert_eq!(parse_brightness_step("+10%").unwrap(), 25);
+        assert_eq!(parse_brightness_step("-25").unwrap(), -25);
+        assert_eq!(parse_brightness_step("-100%").unwrap(), -254);
+        assert!(parse_brightness_step("10%").is_err());
+        assert!(parse_brightness_step("+300").is_err());
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("60").unwrap(), Duration::from_secs(60));
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+        assert_eq!(
+            parse_duration("1h 30 min").unwrap(),
+            Duration::from_secs(5400)
+        );
+        assert_eq!(parse_duration("1.5h").unwrap(), Duration::from_secs(5400));
+        assert_eq!(parse_duration("250ms").unwrap(), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn duration_errors() {
+        let error = parse_duration("10x").unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "Invalid duration '10x': unknown unit 'x' (use h, m, s, or ms)."
+        );
+
+        let error = parse_duration("5m3").unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "Invalid duration '5m3': missing unit after '3' (use h, m, s, or ms)."
+        );
+
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("-5").is_err());
+        assert!(parse_duration("m").is_err());
+    }
+
+    #[test]
+    fn words() {
+        assert_eq!(
+            split_words("  scene 'Movie night' -s \"room:Living + Desk\"").unwrap(),
+            vec!["scene", "Movie night", "-s", "room:Living + Desk"]
+        );
+        assert_eq!(split_words("off ''").unwrap(), vec!["off", ""]);
+        assert!(split_words("scene 'Movie").is_err());
+    }
+
+    #[test]
+    fn times_of_day() {
+        let time = |value| parse_time_of_day(value).unwrap().to_string();
+
+        assert_eq!(time("7:30pm"), "19:30:00");
+        assert_eq!(time("7 PM"), "19:00:00");
+        assert_eq!(time("12am"), "00:00:00");
+        assert_eq!(time("12:15 p.m."), "12:15:00");
+        assert_eq!(time("19:30"), "19:30:00");
+        assert_eq!(time("19h30"), "19:30:00");
+        assert_eq!(time("06.45"), "06:45:00");
+        assert_eq!(time("noon"), "12:00:00");
+    }
+
+    #[test]
+    fn time_of_day_errors() {
+        assert!(parse_time_of_day("25:00").is_err());
+        assert!(parse_time_of_day("13pm").is_err());
+        assert!(parse_time_of_day("7:5").is_err());
+        assert!(parse_time_of_day("7:60").is_err());
+        assert!(parse_time_of_day("soon").is_err());
+    }
+}
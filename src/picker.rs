@@ -0,0 +1,542 @@
+//! # picker
+//!
+//! Interactive color picker in the terminal. Hue, saturation, and brightness are chosen with the
+//! arrow keys, or by clicking on their bars in terminals that report the mouse, and the lights
+//! follow along as the color changes. The terminal is put in raw mode with `stty`, so picking
+//! needs a Unix terminal.
+
+use std::error::Error;
+use std::io;
+use std::io::prelude::*;
+use std::io::IsTerminal;
+use std::process::{Command, Stdio};
+use std::time::Instant;
+
+use colors::{RGB, XY};
+use hue::{Hue, LightState};
+use state::LightStateUpdate;
+
+/// Column of the terminal the bars start at, counting from 1.
+const BAR_START: u16 = 16;
+
+/// Width of the bars, in characters.
+const BAR_WIDTH: u16 = 40;
+
+/// Row of the terminal the bar of the first field is on, counting from 1.
+const FIRST_ROW: u16 = 3;
+
+/// A value the picker changes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Hue,
+    Saturation,
+    Brightness,
+}
+
+const FIELDS: [Field; 3] = [Field::Hue, Field::Saturation, Field::Brightness];
+
+impl Field {
+    /// Largest value of the field: 360 degrees of hue, or 100 percent.
+    fn max(self) -> f32 {
+        match self {
+            Field::Hue => 360.0,
+            Field::Saturation | Field::Brightness => 100.0,
+        }
+    }
+
+    /// Change of the value for a single key press.
+    fn step(self) -> f32 {
+        match self {
+            Field::Hue => 5.0,
+            Field::Saturation | Field::Brightness => 2.0,
+        }
+    }
+}
+
+/// Input read from the terminal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    /// Keeps the picked color.
+    Enter,
+    /// Stops picking, setting the lights back.
+    Cancel,
+    /// A mouse button pressed at a column and row, counting from 1.
+    Click(u16, u16),
+}
+
+/// Parses what was read from a terminal in raw mode into keys, e.x. "\x1b[A" into `Key::Up`.
+/// Arrow keys, the vi keys h, j, k, and l, and SGR mouse reports are understood, anything else is
+/// skipped.
+pub fn parse_keys(input: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+
+    while i < input.len() {
+        match input[i] {
+            b'\x1b' if input.get(i + 1) == Some(&b'[') => {
+                let rest = &input[i + 2..];
+                let end = rest
+                    .iter()
+                    .position(|&b| b.is_ascii_alphabetic() || b == b'~')
+                    .unwrap_or(rest.len());
+                let sequence = &rest[..end];
+                let last = rest.get(end).cloned();
+
+                match (sequence, last) {
+                    (b"", Some(b'A')) => keys.push(Key::Up),
+                    (b"", Some(b'B')) => keys.push(Key::Down),
+                    (b"", Some(b'C')) => keys.push(Key::Right),
+                    (b"", Some(b'D')) => keys.push(Key::Left),
+                    (_, Some(b'M')) if sequence.first() == Some(&b'<') => {
+                        if let Some(click) = parse_click(&sequence[1..]) {
+                            keys.push(click);
+                        }
+                    }
+                    _ => {}
+                }
+                i += 2 + end + 1;
+                continue;
+            }
+            b'\x1b' | b'q' | b'\x03' => keys.push(Key::Cancel),
+            b'\r' | b'\n' => keys.push(Key::Enter),
+            b'k' => keys.push(Key::Up),
+            b'j' => keys.push(Key::Down),
+            b'l' => keys.push(Key::Right),
+            b'h' => keys.push(Key::Left),
+            _ => {}
+        }
+        i += 1;
+    }
+
+    keys
+}
+
+/// How much of what was read from a terminal is complete, leaving out an escape sequence cut off
+/// at the end, e.x. 1 for "l\x1b[", so it's parsed once the rest arrives rather than the escape
+/// taken as `Key::Cancel`.
+pub fn complete_input(input: &[u8]) -> usize {
+    let start = match input.iter().rposition(|&b| b == b'\x1b') {
+        Some(start) => start,
+        None => return input.len(),
+    };
+
+    let rest = &input[start + 1..];
+    let cut_off = match rest.first() {
+        None => true,
+        Some(&b'[') => !rest[1..]
+            .iter()
+            .any(|&b| b.is_ascii_alphabetic() || b == b'~'),
+        Some(_) => false,
+    };
+
+    if cut_off {
+        start
+    } else {
+        input.len()
+    }
+}
+
+/// Helper function to parse the button, column, and row of an SGR mouse report, e.x. "0;20;4".
+/// Only presses of the left button count, not drags, scrolls, or other buttons.
+fn parse_click(report: &[u8]) -> Option<Key> {
+    let report = String::from_utf8_lossy(report);
+    let parts: Vec<u16> = report
+        .split(';')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+
+    match *parts {
+        [0, column, row] => Some(Key::Click(column, row)),
+        _ => None,
+    }
+}
+
+/// What handling a key did.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Changed,
+    Unchanged,
+    Done,
+    Cancelled,
+}
+
+/// A color being picked, and the field being changed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Picker {
+    /// Hue, from 0 to 360 degrees.
+    pub hue: f32,
+    /// Saturation, from 0 to 100.
+    pub sat: f32,
+    /// Brightness, from 1 to 100. Lights can't be set to 0 without turning off.
+    pub bri: f32,
+    pub field: Field,
+}
+
+impl Picker {
+    /// Starts picking from a light's state, e.x. the current state of the first light.
+    pub fn from_state(state: &LightState) -> Picker {
+        Picker {
+            hue: (f32::from(state.hue()) / 65535.0 * 360.0).round(),
+            sat: (f32::from(state.sat()) / 254.0 * 100.0).round(),
+            bri: (f32::from(state.bri()) / 254.0 * 100.0).round().max(1.0),
+            field: Field::Hue,
+        }
+    }
+
+    /// The picked color.
+    pub fn rgb(&self) -> RGB {
+        RGB::from_hsv(self.hue, self.sat, self.bri)
+    }
+
+    /// Helper function for the value of a field.
+    fn value_mut(&mut self, field: Field) -> &mut f32 {
+        match field {
+            Field::Hue => &mut self.hue,
+            Field::Saturation => &mut self.sat,
+            Field::Brightness => &mut self.bri,
+        }
+    }
+
+    /// Helper function to set the value of a field, keeping it in range. Hue wraps around.
+    fn set(&mut self, field: Field, value: f32) -> Outcome {
+        let value = match field {
+            Field::Hue => value.rem_euclid(360.0),
+            Field::Saturation => value.clamp(0.0, 100.0),
+            Field::Brightness => value.clamp(1.0, 100.0),
+        };
+
+        let current = self.value_mut(field);
+        if *current == value {
+            return Outcome::Unchanged;
+        }
+        *current = value;
+        Outcome::Changed
+    }
+
+    /// Changes the picker for a key: up and down choose the field, left and right change it, and
+    /// a click on a bar chooses its field and sets it to where it was clicked.
+    pub fn handle(&mut self, key: Key) -> Outcome {
+        let position = FIELDS.iter().position(|&f| f == self.field).unwrap_or(0);
+
+        match key {
+            Key::Up => {
+                self.field = FIELDS[(position + FIELDS.len() - 1) % FIELDS.len()];
+                Outcome::Unchanged
+            }
+            Key::Down => {
+                self.field = FIELDS[(position + 1) % FIELDS.len()];
+                Outcome::Unchanged
+            }
+            Key::Left | Key::Right => {
+                let field = self.field;
+                let step = if key == Key::Left {
+                    -field.step()
+                } else {
+                    field.step()
+                };
+                let value = *self.value_mut(field) + step;
+                self.set(field, value)
+            }
+            Key::Click(column, row) => {
+                let field = match row.checked_sub(FIRST_ROW).map(usize::from) {
+                    Some(index) if index < FIELDS.len() => FIELDS[index],
+                    _ => return Outcome::Unchanged,
+                };
+                if !(BAR_START..BAR_START + BAR_WIDTH).contains(&column) {
+                    return Outcome::Unchanged;
+                }
+
+                self.field = field;
+                let share = f32::from(column - BAR_START) / f32::from(BAR_WIDTH - 1);
+                // A click at the end of the hue bar is red again, so it's kept just short of it.
+                let max = if field == Field::Hue {
+                    359.0
+                } else {
+                    field.max()
+                };
+                self.set(field, (share * max).round())
+            }
+            Key::Enter => Outcome::Done,
+            Key::Cancel => Outcome::Cancelled,
+        }
+    }
+
+    /// Draws the picker from the top left of the terminal, e.x.
+    ///
+    /// ```text
+    ///  > Hue          [#############|..........................] 120°
+    ///    Saturation   [...
+    /// ```
+    pub fn render(&self, lights: usize) -> String {
+        let mut screen = format!(
+            "\x1b[HPicking for {} light(s). Arrows change, Enter keeps, q cancels.\x1b[K\r\n\r\n",
+            lights
+        );
+
+        for &field in FIELDS.iter() {
+            let (label, value, unit) = match field {
+                Field::Hue => ("Hue", self.hue, "°"),
+                Field::Saturation => ("Saturation", self.sat, "%"),
+                Field::Brightness => ("Brightness", self.bri, "%"),
+            };
+            let marker = ((value / field.max()) * f32::from(BAR_WIDTH - 1)).round() as usize;
+            let bar: String = (0..usize::from(BAR_WIDTH))
+                .map(|i| match i {
+                    _ if i == marker => '|',
+                    _ if i < marker => '#',
+                    _ => '.',
+                })
+                .collect();
+            let selected = if field == self.field { '>' } else { ' ' };
+
+            screen += &format!(
+                " {} {:<10}  [{}] {}{}\x1b[K\r\n",
+                selected, label, bar, value, unit
+            );
+        }
+
+        screen + &format!("\r\n   {}\x1b[J", describe(&self.rgb()))
+    }
+}
+
+/// The color as hex and (x, y) coordinates, e.x. "#ff8000, xy [0.5594, 0.3993]".
+pub fn describe(rgb: &RGB) -> String {
+    let xy = XY::from_rgb(rgb);
+    format!(
+        "#{:02x}{:02x}{:02x}, xy [{:.4}, {:.4}]",
+        rgb.r, rgb.g, rgb.b, xy.x, xy.y
+    )
+}
+
+/// The change setting a light back to a state it was in.
+fn restore(state: &LightState) -> LightStateUpdate {
+    let update = LightStateUpdate::new().on(state.on()).bri(state.bri());
+
+    match (state.colormode(), state.xy()) {
+        ("xy", Some((x, y))) => update.xy(x, y),
+        ("ct", _) => update.ct(state.ct() as u16),
+        ("hs", _) => update.hue(state.hue()).sat(state.sat()),
+        _ => update,
+    }
+}
+
+/// The terminal in raw mode, showing the picker on its alternate screen, until dropped.
+struct Terminal {
+    /// Settings of the terminal before, as `stty -g` prints them.
+    saved: String,
+}
+
+impl Terminal {
+    fn enter() -> Result<Terminal, Box<dyn Error>> {
+        if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+            return Err(From::from("Picking a color needs a terminal."));
+        }
+
+        let saved = stty(&["-g"])?;
+        // Key presses are read as they come, waiting at most a tenth of a second, and Ctrl-C is
+        // read as a key so the terminal is always set back.
+        stty(&["-icanon", "-echo", "-isig", "min", "0", "time", "1"])?;
+
+        // Alternate screen, hidden cursor, and SGR mouse reports.
+        print!("\x1b[?1049h\x1b[?25l\x1b[?1000h\x1b[?1006h");
+        io::stdout().flush()?;
+
+        Ok(Terminal {
+            saved: saved.trim().to_string(),
+        })
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?1006l\x1b[?1000l\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        let _ = stty(&[self.saved.as_str()]);
+    }
+}
+
+/// Helper function to run `stty` on the terminal, returning what it prints.
+fn stty(args: &[&str]) -> Result<String, Box<dyn Error>> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("Failed to run stty: {}", e))?;
+
+    if !output.status.success() {
+        return Err(From::from(format!(
+            "stty failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+impl Hue {
+    /// Helper function to turn the lights on in the picked color. The color is sent at full
+    /// brightness and the brightness apart, so it follows the picker rather than the color's
+    /// luminance, which is far dimmer for e.x. blue than yellow.
+    fn show_picked(&self, indices: &[String], picker: &Picker) -> Result<(), Box<dyn Error>> {
+        let color = RGB::from_hsv(picker.hue, picker.sat, 100.0);
+        let bri = (picker.bri / 100.0 * 254.0).round().max(1.0) as u8;
+
+        for index in indices {
+            if let Some(light) = self.lights.get(index) {
+                self.set_light_state(index, &light.color_update(&color, 1.0).on(true).bri(bri))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Lets a color be picked interactively for the lights at the given indices, which follow
+    /// along, no more often than the bridge keeps up with. Returns the picked color, or `None`
+    /// if picking was cancelled. The lights are set back to how they were when picking is
+    /// cancelled or fails.
+    pub fn pick(&self, indices: &[String]) -> Result<Option<RGB>, Box<dyn Error>> {
+        let states: Vec<(&String, &LightState)> = indices
+            .iter()
+            .filter_map(|index| self.light_state(index).map(|state| (index, state)))
+            .collect();
+        let mut picker = match states.first() {
+            Some(&(_, state)) => Picker::from_state(state),
+            None => return Err(From::from("No lights to pick a color for.")),
+        };
+
+        let terminal = Terminal::enter()?;
+        let outcome = self.follow_picker(indices, &mut picker);
+        drop(terminal);
+
+        match outcome {
+            Ok(Outcome::Done) => Ok(Some(picker.rgb())),
+            Ok(_) => {
+                for (index, state) in states {
+                    self.set_light_state(index, &restore(state))?;
+                }
+                Ok(None)
+            }
+            Err(e) => {
+                // The lights are set back as for a cancel, the error being what's reported.
+                for (index, state) in states {
+                    let _ = self.set_light_state(index, &restore(state));
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Helper function for `pick`, showing the picker and changing the lights along with it until
+    /// the color is kept or picking is cancelled.
+    fn follow_picker(
+        &self,
+        indices: &[String],
+        picker: &mut Picker,
+    ) -> Result<Outcome, Box<dyn Error>> {
+        let interval = self.min_interval(indices.len());
+        let mut sent = None;
+        let mut pending = true;
+        let mut redraw = true;
+        let mut input = [0; 64];
+        let mut unparsed = Vec::new();
+
+        loop {
+            if redraw {
+                print!("{}", picker.render(indices.len()));
+                io::stdout().flush()?;
+                redraw = false;
+            }
+
+            if pending && sent.is_none_or(|sent: Instant| sent.elapsed() >= interval) {
+                self.show_picked(indices, picker)?;
+                sent = Some(Instant::now());
+                pending = false;
+            }
+
+            // An escape sequence cut off by a read is kept for the next one. If nothing follows
+            // within the read's timeout, it was a press of the escape key.
+            let read = io::stdin().read(&mut input)?;
+            unparsed.extend_from_slice(&input[..read]);
+            let complete = if read == 0 {
+                unparsed.len()
+            } else {
+                complete_input(&unparsed)
+            };
+            let keys = parse_keys(&unparsed[..complete]);
+            unparsed.drain(..complete);
+
+            let mut outcome = Outcome::Unchanged;
+            for key in keys {
+                redraw = true;
+                match picker.handle(key) {
+                    Outcome::Changed => pending = true,
+                    Outcome::Unchanged => {}
+                    done => outcome = done,
+                }
+            }
+
+            match outcome {
+                Outcome::Done if pending => {
+                    self.show_picked(indices, picker)?;
+                    return Ok(outcome);
+                }
+                Outcome::Unchanged => {}
+                _ => return Ok(outcome),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keys() {
+        assert_eq!(
+            parse_keys(b"\x1b[A\x1b[Dlq\r"),
+            vec![Key::Up, Key::Left, Key::Right, Key::Cancel, Key::Enter]
+        );
+        assert_eq!(
+            parse_keys(b"\x1b[<0;20;4M\x1b[<0;20;4m\x1b[<64;1;1M\x1b"),
+            vec![Key::Click(20, 4), Key::Cancel]
+        );
+    }
+
+    #[test]
+    fn cut_off_sequences() {
+        assert_eq!(complete_input(b"l\x1b[A"), 4);
+        assert_eq!(complete_input(b"l\x1b["), 1);
+        assert_eq!(complete_input(b"\x1b"), 0);
+        assert_eq!(complete_input(b"\x1b[<0;20"), 0);
+        assert_eq!(complete_input(b"\x1bq"), 2);
+    }
+
+    #[test]
+    fn picking() {
+        let mut picker = Picker {
+            hue: 355.0,
+            sat: 100.0,
+            bri: 100.0,
+            field: Field::Hue,
+        };
+
+        assert_eq!(picker.handle(Key::Right), Outcome::Changed);
+        assert_eq!(picker.hue, 0.0);
+        assert_eq!(picker.handle(Key::Up), Outcome::Unchanged);
+        assert_eq!(picker.field, Field::Brightness);
+        assert_eq!(picker.handle(Key::Right), Outcome::Unchanged);
+
+        // A click halfway along the saturation bar.
+        assert_eq!(
+            picker.handle(Key::Click(BAR_START + BAR_WIDTH / 2, FIRST_ROW + 1)),
+            Outcome::Changed
+        );
+        assert_eq!((picker.field, picker.sat), (Field::Saturation, 51.0));
+        assert_eq!(picker.handle(Key::Click(1, FIRST_ROW)), Outcome::Unchanged);
+        assert_eq!(describe(&picker.rgb()), "#ff7d7d, xy [0.5217, 0.3134]");
+    }
+}
@@ -0,0 +1,245 @@
+//! # poweron
+//!
+//! Power-on presets for lights whose firmware can't be told how to start up. A light powered on
+//! at the wall comes back with its factory default, full brightness, and `poweron watch` sets it
+//! to its preset instead as soon as the bridge reaches it again. Presets are kept in
+//! $HOME/.config/rusty_hue/poweron.json, keyed by light index.
+
+use serde_json;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+
+use files;
+use hue::{Hue, HueError};
+use state::LightStateUpdate;
+
+/// Brightness lights come back with after losing power, unless set up otherwise.
+pub const DEFAULT_POWER_ON_BRI: u64 = 254;
+
+/// The state a light is set to once it's powered on.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Preset {
+    /// Name of the light, for display.
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bri: Option<u8>,
+    /// Color temperature in mireds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ct: Option<u16>,
+    /// Color as (x, y) coordinates.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub xy: Option<(f32, f32)>,
+}
+
+impl Preset {
+    /// The change setting a light to the preset.
+    pub fn update(&self) -> LightStateUpdate {
+        let mut update = LightStateUpdate::new().on(true);
+        if let Some(bri) = self.bri {
+            update = update.bri(bri);
+        }
+        if let Some(ct) = self.ct {
+            update = update.ct(ct);
+        }
+        if let Some((x, y)) = self.xy {
+            update = update.xy(x, y);
+        }
+        update
+    }
+}
+
+/// Loads the presets keyed by light index. A missing file means no light has one.
+pub fn load_presets() -> Result<BTreeMap<String, Preset>, Box<dyn Error>> {
+    let mut f = match File::open(files::config_file("poweron.json")?) {
+        Ok(f) => f,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(From::from(e)),
+    };
+
+    let mut contents = String::new();
+    f.read_to_string(&mut contents)?;
+
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// Saves the presets keyed by light index.
+fn save_presets(presets: &BTreeMap<String, Preset>) -> Result<(), Box<dyn Error>> {
+    let path = files::config_file("poweron.json")?;
+    files::write_atomic(&path, serde_json::to_string_pretty(presets)?.as_bytes())?;
+    Ok(())
+}
+
+/// A light set to its preset, by name, with the outcome.
+pub type Applied = (String, Result<(), Box<dyn Error>>);
+
+/// Whether a light, as the bridge returns it, was just powered on: it was unreachable when last
+/// seen and now is reachable, on, and at the default brightness. A light seen for the first time
+/// isn't, as there's no telling.
+pub fn powered_on(was_reachable: Option<bool>, light: &Value) -> bool {
+    let state = &light["state"];
+
+    was_reachable == Some(false)
+        && state["reachable"] == true
+        && state["on"] == true
+        && state["bri"].as_u64() == Some(DEFAULT_POWER_ON_BRI)
+}
+
+impl Hue {
+    /// Sets the preset of a light, by name or index, replacing any it had.
+    pub fn set_power_on_preset(
+        &self,
+        light: &str,
+        mut preset: Preset,
+    ) -> Result<Preset, Box<dyn Error>> {
+        let index = match self.light_index(light) {
+            Some(index) => index.to_string(),
+            None => return Err(Box::new(HueError::LightNotFound(light.to_string()))),
+        };
+        preset.name = self.light_name(&index).unwrap_or(light).to_string();
+
+        let mut presets = load_presets()?;
+        presets.insert(index, preset.clone());
+        save_presets(&presets)?;
+
+        Ok(preset)
+    }
+
+    /// Removes the preset of a light, by name or index. Returns false if it had none.
+    pub fn remove_power_on_preset(&self, light: &str) -> Result<bool, Box<dyn Error>> {
+        let index = match self.light_index(light) {
+            Some(index) => index.to_string(),
+            None => return Err(Box::new(HueError::LightNotFound(light.to_string()))),
+        };
+
+        let mut presets = load_presets()?;
+        if presets.remove(&index).is_none() {
+            return Ok(false);
+        }
+
+        save_presets(&presets)?;
+        Ok(true)
+    }
+
+    /// Sets the lights with a preset that were powered on since the last check to it. Whether
+    /// each light was reachable is kept in `reachable` between checks. Returns the name of each
+    /// light set to its preset with the outcome. A light that failed to be set is still taken as
+    /// unreachable, so it's tried again at the next check.
+    pub fn apply_power_on_presets(
+        &self,
+        presets: &BTreeMap<String, Preset>,
+        reachable: &mut BTreeMap<String, bool>,
+    ) -> Result<Vec<Applied>, Box<dyn Error>> {
+        let lights = self.fetch("lights", false)?;
+        let mut applied = Vec::new();
+
+        for (index, light) in lights.as_object().into_iter().flatten() {
+            if let Some(preset) = presets.get(index) {
+                if powered_on(reachable.get(index).cloned(), light) {
+                    let result = self.set_light_state(index, &preset.update());
+                    let failed = result.is_err();
+                    applied.push((preset.name.clone(), result));
+                    if failed {
+                        continue;
+                    }
+                }
+            }
+
+            reachable.insert(
+                index.clone(),
+                light["state"]["reachable"].as_bool().unwrap_or(false),
+            );
+        }
+
+        Ok(applied)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock;
+    use mock::MockBridge;
+
+    #[test]
+    fn power_on_detection() {
+        let light =
+            |reachable, bri| json!({"state": {"on": true, "bri": bri, "reachable": reachable}});
+
+        assert!(powered_on(Some(false), &light(true, 254)));
+        assert!(!powered_on(Some(true), &light(true, 254)));
+        assert!(!powered_on(None, &light(true, 254)));
+        assert!(!powered_on(Some(false), &light(true, 120)));
+        assert!(!powered_on(Some(false), &light(false, 254)));
+    }
+
+    #[test]
+    fn preset_updates() {
+        let preset = Preset {
+            name: String::from("Desk"),
+            bri: Some(120),
+            ct: Some(370),
+            xy: None,
+        };
+
+        assert_eq!(
+            preset.update().to_string(),
+            r#"{"on":true,"bri":120,"ct":370}"#
+        );
+    }
+
+    #[test]
+    fn failed_presets_retried() {
+        let mut lights = mock::lights();
+        for light in ["1", "2"].iter() {
+            lights[*light]["state"]["on"] = json!(true);
+            lights[*light]["state"]["bri"] = json!(DEFAULT_POWER_ON_BRI);
+        }
+        let bridge = MockBridge::start(json!({ "lights": lights }));
+        bridge.answer(
+            "PUT",
+            "lights/1/state",
+            vec![
+                json!([{"error": {"type": 201, "address": "/lights/1/state/bri",
+                "description": "parameter, bri, is not modifiable. Device is set to off."}}]),
+                json!([{"success": {"/lights/1/state/on": true}}]),
+            ],
+        );
+        let hue = bridge.hue();
+
+        let preset = |name: &str| Preset {
+            name: name.to_string(),
+            bri: Some(120),
+            ..Preset::default()
+        };
+        let mut presets = BTreeMap::new();
+        presets.insert(String::from("1"), preset("Desk"));
+        presets.insert(String::from("2"), preset("Lamp"));
+        let mut reachable = BTreeMap::new();
+        reachable.insert(String::from("1"), false);
+        reachable.insert(String::from("2"), false);
+
+        // The failure of the desk doesn't keep the lamp from being set.
+        let applied = hue
+            .apply_power_on_presets(&presets, &mut reachable)
+            .unwrap();
+        let outcomes: Vec<(&str, bool)> = applied
+            .iter()
+            .map(|(name, result)| (name.as_str(), result.is_ok()))
+            .collect();
+        assert_eq!(outcomes, vec![("Desk", false), ("Lamp", true)]);
+        assert!(!reachable["1"]);
+        assert!(reachable["2"]);
+
+        let applied = hue
+            .apply_power_on_presets(&presets, &mut reachable)
+            .unwrap();
+        assert_eq!(applied.len(), 1);
+        assert_eq!(applied[0].0, "Desk");
+        assert!(applied[0].1.is_ok());
+        assert!(reachable["1"]);
+    }
+}
@@ -0,0 +1,97 @@
+//! # profile
+//!
+//! Timing of the steps of a command, e.x. bridge discovery, loading the token, and each request
+//! to the bridge, printed with `--profile` to find where slow invocations spend their time. Steps
+//! are only kept while profiling, so long-running modes don't collect them forever.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Time spent in each step, in the order the steps ran.
+#[derive(Debug, Default)]
+pub struct Profile {
+    enabled: bool,
+    steps: Mutex<Vec<(String, Duration)>>,
+}
+
+impl Profile {
+    /// A profile that records steps when enabled, and otherwise ignores them.
+    pub fn new(enabled: bool) -> Profile {
+        Profile {
+            enabled,
+            steps: Mutex::default(),
+        }
+    }
+
+    /// Records the time a step took, if profiling.
+    pub fn record(&self, step: &str, duration: Duration) {
+        if self.enabled {
+            self.steps
+                .lock()
+                .unwrap()
+                .push((step.to_string(), duration));
+        }
+    }
+
+    /// Runs a step and records the time it took.
+    pub fn time<T, F: FnOnce() -> T>(&self, step: &str, f: F) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.record(step, start.elapsed());
+        result
+    }
+
+    /// Formats the time of each step, with the total time of the command and the time spent
+    /// outside of the recorded steps.
+    pub fn render(&self, total: Duration) -> String {
+        let steps = self.steps.lock().unwrap();
+        let width = steps
+            .iter()
+            .map(|(step, _)| step.len())
+            .max()
+            .unwrap_or(0)
+            .max("total".len());
+        let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+
+        let mut output = String::from("Profile:\n");
+        for (step, duration) in steps.iter() {
+            output += &format!("  {:<width$}  {:>9.1} ms\n", step, millis(*duration));
+        }
+
+        let recorded: Duration = steps.iter().map(|&(_, duration)| duration).sum();
+        output += &format!(
+            "  {:<width$}  {:>9.1} ms ({:.1} ms elsewhere)\n",
+            "total",
+            millis(total),
+            millis(total.saturating_sub(recorded))
+        );
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_steps() {
+        let profile = Profile::new(true);
+        profile.record("discovery", Duration::from_millis(1500));
+        assert_eq!(profile.time("GET lights", || 1 + 1), 2);
+
+        let output = profile.render(Duration::from_secs(2));
+
+        assert!(output.starts_with("Profile:\n  discovery      1500.0 ms\n  GET lights "));
+        assert!(output.ends_with("  total          2000.0 ms (500.0 ms elsewhere)\n"));
+    }
+
+    #[test]
+    fn disabled_keeps_nothing() {
+        let profile = Profile::default();
+        profile.record("discovery", Duration::from_millis(1500));
+        assert_eq!(profile.time("GET lights", || 1 + 1), 2);
+
+        assert!(profile.steps.lock().unwrap().is_empty());
+    }
+}
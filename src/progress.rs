@@ -0,0 +1,93 @@
+//! # progress
+//!
+//! Progress of commands that take a while, e.x. searching for the bridge, importing, or changing
+//! many lights, shown on a single status line on stderr so they don't appear to hang. Progress is
+//! off unless turned on, and only ever shown when stderr is a terminal, so scripts and logs only
+//! see the command's output.
+
+use std::io;
+use std::io::prelude::*;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Width of the bar, in characters.
+const BAR_WIDTH: usize = 20;
+
+/// Turns progress on or off for the whole process.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether progress is shown: turned on, and stderr is a terminal.
+pub fn is_shown() -> bool {
+    ENABLED.load(Ordering::Relaxed) && io::stderr().is_terminal()
+}
+
+/// Replaces the status line with a message, e.x. "Searching for the bridge...".
+pub fn status(message: &str) {
+    if is_shown() {
+        let mut stderr = io::stderr();
+        let _ = write!(stderr, "\r\x1b[K{}", message);
+        let _ = stderr.flush();
+    }
+}
+
+/// Clears the status line.
+pub fn clear() {
+    if is_shown() {
+        let _ = write!(io::stderr(), "\r\x1b[K");
+    }
+}
+
+/// Formats a status line for a step of many, e.x. "[#####...............] 3/12 Desk".
+pub fn render(done: usize, total: usize, step: &str) -> String {
+    let filled = (done * BAR_WIDTH).checked_div(total).unwrap_or(BAR_WIDTH);
+
+    format!(
+        "[{}{}] {}/{} {}",
+        "#".repeat(filled),
+        ".".repeat(BAR_WIDTH - filled.min(BAR_WIDTH)),
+        done,
+        total,
+        step
+    )
+}
+
+/// Progress through a number of steps, cleared once dropped.
+#[derive(Debug)]
+pub struct Progress {
+    total: usize,
+    done: usize,
+}
+
+impl Progress {
+    pub fn new(total: usize) -> Progress {
+        Progress { total, done: 0 }
+    }
+
+    /// Shows the next step as started, e.x. the name of the light being changed.
+    pub fn step(&mut self, step: &str) {
+        status(&render(self.done, self.total, step));
+        self.done += 1;
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_bars() {
+        assert_eq!(render(0, 4, "Desk"), "[....................] 0/4 Desk");
+        assert_eq!(render(2, 4, "Lamp"), "[##########..........] 2/4 Lamp");
+        assert_eq!(render(0, 0, ""), "[####################] 0/0 ");
+    }
+}
@@ -0,0 +1,463 @@
+//! # proxy
+//!
+//! Serves a subset of the bridge's v1 API over HTTP, backed by the real bridge, so apps and devices
+//! that can't be trusted with the bridge itself can be given limited access to the lights. Each
+//! client gets its own token, listed in $HOME/.config/rusty_hue/proxy_clients.json with the
+//! lights it may see and change, e.x. {"<token>": {"name": "tablet", "lights": ["1", "2"]}}.
+//! Clients only see the lights they're allowed, can only change their state, and the list is read
+//! again for every request, so removing a client cuts it off at once.
+//!
+//! Served are `GET /api/<token>`, `GET /api/<token>/config`, `GET /api/<token>/lights`,
+//! `GET /api/<token>/lights/<id>`, and `PUT /api/<token>/lights/<id>/state`. Errors are answered
+//! the way the bridge answers them, e.x. `[{"error": {"type": 1, ...}}]` for an unknown token.
+
+use serde_json;
+use serde_json::{Map, Value};
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+
+use files;
+use hue::Hue;
+use serve::{parse_request_line, read_headers, read_line, response, READ_TIMEOUT};
+
+/// Fields of the bridge's configuration shown to clients. The rest, e.x. the whitelist of the
+/// bridge's own tokens, is kept from them.
+const PUBLIC_CONFIG: [&str; 6] = [
+    "name",
+    "bridgeid",
+    "modelid",
+    "apiversion",
+    "swversion",
+    "mac",
+];
+
+/// Largest request body read from a client. Larger ones are refused.
+const MAX_BODY: usize = 64 * 1024;
+
+/// Most clients served at once. Others are turned away until one is done.
+const MAX_CONNECTIONS: usize = 16;
+
+/// A client of the proxy.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Client {
+    pub name: String,
+    /// Indices of the lights the client may see and change.
+    pub lights: Vec<String>,
+}
+
+impl Client {
+    fn allows(&self, index: &str) -> bool {
+        self.lights.iter().any(|light| light == index)
+    }
+}
+
+/// Loads the clients from $HOME/.config/rusty_hue/proxy_clients.json, keyed by token. A missing
+/// file lists no clients.
+pub fn load_clients() -> Result<BTreeMap<String, Client>, Box<dyn Error>> {
+    let mut f = match File::open(files::config_file("proxy_clients.json")?) {
+        Ok(f) => f,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(From::from(e)),
+    };
+
+    let mut contents = String::new();
+    f.read_to_string(&mut contents)?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// Helper function to save the clients to proxy_clients.json.
+fn save_clients(clients: &BTreeMap<String, Client>) -> Result<(), Box<dyn Error>> {
+    let path = files::config_file("proxy_clients.json")?;
+    files::write_atomic(&path, serde_json::to_string_pretty(clients)?.as_bytes())?;
+    Ok(())
+}
+
+/// Adds a client allowed the lights at the given indices, returning its new token.
+pub fn add_client(name: &str, lights: &[String]) -> Result<String, Box<dyn Error>> {
+    let mut clients = load_clients()?;
+    if clients.values().any(|client| client.name == name) {
+        return Err(From::from(format!(
+            "A client named '{}' already exists.",
+            name
+        )));
+    }
+
+    let token = new_token();
+    clients.insert(
+        token.clone(),
+        Client {
+            name: name.to_string(),
+            lights: lights.to_vec(),
+        },
+    );
+    save_clients(&clients)?;
+
+    Ok(token)
+}
+
+/// Removes a client by name, revoking its token. Returns whether it was found.
+pub fn remove_client(name: &str) -> Result<bool, Box<dyn Error>> {
+    let mut clients = load_clients()?;
+    let count = clients.len();
+    clients.retain(|_, client| client.name != name);
+
+    if clients.len() == count {
+        return Ok(false);
+    }
+
+    save_clients(&clients)?;
+    Ok(true)
+}
+
+/// Helper function to make a random token of 40 hex digits, like the bridge's. The standard
+/// library's hash keys are seeded from the operating system's random source on every platform,
+/// so hashes with fresh keys are as hard to guess.
+fn new_token() -> String {
+    let mut token = String::new();
+
+    for part in 0u8..3 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u8(part);
+        token.push_str(&format!("{:016x}", hasher.finish()));
+    }
+
+    token.truncate(40);
+    token
+}
+
+/// Builds an error the way the bridge reports it.
+fn bridge_error(error_type: u64, address: &str, description: &str) -> Value {
+    json!([{
+        "error": {
+            "type": error_type,
+            "address": address,
+            "description": description,
+        }
+    }])
+}
+
+/// Keeps only the lights a client is allowed, from the bridge's lights.
+pub fn allowed_lights(lights: &Value, client: &Client) -> Value {
+    let allowed: Map<String, Value> = lights
+        .as_object()
+        .map(|lights| {
+            lights
+                .iter()
+                .filter(|(index, _)| client.allows(index))
+                .map(|(index, light)| (index.clone(), light.clone()))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Value::Object(allowed)
+}
+
+/// Keeps only the public fields of the bridge's configuration.
+fn public_config(config: &Value) -> Value {
+    let public: Map<String, Value> = PUBLIC_CONFIG
+        .iter()
+        .filter_map(|field| Some((field.to_string(), config.get(*field)?.clone())))
+        .collect();
+
+    Value::Object(public)
+}
+
+/// Serves the proxy on the address, e.x. "0.0.0.0:8081". Each client is served on its own thread,
+/// up to `MAX_CONNECTIONS` at once, with requests to the bridge made one at a time.
+pub fn serve(hue: Hue, address: &str) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(address)
+        .map_err(|e| format!("Failed to listen on {}: {}", address, e))?;
+    let hue = Arc::new(Mutex::new(hue));
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    for mut stream in listener.incoming().flatten() {
+        if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            connections.fetch_sub(1, Ordering::SeqCst);
+            let busy = response(
+                "503 Service Unavailable",
+                "text/plain",
+                "Too many clients, try again later.\n",
+            );
+            let _ = stream.write_all(busy.as_bytes());
+            continue;
+        }
+
+        let hue = hue.clone();
+        let connections = connections.clone();
+        thread::spawn(move || {
+            // A client hanging up mid-request shouldn't bring the proxy down.
+            let _ = handle_connection(stream, &hue);
+            connections.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+
+    Ok(())
+}
+
+/// Answers a single request from a client.
+fn handle_connection(stream: TcpStream, hue: &Mutex<Hue>) -> io::Result<()> {
+    // A client that stops sending its request is hung up on rather than keeping a thread.
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    read_line(&mut reader, &mut line)?;
+    let length = read_headers(&mut reader)?;
+
+    if length > MAX_BODY {
+        let reply = response(
+            "413 Payload Too Large",
+            "text/plain",
+            "Request body too large.\n",
+        );
+        return reader.into_inner().write_all(reply.as_bytes());
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    let body = String::from_utf8_lossy(&body);
+
+    let mut stream = reader.into_inner();
+    let reply = match parse_request_line(&line) {
+        Some((method, path)) => {
+            // A panic answering another client leaves the connection to the bridge usable, so
+            // the proxy keeps serving.
+            let hue = hue.lock().unwrap_or_else(PoisonError::into_inner);
+            let answer = match load_clients() {
+                Ok(clients) => answer(&hue, &clients, method, path, &body),
+                Err(e) => bridge_error(901, path, &e.to_string()),
+            };
+            response("200 OK", "application/json", &answer.to_string())
+        }
+        None => response("400 Bad Request", "text/plain", "Bad request.\n"),
+    };
+
+    stream.write_all(reply.as_bytes())
+}
+
+/// Answers a request for a path of the API, e.x. "/api/<token>/lights", as the bridge would for
+/// the lights the client is allowed.
+fn answer(
+    hue: &Hue,
+    clients: &BTreeMap<String, Client>,
+    method: &str,
+    path: &str,
+    body: &str,
+) -> Value {
+    let parts: Vec<&str> = path.trim_matches('/').split('/').collect();
+    if parts[0] != "api" || (parts.len() == 1 && method != "POST") {
+        return bridge_error(
+            4,
+            path,
+            &format!("method, {}, not available for resource, {}", method, path),
+        );
+    }
+
+    if parts.len() == 1 {
+        return bridge_error(
+            101,
+            "",
+            "link button not pressed, clients are added with 'rusty_hue proxy add'",
+        );
+    }
+
+    let client = match clients.get(parts[1]) {
+        Some(client) => client,
+        None => return bridge_error(1, "/", "unauthorized user"),
+    };
+
+    let resource = format!("/{}", parts[2..].join("/"));
+    let result = match (method, &parts[2..]) {
+        ("GET", []) => hue.fetch("lights", false).and_then(|lights| {
+            Ok(json!({
+                "lights": allowed_lights(&lights, client),
+                "config": public_config(&hue.fetch("config", false)?),
+            }))
+        }),
+        ("GET", ["config"]) => hue
+            .fetch("config", false)
+            .map(|config| public_config(&config)),
+        ("GET", ["lights"]) => hue
+            .fetch("lights", false)
+            .map(|lights| allowed_lights(&lights, client)),
+        ("GET", ["lights", index]) if client.allows(index) => {
+            hue.fetch(&format!("lights/{}", index), false)
+        }
+        ("PUT", ["lights", index, "state"]) if client.allows(index) => {
+            hue.put_value(&hue.api_url(&format!("lights/{}/state", index)), body)
+        }
+        (_, ["lights", index, ..]) if !client.allows(index) => {
+            return bridge_error(
+                3,
+                &resource,
+                &format!("resource, {}, not available", resource),
+            )
+        }
+        _ => {
+            return bridge_error(
+                4,
+                &resource,
+                &format!(
+                    "method, {}, not available for resource, {}",
+                    method, resource
+                ),
+            )
+        }
+    };
+
+    result.unwrap_or_else(|e| bridge_error(901, &resource, &e.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock;
+    use mock::MockBridge;
+
+    fn client() -> Client {
+        Client {
+            name: String::from("tablet"),
+            lights: vec![String::from("1"), String::from("3")],
+        }
+    }
+
+    #[test]
+    fn only_allowed_lights() {
+        let lights = json!({"1": {"name": "Desk"}, "2": {"name": "Lamp"}, "3": {"name": "Strip"}});
+
+        assert_eq!(
+            allowed_lights(&lights, &client()),
+            json!({"1": {"name": "Desk"}, "3": {"name": "Strip"}})
+        );
+    }
+
+    #[test]
+    fn config_kept_public() {
+        let config = json!({"name": "Hue", "apiversion": "1.50.0", "whitelist": {"abc": {}}});
+
+        assert_eq!(
+            public_config(&config),
+            json!({"name": "Hue", "apiversion": "1.50.0"})
+        );
+    }
+
+    #[test]
+    fn tokens() {
+        let token = new_token();
+
+        assert_eq!(token.len(), 40);
+        assert!(token.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(token, new_token());
+    }
+
+    /// Helper function to start a bridge with a configuration and the test lights, and the
+    /// clients of the proxy, with "tablet" allowed only the desk light.
+    fn setup() -> (MockBridge, BTreeMap<String, Client>) {
+        let bridge = MockBridge::start(json!({
+            "lights": mock::lights(),
+            "config": {"name": "Hue", "apiversion": "1.50.0", "whitelist": {"abc": {}}}
+        }));
+        let mut clients = BTreeMap::new();
+        clients.insert(
+            String::from("tablet-token"),
+            Client {
+                name: String::from("tablet"),
+                lights: vec![String::from("1")],
+            },
+        );
+
+        (bridge, clients)
+    }
+
+    /// Helper function for the type of the error a request is answered with, if any.
+    fn error_type(answer: &Value) -> Option<u64> {
+        answer[0]["error"]["type"].as_u64()
+    }
+
+    #[test]
+    fn clients_authorized() {
+        let (bridge, clients) = setup();
+        let hue = bridge.hue();
+        let ask = |method, path, body| answer(&hue, &clients, method, path, body);
+
+        assert_eq!(error_type(&ask("GET", "/api/other/lights", "")), Some(1));
+        assert_eq!(error_type(&ask("POST", "/api", "{}")), Some(101));
+        assert_eq!(error_type(&ask("GET", "/nope", "")), Some(4));
+
+        let all = ask("GET", "/api/tablet-token", "");
+        assert_eq!(all["lights"].as_object().unwrap().len(), 1);
+        assert_eq!(
+            all["config"],
+            json!({"name": "Hue", "apiversion": "1.50.0"})
+        );
+        assert_eq!(ask("GET", "/api/tablet-token/lights/1", "")["name"], "Desk");
+
+        bridge.answer(
+            "PUT",
+            "lights/1/state",
+            vec![json!([{"success": {"/lights/1/state/on": true}}])],
+        );
+        let on = r#"{"on": true}"#;
+        assert_eq!(
+            error_type(&ask("PUT", "/api/tablet-token/lights/1/state", on)),
+            None
+        );
+        assert_eq!(bridge.changes().len(), 1);
+    }
+
+    #[test]
+    fn clients_kept_to_their_lights() {
+        let (bridge, clients) = setup();
+        let hue = bridge.hue();
+        let ask = |method, path, body| answer(&hue, &clients, method, path, body);
+        let on = r#"{"on": true}"#;
+
+        assert_eq!(
+            error_type(&ask("GET", "/api/tablet-token/lights/2", "")),
+            Some(3)
+        );
+        assert_eq!(
+            error_type(&ask("PUT", "/api/tablet-token/lights/2/state", on)),
+            Some(3)
+        );
+        assert_eq!(
+            error_type(&ask("DELETE", "/api/tablet-token/lights/1", "")),
+            Some(4)
+        );
+        assert_eq!(
+            error_type(&ask("PUT", "/api/tablet-token/groups/0/action", on)),
+            Some(4)
+        );
+        assert!(bridge.changes().is_empty());
+    }
+
+    #[test]
+    fn large_bodies_refused() {
+        let (bridge, _) = setup();
+        let hue = Mutex::new(bridge.hue());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+
+        let request = format!(
+            "PUT /api/tablet-token/lights/1/state HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY + 1
+        );
+        client.write_all(request.as_bytes()).unwrap();
+        handle_connection(stream, &hue).unwrap();
+
+        let mut reply = String::new();
+        client.read_to_string(&mut reply).unwrap();
+        assert!(reply.starts_with("HTTP/1.1 413 "));
+        assert!(bridge.changes().is_empty());
+    }
+}
@@ -0,0 +1,228 @@
+//! # report
+//!
+//! Outcome of a command touching many lights, grouped by the room each light belongs to with
+//! per-room counts, so large operations stay readable. How much is shown is set by the `output`
+//! setting or the `--summary` and `--verbose` flags.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// Heading for lights that don't belong to any room.
+pub const UNASSIGNED: &str = "Unassigned";
+
+/// How much of a report to show.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verbosity {
+    /// Per-room counts only.
+    Summary,
+    /// Per-room counts and the lights that changed or failed, with their new state.
+    Changed,
+    /// Per-room counts and every light, with its state before and after.
+    Verbose,
+}
+
+impl Verbosity {
+    /// Parses a verbosity from the `output` setting: "summary", "changed", or "verbose".
+    pub fn parse(value: &str) -> Result<Verbosity, Box<dyn Error>> {
+        match value {
+            "summary" => Ok(Verbosity::Summary),
+            "changed" => Ok(Verbosity::Changed),
+            "verbose" => Ok(Verbosity::Verbose),
+            _ => Err(From::from(format!(
+                "Invalid output '{}', expected summary, changed, or verbose.",
+                value
+            ))),
+        }
+    }
+}
+
+/// Result of a command for a single light.
+#[derive(Debug)]
+struct Entry {
+    light: String,
+    before: Option<String>,
+    after: Option<String>,
+    error: Option<String>,
+    /// Why the command left the light alone, e.x. a white light given a color.
+    skipped: Option<String>,
+}
+
+impl Entry {
+    /// Whether the light changed. Lights whose state isn't known are assumed to have changed.
+    fn changed(&self) -> bool {
+        self.error.is_none()
+            && self.skipped.is_none()
+            && (self.before.is_none() || self.before != self.after)
+    }
+}
+
+/// Per-light results of a command, grouped by room.
+#[derive(Debug, Default)]
+pub struct Report {
+    rooms: BTreeMap<String, Vec<Entry>>,
+}
+
+impl Report {
+    pub fn new() -> Report {
+        Report::default()
+    }
+
+    /// Records the result of a command for a single light, along with a description of its state
+    /// before and after the command when known.
+    pub fn record(
+        &mut self,
+        room: &str,
+        light: &str,
+        before: Option<String>,
+        after: Option<String>,
+        result: Result<(), Box<dyn Error>>,
+    ) {
+        self.rooms.entry(room.to_string()).or_default().push(Entry {
+            light: light.to_string(),
+            before,
+            after,
+            error: result.err().map(|e| e.to_string()),
+            skipped: None,
+        });
+    }
+
+    /// Records a light the command left alone on purpose, with the reason.
+    pub fn skip(&mut self, room: &str, light: &str, reason: &str) {
+        self.rooms.entry(room.to_string()).or_default().push(Entry {
+            light: light.to_string(),
+            before: None,
+            after: None,
+            error: None,
+            skipped: Some(reason.to_string()),
+        });
+    }
+
+    /// Number of lights the command failed for.
+    pub fn failures(&self) -> usize {
+        self.rooms
+            .values()
+            .flatten()
+            .filter(|entry| entry.error.is_some())
+            .count()
+    }
+
+    /// Formats the report at the given verbosity.
+    pub fn render(&self, verbosity: Verbosity) -> String {
+        let mut output = String::new();
+
+        for (room, entries) in &self.rooms {
+            let changed = entries.iter().filter(|entry| entry.changed()).count();
+            let failed = entries.iter().filter(|entry| entry.error.is_some()).count();
+            let skipped = entries
+                .iter()
+                .filter(|entry| entry.skipped.is_some())
+                .count();
+
+            output += &format!(
+                "{}: {} changed, {} unchanged",
+                room,
+                changed,
+                entries.len() - changed - failed - skipped
+            );
+            if failed > 0 {
+                output += &format!(", {} failed", failed);
+            }
+            if skipped > 0 {
+                output += &format!(", {} skipped", skipped);
+            }
+            output.push('\n');
+
+            if verbosity == Verbosity::Summary {
+                continue;
+            }
+
+            for entry in entries {
+                let state = |state: &Option<String>| state.clone().unwrap_or_else(|| "ok".into());
+
+                let line = match (&entry.error, &entry.skipped) {
+                    (Some(error), _) => format!("failed: {}", error),
+                    (None, Some(reason)) => format!("skipped: {}", reason),
+                    _ if !entry.changed() => {
+                        if verbosity == Verbosity::Changed {
+                            continue;
+                        }
+                        format!("{} (unchanged)", state(&entry.after))
+                    }
+                    _ if verbosity == Verbosity::Verbose && entry.before.is_some() => {
+                        format!("{} -> {}", state(&entry.before), state(&entry.after))
+                    }
+                    _ => state(&entry.after),
+                };
+
+                output += &format!("  {}: {}\n", entry.light, line);
+            }
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn report() -> Report {
+        let on = || Some(String::from("on, bri 254"));
+        let off = || Some(String::from("off"));
+
+        let mut report = Report::new();
+        report.record(
+            "Living",
+            "Lamp",
+            on(),
+            None,
+            Err(From::from("Not reachable.")),
+        );
+        report.record(UNASSIGNED, "Strip", off(), on(), Ok(()));
+        report.record("Living", "Desk", on(), on(), Ok(()));
+        report
+    }
+
+    #[test]
+    fn group_by_room() {
+        let report = report();
+
+        assert_eq!(report.failures(), 1);
+        assert_eq!(
+            report.render(Verbosity::Changed),
+            "Living: 0 changed, 1 unchanged, 1 failed\n  Lamp: failed: Not reachable.\n\
+             Unassigned: 1 changed, 0 unchanged\n  Strip: on, bri 254\n"
+        );
+    }
+
+    #[test]
+    fn skipped_lights() {
+        let mut report = report();
+        report.skip("Living", "Plug", "can't show colors");
+
+        assert_eq!(report.failures(), 1);
+        assert_eq!(
+            report.render(Verbosity::Changed),
+            "Living: 0 changed, 1 unchanged, 1 failed, 1 skipped\n  Lamp: failed: Not reachable.\n  \
+             Plug: skipped: can't show colors\nUnassigned: 1 changed, 0 unchanged\n  Strip: on, bri \
+             254\n"
+        );
+    }
+
+    #[test]
+    fn verbosity_levels() {
+        let report = report();
+
+        assert_eq!(
+            report.render(Verbosity::Summary),
+            "Living: 0 changed, 1 unchanged, 1 failed\nUnassigned: 1 changed, 0 unchanged\n"
+        );
+        assert!(report
+            .render(Verbosity::Verbose)
+            .contains("  Desk: on, bri 254 (unchanged)\n"));
+        assert!(report
+            .render(Verbosity::Verbose)
+            .contains("  Strip: off -> on, bri 254\n"));
+        assert!(Verbosity::parse("loud").is_err());
+    }
+}
@@ -0,0 +1,387 @@
+//! # scenes
+//!
+//! Collection of data structures and methods for working with scenes stored on the Hue bridge.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+
+use colors;
+use decode;
+use hue::{Hue, HueError};
+use state::LightStateUpdate;
+
+/// Represents a scene stored on the bridge. Only the fields needed to identify a scene and the
+/// lights it controls are deserialized.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Scene {
+    pub name: String,
+    pub lights: Vec<String>,
+    /// Group the scene belongs to, for scenes of type "GroupScene".
+    #[serde(default)]
+    pub group: Option<String>,
+}
+
+/// Fields of a scene the bridge sends that aren't decoded.
+const KNOWN_SCENE_FIELDS: [&str; 10] = [
+    "type",
+    "owner",
+    "recycle",
+    "locked",
+    "appdata",
+    "picture",
+    "image",
+    "lastupdated",
+    "version",
+    "lightstates",
+];
+
+/// Changes to the stored state of a single light within a scene. Fields left as `None` are not
+/// sent to the bridge.
+#[derive(Debug, Default)]
+pub struct SceneLightChange {
+    pub on: Option<bool>,
+    pub bri: Option<u8>,
+    pub rgb: Option<colors::RGB>,
+}
+
+/// Light state fields compared when previewing a scene, in the order they're shown.
+const DIFF_FIELDS: [&str; 4] = ["on", "bri", "ct", "xy"];
+
+/// Helper function to describe a light state field for display, e.x. "bri 80%" or "ct 2200K".
+fn describe_field(field: &str, value: &Value) -> String {
+    match (field, value) {
+        ("on", &Value::Bool(on)) => String::from(if on { "on" } else { "off" }),
+        ("bri", value) if value.is_u64() => {
+            format!("bri {}%", (value.as_f64().unwrap() / 254.0 * 100.0).round())
+        }
+        ("ct", value) if value.as_u64().is_some_and(|mired| mired > 0) => {
+            format!(
+                "ct {}K",
+                (1_000_000 / value.as_u64().unwrap() + 50) / 100 * 100
+            )
+        }
+        ("xy", Value::Array(xy)) if xy.len() == 2 => format!(
+            "xy [{:.3}, {:.3}]",
+            xy[0].as_f64().unwrap_or(0.0),
+            xy[1].as_f64().unwrap_or(0.0)
+        ),
+        (field, _) => format!("{} -", field),
+    }
+}
+
+/// Compares the current state of a light with the state a scene stores for it, returning what
+/// would change as before and after descriptions, e.x. ("bri 80%, ct 4000K", "bri 10%, ct
+/// 2200K"). Only the fields that would change are described, or every stored field on both sides
+/// when nothing would.
+pub fn diff_lightstate(current: &Value, stored: &Value) -> (String, String) {
+    let mode = current["colormode"].as_str().unwrap_or("");
+    let turning_off = stored["on"] == Value::Bool(false);
+
+    let mut before = Vec::new();
+    let mut after = Vec::new();
+    let mut unchanged = Vec::new();
+
+    for &field in DIFF_FIELDS.iter() {
+        if stored.get(field).is_none() || (turning_off && field != "on") {
+            continue;
+        }
+
+        // A light only has a meaningful ct or xy while in that color mode.
+        let current_value = match field {
+            "ct" | "xy" if mode != field => &Value::Null,
+            _ => &current[field],
+        };
+
+        let (from, to) = (
+            describe_field(field, current_value),
+            describe_field(field, &stored[field]),
+        );
+        if from == to {
+            unchanged.push(to);
+        } else {
+            before.push(from);
+            after.push(to);
+        }
+    }
+
+    if after.is_empty() {
+        let unchanged = unchanged.join(", ");
+        return (unchanged.clone(), unchanged);
+    }
+
+    (before.join(", "), after.join(", "))
+}
+
+/// The state a scene stores for a light in the given state: its power and brightness, and its
+/// color in the light's current color mode. Other fields, e.x. "reachable", aren't stored.
+pub fn stored_lightstate(state: &Value) -> Value {
+    let mut stored = json!({ "on": state["on"] });
+
+    if state["on"] == true {
+        stored["bri"] = state["bri"].clone();
+        match state["colormode"].as_str() {
+            Some("ct") => stored["ct"] = state["ct"].clone(),
+            Some("xy") | Some("hs") if !state["xy"].is_null() => stored["xy"] = state["xy"].clone(),
+            _ => (),
+        }
+    }
+
+    stored
+}
+
+/// What recalling a scene would change for one of its lights.
+#[derive(Debug)]
+pub struct SceneLightDiff {
+    pub index: String,
+    pub before: String,
+    pub after: String,
+}
+
+impl Hue {
+    /// Compares each light of a scene with the state the scene would recall, without changing
+    /// anything, with before and after descriptions as given by `diff_lightstate`.
+    pub fn diff_scene(&self, scene: &str) -> Result<Vec<SceneLightDiff>, Box<dyn Error>> {
+        let (id, _) = self.find_scene(scene)?;
+        let stored = self.fetch(&format!("scenes/{}", id), true)?;
+        let lights = self.fetch("lights", false)?;
+
+        let lightstates = match stored["lightstates"].as_object() {
+            Some(lightstates) => lightstates,
+            None => {
+                return Err(From::from(
+                    "The bridge did not return the scene's light states.",
+                ))
+            }
+        };
+
+        let mut diffs: Vec<SceneLightDiff> = lightstates
+            .iter()
+            .map(|(index, state)| {
+                let (before, after) = diff_lightstate(&lights[index]["state"], state);
+                SceneLightDiff {
+                    index: index.clone(),
+                    before,
+                    after,
+                }
+            })
+            .collect();
+        diffs.sort_by_key(|diff| diff.index.parse::<u32>().unwrap_or(0));
+
+        Ok(diffs)
+    }
+
+    /// Retrieves all scenes stored on the bridge, keyed by their id.
+    pub fn get_scenes(&self) -> Result<HashMap<String, Scene>, Box<dyn Error>> {
+        let scenes: HashMap<String, Scene> = decode::decode_map(
+            &self.fetch("scenes", true)?,
+            "scene",
+            &KNOWN_SCENE_FIELDS,
+            self.config().strict,
+        )?;
+
+        Ok(scenes)
+    }
+
+    /// Lists the scenes stored on the bridge with their ids, ordered by name.
+    pub fn list_scenes(&self) -> Result<Vec<(String, Scene)>, Box<dyn Error>> {
+        let mut scenes: Vec<(String, Scene)> = self.get_scenes()?.into_iter().collect();
+        scenes.sort_by(|a, b| a.1.name.cmp(&b.1.name).then_with(|| a.0.cmp(&b.0)));
+
+        Ok(scenes)
+    }
+
+    /// Recalls a scene, given by id or name, on its group, or on all lights for scenes that
+    /// don't belong to one.
+    pub fn activate_scene(&self, scene: &str) -> Result<(), Box<dyn Error>> {
+        let (id, found) = self.find_scene(scene)?;
+        let group = found.group.unwrap_or_else(|| String::from("0"));
+
+        self.recall_scene(&group, &id, None)
+    }
+
+    /// Creates a scene on the bridge storing the given state for each light, keyed by index, e.x.
+    /// {"1": {"on": true, "bri": 120, "ct": 366}}. Returns the id of the new scene.
+    pub fn create_scene(
+        &self,
+        name: &str,
+        light_states: &HashMap<String, Value>,
+    ) -> Result<String, Box<dyn Error>> {
+        if light_states.is_empty() {
+            return Err(From::from("A scene needs at least one light."));
+        }
+
+        let mut lights: Vec<&String> = light_states.keys().collect();
+        lights.sort_by_key(|index| index.parse::<u32>().unwrap_or(0));
+
+        let body = json!({
+            "name": name,
+            "lights": lights,
+            "recycle": false,
+            "lightstates": light_states,
+        });
+
+        self.post(&self.api_url("scenes"), body.to_string())
+    }
+
+    /// The current state of each of the lights at the given indices, as a scene would store it.
+    pub fn capture_lightstates(
+        &self,
+        indices: &[String],
+    ) -> Result<HashMap<String, Value>, Box<dyn Error>> {
+        let lights = self.fetch("lights", false)?;
+
+        indices
+            .iter()
+            .map(|index| match lights.get(index) {
+                Some(light) => Ok((index.clone(), stored_lightstate(&light["state"]))),
+                None => Err(From::from(HueError::LightNotFound(index.to_string()))),
+            })
+            .collect()
+    }
+
+    /// Finds a scene given either its id or its name. Scene names are not unique on the bridge,
+    /// so a name matching several scenes is an error.
+    pub fn find_scene(&self, scene: &str) -> Result<(String, Scene), Box<dyn Error>> {
+        let mut scenes = self.get_scenes()?;
+
+        if let Some(found) = scenes.remove(scene) {
+            return Ok((scene.to_string(), found));
+        }
+
+        let mut matches: Vec<(String, Scene)> = scenes
+            .into_iter()
+            .filter(|(_, s)| s.name == scene)
+            .collect();
+
+        match matches.len() {
+            0 => Err(From::from(format!("No scene with name '{}' found.", scene))),
+            1 => Ok(matches.remove(0)),
+            _ => {
+                let ids: Vec<String> = matches.into_iter().map(|(id, _)| id).collect();
+                Err(From::from(format!(
+                    "Scene name '{}' is ambiguous, use one of the ids: {}.",
+                    scene,
+                    ids.join(", ")
+                )))
+            }
+        }
+    }
+
+    /// Modifies the stored state of a single light within a scene, leaving the rest of the scene
+    /// untouched.
+    pub fn edit_scene_lightstate(
+        &self,
+        scene: &str,
+        light: &str,
+        change: &SceneLightChange,
+    ) -> Result<(), Box<dyn Error>> {
+        let index = match self.light_index(light) {
+            Some(index) => index.to_string(),
+            None => return Err(Box::new(HueError::LightNotFound(light.to_string()))),
+        };
+
+        let (id, found) = self.find_scene(scene)?;
+        if !found.lights.contains(&index) {
+            return Err(From::from(format!(
+                "Light '{}' is not part of scene '{}'.",
+                light, found.name
+            )));
+        }
+
+        let mut update = LightStateUpdate {
+            on: change.on,
+            ..LightStateUpdate::new()
+        };
+
+        if let Some(ref rgb) = change.rgb {
+            let xy = self.gamut_adjusted_xy(&index, rgb);
+            update = update.xy(xy.x, xy.y).bri(xy.brightness);
+        }
+
+        if let Some(bri) = change.bri {
+            update = update.bri(bri);
+        }
+
+        if update.is_empty() {
+            return Err(From::from("No changes given for the scene light state."));
+        }
+
+        let url = self.api_url(&format!("scenes/{}/lightstates/{}", id, index));
+        self.put(&url, update.to_string())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json;
+
+    #[test]
+    fn make_scene() {
+        let data = r#"{
+            "name": "Relax",
+            "type": "GroupScene",
+            "group": "1",
+            "lights": ["1", "2"],
+            "owner": "ffffffffe0341b1b376a2389376a2389",
+            "recycle": false,
+            "locked": false,
+            "appdata": {},
+            "picture": "",
+            "lastupdated": "2018-05-20T18:22:05",
+            "version": 2
+        }"#;
+
+        let scene: Scene = serde_json::from_str(data).unwrap();
+
+        assert_eq!(scene.name, "Relax");
+        assert_eq!(scene.lights, vec!["1", "2"]);
+    }
+
+    #[test]
+    fn stored_lightstates() {
+        assert_eq!(
+            stored_lightstate(&json!({"on": true, "bri": 120, "ct": 366, "xy": [0.4, 0.4],
+                "colormode": "ct", "reachable": true})),
+            json!({"on": true, "bri": 120, "ct": 366})
+        );
+        assert_eq!(
+            stored_lightstate(&json!({"on": true, "bri": 50, "hue": 100, "xy": [0.6, 0.3],
+                "colormode": "hs"})),
+            json!({"on": true, "bri": 50, "xy": [0.6, 0.3]})
+        );
+        assert_eq!(
+            stored_lightstate(&json!({"on": false, "bri": 50, "colormode": "ct"})),
+            json!({"on": false})
+        );
+    }
+
+    #[test]
+    fn diff_lightstates() {
+        let current =
+            json!({"on": true, "bri": 203, "ct": 250, "xy": [0.4, 0.4], "colormode": "ct"});
+
+        assert_eq!(
+            diff_lightstate(&current, &json!({"on": true, "bri": 25, "ct": 454})),
+            (
+                String::from("bri 80%, ct 4000K"),
+                String::from("bri 10%, ct 2200K")
+            )
+        );
+        assert_eq!(
+            diff_lightstate(&current, &json!({"on": false, "bri": 25})),
+            (String::from("on"), String::from("off"))
+        );
+        assert_eq!(
+            diff_lightstate(&current, &json!({"on": true, "xy": [0.5, 0.4]})),
+            (String::from("xy -"), String::from("xy [0.500, 0.400]"))
+        );
+        assert_eq!(
+            diff_lightstate(&current, &json!({"on": true, "bri": 203})),
+            (String::from("on, bri 80%"), String::from("on, bri 80%"))
+        );
+    }
+}
@@ -0,0 +1,526 @@
+//! # schedules
+//!
+//! Collection of data structures, functions, and methods for creating, listing, changing, and
+//! deleting schedules on the Hue bridge. Schedules run on the bridge itself, so they fire whether
+//! or not this tool is running.
+
+use serde_json;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+use std::time::Duration;
+use time;
+
+use colors;
+use decode;
+use hue;
+use hue::Hue;
+use parse::TimeOfDay;
+use state::LightStateUpdate;
+
+/// Longest fade a light can do, as the bridge counts transition times in tenths of a second.
+pub const MAX_FADE: Duration = Duration::from_millis(65_535 * 100);
+
+/// Represents a schedule stored on the bridge. Matches the JSON data fields to allow for
+/// serialization.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Schedule {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub command: ScheduleCommand,
+    /// When the schedule fires, e.x. "W124/T07:00:00". See `localtime`.
+    #[serde(default)]
+    pub localtime: String,
+    /// "enabled" or "disabled".
+    #[serde(default = "enabled")]
+    pub status: String,
+    /// Whether a one-time schedule is removed once it fired.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub autodelete: Option<bool>,
+}
+
+/// The request a schedule sends to the bridge when it fires.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleCommand {
+    pub address: String,
+    pub method: String,
+    pub body: Value,
+}
+
+/// Changes to a schedule. Fields left as `None` are not sent to the bridge.
+#[derive(Debug, Default)]
+pub struct ScheduleChange {
+    pub name: Option<String>,
+    pub localtime: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+/// Helper function for the default status of a schedule.
+fn enabled() -> String {
+    String::from("enabled")
+}
+
+/// Bit of each weekday in the bridge's recurring time format, starting with Monday.
+const WEEKDAYS: [(&str, u8); 7] = [
+    ("monday", 64),
+    ("tuesday", 32),
+    ("wednesday", 16),
+    ("thursday", 8),
+    ("friday", 4),
+    ("saturday", 2),
+    ("sunday", 1),
+];
+
+/// Parses the days a schedule repeats on: "daily", "weekdays", "weekends", or a comma separated
+/// list of days such as "mon,wed,fri". Returns the bridge's weekday bitmask.
+pub fn parse_days(value: &str) -> Result<u8, Box<dyn Error>> {
+    match value.trim().to_lowercase().as_str() {
+        "daily" => return Ok(127),
+        "weekdays" => return Ok(124),
+        "weekends" => return Ok(3),
+        _ => (),
+    }
+
+    let mut days = 0;
+
+    for day in value.split(',') {
+        let day = day.trim().to_lowercase();
+        let bit = WEEKDAYS
+            .iter()
+            .find(|(name, _)| day.len() >= 3 && name.starts_with(&day))
+            .map(|&(_, bit)| bit);
+
+        match bit {
+            Some(bit) => days |= bit,
+            None => {
+                return Err(From::from(format!(
+                    "Invalid day '{}', use daily, weekdays, weekends, or days such as 'mon,wed'.",
+                    day
+                )))
+            }
+        }
+    }
+
+    Ok(days)
+}
+
+/// Formats a time for the bridge's `localtime` field. Recurring times repeat on the given days,
+/// e.x. "W127/T21:30:00"; otherwise the time is the next occurrence, e.x. "2018-06-01T21:30:00".
+pub fn localtime(at: &TimeOfDay, days: Option<u8>) -> String {
+    if let Some(days) = days {
+        return format!("W{}/T{}", days, at);
+    }
+
+    format!("{}T{}", next_date(at).strftime("%Y-%m-%d").unwrap(), at)
+}
+
+/// Formats the `localtime`s of some seconds before a time, one for each offset, e.x. for a fade to
+/// start so it ends at the time. A time only given once is moved back from a single occurrence,
+/// the next one far enough ahead for every offset, so the times never fall a day apart even when
+/// they cross midnight.
+pub fn localtimes_before(at: &TimeOfDay, days: Option<u8>, offsets: &[u32]) -> Vec<String> {
+    if days.is_some() {
+        return offsets
+            .iter()
+            .map(|&seconds| {
+                let (at, days) = earlier(at, days, seconds);
+                localtime(&at, days)
+            })
+            .collect();
+    }
+
+    let before = |tm: time::Tm, seconds: u32| tm - time::Duration::seconds(i64::from(seconds));
+    let mut next = next_date(at);
+    next.tm_hour = i32::from(at.hour);
+    next.tm_min = i32::from(at.minute);
+    next.tm_sec = i32::from(at.second);
+    let earliest = offsets.iter().max().cloned().unwrap_or(0);
+    if before(next, earliest).to_timespec() <= time::get_time() {
+        next = next + time::Duration::days(1);
+    }
+
+    offsets
+        .iter()
+        .map(|&seconds| {
+            before(next, seconds)
+                .strftime("%Y-%m-%dT%H:%M:%S")
+                .unwrap()
+                .to_string()
+        })
+        .collect()
+}
+
+/// Helper function for the date of the next occurrence of a time of day: today if it's still
+/// ahead, otherwise tomorrow.
+fn next_date(at: &TimeOfDay) -> time::Tm {
+    let now = time::now();
+    let seconds_now = (now.tm_hour * 3600 + now.tm_min * 60 + now.tm_sec) as u32;
+
+    if at.seconds() > seconds_now {
+        now
+    } else {
+        now + time::Duration::days(1)
+    }
+}
+
+/// Moves a recurring time of day earlier by some seconds. Times that cross midnight move to the
+/// day before, e.x. 00:10 on Mondays less 20 minutes is 23:50 on Sundays.
+pub fn earlier(at: &TimeOfDay, days: Option<u8>, seconds: u32) -> (TimeOfDay, Option<u8>) {
+    const DAY: u32 = 24 * 3600;
+
+    let crosses = seconds % DAY > at.seconds();
+    let moved = (at.seconds() + DAY - seconds % DAY) % DAY;
+    let at = TimeOfDay {
+        hour: (moved / 3600) as u8,
+        minute: (moved / 60 % 60) as u8,
+        second: (moved % 60) as u8,
+    };
+
+    // Monday is the highest bit, so the day before each day is the next higher bit, with
+    // Sunday before Monday wrapping around.
+    let days = match days {
+        Some(days) if crosses => Some(((days << 1) & 127) | (days >> 6)),
+        days => days,
+    };
+
+    (at, days)
+}
+
+/// Describes a `localtime` for display, e.x. "weekdays at 07:00:00" or "2018-06-01 at 21:30:00".
+pub fn describe_localtime(localtime: &str) -> String {
+    if let Some(rest) = localtime.strip_prefix('W') {
+        if let Some((days, at)) = rest.split_once("/T") {
+            let days = match days.parse::<u8>() {
+                Ok(127) => String::from("daily"),
+                Ok(124) => String::from("weekdays"),
+                Ok(3) => String::from("weekends"),
+                Ok(bits) => WEEKDAYS
+                    .iter()
+                    .filter(|&&(_, bit)| bits & bit != 0)
+                    .map(|&(name, _)| &name[..3])
+                    .collect::<Vec<&str>>()
+                    .join(","),
+                Err(_) => return localtime.to_string(),
+            };
+            return format!("{} at {}", days, at);
+        }
+    }
+
+    match localtime.split_once('T') {
+        Some((date, at)) if date.contains('-') => format!("{} at {}", date, at),
+        _ => localtime.to_string(),
+    }
+}
+
+/// Builds the light state change of a schedule: turning off, or turning on with an optional
+/// brightness and a color given as a name from the colors file at `colors_file` or a white color
+/// temperature, e.x. "warm" or "370".
+pub fn action_body(
+    on: bool,
+    bri: Option<u8>,
+    color: Option<&str>,
+    colors_file: &Path,
+) -> Result<LightStateUpdate, Box<dyn Error>> {
+    let mut body = LightStateUpdate::new().on(on);
+    if !on {
+        return Ok(body);
+    }
+
+    if let Some(color) = color {
+        // A colors.json that can't be read is reported rather than the color taken as unknown.
+        match colors::find_color(color, &colors::load_colors(colors_file)?) {
+            Some(rgb) => {
+                let xy = colors::XY::from_rgb(&rgb);
+                body = body.xy(xy.x, xy.y).bri(xy.brightness);
+            }
+            None => {
+                let mired = colors::parse_temperature(color, &colors::load_temperatures()?)
+                    .map_err(|_| {
+                        format!(
+                            "Unknown color '{}', use a name from the colors file, a CSS color \
+                             name, hex, or a white color temperature.",
+                            color
+                        )
+                    })?;
+                body = body.ct(mired);
+            }
+        }
+    }
+
+    if let Some(bri) = bri {
+        body = body.bri(bri);
+    }
+
+    Ok(body)
+}
+
+impl Hue {
+    /// Lists the schedules stored on the bridge with their ids, ordered by id.
+    pub fn list_schedules(&self) -> Result<Vec<(String, Schedule)>, Box<dyn Error>> {
+        let schedules: HashMap<String, Schedule> = decode::decode_map(
+            &self.fetch("schedules", false)?,
+            "schedule",
+            &["created", "starttime", "recycle", "time"],
+            self.config().strict,
+        )?;
+
+        let mut schedules: Vec<(String, Schedule)> = schedules.into_iter().collect();
+        schedules.sort_by_key(|(id, _)| id.parse::<u32>().unwrap_or(u32::MAX));
+
+        Ok(schedules)
+    }
+
+    /// Creates a schedule on the bridge. Returns the id of the new schedule.
+    pub fn create_schedule(&self, schedule: &Schedule) -> Result<String, Box<dyn Error>> {
+        self.post(&self.api_url("schedules"), serde_json::to_string(schedule)?)
+    }
+
+    /// Changes the name, time, or status of a schedule.
+    pub fn update_schedule(&self, id: &str, change: &ScheduleChange) -> Result<(), Box<dyn Error>> {
+        let mut body = json!({});
+
+        if let Some(ref name) = change.name {
+            body["name"] = json!(name);
+        }
+        if let Some(ref localtime) = change.localtime {
+            body["localtime"] = json!(localtime);
+        }
+        if let Some(enabled) = change.enabled {
+            body["status"] = json!(if enabled { "enabled" } else { "disabled" });
+        }
+
+        if body.as_object().is_some_and(|body| body.is_empty()) {
+            return Err(From::from("No changes given for the schedule."));
+        }
+
+        self.put(
+            &self.api_url(&format!("schedules/{}", id)),
+            body.to_string(),
+        )
+    }
+
+    /// Deletes a schedule from the bridge.
+    pub fn delete_schedule(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        self.delete(&self.api_url(&format!("schedules/{}", id)))
+    }
+
+    /// Builds the command of a schedule changing the state of a light, given by name or index, or
+    /// else of all lights of a group, given by name or id.
+    pub fn schedule_command(
+        &self,
+        target: &str,
+        body: &LightStateUpdate,
+    ) -> Result<ScheduleCommand, Box<dyn Error>> {
+        let path = match self.light_index(target) {
+            Some(index) => format!("lights/{}/state", index),
+            None => match self.find_group(target) {
+                Ok((id, _)) => format!("groups/{}/action", id),
+                Err(_) => {
+                    return Err(From::from(format!(
+                        "No light or group with name '{}' found.",
+                        target
+                    )))
+                }
+            },
+        };
+
+        Ok(ScheduleCommand {
+            address: self.api_path(&path),
+            method: String::from("PUT"),
+            body: body.to_value(),
+        })
+    }
+
+    /// Schedules a fade in reaching a state at a time of day, e.x. a wake-up light, repeating on
+    /// the given days or only once. As the bridge fades from a light's current brightness, two
+    /// schedules are created: one turning the light or group on at the lowest brightness a second
+    /// before the fade starts, and one fading to the state. Returns the ids of the new schedules.
+    pub fn schedule_fade_in(
+        &self,
+        name: &str,
+        target: &str,
+        body: &LightStateUpdate,
+        at: &TimeOfDay,
+        days: Option<u8>,
+        fade: Duration,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        if fade > MAX_FADE {
+            return Err(From::from(format!(
+                "Lights can fade for at most {} minutes.",
+                MAX_FADE.as_secs() / 60
+            )));
+        }
+
+        let start = LightStateUpdate {
+            ct: body.ct,
+            xy: body.xy,
+            ..LightStateUpdate::new()
+        }
+        .on(true)
+        .bri(1)
+        .transition(0);
+
+        let ramp = body
+            .clone()
+            .on(true)
+            .bri(body.bri.unwrap_or(254))
+            .transition(hue::transition_time(fade));
+
+        let fade = fade.as_secs() as u32;
+        let mut ids = Vec::new();
+
+        let times = localtimes_before(at, days, &[fade + 1, fade]);
+
+        for ((suffix, body), localtime) in [(" start", start), ("", ramp)].iter().zip(times) {
+            ids.push(self.create_schedule(&Schedule {
+                name: format!("{}{}", name, suffix),
+                description: String::new(),
+                command: self.schedule_command(target, body)?,
+                localtime,
+                status: enabled(),
+                autodelete: None,
+            })?);
+        }
+
+        Ok(ids)
+    }
+
+    /// Creates a schedule on the bridge that recalls a scene at a time of day, repeating on the
+    /// given days or only once. Returns the id of the new schedule.
+    pub fn schedule_scene(
+        &self,
+        scene: &str,
+        at: &TimeOfDay,
+        days: Option<u8>,
+    ) -> Result<String, Box<dyn Error>> {
+        let (id, found) = self.find_scene(scene)?;
+        let group = found.group.unwrap_or_else(|| String::from("0"));
+
+        self.create_schedule(&Schedule {
+            description: format!("Recall scene {}", found.name),
+            name: found.name,
+            command: ScheduleCommand {
+                address: self.api_path(&format!("groups/{}/action", group)),
+                method: String::from("PUT"),
+                body: json!({ "scene": id }),
+            },
+            localtime: localtime(at, days),
+            status: enabled(),
+            autodelete: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn days() {
+        assert_eq!(parse_days("daily").unwrap(), 127);
+        assert_eq!(parse_days("Weekdays").unwrap(), 124);
+        assert_eq!(parse_days("mon,wednesday, fri").unwrap(), 84);
+        assert!(parse_days("someday").is_err());
+        assert!(parse_days("m").is_err());
+    }
+
+    #[test]
+    fn recurring_localtime() {
+        let at = TimeOfDay {
+            hour: 21,
+            minute: 30,
+            second: 0,
+        };
+
+        assert_eq!(localtime(&at, Some(127)), "W127/T21:30:00");
+        assert!(localtime(&at, None).ends_with("T21:30:00"));
+    }
+
+    #[test]
+    fn earlier_times() {
+        let at = |hour, minute| TimeOfDay {
+            hour,
+            minute,
+            second: 0,
+        };
+
+        assert_eq!(earlier(&at(7, 0), Some(124), 1800), (at(6, 30), Some(124)));
+        // Weekdays at 00:10 start on Sunday through Thursday.
+        assert_eq!(
+            earlier(&at(0, 10), Some(124), 1200),
+            (at(23, 50), Some(121))
+        );
+        assert_eq!(earlier(&at(0, 10), Some(64), 1200), (at(23, 50), Some(1)));
+        assert_eq!(earlier(&at(0, 10), None, 1200), (at(23, 50), None));
+    }
+
+    #[test]
+    fn fade_start_times() {
+        let format = |tm: time::Tm| tm.strftime("%Y-%m-%dT%H:%M:%S").unwrap().to_string();
+        let now = time::now();
+        let soon = now + time::Duration::seconds(600);
+        let at = TimeOfDay {
+            hour: soon.tm_hour as u8,
+            minute: soon.tm_min as u8,
+            second: soon.tm_sec as u8,
+        };
+
+        assert_eq!(
+            localtimes_before(&at, None, &[301, 300]),
+            vec![
+                format(soon - time::Duration::seconds(301)),
+                format(soon - time::Duration::seconds(300))
+            ]
+        );
+        // Too late to start the earliest today, so both are in time for tomorrow.
+        let tomorrow = soon + time::Duration::days(1);
+        assert_eq!(
+            localtimes_before(&at, None, &[1200, 300]),
+            vec![
+                format(tomorrow - time::Duration::seconds(1200)),
+                format(tomorrow - time::Duration::seconds(300))
+            ]
+        );
+        assert_eq!(
+            localtimes_before(&at, Some(127), &[1200]),
+            vec![format!("W127/T{}", earlier(&at, Some(127), 1200).0)]
+        );
+    }
+
+    #[test]
+    fn describe_localtimes() {
+        assert_eq!(describe_localtime("W124/T07:00:00"), "weekdays at 07:00:00");
+        assert_eq!(
+            describe_localtime("W84/T07:00:00"),
+            "mon,wed,fri at 07:00:00"
+        );
+        assert_eq!(
+            describe_localtime("2018-06-01T21:30:00"),
+            "2018-06-01 at 21:30:00"
+        );
+        assert_eq!(describe_localtime("PT00:10:00"), "PT00:10:00");
+    }
+
+    #[test]
+    fn read_schedule() {
+        let schedule: Schedule = serde_json::from_str(
+            r#"{
+                "name": "Wake up",
+                "command": {"address": "/api/abc/groups/1/action", "method": "PUT",
+                    "body": {"scene": "abc"}},
+                "localtime": "W124/T07:00:00",
+                "created": "2018-05-20T18:22:05",
+                "recycle": false
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(schedule.status, "enabled");
+        assert_eq!(schedule.command.body["scene"], "abc");
+        assert!(!serde_json::to_string(&schedule)
+            .unwrap()
+            .contains("autodelete"));
+    }
+}
@@ -0,0 +1,618 @@
+//! # selector
+//!
+//! Expressions for targeting a set of lights in one invocation, e.x.
+//! `room:Living + Desk - tag:accent`. Terms are combined from left to right with `+` (union) and
+//! `-` (exclusion); operators must be surrounded by spaces so light names may contain dashes.
+//! Light names may be glob patterns, e.x. `Living*`, where `*` matches any text and `?` a single
+//! character.
+
+use serde_json;
+use std::collections::{BTreeSet, HashMap};
+use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+
+use colors::RGB;
+use groups::Group;
+use hue::api::Request;
+use hue::{ColorCapability, Hue, HueError};
+use state::LightStateUpdate;
+
+/// A parsed selector expression.
+#[derive(Debug, PartialEq)]
+pub enum Selector {
+    /// Every light on the bridge.
+    All,
+    /// A single light by its name or index.
+    Light(String),
+    /// The lights whose name matches a glob pattern, e.x. `Living*`.
+    Pattern(Glob),
+    /// The lights of a room by the room's name or id.
+    Room(String),
+    /// The lights of any group (room, zone, ...) by the group's name or id.
+    Group(String),
+    /// The lights given a tag in $HOME/.config/rusty_hue/tags.json.
+    Tag(String),
+    /// The lights whose manufacturer name contains the given text, ignoring case, e.x. `innr`.
+    Vendor(String),
+    /// Lights selected by either side.
+    Union(Box<Selector>, Box<Selector>),
+    /// Lights selected by the left side but not by the right.
+    Difference(Box<Selector>, Box<Selector>),
+}
+
+impl Selector {
+    /// Parses a selector expression. A leading `-` excludes lights from all lights, e.x.
+    /// `- tag:accent`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rusty_hue::selector::Selector;
+    ///
+    /// let selector = Selector::parse("room:Living + Desk").unwrap();
+    ///
+    /// assert_eq!(
+    ///     selector,
+    ///     Selector::Union(
+    ///         Box::new(Selector::Room(String::from("Living"))),
+    ///         Box::new(Selector::Light(String::from("Desk")))
+    ///     )
+    /// );
+    /// ```
+    pub fn parse(expression: &str) -> Result<Selector, Box<dyn Error>> {
+        let mut selector: Option<Selector> = None;
+        let mut operator = "+";
+        let mut term: Vec<&str> = Vec::new();
+
+        for word in expression.split_whitespace() {
+            if word != "+" && word != "-" {
+                term.push(word);
+                continue;
+            }
+
+            if term.is_empty() && selector.is_none() && word == "-" {
+                selector = Some(Selector::All);
+            } else {
+                selector = Some(combine(selector, operator, &term.join(" "), expression)?);
+                term.clear();
+            }
+            operator = word;
+        }
+
+        combine(selector, operator, &term.join(" "), expression)
+    }
+
+    /// Parses a comma separated list of lights by name, index, or pattern, e.x. "1,3,5" or
+    /// "Desk,Lamp", into the union of the lights.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rusty_hue::selector::Selector;
+    ///
+    /// assert_eq!(
+    ///     Selector::from_list("Desk, 3").unwrap(),
+    ///     Selector::Union(
+    ///         Box::new(Selector::Light(String::from("Desk"))),
+    ///         Box::new(Selector::Light(String::from("3")))
+    ///     )
+    /// );
+    /// ```
+    pub fn from_list(list: &str) -> Result<Selector, Box<dyn Error>> {
+        let mut selector: Option<Selector> = None;
+
+        for light in list.split(',').map(str::trim) {
+            if light.is_empty() {
+                return Err(From::from(format!("Missing light in list '{}'.", list)));
+            }
+
+            let light = if Glob::is_pattern(light) {
+                Selector::Pattern(Glob::new(light))
+            } else {
+                Selector::Light(light.to_string())
+            };
+            selector = Some(match selector {
+                Some(selector) => Selector::Union(Box::new(selector), Box::new(light)),
+                None => light,
+            });
+        }
+
+        selector.ok_or_else(|| From::from("No lights given."))
+    }
+}
+
+/// A glob pattern, compiled once, for matching light names. `*` matches any text, including none,
+/// and `?` any single character.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Glob {
+    pattern: String,
+    tokens: Vec<GlobToken>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum GlobToken {
+    Literal(char),
+    One,
+    Any,
+}
+
+impl Glob {
+    /// Compiles a glob pattern.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rusty_hue::selector::Glob;
+    ///
+    /// let glob = Glob::new("Living*");
+    ///
+    /// assert!(glob.matches("Living room lamp"));
+    /// assert!(!glob.matches("Kitchen"));
+    /// ```
+    pub fn new(pattern: &str) -> Glob {
+        let mut tokens = Vec::new();
+
+        for c in pattern.chars() {
+            match c {
+                // Runs of `*` match the same as one, and would only slow matching down.
+                '*' if tokens.last() == Some(&GlobToken::Any) => (),
+                '*' => tokens.push(GlobToken::Any),
+                '?' => tokens.push(GlobToken::One),
+                c => tokens.push(GlobToken::Literal(c)),
+            }
+        }
+
+        Glob {
+            pattern: pattern.to_string(),
+            tokens,
+        }
+    }
+
+    /// Whether text is a glob pattern rather than a plain name.
+    pub fn is_pattern(text: &str) -> bool {
+        text.contains('*') || text.contains('?')
+    }
+
+    /// Whether a name matches the whole pattern.
+    pub fn matches(&self, name: &str) -> bool {
+        let chars: Vec<char> = name.chars().collect();
+        matches_from(&self.tokens, &chars)
+    }
+
+    /// The pattern as given.
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+}
+
+/// Helper function to match the rest of a pattern against the rest of a name.
+fn matches_from(tokens: &[GlobToken], chars: &[char]) -> bool {
+    match tokens.split_first() {
+        None => chars.is_empty(),
+        Some((&GlobToken::Any, rest)) => (0..=chars.len()).any(|i| matches_from(rest, &chars[i..])),
+        Some((&GlobToken::One, rest)) => !chars.is_empty() && matches_from(rest, &chars[1..]),
+        Some((&GlobToken::Literal(c), rest)) => {
+            chars.first() == Some(&c) && matches_from(rest, &chars[1..])
+        }
+    }
+}
+
+/// Helper function to parse a single term and add it to the expression parsed so far.
+fn combine(
+    selector: Option<Selector>,
+    operator: &str,
+    term: &str,
+    expression: &str,
+) -> Result<Selector, Box<dyn Error>> {
+    if term.is_empty() {
+        return Err(From::from(format!(
+            "Missing light, room, group, or tag in selector '{}'.",
+            expression
+        )));
+    }
+
+    let term = parse_term(term);
+
+    Ok(match (selector, operator) {
+        (None, _) => term,
+        (Some(selector), "-") => Selector::Difference(Box::new(selector), Box::new(term)),
+        (Some(selector), _) => Selector::Union(Box::new(selector), Box::new(term)),
+    })
+}
+
+/// Helper function to parse a single term. Unknown prefixes are treated as part of a light name.
+fn parse_term(term: &str) -> Selector {
+    if term == "all" || term == "*" {
+        return Selector::All;
+    }
+
+    if let Some(i) = term.find(':') {
+        let value = term[i + 1..].to_string();
+        match &term[..i] {
+            "light" if Glob::is_pattern(&value) => return Selector::Pattern(Glob::new(&value)),
+            "light" => return Selector::Light(value),
+            "room" => return Selector::Room(value),
+            "group" => return Selector::Group(value),
+            "tag" => return Selector::Tag(value),
+            "vendor" => return Selector::Vendor(value),
+            _ => (),
+        }
+    }
+
+    if Glob::is_pattern(term) {
+        return Selector::Pattern(Glob::new(term));
+    }
+
+    Selector::Light(term.to_string())
+}
+
+/// Helper function to match a group by name or id, only rooms if `rooms_only`.
+fn is_named(id: &str, group: &Group, name: &str, rooms_only: bool) -> bool {
+    (id == name || group.name == name) && (!rooms_only || group.group_type == "Room")
+}
+
+/// Loads light tags from $HOME/.config/rusty_hue/tags.json, which maps each tag to a list of
+/// light names or indices. A missing file means no tags are defined.
+pub fn load_tags() -> Result<HashMap<String, Vec<String>>, Box<dyn Error>> {
+    match env::home_dir() {
+        Some(path) => {
+            let tags_file = String::from(path.to_string_lossy()) + "/.config/rusty_hue/tags.json";
+
+            let mut f = match File::open(tags_file) {
+                Ok(f) => f,
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+                Err(e) => return Err(From::from(e)),
+            };
+
+            let mut contents = String::new();
+            f.read_to_string(&mut contents)?;
+
+            let tags: HashMap<String, Vec<String>> = serde_json::from_str(&contents)?;
+
+            Ok(tags)
+        }
+        None => Err(From::from("Failed to get home directory.")),
+    }
+}
+
+impl Hue {
+    /// Resolves a selector to the indices of the lights it targets.
+    pub fn resolve(&self, selector: &Selector) -> Result<BTreeSet<String>, Box<dyn Error>> {
+        let mut groups = None;
+        let mut tags = None;
+
+        self.resolve_with(selector, &mut groups, &mut tags)
+    }
+
+    /// Id of the group a selector targets as a whole, "0" for all lights, so the group can be
+    /// changed with a single request. `None` when the selector targets single lights, tags,
+    /// vendors, or a combination.
+    pub fn resolve_group(&self, selector: &Selector) -> Result<Option<String>, Box<dyn Error>> {
+        let (name, rooms_only) = match *selector {
+            Selector::All => return Ok(Some(String::from("0"))),
+            Selector::Room(ref name) => (name, true),
+            Selector::Group(ref name) => (name, false),
+            _ => return Ok(None),
+        };
+
+        self.get_groups()?
+            .into_iter()
+            .find(|(id, group)| is_named(id, group, name, rooms_only))
+            .map(|(id, _)| Some(id))
+            .ok_or_else(|| From::from(format!("No group with name '{}' found.", name)))
+    }
+
+    /// Turns the reachable lights a selector targets on or off.
+    pub fn set_power(&self, selector: &Selector, power: bool) -> Result<(), Box<dyn Error>> {
+        let indices: Vec<String> = self.resolve(selector)?.into_iter().collect();
+        self.set_power_by_indices(&indices, power, None)
+    }
+
+    /// Sets the reachable lights a selector targets that can show colors to a hue (0-65535) and
+    /// saturation (0-254), the bridge's "hs" color mode. Lights without colors are left alone.
+    pub fn set_hue_sat(
+        &self,
+        selector: &Selector,
+        hue: u16,
+        sat: u8,
+    ) -> Result<(), Box<dyn Error>> {
+        let reachable = self.reachable_indices();
+        let changes: Vec<Request> = self
+            .resolve(selector)?
+            .into_iter()
+            .filter(|index| reachable.contains(index))
+            .filter(|index| self.color_capability(index) == Some(ColorCapability::Color))
+            .map(|index| {
+                Request::light_state(&index, &LightStateUpdate::new().on(true).hue(hue).sat(sat))
+            })
+            .collect();
+
+        if changes.is_empty() {
+            return Err(From::from("None of the selected lights can show colors."));
+        }
+
+        self.put_all(&changes)
+    }
+
+    /// Sets the reachable lights a selector targets that can show colors to an RGB color, each in
+    /// the color mode that shows it best and moved into its gamut, as with named colors. Lights
+    /// without colors are left alone.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use rusty_hue::colors::RGB;
+    /// use rusty_hue::hue::Hue;
+    /// use rusty_hue::selector::Selector;
+    ///
+    /// let hue = Hue::new().unwrap();
+    /// let rgb = RGB { r: 233, g: 222, b: 123 };
+    /// hue.set_rgb(&Selector::parse("room:Living").unwrap(), &rgb)
+    ///     .unwrap();
+    /// ```
+    pub fn set_rgb(&self, selector: &Selector, rgb: &RGB) -> Result<(), Box<dyn Error>> {
+        let reachable = self.reachable_indices();
+        let changes: Vec<Request> = self
+            .resolve(selector)?
+            .into_iter()
+            .filter(|index| reachable.contains(index))
+            .filter(|index| self.color_capability(index) == Some(ColorCapability::Color))
+            .map(|index| Request::light_state(&index, &self.lights[&index].color_update(rgb, 1.0)))
+            .collect();
+
+        if changes.is_empty() {
+            return Err(From::from("None of the selected lights can show colors."));
+        }
+
+        self.put_all(&changes)
+    }
+
+    /// Helper function for resolving a selector, fetching groups and loading tags only once and
+    /// only when the expression uses them.
+    fn resolve_with(
+        &self,
+        selector: &Selector,
+        groups: &mut Option<HashMap<String, Group>>,
+        tags: &mut Option<HashMap<String, Vec<String>>>,
+    ) -> Result<BTreeSet<String>, Box<dyn Error>> {
+        match *selector {
+            Selector::All => Ok(self.lights.keys().cloned().collect()),
+            Selector::Light(ref light) => match self.light_index(light) {
+                Some(index) => Ok(vec![index.to_string()].into_iter().collect()),
+                None => Err(Box::new(HueError::LightNotFound(light.to_string()))),
+            },
+            Selector::Pattern(ref glob) => {
+                let lights: BTreeSet<String> = self
+                    .lights
+                    .iter()
+                    .filter(|(_, light)| glob.matches(light.name()))
+                    .map(|(index, _)| index.clone())
+                    .collect();
+
+                if lights.is_empty() {
+                    return Err(From::from(format!(
+                        "No light names match '{}'.",
+                        glob.as_str()
+                    )));
+                }
+
+                Ok(lights)
+            }
+            Selector::Room(ref name) | Selector::Group(ref name) => {
+                if groups.is_none() {
+                    *groups = Some(self.get_groups()?);
+                }
+
+                let rooms_only = matches!(*selector, Selector::Room(_));
+
+                groups
+                    .as_ref()
+                    .unwrap()
+                    .iter()
+                    .find(|(id, group)| is_named(id, group, name, rooms_only))
+                    .map(|(_, group)| group.lights.iter().cloned().collect())
+                    .ok_or_else(|| From::from(format!("No group with name '{}' found.", name)))
+            }
+            Selector::Tag(ref tag) => {
+                if tags.is_none() {
+                    *tags = Some(load_tags()?);
+                }
+
+                let lights = match tags.as_ref().unwrap().get(tag) {
+                    Some(lights) => lights,
+                    None => return Err(From::from(format!("No lights tagged '{}'.", tag))),
+                };
+
+                lights
+                    .iter()
+                    .map(|light| match self.light_index(light) {
+                        Some(index) => Ok(index.to_string()),
+                        None => Err(From::from(format!(
+                            "No light with name '{}' found for tag '{}'.",
+                            light, tag
+                        ))),
+                    })
+                    .collect()
+            }
+            Selector::Vendor(ref vendor) => {
+                let vendor = vendor.to_lowercase();
+                let lights: BTreeSet<String> = self
+                    .lights
+                    .keys()
+                    .filter(|index| {
+                        self.light_manufacturer(index)
+                            .is_some_and(|name| name.to_lowercase().contains(&vendor))
+                    })
+                    .cloned()
+                    .collect();
+
+                if lights.is_empty() {
+                    return Err(From::from(format!("No lights made by '{}' found.", vendor)));
+                }
+
+                Ok(lights)
+            }
+            Selector::Union(ref left, ref right) => {
+                let left = self.resolve_with(left, groups, tags)?;
+                let right = self.resolve_with(right, groups, tags)?;
+                Ok(left.union(&right).cloned().collect())
+            }
+            Selector::Difference(ref left, ref right) => {
+                let left = self.resolve_with(left, groups, tags)?;
+                let right = self.resolve_with(right, groups, tags)?;
+                Ok(left.difference(&right).cloned().collect())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use colors::{COLOR_GAMUT_C, XY};
+    use mock;
+    use mock::MockBridge;
+
+    fn light(name: &str) -> Box<Selector> {
+        Box::new(Selector::Light(name.to_string()))
+    }
+
+    #[test]
+    fn parse_union_and_difference() {
+        let selector = Selector::parse("room:Living room + Desk - tag:accent").unwrap();
+
+        assert_eq!(
+            selector,
+            Selector::Difference(
+                Box::new(Selector::Union(
+                    Box::new(Selector::Room(String::from("Living room"))),
+                    light("Desk")
+                )),
+                Box::new(Selector::Tag(String::from("accent")))
+            )
+        );
+    }
+
+    #[test]
+    fn parse_leading_exclusion() {
+        let selector = Selector::parse("- Hall-way").unwrap();
+
+        assert_eq!(
+            selector,
+            Selector::Difference(Box::new(Selector::All), light("Hall-way"))
+        );
+    }
+
+    #[test]
+    fn parse_vendor() {
+        assert_eq!(
+            Selector::parse("vendor:innr - Desk").unwrap(),
+            Selector::Difference(
+                Box::new(Selector::Vendor(String::from("innr"))),
+                light("Desk")
+            )
+        );
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert_eq!(
+            Selector::parse("Living* - Living lamp").unwrap(),
+            Selector::Difference(
+                Box::new(Selector::Pattern(Glob::new("Living*"))),
+                light("Living lamp")
+            )
+        );
+
+        let glob = Glob::new("Hall?ay *");
+        assert!(glob.matches("Hallway 1"));
+        assert!(glob.matches("Hall-ay "));
+        assert!(!glob.matches("Hallway"));
+        assert!(!glob.matches("hallway 1"));
+        assert!(Glob::new("**").matches(""));
+    }
+
+    #[test]
+    fn parse_missing_term() {
+        assert!(Selector::parse("").is_err());
+        assert!(Selector::parse("Desk +").is_err());
+        assert!(Selector::parse("Desk + - Lamp").is_err());
+    }
+
+    #[test]
+    fn groups_by_name_or_id() {
+        let bridge = MockBridge::start(json!({
+            "lights": mock::lights(),
+            "groups": {
+                "1": {"name": "Living", "type": "Room", "lights": ["1"]},
+                "2": {"name": "Upstairs", "type": "Zone", "lights": ["2"]}
+            }
+        }));
+        let hue = bridge.hue();
+        let lights =
+            |selector| -> Vec<String> { hue.resolve(&selector).unwrap().into_iter().collect() };
+
+        assert_eq!(lights(Selector::Room(String::from("Living"))), vec!["1"]);
+        assert_eq!(lights(Selector::Room(String::from("1"))), vec!["1"]);
+        assert_eq!(lights(Selector::Group(String::from("2"))), vec!["2"]);
+        assert!(hue
+            .resolve(&Selector::Room(String::from("Upstairs")))
+            .is_err());
+    }
+
+    #[test]
+    fn hue_sat_only_on_color_lights() {
+        let bridge = mock::bridge();
+        let hue = bridge.hue();
+
+        hue.set_hue_sat(&Selector::All, 8000, 200).unwrap();
+        let changes = bridge.changes();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "lights/1/state");
+        assert_eq!(
+            mock::body(&changes[0]),
+            json!({"on": true, "hue": 8000, "sat": 200})
+        );
+
+        assert!(hue
+            .set_hue_sat(&Selector::Light(String::from("Lamp")), 8000, 200)
+            .is_err());
+        assert_eq!(bridge.changes().len(), 1);
+    }
+
+    #[test]
+    fn rgb_in_gamut_on_color_lights() {
+        let bridge = mock::bridge();
+        let hue = bridge.hue();
+
+        hue.set_rgb(&Selector::All, &RGB { r: 0, g: 255, b: 0 })
+            .unwrap();
+        let changes = bridge.changes();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "lights/1/state");
+
+        // Pure green is outside gamut C, the closest color the light shows is sent.
+        let green = XY::from_rgb(&RGB { r: 0, g: 255, b: 0 });
+        let mut expected = XY::from_rgb(&RGB { r: 0, g: 255, b: 0 });
+        expected.adjust_for_gamut(&COLOR_GAMUT_C);
+        assert_ne!((expected.x, expected.y), (green.x, green.y));
+        assert_eq!(
+            changes[0].body,
+            LightStateUpdate::new()
+                .bri(green.brightness)
+                .xy(expected.x, expected.y)
+                .to_string()
+        );
+
+        assert!(hue
+            .set_rgb(
+                &Selector::Light(String::from("Lamp")),
+                &RGB { r: 0, g: 255, b: 0 }
+            )
+            .is_err());
+        assert_eq!(bridge.changes().len(), 1);
+    }
+}
@@ -0,0 +1,231 @@
+//! # selftest
+//!
+//! Checks run before a daemon starts, so a missing prerequisite, e.x. a rejected token or a room
+//! that was renamed, is reported right away rather than the daemon failing hours later. Each
+//! daemon states what it needs in `Requirements`, and the bridge and configuration files are
+//! checked for it.
+
+use std::error::Error;
+use std::fmt;
+
+use groups;
+use hue::{Hue, HueError};
+use nightlight;
+#[cfg(feature = "server")]
+use proxy;
+
+/// Outcome of a single check.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    /// What was checked, e.x. "group 'Living' exists".
+    pub name: String,
+    /// Why the check failed, `None` if it passed.
+    pub problem: Option<String>,
+}
+
+/// What a daemon needs besides a bridge that is reachable and accepts the API token.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Requirements {
+    /// Whether the bridge's event stream has to connect.
+    pub event_stream: bool,
+    /// Groups, by name or id.
+    pub groups: Vec<String>,
+    /// Scenes, by name or id.
+    pub scenes: Vec<String>,
+    /// Sensors, by name.
+    pub sensors: Vec<String>,
+    /// Whether the rooms and scenes of default_scenes.json have to exist.
+    pub default_scenes: bool,
+    /// Whether the rooms of the night light caps have to exist.
+    pub nightlights: bool,
+    /// Whether the lights of the proxy's clients have to exist.
+    pub proxy_clients: bool,
+}
+
+/// Outcome of all checks, in the order they ran.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SelfTest {
+    pub checks: Vec<Check>,
+}
+
+impl SelfTest {
+    /// Whether every check passed.
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.problem.is_none())
+    }
+
+    /// Helper function to record the outcome of a check.
+    fn check(&mut self, name: &str, result: Result<(), Box<dyn Error>>) {
+        self.checks.push(Check {
+            name: name.to_string(),
+            problem: result.err().map(|e| e.to_string()),
+        });
+    }
+}
+
+impl fmt::Display for SelfTest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Self-test:")?;
+        for check in &self.checks {
+            match check.problem {
+                None => writeln!(f, "  ok    {}", check.name)?,
+                Some(ref problem) => writeln!(f, "  FAIL  {}: {}", check.name, problem)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Hue {
+    /// Checks that the bridge is reachable and accepts the API token, and that everything in
+    /// `requirements` exists. Checks that need the bridge are
+    /// skipped when it can't be used.
+    pub fn self_test(&self, requirements: &Requirements) -> SelfTest {
+        let mut test = SelfTest::default();
+
+        match self.fetch("lights", false) {
+            Ok(_) => {
+                test.check("bridge reachable", Ok(()));
+                test.check("API token accepted", Ok(()));
+            }
+            Err(e) => {
+                if let Some(HueError::Unauthorized(_)) = e.downcast_ref::<HueError>() {
+                    test.check("bridge reachable", Ok(()));
+                    test.check("API token accepted", Err(e));
+                } else {
+                    test.check("bridge reachable", Err(e));
+                }
+                return test;
+            }
+        }
+
+        if requirements.event_stream {
+            test.check("event stream connects", self.check_event_stream());
+        }
+
+        for group in &requirements.groups {
+            test.check(
+                &format!("group '{}' exists", group),
+                self.find_group(group).map(|_| ()),
+            );
+        }
+
+        for scene in &requirements.scenes {
+            test.check(
+                &format!("scene '{}' exists", scene),
+                self.find_scene(scene).map(|_| ()),
+            );
+        }
+
+        if !requirements.sensors.is_empty() {
+            let sensors = self.sensors();
+            for name in &requirements.sensors {
+                let result = match sensors {
+                    Ok(ref sensors) if sensors.iter().any(|sensor| &sensor.name == name) => Ok(()),
+                    Ok(_) => Err(From::from(format!("No sensor '{}' found.", name))),
+                    Err(ref e) => Err(From::from(e.to_string())),
+                };
+                test.check(&format!("sensor '{}' exists", name), result);
+            }
+        }
+
+        if requirements.default_scenes {
+            test.check("default scenes resolve", self.check_default_scenes());
+        }
+
+        if requirements.nightlights {
+            test.check("night light rooms exist", self.check_nightlights());
+        }
+
+        #[cfg(feature = "server")]
+        if requirements.proxy_clients {
+            test.check("proxy client lights exist", self.check_proxy_clients());
+        }
+
+        test
+    }
+
+    /// Helper function to check the rooms and scenes of default_scenes.json exist.
+    fn check_default_scenes(&self) -> Result<(), Box<dyn Error>> {
+        for (room, scene) in groups::load_default_scenes()? {
+            self.find_group(&room)?;
+            self.find_scene(&scene)?;
+        }
+        Ok(())
+    }
+
+    /// Helper function to check the rooms of the night light caps exist.
+    fn check_nightlights(&self) -> Result<(), Box<dyn Error>> {
+        let groups = self.get_groups()?;
+        for (id, cap) in nightlight::load_nightlights()? {
+            if !groups.contains_key(&id) {
+                return Err(From::from(format!(
+                    "'{}' (group {}) no longer exists, remove its cap from nightlights.json.",
+                    cap.room, id
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Helper function to check the lights the proxy's clients are allowed exist.
+    #[cfg(feature = "server")]
+    fn check_proxy_clients(&self) -> Result<(), Box<dyn Error>> {
+        for client in proxy::load_clients()?.values() {
+            if let Some(index) = client
+                .lights
+                .iter()
+                .find(|index| self.light_name(index).is_none())
+            {
+                return Err(From::from(format!(
+                    "Light {} of client '{}' doesn't exist.",
+                    index, client.name
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock;
+
+    #[test]
+    fn report() {
+        let mut test = SelfTest::default();
+        test.check("bridge reachable", Ok(()));
+        assert!(test.passed());
+
+        test.check(
+            "group 'Living' exists",
+            Err(From::from("No group 'Living' found.")),
+        );
+        assert!(!test.passed());
+        assert_eq!(
+            test.to_string(),
+            "Self-test:\n  ok    bridge reachable\n  FAIL  group 'Living' exists: No group 'Living' found.\n"
+        );
+    }
+
+    #[test]
+    fn only_required_checks() {
+        let bridge = mock::bridge();
+        let hue = bridge.hue();
+
+        let names = |requirements: &Requirements| -> Vec<String> {
+            let test = hue.self_test(requirements);
+            test.checks.into_iter().map(|check| check.name).collect()
+        };
+        assert_eq!(
+            names(&Requirements::default()),
+            vec!["bridge reachable", "API token accepted"]
+        );
+        assert!(names(&Requirements {
+            default_scenes: true,
+            ..Requirements::default()
+        })
+        .contains(&String::from("default scenes resolve")));
+    }
+}
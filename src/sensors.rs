@@ -0,0 +1,382 @@
+//! # sensors
+//!
+//! Readings of the sensors paired with the Hue bridge: motion sensors, temperature sensors, light
+//! level sensors, dimmer switches, and tap switches. Other sensors, e.x. the daylight sensor built
+//! into the bridge, are left out.
+
+use serde_json::Value;
+use std::error::Error;
+use std::fmt;
+use time;
+
+use condition::Context;
+use decode;
+use hue::Hue;
+use parse::TimeOfDay;
+
+/// A sensor as the bridge sends it, before its reading is picked out by its type.
+#[derive(Debug, Serialize, Deserialize)]
+struct RawSensor {
+    name: String,
+    #[serde(rename = "type")]
+    sensor_type: String,
+    #[serde(default)]
+    state: Value,
+    #[serde(default)]
+    config: Value,
+}
+
+/// Fields of a sensor the bridge sends that aren't decoded.
+const KNOWN_SENSOR_FIELDS: [&str; 10] = [
+    "modelid",
+    "manufacturername",
+    "productname",
+    "swversion",
+    "uniqueid",
+    "recycle",
+    "capabilities",
+    "swupdate",
+    "diversityid",
+    "productid",
+];
+
+/// A motion sensor, temperature sensor, light level sensor, or dimmer switch with its latest
+/// reading.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Sensor {
+    pub id: String,
+    pub name: String,
+    /// Percentage of battery left, for battery powered sensors.
+    pub battery: Option<u8>,
+    /// When the reading was taken, in UTC, e.x. "2018-06-01T21:30:00". `None` if the sensor
+    /// hasn't reported yet.
+    pub lastupdated: Option<String>,
+    pub reading: Reading,
+}
+
+/// The latest reading of a sensor. Serialized tagged by its type, e.x.
+/// {"type": "temperature", "celsius": 21.5}.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Reading {
+    Motion {
+        presence: bool,
+    },
+    /// Temperature in degrees Celsius.
+    Temperature {
+        celsius: f64,
+    },
+    /// Illuminance in lux.
+    LightLevel {
+        lux: f64,
+    },
+    /// Last button event of a dimmer switch, `None` if no button was pressed yet.
+    Switch {
+        event: Option<ButtonEvent>,
+    },
+}
+
+impl fmt::Display for Reading {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Reading::Motion { presence: true } => write!(f, "motion"),
+            Reading::Motion { presence: false } => write!(f, "no motion"),
+            Reading::Temperature { celsius } => write!(f, "{:.1}°C", celsius),
+            Reading::LightLevel { lux } => write!(f, "{:.0} lux", lux),
+            Reading::Switch {
+                event: Some(ref event),
+            } => write!(f, "{}", event),
+            Reading::Switch { event: None } => write!(f, "no button pressed"),
+        }
+    }
+}
+
+/// Kind of switch a button event came from, which decides how its buttons are named.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SwitchKind {
+    /// A dimmer switch, with "on", "brighter", "dimmer", and "off" buttons.
+    Dimmer,
+    /// A tap switch, with four numbered buttons that can only be pressed.
+    Tap,
+}
+
+/// What happened to a button of a switch.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ButtonAction {
+    Press,
+    Hold,
+    ShortRelease,
+    LongRelease,
+}
+
+/// An event of a switch's button, e.x. the "off" button of a dimmer switch released after a short
+/// press.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ButtonEvent {
+    pub switch: SwitchKind,
+    /// Button of the switch, from the top for a dimmer switch, 1 being "on" and 4 "off".
+    pub button: u8,
+    pub action: ButtonAction,
+}
+
+impl ButtonEvent {
+    /// Decodes a button event code of a dimmer switch from the bridge, e.x. 4002, which holds the
+    /// button in the thousands and the action in the ones.
+    pub fn from_code(code: u64) -> Option<ButtonEvent> {
+        let button = code / 1000;
+        let action = match code % 1000 {
+            0 => ButtonAction::Press,
+            1 => ButtonAction::Hold,
+            2 => ButtonAction::ShortRelease,
+            3 => ButtonAction::LongRelease,
+            _ => return None,
+        };
+
+        if button == 0 || button > 4 {
+            return None;
+        }
+
+        Some(ButtonEvent {
+            switch: SwitchKind::Dimmer,
+            button: button as u8,
+            action,
+        })
+    }
+
+    /// Decodes a button event code of a tap switch from the bridge. Its buttons send 34, 16, 17,
+    /// and 18, and are only ever pressed.
+    pub fn from_tap_code(code: u64) -> Option<ButtonEvent> {
+        let button = match code {
+            34 => 1,
+            16 => 2,
+            17 => 3,
+            18 => 4,
+            _ => return None,
+        };
+
+        Some(ButtonEvent {
+            switch: SwitchKind::Tap,
+            button,
+            action: ButtonAction::Press,
+        })
+    }
+
+    /// Decodes a button event code from a sensor of the given type, "ZGPSwitch" for a tap switch
+    /// and "ZLLSwitch" for a dimmer switch.
+    pub fn from_sensor(sensor_type: &str, code: u64) -> Option<ButtonEvent> {
+        match sensor_type {
+            "ZGPSwitch" => ButtonEvent::from_tap_code(code),
+            _ => ButtonEvent::from_code(code),
+        }
+    }
+}
+
+impl fmt::Display for ButtonEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let button = match self.switch {
+            SwitchKind::Dimmer => {
+                String::from(["on", "brighter", "dimmer", "off"][usize::from(self.button - 1)])
+            }
+            SwitchKind::Tap => format!("button {}", self.button),
+        };
+        let action = match self.action {
+            ButtonAction::Press => "pressed",
+            ButtonAction::Hold => "held",
+            ButtonAction::ShortRelease => "released",
+            ButtonAction::LongRelease => "released after holding",
+        };
+
+        write!(f, "{} {}", button, action)
+    }
+}
+
+/// Converts a light level as the bridge sends it, 10000 log10(lux) + 1, to lux.
+pub fn lux(lightlevel: f64) -> f64 {
+    10f64.powf((lightlevel - 1.0) / 10000.0)
+}
+
+/// Helper function to pick out the reading of a sensor by its type, `None` for other sensors.
+fn reading(sensor: &RawSensor) -> Option<Reading> {
+    let state = &sensor.state;
+
+    match sensor.sensor_type.as_str() {
+        "ZLLPresence" | "CLIPPresence" => Some(Reading::Motion {
+            presence: state["presence"].as_bool()?,
+        }),
+        "ZLLTemperature" | "CLIPTemperature" => Some(Reading::Temperature {
+            // The bridge sends hundredths of a degree.
+            celsius: state["temperature"].as_f64()? / 100.0,
+        }),
+        "ZLLLightLevel" | "CLIPLightLevel" => Some(Reading::LightLevel {
+            lux: lux(state["lightlevel"].as_f64()?),
+        }),
+        "ZLLSwitch" | "ZGPSwitch" => Some(Reading::Switch {
+            event: state["buttonevent"]
+                .as_u64()
+                .and_then(|code| ButtonEvent::from_sensor(&sensor.sensor_type, code)),
+        }),
+        _ => None,
+    }
+}
+
+/// Decodes the motion sensors, temperature sensors, light level sensors, and dimmer and tap
+/// switches out of the bridge's sensors, sorted by name.
+pub fn sensors(sensors: &Value, strict: bool) -> Result<Vec<Sensor>, Box<dyn Error>> {
+    let raw = decode::decode_map::<RawSensor>(sensors, "sensor", &KNOWN_SENSOR_FIELDS, strict)?;
+
+    let mut sensors: Vec<Sensor> = raw
+        .into_iter()
+        .filter_map(|(id, sensor)| {
+            Some(Sensor {
+                reading: reading(&sensor)?,
+                battery: sensor.config["battery"]
+                    .as_u64()
+                    .map(|level| level.min(100) as u8),
+                // Sensors that haven't reported yet have "none".
+                lastupdated: sensor.state["lastupdated"]
+                    .as_str()
+                    .filter(|&at| at != "none")
+                    .map(String::from),
+                name: sensor.name,
+                id,
+            })
+        })
+        .collect();
+
+    sensors.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+    Ok(sensors)
+}
+
+/// Sensor readings to evaluate conditions against, by sensor name: `motion:NAME` is active while
+/// the sensor sees motion, and `temperature:NAME` and `lightlevel:NAME` have its reading in degrees
+/// Celsius and lux.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Readings {
+    pub sensors: Vec<Sensor>,
+}
+
+impl Context for Readings {
+    fn is_active(&self, source: &str, name: &str) -> bool {
+        source == "motion"
+            && self.sensors.iter().any(|sensor| {
+                sensor.name == name && sensor.reading == Reading::Motion { presence: true }
+            })
+    }
+
+    fn time(&self) -> TimeOfDay {
+        let now = time::now();
+        TimeOfDay {
+            hour: now.tm_hour as u8,
+            minute: now.tm_min as u8,
+            second: now.tm_sec as u8,
+        }
+    }
+
+    fn value(&self, source: &str, name: &str) -> Option<f64> {
+        self.sensors
+            .iter()
+            .filter(|sensor| sensor.name == name)
+            .find_map(|sensor| match (source, &sensor.reading) {
+                ("temperature", &Reading::Temperature { celsius }) => Some(celsius),
+                ("lightlevel", &Reading::LightLevel { lux }) => Some(lux),
+                _ => None,
+            })
+    }
+}
+
+impl Hue {
+    /// Returns the current readings of all motion sensors, temperature sensors, light level
+    /// sensors, and dimmer switches, sorted by name.
+    pub fn sensors(&self) -> Result<Vec<Sensor>, Box<dyn Error>> {
+        sensors(&self.fetch("sensors", false)?, self.config().strict)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn button_events() {
+        assert_eq!(
+            ButtonEvent::from_code(4002),
+            Some(ButtonEvent {
+                switch: SwitchKind::Dimmer,
+                button: 4,
+                action: ButtonAction::ShortRelease,
+            })
+        );
+        assert_eq!(
+            ButtonEvent::from_code(2001).unwrap().to_string(),
+            "brighter held"
+        );
+        assert_eq!(ButtonEvent::from_code(5002), None);
+        assert_eq!(ButtonEvent::from_code(1009), None);
+
+        // Tap switch codes aren't misread as a dimmer switch's.
+        assert_eq!(
+            ButtonEvent::from_sensor("ZGPSwitch", 34)
+                .unwrap()
+                .to_string(),
+            "button 1 pressed"
+        );
+        assert_eq!(ButtonEvent::from_sensor("ZGPSwitch", 18).unwrap().button, 4);
+        assert_eq!(ButtonEvent::from_sensor("ZGPSwitch", 1002), None);
+        assert_eq!(ButtonEvent::from_sensor("ZLLSwitch", 34), None);
+    }
+
+    #[test]
+    fn typed_sensors() {
+        let sensors = sensors(
+            &json!({
+                "1": {"name": "Daylight", "type": "Daylight", "config": {"on": true}},
+                "4": {"name": "Hallway switch", "type": "ZLLSwitch", "config": {"battery": 80},
+                      "state": {"buttonevent": 1002, "lastupdated": "2018-06-01T21:30:00"}},
+                "7": {"name": "Kitchen motion", "type": "ZLLPresence", "config": {"battery": 12},
+                      "state": {"presence": true, "lastupdated": "none"}},
+                "8": {"name": "Kitchen temperature", "type": "ZLLTemperature",
+                      "state": {"temperature": 2134}},
+                "9": {"name": "Kitchen light level", "type": "ZLLLightLevel",
+                      "state": {"lightlevel": 20001}}
+            }),
+            false,
+        )
+        .unwrap();
+
+        let readings: Vec<String> = sensors.iter().map(|s| s.reading.to_string()).collect();
+        assert_eq!(readings, vec!["on released", "100 lux", "motion", "21.3°C"]);
+
+        assert_eq!(sensors[0].battery, Some(80));
+        assert_eq!(
+            sensors[0].lastupdated.as_deref(),
+            Some("2018-06-01T21:30:00")
+        );
+        assert_eq!(sensors[2].lastupdated, None);
+        assert_eq!(sensors[3].battery, None);
+
+        let readings = Readings { sensors };
+        assert!(readings.is_active("motion", "Kitchen motion"));
+        assert_eq!(
+            readings.value("temperature", "Kitchen temperature"),
+            Some(21.34)
+        );
+        assert_eq!(readings.value("lightlevel", "Kitchen temperature"), None);
+    }
+
+    #[test]
+    fn readings_as_json() {
+        assert_eq!(
+            serde_json::to_value(Reading::Temperature { celsius: 21.5 }).unwrap(),
+            json!({"type": "temperature", "celsius": 21.5})
+        );
+        assert_eq!(
+            serde_json::to_value(Reading::Switch {
+                event: ButtonEvent::from_code(1002)
+            })
+            .unwrap(),
+            json!({"type": "switch", "event": {"switch": "dimmer", "button": 1, "action": "short_release"}})
+        );
+    }
+}
@@ -0,0 +1,260 @@
+//! # serve
+//!
+//! A read-only dashboard of the lights and rooms, served over HTTP. It's only served to this
+//! machine unless listening on another address, e.x. to open it from a phone on the same network.
+//! The page is embedded in the binary and kept live with server-sent events pushed whenever the
+//! bridge's state changes. Nothing served can change a light.
+
+use serde_json::Value;
+use std::error::Error;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use hue::Hue;
+
+/// The dashboard page.
+const DASHBOARD: &str = include_str!("dashboard.html");
+
+/// How often a comment is sent to idle event stream clients, so hung up clients are noticed.
+const KEEPALIVE: Duration = Duration::from_secs(15);
+
+/// How long a client may take to send each part of its request before it's hung up on.
+pub(crate) const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest request line or header read, so a client can't make the server buffer without end.
+pub(crate) const MAX_LINE: u64 = 8 * 1024;
+
+/// Most headers read from a request.
+pub(crate) const MAX_HEADERS: usize = 100;
+
+/// Latest state of the bridge and a count of its changes, shared with the clients.
+#[derive(Debug, Default)]
+struct Shared {
+    state: Value,
+    version: u64,
+}
+
+type Dashboard = Arc<(Mutex<Shared>, Condvar)>;
+
+/// Serves the dashboard on the address, e.x. "127.0.0.1:8080" or "0.0.0.0:8080" to open it from
+/// other devices, fetching the bridge's state every interval. Each client is served on its own
+/// thread.
+pub fn serve(hue: Hue, address: &str, interval: Duration) -> Result<(), Box<dyn Error>> {
+    let dashboard: Dashboard = Arc::new((Mutex::new(Shared::default()), Condvar::new()));
+    update(&hue, &dashboard)?;
+
+    let listener = TcpListener::bind(address)
+        .map_err(|e| format!("Failed to listen on {}: {}", address, e))?;
+
+    let polled = dashboard.clone();
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        // The page shows the bridge as offline until it answers again.
+        if let Err(e) = update(&hue, &polled) {
+            set_state(&polled, json!({ "offline": e.to_string() }));
+        }
+    });
+
+    for stream in listener.incoming().flatten() {
+        let dashboard = dashboard.clone();
+        thread::spawn(move || {
+            // A client hanging up mid-request shouldn't bring the server down.
+            let _ = handle_connection(stream, &dashboard);
+        });
+    }
+
+    Ok(())
+}
+
+/// Helper function to fetch the lights and groups into the dashboard.
+fn update(hue: &Hue, dashboard: &Dashboard) -> Result<(), Box<dyn Error>> {
+    let state = json!({
+        "lights": hue.fetch("lights", false)?,
+        "groups": hue.fetch("groups", false)?,
+    });
+
+    set_state(dashboard, state);
+    Ok(())
+}
+
+/// Helper function to replace the state, waking the event stream clients if it changed.
+fn set_state(dashboard: &Dashboard, state: Value) {
+    let (ref shared, ref changed) = **dashboard;
+    let mut shared = shared.lock().unwrap();
+
+    if shared.state != state {
+        shared.state = state;
+        shared.version += 1;
+        changed.notify_all();
+    }
+}
+
+/// Parses the request line of an HTTP request, e.x. "GET /state HTTP/1.1", into its method and
+/// path, leaving out any query string.
+pub(crate) fn parse_request_line(line: &str) -> Option<(&str, &str)> {
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?;
+    let target = parts.next()?;
+    parts.next()?.strip_prefix("HTTP/")?;
+
+    Some((method, target.split('?').next().unwrap_or(target)))
+}
+
+/// Reads a line of a request, failing if it's longer than `MAX_LINE`. Returns the bytes read,
+/// which is 0 once the client has sent everything.
+pub(crate) fn read_line<R: BufRead>(reader: &mut R, line: &mut String) -> io::Result<usize> {
+    let read = reader.by_ref().take(MAX_LINE).read_line(line)?;
+
+    if read as u64 == MAX_LINE && !line.ends_with('\n') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Request line too long.",
+        ));
+    }
+    Ok(read)
+}
+
+/// Reads the headers of a request, up to the blank line ending them, returning the length of the
+/// body, 0 without a Content-Length. Fails if there are more than `MAX_HEADERS`.
+pub(crate) fn read_headers<R: BufRead>(reader: &mut R) -> io::Result<usize> {
+    let mut length = 0;
+    let mut header = String::new();
+
+    for _ in 0..=MAX_HEADERS {
+        header.clear();
+        if read_line(reader, &mut header)? <= 2 {
+            return Ok(length);
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, "Invalid Content-Length.")
+                })?;
+            }
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Too many headers.",
+    ))
+}
+
+/// Builds a complete HTTP response.
+pub(crate) fn response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+/// Answers a single request from a client.
+fn handle_connection(stream: TcpStream, dashboard: &Dashboard) -> io::Result<()> {
+    // A client that stops sending its request is hung up on rather than keeping a thread.
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    read_line(&mut reader, &mut line)?;
+
+    // The headers aren't needed, but are read so the client isn't cut off while sending them.
+    read_headers(&mut reader)?;
+
+    let mut stream = reader.into_inner();
+    let reply = match parse_request_line(&line) {
+        Some(("GET", "/")) => response("200 OK", "text/html; charset=utf-8", DASHBOARD),
+        Some(("GET", "/state")) => {
+            let state = dashboard.0.lock().unwrap().state.to_string();
+            response("200 OK", "application/json", &state)
+        }
+        Some(("GET", "/events")) => return stream_events(stream, dashboard),
+        Some(("GET", _)) => response("404 Not Found", "text/plain", "Not found.\n"),
+        Some(_) => response(
+            "405 Method Not Allowed",
+            "text/plain",
+            "The dashboard is read-only.\n",
+        ),
+        None => response("400 Bad Request", "text/plain", "Bad request.\n"),
+    };
+
+    stream.write_all(reply.as_bytes())
+}
+
+/// Sends the state to a client as server-sent events, once now and again after every change,
+/// until it hangs up.
+fn stream_events(mut stream: TcpStream, dashboard: &Dashboard) -> io::Result<()> {
+    stream.write_all(
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-store\r\n\r\n",
+    )?;
+
+    let (ref shared, ref changed) = **dashboard;
+    let mut sent = None;
+
+    loop {
+        let message = {
+            let mut shared = shared.lock().unwrap();
+            if sent == Some(shared.version) {
+                shared = changed.wait_timeout(shared, KEEPALIVE).unwrap().0;
+            }
+
+            if sent == Some(shared.version) {
+                String::from(": keepalive\n\n")
+            } else {
+                sent = Some(shared.version);
+                format!("data: {}\n\n", shared.state)
+            }
+        };
+
+        stream.write_all(message.as_bytes())?;
+        stream.flush()?;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn request_lines() {
+        assert_eq!(
+            parse_request_line("GET /state?t=1 HTTP/1.1\r\n"),
+            Some(("GET", "/state"))
+        );
+        assert_eq!(parse_request_line("PUT / HTTP/1.0"), Some(("PUT", "/")));
+        assert_eq!(parse_request_line("GET /"), None);
+        assert_eq!(parse_request_line(""), None);
+    }
+
+    #[test]
+    fn responses() {
+        let reply = response("200 OK", "application/json", "{}");
+
+        assert!(reply.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(reply.contains("Content-Length: 2\r\n"));
+        assert!(reply.ends_with("\r\n\r\n{}"));
+    }
+
+    #[test]
+    fn bounded_lines() {
+        let mut line = String::new();
+        let mut reader = io::Cursor::new("PUT / HTTP/1.1\r\nHost: x\r\ncontent-length: 12\r\n\r\n");
+        assert_eq!(read_line(&mut reader, &mut line).unwrap(), 16);
+        assert_eq!(read_headers(&mut reader).unwrap(), 12);
+
+        let long = "a".repeat(MAX_LINE as usize + 1);
+        let mut line = String::new();
+        assert!(read_line(&mut io::Cursor::new(long), &mut line).is_err());
+
+        let headers = "X: y\r\n".repeat(MAX_HEADERS + 1);
+        assert!(read_headers(&mut io::Cursor::new(headers)).is_err());
+    }
+}
@@ -0,0 +1,265 @@
+//! # shuffle
+//!
+//! Gives the lights of a room random colors from a named palette, within a brightness range, as
+//! ambient variation for parties or holidays. Palettes are built in, e.x. "cozy", and more can be
+//! added in $HOME/.config/rusty_hue/palettes.json.
+
+use serde_json;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use colors;
+use colors::RGB;
+use files;
+use hue::Hue;
+use parse;
+
+/// A named palette of RGB colors.
+type Palette = (&'static str, &'static [(u8, u8, u8)]);
+
+/// Built-in palettes.
+const PALETTES: [Palette; 4] = [
+    (
+        "cozy",
+        &[
+            (255, 147, 41),
+            (255, 120, 60),
+            (230, 80, 40),
+            (255, 180, 90),
+        ],
+    ),
+    (
+        "party",
+        &[
+            (255, 0, 170),
+            (0, 220, 255),
+            (140, 0, 255),
+            (60, 255, 60),
+            (255, 40, 40),
+        ],
+    ),
+    (
+        "holiday",
+        &[
+            (230, 20, 20),
+            (20, 180, 40),
+            (255, 190, 60),
+            (255, 230, 200),
+        ],
+    ),
+    (
+        "ocean",
+        &[(0, 90, 255), (0, 200, 200), (40, 140, 255), (120, 220, 255)],
+    ),
+];
+
+/// Small pseudo-random number generator (xorshift), good enough to pick colors.
+#[derive(Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Creates a generator seeded from the clock.
+    pub fn from_time() -> Rng {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or(0);
+
+        Rng::with_seed(nanos)
+    }
+
+    pub fn with_seed(seed: u64) -> Rng {
+        // The state must never be zero.
+        Rng { state: seed | 1 }
+    }
+
+    /// A number between 0 and `bound`, excluding `bound`.
+    pub fn below(&mut self, bound: usize) -> usize {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+
+        (self.state % bound.max(1) as u64) as usize
+    }
+
+    /// A number between `min` and `max`, inclusive.
+    pub fn between(&mut self, min: u8, max: u8) -> u8 {
+        min + self.below(usize::from(max - min) + 1) as u8
+    }
+}
+
+/// Loads the palettes, the built-in ones extended or overridden by
+/// $HOME/.config/rusty_hue/palettes.json. The file maps palette names to lists of colors, each a
+/// name from colors.json or "r,g,b", e.x. {"autumn": ["orange", "200,60,20"]}.
+pub fn load_palettes(
+    named: &HashMap<String, RGB>,
+) -> Result<HashMap<String, Vec<RGB>>, Box<dyn Error>> {
+    let mut palettes: HashMap<String, Vec<RGB>> = PALETTES
+        .iter()
+        .map(|&(name, rgbs)| {
+            let rgbs = rgbs.iter().map(|&(r, g, b)| RGB { r, g, b }).collect();
+            (name.to_string(), rgbs)
+        })
+        .collect();
+
+    let mut f = match File::open(files::config_file("palettes.json")?) {
+        Ok(f) => f,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(palettes),
+        Err(e) => return Err(From::from(e)),
+    };
+
+    let mut contents = String::new();
+    f.read_to_string(&mut contents)?;
+
+    let overrides: HashMap<String, Vec<String>> = serde_json::from_str(&contents)?;
+    for (name, colors) in overrides {
+        let colors = colors
+            .iter()
+            .map(|color| resolve_color(color, named))
+            .collect::<Result<Vec<RGB>, Box<dyn Error>>>()?;
+
+        if colors.is_empty() {
+            return Err(From::from(format!("Palette '{}' has no colors.", name)));
+        }
+        palettes.insert(name, colors);
+    }
+
+    Ok(palettes)
+}
+
+/// Resolves a color given by name from colors.json, CSS color name, hex, or as "r,g,b".
+pub fn resolve_color(color: &str, named: &HashMap<String, RGB>) -> Result<RGB, Box<dyn Error>> {
+    if let Some(rgb) = colors::find_color(color, named) {
+        return Ok(rgb);
+    }
+
+    let parts: Vec<Result<u8, _>> = color.split(',').map(|part| part.trim().parse()).collect();
+    match parts.as_slice() {
+        [Ok(r), Ok(g), Ok(b)] => Ok(RGB {
+            r: *r,
+            g: *g,
+            b: *b,
+        }),
+        _ => Err(From::from(format!(
+            "Unknown palette color '{}', expected a name from colors.json, a CSS color name, hex, \
+             or 'r,g,b'.",
+            color
+        ))),
+    }
+}
+
+/// Parses a brightness range, e.x. "20%-60%" or "50-200". A single value is a range of one.
+pub fn parse_brightness_range(value: &str) -> Result<(u8, u8), Box<dyn Error>> {
+    let (min, max) = match value.split_once('-') {
+        Some((min, max)) => (parse::parse_brightness(min)?, parse::parse_brightness(max)?),
+        None => {
+            let bri = parse::parse_brightness(value)?;
+            (bri, bri)
+        }
+    };
+
+    if min > max {
+        return Err(From::from(format!(
+            "Invalid brightness range '{}', the lower bound comes first.",
+            value
+        )));
+    }
+
+    Ok((min, max))
+}
+
+/// Picks a color from the palette for each light, avoiding giving neighbouring lights the same
+/// color when the palette has more than one.
+pub fn pick_colors(rng: &mut Rng, lights: usize, palette: usize) -> Vec<usize> {
+    let mut picks: Vec<usize> = Vec::with_capacity(lights);
+
+    for _ in 0..lights {
+        let mut pick = rng.below(palette);
+        if palette > 1 && picks.last() == Some(&pick) {
+            pick = (pick + 1 + rng.below(palette - 1)) % palette;
+        }
+        picks.push(pick);
+    }
+
+    picks
+}
+
+impl Hue {
+    /// Gives each color capable light in a room a random color from the palette and a random
+    /// brightness within the range. Returns the number of lights changed.
+    pub fn shuffle_room(
+        &self,
+        room: &str,
+        palette: &[RGB],
+        bri: (u8, u8),
+        rng: &mut Rng,
+    ) -> Result<usize, Box<dyn Error>> {
+        let (_, group) = self.find_group(room)?;
+        let unlocked = self.unlocked(&group.lights)?;
+        let lights: Vec<&String> = unlocked
+            .iter()
+            .filter(|index| self.light_supports_color(index))
+            .collect();
+
+        if lights.is_empty() {
+            return Err(From::from(format!(
+                "No lights in '{}' can show colors.",
+                group.name
+            )));
+        }
+
+        for (index, pick) in lights
+            .iter()
+            .zip(pick_colors(rng, lights.len(), palette.len()))
+        {
+            let xy = colors::XY::from_rgb(&palette[pick]);
+            self.set_xy_by_index(index, xy.x, xy.y, Some(rng.between(bri.0, bri.1)), true)?;
+        }
+
+        Ok(lights.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn palette_colors() {
+        let mut named = HashMap::new();
+        named.insert(String::from("red"), RGB { r: 255, g: 0, b: 0 });
+
+        assert_eq!(resolve_color("red", &named).unwrap().r, 255);
+        assert_eq!(resolve_color("1, 2,3", &named).unwrap().b, 3);
+        assert!(resolve_color("mauve", &named).is_err());
+        assert!(resolve_color("1,2,300", &named).is_err());
+    }
+
+    #[test]
+    fn brightness_ranges() {
+        assert_eq!(parse_brightness_range("20%-60%").unwrap(), (51, 152));
+        assert_eq!(parse_brightness_range("100").unwrap(), (100, 100));
+        assert!(parse_brightness_range("200-100").is_err());
+    }
+
+    #[test]
+    fn random_picks() {
+        let mut rng = Rng::with_seed(42);
+
+        let picks = pick_colors(&mut rng, 50, 3);
+        assert!(picks.iter().all(|&pick| pick < 3));
+        assert!(picks.windows(2).all(|pair| pair[0] != pair[1]));
+        assert_eq!(pick_colors(&mut rng, 3, 1), vec![0, 0, 0]);
+
+        for _ in 0..100 {
+            let bri = rng.between(50, 60);
+            assert!((50..=60).contains(&bri));
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! # ssdp
+//!
+//! Finds bridges on the local network with SSDP, the discovery part of UPnP. Unlike the
+//! meethue.com endpoint this works without internet access: a search is multicast on the LAN and
+//! bridges answer with their id in a `hue-bridgeid` header.
+
+use std::error::Error;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// Multicast address and port SSDP searches are sent to.
+const SSDP_ADDRESS: &str = "239.255.255.250:1900";
+
+/// How long to wait for bridges to answer a search.
+pub const SEARCH_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The search request. Bridges answer within MX seconds.
+const SEARCH: &str = "M-SEARCH * HTTP/1.1\r\n\
+                      HOST: 239.255.255.250:1900\r\n\
+                      MAN: \"ssdp:discover\"\r\n\
+                      MX: 2\r\n\
+                      ST: ssdp:all\r\n\
+                      \r\n";
+
+/// A bridge that answered a search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SsdpBridge {
+    pub id: String,
+    pub ip: String,
+}
+
+/// Multicasts a search and passes each bridge that answers within the timeout to `found`, until
+/// it returns false. Other UPnP devices answer too, and are skipped since they don't send a
+/// `hue-bridgeid` header.
+pub fn search<F: FnMut(SsdpBridge) -> bool>(
+    timeout: Duration,
+    mut found: F,
+) -> Result<(), Box<dyn Error>> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.send_to(SEARCH.as_bytes(), SSDP_ADDRESS)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut ids: Vec<String> = Vec::new();
+    let mut buffer = [0; 2048];
+
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        if remaining == Duration::from_secs(0) {
+            break;
+        }
+        socket.set_read_timeout(Some(remaining))?;
+
+        let (len, from) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            // Timed out, or an error from an unrelated datagram.
+            Err(_) => break,
+        };
+
+        let response = String::from_utf8_lossy(&buffer[..len]);
+        if let Some(bridge) = parse_response(&response, from) {
+            // Bridges answer once per search target, so each usually answers several times.
+            if !ids.contains(&bridge.id) {
+                ids.push(bridge.id.clone());
+                if !found(bridge) {
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses an answer to a search, returning the bridge that sent it. The bridge's IP is taken from
+/// the LOCATION header of its description, falling back to the address the answer came from.
+fn parse_response(response: &str, from: SocketAddr) -> Option<SsdpBridge> {
+    let header = |name: &str| {
+        response.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            if key.trim().eq_ignore_ascii_case(name) {
+                Some(value.trim())
+            } else {
+                None
+            }
+        })
+    };
+
+    let id = header("hue-bridgeid")?;
+    let ip = header("location")
+        .and_then(|location| location.split("://").nth(1))
+        .and_then(|rest| rest.split(['/', ':']).next())
+        .filter(|host| !host.is_empty())
+        .map_or_else(|| from.ip().to_string(), String::from);
+
+    Some(SsdpBridge {
+        id: id.to_uppercase(),
+        ip,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_responses() {
+        let from: SocketAddr = "192.168.1.20:1900".parse().unwrap();
+        let response = "HTTP/1.1 200 OK\r\n\
+                        CACHE-CONTROL: max-age=100\r\n\
+                        LOCATION: http://192.168.1.2:80/description.xml\r\n\
+                        SERVER: Hue/1.0 UPnP/1.0 IpBridge/1.50.0\r\n\
+                        hue-bridgeid: 001788fffe000000\r\n\
+                        ST: upnp:rootdevice\r\n\r\n";
+
+        assert_eq!(
+            parse_response(response, from),
+            Some(SsdpBridge {
+                id: String::from("001788FFFE000000"),
+                ip: String::from("192.168.1.2"),
+            })
+        );
+
+        // Without a usable location, the sender's address is used.
+        let response = "HTTP/1.1 200 OK\r\nhue-bridgeid: 001788FFFE000000\r\n\r\n";
+        assert_eq!(parse_response(response, from).unwrap().ip, "192.168.1.20");
+
+        let response = "HTTP/1.1 200 OK\r\nLOCATION: http://192.168.1.9/desc.xml\r\n\r\n";
+        assert_eq!(parse_response(response, from), None);
+    }
+}
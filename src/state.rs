@@ -0,0 +1,261 @@
+//! # state
+//!
+//! Changes to the state of a light, or to all lights of a group, built up field by field and sent
+//! in one request, e.x. `LightStateUpdate::new().on(true).bri(200).transition(4)`. Only the fields
+//! set are sent, so the rest of the light's state is left as is. Changes that don't give a
+//! transition get the default one, if set, see `set_default_transition` and `or_transition`.
+
+use serde_json;
+use serde_json::Value;
+use std::fmt;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use alert::{AlertMode, Effect};
+
+/// Default transition in tenths of a second, or `NO_TRANSITION` for the bridge's own, 400ms.
+static DEFAULT_TRANSITION: AtomicU32 = AtomicU32::new(NO_TRANSITION);
+
+const NO_TRANSITION: u32 = u32::MAX;
+
+/// Whether a change, as sent to the bridge, steps values from the current ones, e.x. with
+/// "bri_inc", so sending it twice steps twice.
+pub fn is_relative(body: &str) -> bool {
+    match serde_json::from_str::<Value>(body) {
+        Ok(Value::Object(fields)) => fields.keys().any(|key| key.ends_with("_inc")),
+        _ => false,
+    }
+}
+
+/// Sets the transition, in tenths of a second, of changes sent for the whole process that don't
+/// give their own, or goes back to the bridge's with `None`.
+pub fn set_default_transition(transition: Option<u16>) {
+    let transition = transition.map_or(NO_TRANSITION, u32::from);
+    DEFAULT_TRANSITION.store(transition, Ordering::Relaxed);
+}
+
+/// The default transition in tenths of a second, if one was set.
+pub fn default_transition() -> Option<u16> {
+    match DEFAULT_TRANSITION.load(Ordering::Relaxed) {
+        NO_TRANSITION => None,
+        transition => Some(transition as u16),
+    }
+}
+
+/// A change to the state of a light or the action of a group.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LightStateUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bri: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hue: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sat: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub xy: Option<[f32; 2]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ct: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bri_inc: Option<i16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hue_inc: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ct_inc: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alert: Option<AlertMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub effect: Option<Effect>,
+    /// Scene to recall, by id, for groups.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scene: Option<String>,
+    /// In tenths of a second.
+    #[serde(rename = "transitiontime", skip_serializing_if = "Option::is_none")]
+    pub transition: Option<u16>,
+}
+
+impl LightStateUpdate {
+    /// An update changing nothing yet.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rusty_hue::state::LightStateUpdate;
+    ///
+    /// let update = LightStateUpdate::new().on(true).bri(200).transition(4);
+    ///
+    /// assert_eq!(update.to_string(), r#"{"on":true,"bri":200,"transitiontime":4}"#);
+    /// ```
+    pub fn new() -> LightStateUpdate {
+        LightStateUpdate::default()
+    }
+
+    pub fn on(mut self, on: bool) -> LightStateUpdate {
+        self.on = Some(on);
+        self
+    }
+
+    /// Brightness, 1-254. A light at 1 is still on, lights are turned off with `on`.
+    pub fn bri(mut self, bri: u8) -> LightStateUpdate {
+        self.bri = Some(bri);
+        self
+    }
+
+    /// Hue, 0-65535, going around the color wheel from red.
+    pub fn hue(mut self, hue: u16) -> LightStateUpdate {
+        self.hue = Some(hue);
+        self
+    }
+
+    /// Saturation, 0-254.
+    pub fn sat(mut self, sat: u8) -> LightStateUpdate {
+        self.sat = Some(sat.min(254));
+        self
+    }
+
+    /// Color as CIE (x, y) coordinates.
+    pub fn xy(mut self, x: f32, y: f32) -> LightStateUpdate {
+        self.xy = Some([x, y]);
+        self
+    }
+
+    /// Color temperature in mireds.
+    pub fn ct(mut self, ct: u16) -> LightStateUpdate {
+        self.ct = Some(ct);
+        self
+    }
+
+    /// Change of brightness, -254 to 254, made by the bridge from the light's brightness at the
+    /// time. Unlike setting a brightness read beforehand, changes sent at the same time by
+    /// different clients all count.
+    pub fn bri_inc(mut self, step: i16) -> LightStateUpdate {
+        self.bri_inc = Some(step.clamp(-254, 254));
+        self
+    }
+
+    /// Change of hue, -65534 to 65534, made by the bridge as with `bri_inc`.
+    pub fn hue_inc(mut self, step: i32) -> LightStateUpdate {
+        self.hue_inc = Some(step.clamp(-65534, 65534));
+        self
+    }
+
+    /// Change of color temperature in mireds, -65534 to 65534, made by the bridge as with
+    /// `bri_inc`. The bridge keeps the result within the light's range.
+    pub fn ct_inc(mut self, step: i32) -> LightStateUpdate {
+        self.ct_inc = Some(step.clamp(-65534, 65534));
+        self
+    }
+
+    pub fn alert(mut self, alert: AlertMode) -> LightStateUpdate {
+        self.alert = Some(alert);
+        self
+    }
+
+    pub fn effect(mut self, effect: Effect) -> LightStateUpdate {
+        self.effect = Some(effect);
+        self
+    }
+
+    /// Scene to recall, by id. Only groups recall scenes.
+    pub fn scene(mut self, scene: &str) -> LightStateUpdate {
+        self.scene = Some(scene.to_string());
+        self
+    }
+
+    /// Time taken to reach the state, in tenths of a second as the bridge takes it. See
+    /// `hue::transition_time` for durations.
+    pub fn transition(mut self, transition: u16) -> LightStateUpdate {
+        self.transition = Some(transition);
+        self
+    }
+
+    /// The update with a transition, if it doesn't give its own and changes more than whether
+    /// the light is on, e.x. the default transition as the update is sent. Lights turned off
+    /// with a transition come back on at the lowest brightness, so turning on and off is left
+    /// without one.
+    pub fn or_transition(&self, transition: Option<u16>) -> LightStateUpdate {
+        let mut update = self.clone();
+        let only_power = LightStateUpdate {
+            on: None,
+            ..self.clone()
+        }
+        .is_empty();
+        if update.transition.is_none() && !only_power {
+            update.transition = transition;
+        }
+        update
+    }
+
+    /// Whether nothing is changed.
+    pub fn is_empty(&self) -> bool {
+        *self == LightStateUpdate::default()
+    }
+
+    /// The update as the JSON body the bridge takes.
+    pub fn to_value(&self) -> Value {
+        // Going through the text keeps coordinates as written, e.x. 0.4 rather than 0.4000000059.
+        serde_json::from_str(&self.to_string()).unwrap_or_default()
+    }
+}
+
+impl fmt::Display for LightStateUpdate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&serde_json::to_string(self).map_err(|_| fmt::Error)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn only_set_fields() {
+        assert!(LightStateUpdate::new().is_empty());
+        assert_eq!(LightStateUpdate::new().to_string(), "{}");
+
+        let update = LightStateUpdate::new()
+            .xy(0.4, 0.5)
+            .bri(10)
+            .alert(AlertMode::LSelect)
+            .effect(Effect::ColorLoop);
+        assert_eq!(
+            update.to_value(),
+            json!({"bri": 10, "xy": [0.4, 0.5], "alert": "lselect", "effect": "colorloop"})
+        );
+    }
+
+    #[test]
+    fn increments() {
+        let update = LightStateUpdate::new()
+            .bri_inc(-300)
+            .hue_inc(70000)
+            .ct_inc(-50);
+
+        assert_eq!(
+            update.to_value(),
+            json!({"bri_inc": -254, "hue_inc": 65534, "ct_inc": -50})
+        );
+    }
+
+    #[test]
+    fn default_transitions() {
+        let update = LightStateUpdate::new().on(true).bri(200);
+
+        assert_eq!(
+            update.or_transition(Some(20)).to_string(),
+            r#"{"on":true,"bri":200,"transitiontime":20}"#
+        );
+        assert_eq!(
+            update.transition(0).or_transition(Some(20)).to_string(),
+            r#"{"on":true,"bri":200,"transitiontime":0}"#
+        );
+        assert!(LightStateUpdate::new().or_transition(Some(20)).is_empty());
+        assert_eq!(
+            LightStateUpdate::new()
+                .on(false)
+                .or_transition(Some(20))
+                .to_string(),
+            r#"{"on":false}"#
+        );
+    }
+}
@@ -0,0 +1,246 @@
+//! # store
+//!
+//! Where history, e.x. the journal of recalled scenes, is kept. By default a history is a flat
+//! JSON file in $HOME/.config/rusty_hue with one record per line. Built with the `sqlite`
+//! feature, histories can instead be kept in $HOME/.config/rusty_hue/store.sqlite, where they can
+//! be queried, e.x. `SELECT record FROM history WHERE name = 'journal'`.
+
+use serde_json;
+use serde_json::Value;
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::PathBuf;
+
+use files;
+
+/// Keeps histories of records.
+pub trait Store {
+    /// Appends a record to a history, e.x. "journal".
+    fn append(&self, history: &str, record: &Value) -> Result<(), Box<dyn Error>>;
+
+    /// Records of a history, oldest first. A history never appended to is empty.
+    fn history(&self, history: &str) -> Result<Vec<Value>, Box<dyn Error>>;
+}
+
+/// Where histories are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// JSON files, see `JsonStore`.
+    #[default]
+    Json,
+    /// An SQLite database, see `SqliteStore`.
+    Sqlite,
+}
+
+impl Backend {
+    /// Parses the name of a backend, "json" or "sqlite". SQLite is an error in builds without it.
+    pub fn parse(backend: &str) -> Result<Backend, Box<dyn Error>> {
+        match backend {
+            "json" => Ok(Backend::Json),
+            "sqlite" if cfg!(feature = "sqlite") => Ok(Backend::Sqlite),
+            "sqlite" => Err(From::from(
+                "This build has no SQLite support, rebuild with '--features sqlite' or use the \
+                 'json' store.",
+            )),
+            _ => Err(From::from(format!(
+                "Unknown store '{}', expected 'json' or 'sqlite'.",
+                backend
+            ))),
+        }
+    }
+
+    /// Opens the store in $HOME/.config/rusty_hue.
+    pub fn open(self) -> Result<Box<dyn Store>, Box<dyn Error>> {
+        match self {
+            Backend::Json => Ok(Box::new(JsonStore::new(files::config_file("")?))),
+            Backend::Sqlite => open_sqlite(),
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn open_sqlite() -> Result<Box<dyn Store>, Box<dyn Error>> {
+    Ok(Box::new(SqliteStore::open(&files::config_file(
+        "store.sqlite",
+    )?)?))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn open_sqlite() -> Result<Box<dyn Store>, Box<dyn Error>> {
+    Err(From::from("This build has no SQLite support."))
+}
+
+/// Checks a history name is safe to use in a file name, e.x. "journal".
+fn check_name(name: &str) -> Result<(), Box<dyn Error>> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(From::from(format!(
+            "Invalid name '{}', use letters, digits, '-', and '_'.",
+            name
+        )))
+    }
+}
+
+/// Histories as JSON files in a directory. A history is kept in <name>.jsonl, with one record per
+/// line.
+#[derive(Debug, Clone)]
+pub struct JsonStore {
+    dir: PathBuf,
+}
+
+impl JsonStore {
+    pub fn new(dir: PathBuf) -> JsonStore {
+        JsonStore { dir }
+    }
+
+    /// Helper function to read a file, `None` if it doesn't exist.
+    fn read(&self, path: PathBuf) -> Result<Option<String>, Box<dyn Error>> {
+        let mut f = match File::open(path) {
+            Ok(f) => f,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(From::from(e)),
+        };
+
+        let mut contents = String::new();
+        f.read_to_string(&mut contents)?;
+        Ok(Some(contents))
+    }
+}
+
+impl Store for JsonStore {
+    fn append(&self, history: &str, record: &Value) -> Result<(), Box<dyn Error>> {
+        check_name(history)?;
+        let path = self.dir.join(format!("{}.jsonl", history));
+        files::append_line(&path, &serde_json::to_string(record)?)?;
+        Ok(())
+    }
+
+    /// Lines that can't be read are skipped.
+    fn history(&self, history: &str) -> Result<Vec<Value>, Box<dyn Error>> {
+        check_name(history)?;
+        let contents = self
+            .read(self.dir.join(format!("{}.jsonl", history)))?
+            .unwrap_or_default();
+
+        Ok(contents
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+}
+
+/// Histories in an SQLite database, with the table `history(id, name, record)`. Records are kept
+/// as JSON text.
+#[cfg(feature = "sqlite")]
+pub struct SqliteStore {
+    connection: rusqlite::Connection,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteStore {
+    /// Opens the database at the given path, creating it and its table if missing.
+    pub fn open(path: &std::path::Path) -> Result<SqliteStore, Box<dyn Error>> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        SqliteStore::with_connection(rusqlite::Connection::open(path)?)
+    }
+
+    /// Uses an open connection, e.x. to an in-memory database, creating the table if missing.
+    pub fn with_connection(
+        connection: rusqlite::Connection,
+    ) -> Result<SqliteStore, Box<dyn Error>> {
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS history (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 name TEXT NOT NULL,
+                 record TEXT NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS history_name ON history (name);",
+        )?;
+
+        Ok(SqliteStore { connection })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl Store for SqliteStore {
+    fn append(&self, history: &str, record: &Value) -> Result<(), Box<dyn Error>> {
+        check_name(history)?;
+        self.connection.execute(
+            "INSERT INTO history (name, record) VALUES (?1, ?2)",
+            rusqlite::params![history, record.to_string()],
+        )?;
+        Ok(())
+    }
+
+    /// Records that can't be read are skipped.
+    fn history(&self, history: &str) -> Result<Vec<Value>, Box<dyn Error>> {
+        check_name(history)?;
+        let mut statement = self
+            .connection
+            .prepare("SELECT record FROM history WHERE name = ?1 ORDER BY id")?;
+        let records =
+            statement.query_map(rusqlite::params![history], |row| row.get::<_, String>(0))?;
+
+        let mut values = Vec::new();
+        for record in records {
+            if let Ok(value) = serde_json::from_str(&record?) {
+                values.push(value);
+            }
+        }
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    /// Helper function to check a store keeps histories apart and in order.
+    fn check_store(store: &dyn Store) {
+        assert!(store.history("journal").unwrap().is_empty());
+        store.append("journal", &json!({"scene": "a"})).unwrap();
+        store.append("journal", &json!({"scene": "b"})).unwrap();
+        store.append("other", &json!(1)).unwrap();
+        assert_eq!(
+            store.history("journal").unwrap(),
+            vec![json!({"scene": "a"}), json!({"scene": "b"})]
+        );
+
+        assert!(store.append("../journal", &json!(1)).is_err());
+    }
+
+    #[test]
+    fn json_store() {
+        let dir = env::temp_dir().join(format!("rusty_hue_store_{}", process::id()));
+        check_store(&JsonStore::new(dir.clone()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_store() {
+        let connection = rusqlite::Connection::open_in_memory().unwrap();
+        check_store(&SqliteStore::with_connection(connection).unwrap());
+    }
+
+    #[test]
+    fn backends() {
+        assert_eq!(Backend::parse("json").unwrap(), Backend::Json);
+        assert!(Backend::parse("csv").is_err());
+        assert_eq!(Backend::parse("sqlite").is_ok(), cfg!(feature = "sqlite"));
+    }
+}
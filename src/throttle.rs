@@ -0,0 +1,94 @@
+//! # throttle
+//!
+//! Backs off when the bridge reports it is overloaded. The bridge answers with 429 (too many
+//! requests) or 503 (service unavailable) when it can't keep up, so requests are slowed down and
+//! retried instead of failing. Requests turned away are counted rather than reported one by one,
+//! so the caller can warn about them once.
+
+use std::cmp;
+use std::time::Duration;
+
+/// Attempts made for a single request before giving up on an overloaded bridge.
+pub const MAX_ATTEMPTS: u32 = 5;
+
+/// Shortest delay between requests once the bridge has pushed back.
+const MIN_DELAY: Duration = Duration::from_millis(100);
+
+/// Longest delay between requests, whatever the bridge asks for.
+const MAX_DELAY: Duration = Duration::from_secs(5);
+
+/// Pacing of requests to the bridge, shared by every request of a Hue system.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Throttle {
+    delay: Duration,
+    throttled: u32,
+}
+
+impl Throttle {
+    /// Delay to wait before sending the next request.
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Records an overload response and slows down, doubling the delay or using the bridge's
+    /// Retry-After when given.
+    pub fn overloaded(&mut self, retry_after: Option<Duration>) {
+        let delay = match retry_after {
+            Some(delay) => delay,
+            None => cmp::max(self.delay * 2, MIN_DELAY),
+        };
+        self.delay = cmp::min(delay, MAX_DELAY);
+        self.throttled += 1;
+    }
+
+    /// Records a successful request, speeding back up gradually.
+    pub fn succeeded(&mut self) {
+        let delay = self.delay / 2;
+        self.delay = if delay < MIN_DELAY {
+            Duration::from_secs(0)
+        } else {
+            delay
+        };
+    }
+
+    /// Number of requests the bridge turned away for being overloaded.
+    pub fn throttled(&self) -> u32 {
+        self.throttled
+    }
+}
+
+/// Reads a Retry-After header, which the bridge gives in seconds.
+pub fn retry_after(value: &str) -> Option<Duration> {
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+/// Whether an HTTP status code is the bridge reporting it's overloaded.
+pub fn is_overload(status: u16) -> bool {
+    status == 429 || status == 503
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn back_off_and_recover() {
+        let mut throttle = Throttle::default();
+        assert_eq!(throttle.delay(), Duration::from_secs(0));
+
+        throttle.overloaded(None);
+        throttle.overloaded(None);
+        assert_eq!(throttle.delay(), Duration::from_millis(200));
+
+        throttle.overloaded(Some(Duration::from_secs(60)));
+        assert_eq!(throttle.delay(), MAX_DELAY);
+        assert_eq!(throttle.throttled(), 3);
+
+        throttle.succeeded();
+        assert_eq!(throttle.delay(), Duration::from_millis(2500));
+        for _ in 0..6 {
+            throttle.succeeded();
+        }
+        assert_eq!(throttle.delay(), Duration::from_secs(0));
+    }
+}
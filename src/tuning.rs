@@ -0,0 +1,150 @@
+//! # tuning
+//!
+//! Pacing of requests learned per bridge. The latency of requests and the share of them that are
+//! dropped, failing to reach the bridge or turned away as overloaded, are tracked as running
+//! averages and kept between runs in $HOME/.config/rusty_hue/performance.json, keyed by bridge IP.
+//! Bridges on a congested Zigbee network answer slower and drop more, and are then sent fewer
+//! requests in parallel and animated at a lower frame rate. Both can be pinned in the
+//! configuration instead, with `parallel_requests` and `frame_rate`.
+
+use serde_json;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::time::Duration;
+
+use animation::TIME_PER_COMMAND;
+use files;
+
+/// Most requests sent to the bridge at once. The bridge only handles around ten light changes a
+/// second, so a few at a time hides the round trips without overloading it.
+pub const MAX_PARALLEL_REQUESTS: usize = 4;
+
+/// Weight of the newest request in the running averages, so about the last 50 requests count.
+const WEIGHT: f64 = 0.02;
+
+/// Requests seen before the learned values are used, until then the defaults apply.
+const MIN_SAMPLES: u64 = 20;
+
+/// How a bridge has been answering requests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Performance {
+    /// Requests seen.
+    pub samples: u64,
+    /// Running average of the time the bridge takes to answer, in milliseconds.
+    pub latency_ms: f64,
+    /// Running average of the share of requests dropped, from 0 to 1.
+    pub drop_rate: f64,
+}
+
+impl Performance {
+    /// Records a request that the bridge answered after `latency`, or that was dropped.
+    pub fn record(&mut self, latency: Option<Duration>) {
+        let weight = WEIGHT.max(1.0 / (self.samples + 1) as f64);
+        let dropped = if latency.is_some() { 0.0 } else { 1.0 };
+
+        if let Some(latency) = latency {
+            let latency_ms = latency.as_secs_f64() * 1000.0;
+            // Drops don't tell how long an answer takes, so they don't count towards latency.
+            self.latency_ms = if self.latency_ms == 0.0 {
+                latency_ms
+            } else {
+                self.latency_ms + (latency_ms - self.latency_ms) * weight
+            };
+        }
+        self.drop_rate += (dropped - self.drop_rate) * weight;
+        self.samples += 1;
+    }
+
+    /// Whether enough requests were seen for the learned values to be used.
+    pub fn learned(&self) -> bool {
+        self.samples >= MIN_SAMPLES
+    }
+
+    /// Requests to send at once: all `MAX_PARALLEL_REQUESTS` for a bridge that keeps up, fewer
+    /// for one that answers slowly or drops requests, and one at a time for one that drops a lot.
+    pub fn parallelism(&self) -> usize {
+        if !self.learned() {
+            MAX_PARALLEL_REQUESTS
+        } else if self.drop_rate >= 0.1 {
+            1
+        } else if self.drop_rate >= 0.02 || self.latency_ms >= 250.0 {
+            2
+        } else {
+            MAX_PARALLEL_REQUESTS
+        }
+    }
+
+    /// Time to allow the bridge per light command: its latency, stretched by its drop rate, and
+    /// never less than `TIME_PER_COMMAND`.
+    pub fn time_per_command(&self) -> Duration {
+        if !self.learned() {
+            return TIME_PER_COMMAND;
+        }
+
+        let ms = self.latency_ms * (1.0 + 4.0 * self.drop_rate);
+        TIME_PER_COMMAND.max(Duration::from_secs_f64(ms / 1000.0))
+    }
+}
+
+/// Loads what was learned about the bridge at an IP. A missing file means nothing was.
+pub fn load(ip: &str) -> Result<Performance, Box<dyn Error>> {
+    Ok(load_all()?.remove(ip).unwrap_or_default())
+}
+
+/// Saves what was learned about the bridge at an IP, keeping that of other bridges.
+pub fn save(ip: &str, performance: &Performance) -> Result<(), Box<dyn Error>> {
+    let mut all = load_all()?;
+    all.insert(ip.to_string(), *performance);
+
+    let path = files::config_file("performance.json")?;
+    files::write_atomic(&path, serde_json::to_string_pretty(&all)?.as_bytes())?;
+    Ok(())
+}
+
+/// Helper function to load what was learned about every bridge, by IP.
+fn load_all() -> Result<BTreeMap<String, Performance>, Box<dyn Error>> {
+    let mut f = match File::open(files::config_file("performance.json")?) {
+        Ok(f) => f,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(From::from(e)),
+    };
+
+    let mut contents = String::new();
+    f.read_to_string(&mut contents)?;
+
+    Ok(serde_json::from_str(&contents)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn adapts_to_congestion() {
+        let mut performance = Performance::default();
+        for _ in 0..MIN_SAMPLES {
+            performance.record(Some(Duration::from_millis(40)));
+        }
+        assert_eq!(performance.parallelism(), MAX_PARALLEL_REQUESTS);
+        assert_eq!(performance.time_per_command(), TIME_PER_COMMAND);
+
+        for _ in 0..50 {
+            performance.record(Some(Duration::from_millis(400)));
+            performance.record(None);
+        }
+        assert_eq!(performance.parallelism(), 1);
+        assert!(performance.time_per_command() > Duration::from_millis(400));
+    }
+
+    #[test]
+    fn defaults_until_learned() {
+        let mut performance = Performance::default();
+        performance.record(None);
+
+        assert_eq!(performance.drop_rate, 1.0);
+        assert_eq!(performance.parallelism(), MAX_PARALLEL_REQUESTS);
+    }
+}
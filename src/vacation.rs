@@ -0,0 +1,304 @@
+//! # vacation
+//!
+//! Presence simulation while away. Rooms are turned on and off in the evening at times that follow
+//! what the room is used for, with each day's times shifted at random so the pattern doesn't look
+//! automated. Rooms whose scenes are usually recalled at another time, as learned from the
+//! journal, are turned on around that time instead. The pattern is either played by this tool
+//! running as a daemon or turned into one-time schedules on the bridge for the next days, which
+//! keep working while the tool isn't running.
+
+use std::collections::HashMap;
+use std::error::Error;
+use time;
+
+use groups::Group;
+use hue::Hue;
+use parse::TimeOfDay;
+use shuffle::Rng;
+use state::LightStateUpdate;
+
+/// Description given to the schedules created for a vacation, so they can be found and removed.
+pub const SCHEDULE_DESCRIPTION: &str = "rusty_hue vacation";
+
+/// Usual evening hours of rooms by class, as minutes since midnight.
+const WINDOWS: [(&str, u32, u32); 5] = [
+    ("Living room", 18 * 60 + 30, 23 * 60),
+    ("Kitchen", 18 * 60, 20 * 60 + 30),
+    ("Dining", 18 * 60 + 30, 20 * 60),
+    ("Bedroom", 21 * 60 + 30, 23 * 60 + 30),
+    ("Kids bedroom", 19 * 60 + 30, 20 * 60 + 30),
+];
+
+/// Hours of rooms of any other class.
+const DEFAULT_WINDOW: (u32, u32) = (19 * 60, 22 * 60 + 30);
+
+/// Most minutes the times of a day are moved from the usual ones, earlier or later.
+const JITTER_MINUTES: u32 = 30;
+
+/// Fewest minutes a room stays on.
+const MIN_ON_MINUTES: u32 = 15;
+
+/// One day in this many, a room stays dark, as it would when nobody uses it that evening.
+const DARK_DAYS: usize = 6;
+
+/// Seconds in a day.
+const DAY_SECONDS: u32 = 24 * 3600;
+
+/// A room turned on and off on one day.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Slot {
+    /// Id of the room's group.
+    pub group: String,
+    pub room: String,
+    /// Days from today the room is turned on, with today as 0.
+    pub day: u32,
+    pub on: TimeOfDay,
+    /// Time the room is turned off, on the next day when it's before `on`.
+    pub off: TimeOfDay,
+}
+
+impl Slot {
+    /// Seconds from midnight today until the room is turned on.
+    pub fn on_seconds(&self) -> u32 {
+        self.day * DAY_SECONDS + self.on.seconds()
+    }
+
+    /// Seconds from midnight today until the room is turned off.
+    pub fn off_seconds(&self) -> u32 {
+        let day = if self.off < self.on {
+            self.day + 1
+        } else {
+            self.day
+        };
+        day * DAY_SECONDS + self.off.seconds()
+    }
+}
+
+/// Helper function to build a time of day from minutes since midnight, wrapping past midnight.
+fn time_of_day(minutes: u32) -> TimeOfDay {
+    let minutes = minutes % (24 * 60);
+
+    TimeOfDay {
+        hour: (minutes / 60) as u8,
+        minute: (minutes % 60) as u8,
+        second: 0,
+    }
+}
+
+/// Helper function to move a time by up to `JITTER_MINUTES` either way.
+fn jitter(rng: &mut Rng, minutes: u32) -> u32 {
+    (minutes + rng.below(2 * JITTER_MINUTES as usize + 1) as u32).saturating_sub(JITTER_MINUTES)
+}
+
+/// Plans the given number of days, starting today, for rooms given as their id and group.
+/// `learned` holds the usual time rooms are turned on, by id in minutes since midnight, which
+/// replaces the usual hours of their class while keeping how long they stay on.
+pub fn plan(
+    rooms: &[(String, Group)],
+    days: u32,
+    learned: &HashMap<String, u32>,
+    rng: &mut Rng,
+) -> Vec<Slot> {
+    let mut slots = Vec::new();
+
+    for day in 0..days {
+        for (id, room) in rooms {
+            if rng.below(DARK_DAYS) == 0 {
+                continue;
+            }
+
+            let (on, off) = WINDOWS
+                .iter()
+                .find(|&&(class, _, _)| room.class.as_deref() == Some(class))
+                .map_or(DEFAULT_WINDOW, |&(_, on, off)| (on, off));
+            let (on, off) = match learned.get(id) {
+                Some(&learned) => (learned, learned + off - on),
+                None => (on, off),
+            };
+
+            let on = jitter(rng, on);
+            let off = jitter(rng, off).max(on + MIN_ON_MINUTES);
+
+            slots.push(Slot {
+                group: id.clone(),
+                room: room.name.clone(),
+                day: day + on / (24 * 60),
+                on: time_of_day(on),
+                off: time_of_day(off),
+            });
+        }
+    }
+
+    slots.sort_by_key(|slot| (slot.day, slot.on));
+    slots
+}
+
+/// Seconds since midnight, local time.
+pub fn seconds_since_midnight() -> u32 {
+    let now = time::now();
+    (now.tm_hour * 3600 + now.tm_min * 60 + now.tm_sec) as u32
+}
+
+/// Formats a time, in seconds from midnight today, for the bridge's `localtime` field, e.x.
+/// "2018-06-01T21:30:00".
+fn localtime(seconds: u32) -> String {
+    let date = time::now() + time::Duration::days(i64::from(seconds / DAY_SECONDS));
+    let seconds = seconds % DAY_SECONDS;
+    format!(
+        "{}T{:02}:{:02}:{:02}",
+        date.strftime("%Y-%m-%d").unwrap(),
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+impl Hue {
+    /// The rooms to simulate presence in: all rooms, or only those named, by name or id.
+    pub fn vacation_rooms(&self, names: &[&str]) -> Result<Vec<(String, Group)>, Box<dyn Error>> {
+        if !names.is_empty() {
+            return names.iter().map(|name| self.find_group(name)).collect();
+        }
+
+        let mut rooms: Vec<(String, Group)> = self
+            .get_groups()?
+            .into_iter()
+            .filter(|(_, group)| group.group_type == "Room")
+            .collect();
+        rooms.sort_by(|a, b| a.0.cmp(&b.0));
+
+        Ok(rooms)
+    }
+
+    /// Turns a room of a slot on or off.
+    pub fn play_slot(&self, slot: &Slot, on: bool) -> Result<(), Box<dyn Error>> {
+        self.set_group_state(&slot.group, &LightStateUpdate::new().on(on))
+    }
+
+    /// Creates one-time schedules on the bridge turning each slot's room on and off. Slots
+    /// already past are skipped. Returns the number of schedules created.
+    pub fn schedule_vacation(&self, slots: &[Slot]) -> Result<usize, Box<dyn Error>> {
+        let now = seconds_since_midnight();
+        let mut created = 0;
+
+        for slot in slots {
+            for &(on, at) in &[(true, slot.on_seconds()), (false, slot.off_seconds())] {
+                if at <= now {
+                    continue;
+                }
+
+                let body = json!({
+                    "name": format!("Vacation {} {}", slot.room, if on { "on" } else { "off" }),
+                    "description": SCHEDULE_DESCRIPTION,
+                    "command": {
+                        "address": self.api_path(&format!("groups/{}/action", slot.group)),
+                        "method": "PUT",
+                        "body": { "on": on },
+                    },
+                    "localtime": localtime(at),
+                    "autodelete": true,
+                });
+
+                self.post(&self.api_url("schedules"), body.to_string())?;
+                created += 1;
+            }
+        }
+
+        Ok(created)
+    }
+
+    /// Removes the schedules created for a vacation. Returns the number removed.
+    pub fn clear_vacation(&self) -> Result<usize, Box<dyn Error>> {
+        let schedules = self.fetch("schedules", false)?;
+        let ids: Vec<&String> = match schedules.as_object() {
+            Some(schedules) => schedules
+                .iter()
+                .filter(|(_, schedule)| schedule["description"] == SCHEDULE_DESCRIPTION)
+                .map(|(id, _)| id)
+                .collect(),
+            None => Vec::new(),
+        };
+
+        for id in &ids {
+            self.delete(&self.api_url(&format!("schedules/{}", id)))?;
+        }
+
+        Ok(ids.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn room(name: &str, class: &str) -> Group {
+        Group {
+            name: name.to_string(),
+            lights: vec![String::from("1")],
+            group_type: String::from("Room"),
+            class: Some(class.to_string()),
+            derived_bri: None,
+        }
+    }
+
+    #[test]
+    fn realistic_plan() {
+        let rooms = vec![
+            (String::from("1"), room("Living", "Living room")),
+            (String::from("2"), room("Bedroom", "Bedroom")),
+        ];
+        let mut rng = Rng::with_seed(3);
+
+        let slots = plan(&rooms, 30, &HashMap::new(), &mut rng);
+
+        // Some evenings a room stays dark, but most it doesn't.
+        assert!(slots.len() > 40 && slots.len() < 60);
+
+        for slot in &slots {
+            assert!(slot.off_seconds() >= slot.on_seconds() + MIN_ON_MINUTES * 60);
+            let (on, _) = if slot.room == "Living" {
+                (18 * 60 + 30, 23 * 60)
+            } else {
+                (21 * 60 + 30, 23 * 60 + 30)
+            };
+            let minutes = slot.on.seconds() / 60;
+            assert!(minutes + JITTER_MINUTES >= on && minutes <= on + JITTER_MINUTES);
+        }
+
+        assert!(slots.windows(2).all(|pair| pair[0].day <= pair[1].day));
+        assert_ne!(slots[0].on, slots[2].on);
+    }
+
+    #[test]
+    fn learned_times() {
+        let rooms = vec![(String::from("1"), room("Living", "Living room"))];
+        let mut learned = HashMap::new();
+        learned.insert(String::from("1"), 20 * 60);
+
+        for slot in plan(&rooms, 10, &learned, &mut Rng::with_seed(5)) {
+            let on = slot.on.seconds() / 60;
+            assert!(on + JITTER_MINUTES >= 20 * 60 && on <= 20 * 60 + JITTER_MINUTES);
+        }
+    }
+
+    #[test]
+    fn times_of_day() {
+        assert_eq!(time_of_day(21 * 60 + 5).to_string(), "21:05:00");
+        assert_eq!(time_of_day(25 * 60).to_string(), "01:00:00");
+    }
+
+    #[test]
+    fn past_midnight() {
+        let rooms = vec![(String::from("1"), room("Bedroom", "Bedroom"))];
+        let mut learned = HashMap::new();
+        learned.insert(String::from("1"), 23 * 60 + 50);
+
+        let slots = plan(&rooms, 10, &learned, &mut Rng::with_seed(5));
+        assert!(slots.iter().any(|slot| slot.off < slot.on));
+
+        for slot in &slots {
+            assert!(slot.off_seconds() >= slot.on_seconds() + MIN_ON_MINUTES * 60);
+            assert!(slot.off_seconds() <= slot.on_seconds() + 3 * 3600);
+        }
+    }
+}
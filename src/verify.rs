@@ -0,0 +1,320 @@
+//! # verify
+//!
+//! Checks that lights reached the state they were sent. The bridge accepts a change before the
+//! light has applied it, so a light that dropped the change, or clamped a value to its range,
+//! goes unnoticed. With verification on, the changes sent are recorded, and afterwards the lights
+//! are fetched again, changes they didn't reach are sent again, and those still not reached are
+//! reported.
+
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::mem;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use hue::Hue;
+
+/// Fields of a change that are compared with the light's state.
+const VERIFIED_FIELDS: [&str; 6] = ["on", "bri", "ct", "xy", "hue", "sat"];
+
+/// Largest difference of an x or y coordinate still taken as reached, as the bridge rounds them.
+const XY_TOLERANCE: f64 = 0.005;
+
+/// Time given to the lights to apply changes before they are checked, once their transitions
+/// are over.
+const SETTLE_DELAY: Duration = Duration::from_millis(500);
+
+/// Transition of a change that doesn't give one.
+const DEFAULT_TRANSITION: Duration = Duration::from_millis(400);
+
+/// Longest transition waited for. Fields sent with a slower transition aren't checked, as the
+/// lights would still be on their way.
+const MAX_TRANSITION: Duration = Duration::from_secs(10);
+
+/// Changes sent to lights and groups while recording, as the resource path and the body sent,
+/// e.x. ("lights/3/state", {"bri": 100}).
+#[derive(Debug, Default)]
+pub struct Applied {
+    changes: Mutex<Option<Vec<(String, Value)>>>,
+}
+
+impl Applied {
+    /// Starts recording changes.
+    pub fn start(&self) {
+        self.changes.lock().unwrap().get_or_insert_with(Vec::new);
+    }
+
+    /// Records a change if recording, ignoring requests other than light and group changes.
+    pub fn record(&self, path: &str, body: &str) {
+        if let Some(ref mut changes) = *self.changes.lock().unwrap() {
+            let parts: Vec<&str> = path.split('/').collect();
+            let is_change = matches!(parts[..], ["lights", _, "state"] | ["groups", _, "action"]);
+
+            if let (true, Ok(body)) = (is_change, serde_json::from_str(body)) {
+                changes.push((path.to_string(), body));
+            }
+        }
+    }
+
+    /// Takes the recorded changes, leaving recording on.
+    fn take(&self) -> Vec<(String, Value)> {
+        match *self.changes.lock().unwrap() {
+            Some(ref mut changes) => mem::take(changes),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// A light that didn't reach the state it was sent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unverified {
+    pub index: String,
+    pub name: String,
+    /// The fields not reached, e.x. "ct 153 (is 233)".
+    pub fields: Vec<String>,
+}
+
+/// Merges recorded changes into the state requested of each light by index, later changes
+/// overriding earlier ones. Group changes apply to the group's lights, given by `groups`, with
+/// group "0" being all lights. Fields changed with a transition longer than `MAX_TRANSITION` are
+/// left out.
+pub fn requested_states(
+    changes: &[(String, Value)],
+    groups: &Value,
+    lights: &Value,
+) -> BTreeMap<String, Value> {
+    let mut requested: BTreeMap<String, Value> = BTreeMap::new();
+
+    for (path, body) in changes {
+        let parts: Vec<&str> = path.split('/').collect();
+        let indices: Vec<String> = match parts[..] {
+            ["lights", index, _] => vec![index.to_string()],
+            ["groups", "0", _] => lights
+                .as_object()
+                .map_or(Vec::new(), |lights| lights.keys().cloned().collect()),
+            ["groups", id, _] => groups[id]["lights"]
+                .as_array()
+                .map_or(Vec::new(), |lights| {
+                    lights
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .map(String::from)
+                        .collect()
+                }),
+            _ => continue,
+        };
+
+        let slow = transition(body) > MAX_TRANSITION;
+        for index in indices {
+            let state = requested.entry(index).or_insert_with(|| json!({}));
+            for field in VERIFIED_FIELDS.iter() {
+                match (body.get(*field), state.as_object_mut()) {
+                    (Some(_), Some(state)) if slow => {
+                        state.remove(*field);
+                    }
+                    (Some(value), Some(state)) => {
+                        state.insert(field.to_string(), value.clone());
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    requested.retain(|_, state| state.as_object().is_some_and(|state| !state.is_empty()));
+    requested
+}
+
+/// Time to wait for changes to be applied before checking them: their longest transition up to
+/// `MAX_TRANSITION`, and then `SETTLE_DELAY`.
+fn settle_delay(changes: &[(String, Value)]) -> Duration {
+    let longest = changes
+        .iter()
+        .map(|(_, body)| transition(body))
+        .filter(|&transition| transition <= MAX_TRANSITION)
+        .max()
+        .unwrap_or(DEFAULT_TRANSITION);
+
+    longest + SETTLE_DELAY
+}
+
+/// Helper function for the transition of a change, given by the bridge in multiples of 100ms.
+fn transition(body: &Value) -> Duration {
+    body["transitiontime"]
+        .as_u64()
+        .map_or(DEFAULT_TRANSITION, |tenths| {
+            Duration::from_millis(tenths * 100)
+        })
+}
+
+/// The fields of a requested state that a light's state doesn't match, described for display.
+/// Lights sent "off" are only checked for being off.
+pub fn mismatches(requested: &Value, state: &Value) -> Vec<String> {
+    let fields: &[&str] = if requested["on"] == false {
+        &["on"]
+    } else {
+        &VERIFIED_FIELDS
+    };
+
+    let mut mismatches = Vec::new();
+
+    for field in fields {
+        let (wanted, actual) = match requested.get(*field) {
+            Some(wanted) => (wanted, &state[*field]),
+            None => continue,
+        };
+
+        let reached = match *field {
+            "xy" => match (wanted.as_array(), actual.as_array()) {
+                (Some(wanted), Some(actual)) if wanted.len() == actual.len() => {
+                    wanted.iter().zip(actual).all(|(a, b)| {
+                        (a.as_f64().unwrap_or(-1.0) - b.as_f64().unwrap_or(1.0)).abs()
+                            <= XY_TOLERANCE
+                    })
+                }
+                _ => false,
+            },
+            _ => wanted == actual,
+        };
+
+        if !reached {
+            mismatches.push(format!("{} {} (is {})", field, wanted, actual));
+        }
+    }
+
+    mismatches
+}
+
+impl Hue {
+    /// Starts recording the changes sent to lights and groups, to verify them with
+    /// `verify_changes`.
+    pub fn record_changes(&self) {
+        self.applied.start();
+    }
+
+    /// Checks that the lights reached the changes recorded since the last check, sending the
+    /// changes not reached again up to `retries` times. Returns the lights that still didn't
+    /// reach them, and unreachable lights sent a change.
+    pub fn verify_changes(&self, retries: u32) -> Result<Vec<Unverified>, Box<dyn Error>> {
+        let changes = self.applied.take();
+        if changes.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let groups = self.fetch("groups", false)?;
+        let mut requested = None;
+        let mut attempt = 0;
+        let mut delay = settle_delay(&changes);
+
+        loop {
+            thread::sleep(delay);
+            let lights = self.fetch("lights", false)?;
+            let requested =
+                requested.get_or_insert_with(|| requested_states(&changes, &groups, &lights));
+
+            let mut unverified = Vec::new();
+            for (index, wanted) in requested.iter() {
+                let light = &lights[index.as_str()];
+                let fields = if light["state"]["reachable"] == false {
+                    vec![String::from("unreachable")]
+                } else {
+                    mismatches(wanted, &light["state"])
+                };
+
+                if !fields.is_empty() {
+                    unverified.push(Unverified {
+                        index: index.clone(),
+                        name: light["name"].as_str().unwrap_or(index).to_string(),
+                        fields,
+                    });
+                }
+            }
+
+            if unverified.is_empty() || attempt == retries {
+                self.applied.take();
+                return Ok(unverified);
+            }
+
+            for light in &unverified {
+                self.put(
+                    &self.api_url(&format!("lights/{}/state", light.index)),
+                    requested[&light.index].to_string(),
+                )?;
+            }
+            // Changes sent again have the default transition.
+            delay = DEFAULT_TRANSITION + SETTLE_DELAY;
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn merge_requested_states() {
+        let changes = vec![
+            (
+                String::from("groups/1/action"),
+                json!({"on": true, "bri": 100}),
+            ),
+            (
+                String::from("lights/2/state"),
+                json!({"ct": 300, "transitiontime": 4}),
+            ),
+            (String::from("lights/3/state"), json!({"alert": "select"})),
+        ];
+        let groups = json!({"1": {"lights": ["1", "2"]}});
+        let lights = json!({"1": {}, "2": {}, "3": {}});
+
+        let requested = requested_states(&changes, &groups, &lights);
+
+        assert_eq!(requested.len(), 2);
+        assert_eq!(requested["1"], json!({"on": true, "bri": 100}));
+        assert_eq!(requested["2"], json!({"on": true, "bri": 100, "ct": 300}));
+    }
+
+    #[test]
+    fn wait_for_transitions() {
+        let change = |body: Value| (String::from("lights/1/state"), body);
+
+        assert_eq!(
+            settle_delay(&[change(json!({"bri": 1}))]),
+            Duration::from_millis(900)
+        );
+        assert_eq!(
+            settle_delay(&[
+                change(json!({"bri": 1, "transitiontime": 0})),
+                change(json!({"bri": 2, "transitiontime": 30})),
+            ]),
+            Duration::from_millis(3500)
+        );
+
+        // A slow fade isn't waited for, nor are the fields it changes checked.
+        let changes = [
+            change(json!({"on": true, "bri": 1})),
+            change(json!({"bri": 254, "transitiontime": 6000})),
+        ];
+        assert_eq!(settle_delay(&changes), Duration::from_millis(900));
+        let requested = requested_states(&changes, &json!({}), &json!({"1": {}}));
+        assert_eq!(requested["1"], json!({"on": true}));
+    }
+
+    #[test]
+    fn find_mismatches() {
+        let state = json!({"on": true, "bri": 100, "ct": 233, "xy": [0.4573, 0.41]});
+
+        assert!(mismatches(&json!({"on": true, "xy": [0.4571, 0.4102]}), &state).is_empty());
+        assert_eq!(
+            mismatches(&json!({"bri": 100, "ct": 153}), &state),
+            vec!["ct 153 (is 233)"]
+        );
+        assert_eq!(
+            mismatches(&json!({"on": false, "bri": 1}), &state),
+            vec!["on false (is true)"]
+        );
+    }
+}